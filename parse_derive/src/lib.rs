@@ -1,24 +1,32 @@
 extern crate proc_macro;
 
-#[proc_macro_derive(Grammar)]
+// Every derive emits the `Grammar`, `Spanned` and `Walk` impls of the node. The category derives
+// (statements, types and expressions) additionally emit the impl of their marker trait.
+//
+// Enums only get the `Spanned`, `Walk` and marker impls. Parsing them requires a lookahead so
+// their `Grammar` impls are written by hand.
+//
+// Named fields are parsed with their own `Grammar` impls, unless they have a
+// `#[parse_with(function)]` attribute, in which case they are parsed by the function instead.
+
+#[proc_macro_derive(Grammar, attributes(parse_with))]
 pub fn grammar_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let subtrait_path = quote::quote!(crate::ast::statements::StatementGrammar);
-    derive(Some(&subtrait_path), input)
+    derive(None, input)
 }
 
-#[proc_macro_derive(StatementGrammar)]
+#[proc_macro_derive(StatementGrammar, attributes(parse_with))]
 pub fn statement_grammar_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let subtrait_path = quote::quote!(crate::ast::statements::StatementGrammar);
     derive(Some(&subtrait_path), input)
 }
 
-#[proc_macro_derive(TypeGrammar)]
+#[proc_macro_derive(TypeGrammar, attributes(parse_with))]
 pub fn type_grammar_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let subtrait_path = quote::quote!(crate::ast::types::TypeGrammar);
     derive(Some(&subtrait_path), input)
 }
 
-#[proc_macro_derive(ExpressionGrammar)]
+#[proc_macro_derive(ExpressionGrammar, attributes(parse_with))]
 pub fn expression_grammar_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let subtrait_path = quote::quote!(crate::ast::expressions::ExpressionGrammar);
     derive(Some(&subtrait_path), input)
@@ -31,6 +39,7 @@ fn derive(
     let derive_input = syn::parse_macro_input!(input as syn::DeriveInput);
     match &derive_input.data {
         syn::Data::Struct(struct_) => derive_struct(subtrait_path, &derive_input, &struct_),
+        syn::Data::Enum(enum_) => derive_enum(subtrait_path, &derive_input, &enum_),
         _ => unimplemented!(),
     }
     .into()
@@ -46,14 +55,144 @@ fn derive_struct(
         syn::Fields::Unnamed(unnamed) => derive_struct_unnamed(derive_input, unnamed),
         syn::Fields::Unit => derive_struct_unit(derive_input),
     };
-    let subgrammar = if let Some(subtrait_path) = subtrait_path {
+    let subgrammar = derive_subgrammar(subtrait_path, derive_input);
+    let spanned = derive_struct_spanned(derive_input, &data.fields);
+    let walk = derive_struct_walk(derive_input, &data.fields);
+    quote::quote! {#grammar #subgrammar #spanned #walk}
+}
+
+fn derive_enum(
+    subtrait_path: Option<&proc_macro2::TokenStream>,
+    derive_input: &syn::DeriveInput,
+    data: &syn::DataEnum,
+) -> proc_macro2::TokenStream {
+    let (impl_, ty, where_) = derive_input.generics.split_for_impl();
+    let ident = &derive_input.ident;
+    let (visit, visit_mut) = visit_methods(ident);
+    let variants: Vec<_> = data.variants.iter().map(|variant| &variant.ident).collect();
+    let subgrammar = derive_subgrammar(subtrait_path, derive_input);
+    quote::quote! {
+        #subgrammar
+
+        impl #impl_ crate::Spanned for #ident #ty #where_ {
+            fn span(&self) -> crate::Span {
+                match self {
+                    #(Self::#variants(node) => crate::Spanned::span(node),)*
+                }
+            }
+        }
+
+        impl #impl_ crate::ast::visit::Walk <'input> for #ident #ty #where_ {
+            fn accept<V: crate::ast::visit::Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+                visitor.#visit(self)
+            }
+
+            fn accept_mut<V: crate::ast::visit::VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+                visitor.#visit_mut(self)
+            }
+
+            fn walk<V: crate::ast::visit::Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+                match self {
+                    #(Self::#variants(node) => crate::ast::visit::Walk::accept(node, visitor),)*
+                }
+            }
+
+            fn walk_mut<V: crate::ast::visit::VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+                match self {
+                    #(Self::#variants(node) => crate::ast::visit::Walk::accept_mut(node, visitor),)*
+                }
+            }
+        }
+    }
+}
+
+fn derive_subgrammar(
+    subtrait_path: Option<&proc_macro2::TokenStream>,
+    derive_input: &syn::DeriveInput,
+) -> proc_macro2::TokenStream {
+    if let Some(subtrait_path) = subtrait_path {
         let ident = &derive_input.ident;
         let (impl_, ty, where_) = derive_input.generics.split_for_impl();
         quote::quote! { impl #impl_ #subtrait_path <'input> for #ident #ty #where_ {} }
     } else {
         proc_macro2::TokenStream::new()
-    };
-    quote::quote! {#grammar #subgrammar}
+    }
+}
+
+/// Names of the `Visit` and `VisitMut` methods of a node (`visit_foo_bar` and
+/// `visit_foo_bar_mut` for a `FooBar` node).
+fn visit_methods(ident: &syn::Ident) -> (syn::Ident, syn::Ident) {
+    let mut snake = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    (
+        quote::format_ident!("visit_{}", snake),
+        quote::format_ident!("visit_{}_mut", snake),
+    )
+}
+
+/// Member access expressions (`self.foo`, `self.0`, ...) for every field, in declaration order.
+fn field_members(fields: &syn::Fields) -> Vec<proc_macro2::TokenStream> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match &field.ident {
+            Some(ident) => quote::quote!(#ident),
+            None => {
+                let index = syn::Index::from(index);
+                quote::quote!(#index)
+            }
+        })
+        .collect()
+}
+
+fn derive_struct_spanned(
+    derive_input: &syn::DeriveInput,
+    fields: &syn::Fields,
+) -> proc_macro2::TokenStream {
+    let (impl_, ty, where_) = derive_input.generics.split_for_impl();
+    let ident = &derive_input.ident;
+    let members = field_members(fields);
+    quote::quote! {
+        impl #impl_ crate::Spanned for #ident #ty #where_ {
+            fn span(&self) -> crate::Span {
+                crate::Span::default()#(.join(crate::Spanned::span(&self.#members)))*
+            }
+        }
+    }
+}
+
+fn derive_struct_walk(
+    derive_input: &syn::DeriveInput,
+    fields: &syn::Fields,
+) -> proc_macro2::TokenStream {
+    let (impl_, ty, where_) = derive_input.generics.split_for_impl();
+    let ident = &derive_input.ident;
+    let members = field_members(fields);
+    let (visit, visit_mut) = visit_methods(ident);
+    quote::quote! {
+        impl #impl_ crate::ast::visit::Walk <'input> for #ident #ty #where_ {
+            fn accept<V: crate::ast::visit::Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+                visitor.#visit(self)
+            }
+
+            fn accept_mut<V: crate::ast::visit::VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+                visitor.#visit_mut(self)
+            }
+
+            fn walk<V: crate::ast::visit::Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+                #(crate::ast::visit::Walk::accept(&self.#members, visitor);)*
+            }
+
+            fn walk_mut<V: crate::ast::visit::VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+                #(crate::ast::visit::Walk::accept_mut(&mut self.#members, visitor);)*
+            }
+        }
+    }
 }
 
 fn derive_struct_named(
@@ -62,11 +201,20 @@ fn derive_struct_named(
 ) -> proc_macro2::TokenStream {
     let (impl_, ty, where_) = derive_input.generics.split_for_impl();
     let ident = &derive_input.ident;
-    let fields = fields_named
-        .named
-        .iter()
-        .filter_map(|field| field.ident.as_ref())
-        .map(|ident| quote::quote!(#ident : Grammar::parse(tokens, context)?,));
+    let fields = fields_named.named.iter().filter_map(|field| {
+        let ident = field.ident.as_ref()?;
+        let parse_with = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("parse_with"));
+        Some(match parse_with {
+            Some(attr) => {
+                let function: syn::Path = attr.parse_args().expect("Expected a function");
+                quote::quote!(#ident : #function(tokens, context)?,)
+            }
+            None => quote::quote!(#ident : Grammar::parse(tokens, context)?,),
+        })
+    });
     quote::quote! {
         impl #impl_ crate::ast::Grammar <'input> for #ident #ty #where_ {
             fn parse(tokens: &mut std::iter::Peekable<crate::lex::Tokenizer<'input>>,
@@ -0,0 +1,568 @@
+//! SM83 assembly.
+//!
+//! [`Assembly`] is a list of labels, [instructions](Instruction) and data, as emitted by the
+//! [code generator](crate::codegen). It prints in RGBDS syntax, which can be [parsed](parse)
+//! back. Programs can be [assembled](encode) to machine code (and [disassembled](decode)), or
+//! run by the [interpreter](interp). The `asm { ... }` blocks of gb-lang programs are handled by
+//! [`inline`].
+//!
+//! ```
+//! use gb_lang::asm::{Instruction, Reg, Source};
+//!
+//! assert_eq!("ld a, [hl]", Instruction::Ld(Reg::A, Source::HlInd).to_string());
+//! ```
+use crate::Span;
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+pub mod decode;
+pub mod encode;
+pub mod inline;
+pub mod interp;
+pub mod parse;
+
+/// 8-bit register.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Reg {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Reg::A => "a",
+            Reg::B => "b",
+            Reg::C => "c",
+            Reg::D => "d",
+            Reg::E => "e",
+            Reg::H => "h",
+            Reg::L => "l",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 16-bit register.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Reg16 {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+impl Reg16 {
+    /// High and low halves of the register (`None` for `sp`).
+    pub fn halves(self) -> Option<(Reg, Reg)> {
+        match self {
+            Reg16::BC => Some((Reg::B, Reg::C)),
+            Reg16::DE => Some((Reg::D, Reg::E)),
+            Reg16::HL => Some((Reg::H, Reg::L)),
+            Reg16::SP => None,
+        }
+    }
+}
+
+impl Display for Reg16 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Reg16::BC => "bc",
+            Reg16::DE => "de",
+            Reg16::HL => "hl",
+            Reg16::SP => "sp",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 16-bit register that can be pushed to (and popped from) the stack.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum StackReg {
+    BC,
+    DE,
+    HL,
+    AF,
+}
+
+impl Display for StackReg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StackReg::BC => "bc",
+            StackReg::DE => "de",
+            StackReg::HL => "hl",
+            StackReg::AF => "af",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Condition of a jump, call or return.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Condition {
+    Z,
+    NZ,
+    C,
+    NC,
+}
+
+impl Condition {
+    /// Condition that holds when this one doesn't.
+    pub fn negate(self) -> Condition {
+        match self {
+            Condition::Z => Condition::NZ,
+            Condition::NZ => Condition::Z,
+            Condition::C => Condition::NC,
+            Condition::NC => Condition::C,
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Condition::Z => "z",
+            Condition::NZ => "nz",
+            Condition::C => "c",
+            Condition::NC => "nc",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 16-bit immediate: a number, or the address of a label.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Value {
+    Number(u16),
+    Label(String),
+}
+
+impl Value {
+    pub fn label(name: impl Into<String>) -> Self {
+        Value::Label(name.into())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "${:04X}", n),
+            Value::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
+
+/// 8-bit operand of loads and arithmetic.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Source {
+    Reg(Reg),
+    Imm(u8),
+    /// `[hl]`
+    HlInd,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Reg(reg) => write!(f, "{}", reg),
+            Source::Imm(n) => write!(f, "${:02X}", n),
+            Source::HlInd => write!(f, "[hl]"),
+        }
+    }
+}
+
+/// Arithmetic and logic operation on `a`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Alu {
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
+
+impl Display for Alu {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Alu::Add => "add",
+            Alu::Adc => "adc",
+            Alu::Sub => "sub",
+            Alu::Sbc => "sbc",
+            Alu::And => "and",
+            Alu::Xor => "xor",
+            Alu::Or => "or",
+            Alu::Cp => "cp",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Operand of `inc`, `dec` and the CB-prefixed instructions: an 8-bit register, or `[hl]`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Target {
+    Reg(Reg),
+    HlInd,
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Reg(reg) => write!(f, "{}", reg),
+            Target::HlInd => write!(f, "[hl]"),
+        }
+    }
+}
+
+/// CB-prefixed rotation or shift.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Shift {
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+}
+
+impl Display for Shift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Shift::Rlc => "rlc",
+            Shift::Rrc => "rrc",
+            Shift::Rl => "rl",
+            Shift::Rr => "rr",
+            Shift::Sla => "sla",
+            Shift::Sra => "sra",
+            Shift::Swap => "swap",
+            Shift::Srl => "srl",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// SM83 instruction.
+///
+/// Every opcode (and CB-prefixed opcode) has a variant, although some variants can hold operands
+/// that don't [encode](encode) to anything (such as `ld [hl], [hl]` or `ld a, [sp]`).
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Instruction {
+    Nop,
+    Stop,
+    Halt,
+    Di,
+    Ei,
+
+    /// `ld r, r'`, `ld r, n8` or `ld r, [hl]`
+    Ld(Reg, Source),
+
+    /// `ld [hl], r` or `ld [hl], n8`
+    LdHlInd(Source),
+
+    /// `ld a, [bc]` or `ld a, [de]`
+    LdAFrom(Reg16),
+
+    /// `ld [bc], a` or `ld [de], a`
+    LdToA(Reg16),
+
+    /// `ld a, [hl+]`
+    LdAHlInc,
+
+    /// `ld [hl+], a`
+    LdHlIncA,
+
+    /// `ld a, [hl-]`
+    LdAHlDec,
+
+    /// `ld [hl-], a`
+    LdHlDecA,
+
+    /// `ld a, [n16]`
+    LdAFromAddress(Value),
+
+    /// `ld [n16], a`
+    LdAddressA(Value),
+
+    /// `ldh a, [n16]`, where the address is in `$FF00-$FFFF`.
+    LdhAFromAddress(Value),
+
+    /// `ldh [n16], a`, where the address is in `$FF00-$FFFF`.
+    LdhAddressA(Value),
+
+    /// `ldh a, [c]`
+    LdhAFromC,
+
+    /// `ldh [c], a`
+    LdhCA,
+
+    /// `ld rr, n16`
+    Ld16(Reg16, Value),
+
+    /// `ld [n16], sp`
+    LdAddressSp(Value),
+
+    /// `ld sp, hl`
+    LdSpHl,
+
+    /// `ld hl, sp + e8`
+    LdHlSp(i8),
+
+    Inc(Target),
+    Dec(Target),
+    Inc16(Reg16),
+    Dec16(Reg16),
+
+    /// Operation between `a` and an operand, storing the result in `a` (except for `cp`).
+    Alu(Alu, Source),
+
+    /// `add hl, rr`
+    AddHl(Reg16),
+
+    /// `add sp, e8`
+    AddSp(i8),
+
+    /// Decimal adjust `a`.
+    Daa,
+
+    /// Complement `a`.
+    Cpl,
+
+    /// Set the carry flag.
+    Scf,
+
+    /// Complement the carry flag.
+    Ccf,
+
+    /// Rotate `a` left.
+    Rlca,
+
+    /// Rotate `a` right.
+    Rrca,
+
+    /// Rotate `a` left through the carry.
+    Rla,
+
+    /// Rotate `a` right through the carry.
+    Rra,
+
+    /// Rotate or shift a register (or `[hl]`).
+    Shift(Shift, Target),
+
+    /// Test a bit.
+    Bit(u8, Target),
+
+    /// Reset a bit.
+    Res(u8, Target),
+
+    /// Set a bit.
+    Set(u8, Target),
+
+    Push(StackReg),
+    Pop(StackReg),
+
+    Jp(Option<Condition>, Value),
+
+    /// `jp hl`
+    JpHl,
+
+    /// Relative jump, to a target at most 128 bytes away.
+    Jr(Option<Condition>, Value),
+
+    Call(Option<Condition>, Value),
+    Ret(Option<Condition>),
+    Reti,
+
+    /// Call one of the fixed addresses `$00`, `$08`, ..., `$38`.
+    Rst(u8),
+}
+
+impl Instruction {
+    /// 16-bit immediate operand of the instruction, if it has one.
+    pub fn value_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            Instruction::LdAFromAddress(value)
+            | Instruction::LdAddressA(value)
+            | Instruction::LdhAFromAddress(value)
+            | Instruction::LdhAddressA(value)
+            | Instruction::Ld16(_, value)
+            | Instruction::LdAddressSp(value)
+            | Instruction::Jp(_, value)
+            | Instruction::Jr(_, value)
+            | Instruction::Call(_, value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let condition = |condition: &Option<Condition>| match condition {
+            Some(condition) => format!(" {},", condition),
+            None => String::new(),
+        };
+        let offset = |e: &i8| {
+            if *e < 0 {
+                format!("- {}", -i16::from(*e))
+            } else {
+                format!("+ {}", e)
+            }
+        };
+        match self {
+            Instruction::Nop => write!(f, "nop"),
+            Instruction::Stop => write!(f, "stop"),
+            Instruction::Halt => write!(f, "halt"),
+            Instruction::Di => write!(f, "di"),
+            Instruction::Ei => write!(f, "ei"),
+            Instruction::Ld(reg, source) => write!(f, "ld {}, {}", reg, source),
+            Instruction::LdHlInd(source) => write!(f, "ld [hl], {}", source),
+            Instruction::LdAFrom(reg) => write!(f, "ld a, [{}]", reg),
+            Instruction::LdToA(reg) => write!(f, "ld [{}], a", reg),
+            Instruction::LdAHlInc => write!(f, "ld a, [hl+]"),
+            Instruction::LdHlIncA => write!(f, "ld [hl+], a"),
+            Instruction::LdAHlDec => write!(f, "ld a, [hl-]"),
+            Instruction::LdHlDecA => write!(f, "ld [hl-], a"),
+            Instruction::LdAFromAddress(address) => write!(f, "ld a, [{}]", address),
+            Instruction::LdAddressA(address) => write!(f, "ld [{}], a", address),
+            Instruction::LdhAFromAddress(address) => write!(f, "ldh a, [{}]", address),
+            Instruction::LdhAddressA(address) => write!(f, "ldh [{}], a", address),
+            Instruction::LdhAFromC => write!(f, "ldh a, [c]"),
+            Instruction::LdhCA => write!(f, "ldh [c], a"),
+            Instruction::Ld16(reg, value) => write!(f, "ld {}, {}", reg, value),
+            Instruction::LdAddressSp(address) => write!(f, "ld [{}], sp", address),
+            Instruction::LdSpHl => write!(f, "ld sp, hl"),
+            Instruction::LdHlSp(e) => write!(f, "ld hl, sp {}", offset(e)),
+            Instruction::Inc(target) => write!(f, "inc {}", target),
+            Instruction::Dec(target) => write!(f, "dec {}", target),
+            Instruction::Inc16(reg) => write!(f, "inc {}", reg),
+            Instruction::Dec16(reg) => write!(f, "dec {}", reg),
+            Instruction::Alu(op, source) => write!(f, "{} a, {}", op, source),
+            Instruction::AddHl(reg) => write!(f, "add hl, {}", reg),
+            Instruction::AddSp(e) => write!(f, "add sp, {}", e),
+            Instruction::Daa => write!(f, "daa"),
+            Instruction::Cpl => write!(f, "cpl"),
+            Instruction::Scf => write!(f, "scf"),
+            Instruction::Ccf => write!(f, "ccf"),
+            Instruction::Rlca => write!(f, "rlca"),
+            Instruction::Rrca => write!(f, "rrca"),
+            Instruction::Rla => write!(f, "rla"),
+            Instruction::Rra => write!(f, "rra"),
+            Instruction::Shift(op, target) => write!(f, "{} {}", op, target),
+            Instruction::Bit(bit, target) => write!(f, "bit {}, {}", bit, target),
+            Instruction::Res(bit, target) => write!(f, "res {}, {}", bit, target),
+            Instruction::Set(bit, target) => write!(f, "set {}, {}", bit, target),
+            Instruction::Push(reg) => write!(f, "push {}", reg),
+            Instruction::Pop(reg) => write!(f, "pop {}", reg),
+            Instruction::Jp(cc, target) => {
+                let cc = condition(cc);
+                write!(f, "jp{} {}", cc, target)
+            }
+            Instruction::JpHl => write!(f, "jp hl"),
+            Instruction::Jr(cc, target) => {
+                let cc = condition(cc);
+                write!(f, "jr{} {}", cc, target)
+            }
+            Instruction::Call(cc, target) => {
+                let cc = condition(cc);
+                write!(f, "call{} {}", cc, target)
+            }
+            Instruction::Ret(None) => write!(f, "ret"),
+            Instruction::Ret(Some(cc)) => write!(f, "ret {}", cc),
+            Instruction::Reti => write!(f, "reti"),
+            Instruction::Rst(vector) => write!(f, "rst ${:02X}", vector),
+        }
+    }
+}
+
+/// Line of an [`Assembly`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Line {
+    Label(String),
+    Instruction(Instruction),
+
+    /// Bytes of data (`db`).
+    Data(Vec<u8>),
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Label(label) => write!(f, "{}:", label),
+            Line::Instruction(instruction) => write!(f, "    {}", instruction),
+            Line::Data(bytes) => {
+                write!(f, "    db ")?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "${:02X}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Assembly program.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Assembly {
+    pub lines: Vec<Line>,
+
+    /// Spans of the source the lines were generated from, by the index of the first line each
+    /// one covers (up to the next one). Lines before the first span, and lines covered by the
+    /// default span, don't come from the source.
+    pub spans: Vec<(usize, Span)>,
+}
+
+impl Assembly {
+    /// Mark the lines pushed from now on as generated from a span of the source.
+    pub fn span(&mut self, span: Span) {
+        let index = self.lines.len();
+        match self.spans.last_mut() {
+            Some((_, last)) if *last == span => {}
+            Some((start, last)) if *start == index => *last = span,
+            None if span == Span::default() => {}
+            _ => self.spans.push((index, span)),
+        }
+    }
+
+    /// Span of the source a line was generated from.
+    pub fn span_of(&self, line: usize) -> Span {
+        match self.spans.partition_point(|(start, _)| *start <= line) {
+            0 => Span::default(),
+            i => self.spans[i - 1].1,
+        }
+    }
+
+    pub fn label(&mut self, label: impl Into<String>) {
+        self.lines.push(Line::Label(label.into()));
+    }
+
+    pub fn push(&mut self, instruction: Instruction) {
+        self.lines.push(Line::Instruction(instruction));
+    }
+
+    /// Textual form of the program.
+    pub fn dump(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Assembly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Disassembler of SM83 machine code.
+//!
+//! Addresses are decoded as [numbers](Value::Number), and relative jumps to the absolute
+//! address of their target. Bytes that aren't a valid instruction are disassembled as data.
+//!
+//! ```
+//! use gb_lang::asm::decode::disassemble;
+//!
+//! let assembly = disassemble(&[0x3e, 0x2a, 0xe0, 0x40, 0x18, 0xfa, 0xdd], 0x150);
+//! assert_eq!(
+//!     "    ld a, $2A\n    ldh [$FF40], a\n    jr $0150\n    db $DD\n",
+//!     assembly.dump()
+//! );
+//! ```
+use crate::asm::{
+    encode::PREFIX, Alu, Assembly, Condition, Instruction, Line, Reg, Reg16, Shift, Source,
+    StackReg, Target, Value,
+};
+
+/// Decode the instruction at the start of `bytes`, located at `address`. Returns `None` if the
+/// bytes don't start with a valid (and complete) instruction.
+pub fn decode(bytes: &[u8], address: u16) -> Option<Instruction> {
+    let opcode = *bytes.first()?;
+    let n8 = || bytes.get(1).copied();
+    let n16 = || Some(Value::Number(u16::from_le_bytes([n8()?, *bytes.get(2)?])));
+    let e8 = || Some(n8()? as i8);
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 7;
+    let z = opcode & 7;
+    let instruction = match opcode {
+        0x00 => Instruction::Nop,
+        0x10 => match n8()? {
+            0x00 => Instruction::Stop,
+            _ => return None,
+        },
+        0x76 => Instruction::Halt,
+        0xf3 => Instruction::Di,
+        0xfb => Instruction::Ei,
+        0x02 => Instruction::LdToA(Reg16::BC),
+        0x12 => Instruction::LdToA(Reg16::DE),
+        0x22 => Instruction::LdHlIncA,
+        0x32 => Instruction::LdHlDecA,
+        0x0a => Instruction::LdAFrom(Reg16::BC),
+        0x1a => Instruction::LdAFrom(Reg16::DE),
+        0x2a => Instruction::LdAHlInc,
+        0x3a => Instruction::LdAHlDec,
+        0x08 => Instruction::LdAddressSp(n16()?),
+        0x07 => Instruction::Rlca,
+        0x0f => Instruction::Rrca,
+        0x17 => Instruction::Rla,
+        0x1f => Instruction::Rra,
+        0x27 => Instruction::Daa,
+        0x2f => Instruction::Cpl,
+        0x37 => Instruction::Scf,
+        0x3f => Instruction::Ccf,
+        0x18 => Instruction::Jr(None, relative(address, e8()?)),
+        0x20 | 0x28 | 0x30 | 0x38 => {
+            Instruction::Jr(Some(condition(y & 3)), relative(address, e8()?))
+        }
+        0xc9 => Instruction::Ret(None),
+        0xd9 => Instruction::Reti,
+        0xc3 => Instruction::Jp(None, n16()?),
+        0xe9 => Instruction::JpHl,
+        0xcd => Instruction::Call(None, n16()?),
+        0xe0 => Instruction::LdhAddressA(Value::Number(0xff00 | u16::from(n8()?))),
+        0xf0 => Instruction::LdhAFromAddress(Value::Number(0xff00 | u16::from(n8()?))),
+        0xe2 => Instruction::LdhCA,
+        0xf2 => Instruction::LdhAFromC,
+        0xea => Instruction::LdAddressA(n16()?),
+        0xfa => Instruction::LdAFromAddress(n16()?),
+        0xe8 => Instruction::AddSp(e8()?),
+        0xf8 => Instruction::LdHlSp(e8()?),
+        0xf9 => Instruction::LdSpHl,
+        PREFIX => {
+            let opcode = n8()?;
+            let target = target(opcode & 7);
+            let bit = (opcode >> 3) & 7;
+            match opcode >> 6 {
+                0 => Instruction::Shift(shift(bit), target),
+                1 => Instruction::Bit(bit, target),
+                2 => Instruction::Res(bit, target),
+                _ => Instruction::Set(bit, target),
+            }
+        }
+        _ => match (x, z) {
+            (0, 1) if y & 1 == 0 => Instruction::Ld16(reg16(y >> 1), n16()?),
+            (0, 1) => Instruction::AddHl(reg16(y >> 1)),
+            (0, 3) if y & 1 == 0 => Instruction::Inc16(reg16(y >> 1)),
+            (0, 3) => Instruction::Dec16(reg16(y >> 1)),
+            (0, 4) => Instruction::Inc(target(y)),
+            (0, 5) => Instruction::Dec(target(y)),
+            (0, 6) if y == 6 => Instruction::LdHlInd(Source::Imm(n8()?)),
+            (0, 6) => Instruction::Ld(reg(y), Source::Imm(n8()?)),
+            (0, _) => unreachable!(),
+            (1, _) if y == 6 => Instruction::LdHlInd(source(z)),
+            (1, _) => Instruction::Ld(reg(y), source(z)),
+            (2, _) => Instruction::Alu(alu(y), source(z)),
+            (_, 0) if y < 4 => Instruction::Ret(Some(condition(y))),
+            (_, 1) if y & 1 == 0 => Instruction::Pop(stack_reg(y >> 1)),
+            (_, 2) if y < 4 => Instruction::Jp(Some(condition(y)), n16()?),
+            (_, 4) if y < 4 => Instruction::Call(Some(condition(y)), n16()?),
+            (_, 5) if y & 1 == 0 => Instruction::Push(stack_reg(y >> 1)),
+            (_, 6) => Instruction::Alu(alu(y), Source::Imm(n8()?)),
+            (_, 7) => Instruction::Rst(y << 3),
+            // 0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc and 0xfd
+            _ => return None,
+        },
+    };
+    Some(instruction)
+}
+
+/// Disassemble a program loaded at the `origin` address.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Assembly {
+    let mut assembly = Assembly::default();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        match decode(&bytes[offset..], address) {
+            Some(instruction) => {
+                offset += usize::from(instruction.size());
+                assembly.push(instruction);
+            }
+            None => {
+                match assembly.lines.last_mut() {
+                    Some(Line::Data(data)) => data.push(bytes[offset]),
+                    _ => assembly.lines.push(Line::Data(vec![bytes[offset]])),
+                }
+                offset += 1;
+            }
+        }
+    }
+    assembly
+}
+
+fn relative(address: u16, offset: i8) -> Value {
+    Value::Number(address.wrapping_add(2).wrapping_add(offset as u16))
+}
+
+// Operand fields of the opcodes.
+
+fn reg(index: u8) -> Reg {
+    match index {
+        0 => Reg::B,
+        1 => Reg::C,
+        2 => Reg::D,
+        3 => Reg::E,
+        4 => Reg::H,
+        5 => Reg::L,
+        7 => Reg::A,
+        _ => unreachable!("Expected a register"),
+    }
+}
+
+fn target(index: u8) -> Target {
+    match index {
+        6 => Target::HlInd,
+        index => Target::Reg(reg(index)),
+    }
+}
+
+fn source(index: u8) -> Source {
+    match index {
+        6 => Source::HlInd,
+        index => Source::Reg(reg(index)),
+    }
+}
+
+fn reg16(index: u8) -> Reg16 {
+    [Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP][usize::from(index)]
+}
+
+fn stack_reg(index: u8) -> StackReg {
+    [StackReg::BC, StackReg::DE, StackReg::HL, StackReg::AF][usize::from(index)]
+}
+
+fn condition(index: u8) -> Condition {
+    [Condition::NZ, Condition::Z, Condition::NC, Condition::C][usize::from(index)]
+}
+
+fn alu(index: u8) -> Alu {
+    [
+        Alu::Add,
+        Alu::Adc,
+        Alu::Sub,
+        Alu::Sbc,
+        Alu::And,
+        Alu::Xor,
+        Alu::Or,
+        Alu::Cp,
+    ][usize::from(index)]
+}
+
+fn shift(index: u8) -> Shift {
+    [
+        Shift::Rlc,
+        Shift::Rrc,
+        Shift::Rl,
+        Shift::Rr,
+        Shift::Sla,
+        Shift::Sra,
+        Shift::Swap,
+        Shift::Srl,
+    ][usize::from(index)]
+}
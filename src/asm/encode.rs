@@ -0,0 +1,324 @@
+//! Assembler of SM83 machine code.
+//!
+//! Every instruction has a fixed size, so labels are resolved in two passes: the first one
+//! assigns addresses to every line, and the second one encodes instructions with the values of
+//! the labels they refer to.
+//!
+//! ```
+//! use gb_lang::asm::{encode::assemble, parse::parse};
+//!
+//! let assembly = parse("loop:\n    dec a\n    jr nz, loop\n    halt\n").unwrap();
+//! let object = assemble(&assembly, 0x150).unwrap();
+//! assert_eq!(vec![0x3d, 0x20, 0xfd, 0x76], object.bytes);
+//! assert_eq!(Some(&0x150), object.labels.get("loop"));
+//! ```
+use crate::asm::{
+    Alu, Assembly, Condition, Instruction, Line, Reg, Reg16, Shift, Source, StackReg, Target, Value,
+};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// Prefix of the rotation, shift and bit instructions.
+pub const PREFIX: u8 = 0xcb;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("undefined label `{0}`")]
+    UndefinedLabel(String),
+
+    #[error("label `{0}` is defined more than once")]
+    DuplicateLabel(String),
+
+    #[error("`{0}` isn't a valid instruction")]
+    InvalidInstruction(Instruction),
+
+    #[error("`{instruction}` jumps {distance} bytes away (relative jumps range from -128 to 127)")]
+    JumpOutOfRange {
+        instruction: Instruction,
+        distance: i32,
+    },
+
+    #[error("program doesn't fit in the address space")]
+    Overflow,
+}
+
+/// Assembled machine code.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Object {
+    /// Address of the first byte.
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+
+    /// Address of every label.
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// Assemble a program, to be loaded at the `origin` address.
+pub fn assemble(assembly: &Assembly, origin: u16) -> Result<Object, Error> {
+    let mut labels = BTreeMap::new();
+    let mut address = usize::from(origin);
+    for line in &assembly.lines {
+        if address > 0xffff + 1 {
+            return Err(Error::Overflow);
+        }
+        match line {
+            Line::Label(label) => {
+                if labels.insert(label.clone(), address as u16).is_some() {
+                    return Err(Error::DuplicateLabel(label.clone()));
+                }
+            }
+            Line::Instruction(instruction) => address += usize::from(instruction.size()),
+            Line::Data(bytes) => address += bytes.len(),
+        }
+    }
+    if address > 0xffff + 1 {
+        return Err(Error::Overflow);
+    }
+
+    let mut bytes = Vec::with_capacity(address - usize::from(origin));
+    for line in &assembly.lines {
+        match line {
+            Line::Label(_) => {}
+            Line::Instruction(instruction) => {
+                let address = origin.wrapping_add(bytes.len() as u16);
+                let resolve = |label: &str| labels.get(label).copied();
+                bytes.extend(instruction.encode(address, resolve)?);
+            }
+            Line::Data(data) => bytes.extend(data),
+        }
+    }
+    Ok(Object {
+        origin,
+        bytes,
+        labels,
+    })
+}
+
+impl Instruction {
+    /// Size of the encoded instruction, in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::Ld(_, Source::Imm(_))
+            | Instruction::LdHlInd(Source::Imm(_))
+            | Instruction::Alu(_, Source::Imm(_))
+            | Instruction::LdhAFromAddress(_)
+            | Instruction::LdhAddressA(_)
+            | Instruction::LdHlSp(_)
+            | Instruction::AddSp(_)
+            | Instruction::Stop
+            | Instruction::Shift(..)
+            | Instruction::Bit(..)
+            | Instruction::Res(..)
+            | Instruction::Set(..)
+            | Instruction::Jr(..) => 2,
+            Instruction::LdAFromAddress(_)
+            | Instruction::LdAddressA(_)
+            | Instruction::Ld16(..)
+            | Instruction::LdAddressSp(_)
+            | Instruction::Jp(..)
+            | Instruction::Call(..) => 3,
+            _ => 1,
+        }
+    }
+
+    /// Encode an instruction located at `address`, using `resolve` for the values of labels.
+    pub fn encode(
+        &self,
+        address: u16,
+        resolve: impl Fn(&str) -> Option<u16>,
+    ) -> Result<Vec<u8>, Error> {
+        let invalid = || Error::InvalidInstruction(self.clone());
+        let value = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(label) => {
+                resolve(label).ok_or_else(|| Error::UndefinedLabel(label.clone()))
+            }
+        };
+        let word = |opcode: u8, n: u16| {
+            let [low, high] = n.to_le_bytes();
+            vec![opcode, low, high]
+        };
+        let high = |value: u16| {
+            if value >= 0xff00 {
+                Ok(value as u8)
+            } else {
+                Err(invalid())
+            }
+        };
+        let bit = |bit: u8| {
+            if bit < 8 {
+                Ok(bit << 3)
+            } else {
+                Err(invalid())
+            }
+        };
+        let bytes = match self {
+            Instruction::Nop => vec![0x00],
+            Instruction::Stop => vec![0x10, 0x00],
+            Instruction::Halt => vec![0x76],
+            Instruction::Di => vec![0xf3],
+            Instruction::Ei => vec![0xfb],
+            Instruction::Ld(reg, source) => match source {
+                Source::Imm(n) => vec![0x06 | reg_index(*reg) << 3, *n],
+                source => vec![0x40 | reg_index(*reg) << 3 | source_index(*source)],
+            },
+            Instruction::LdHlInd(Source::HlInd) => return Err(invalid()),
+            Instruction::LdHlInd(Source::Imm(n)) => vec![0x36, *n],
+            Instruction::LdHlInd(source) => vec![0x70 | source_index(*source)],
+            Instruction::LdAFrom(Reg16::BC) => vec![0x0a],
+            Instruction::LdAFrom(Reg16::DE) => vec![0x1a],
+            Instruction::LdToA(Reg16::BC) => vec![0x02],
+            Instruction::LdToA(Reg16::DE) => vec![0x12],
+            Instruction::LdAFrom(_) | Instruction::LdToA(_) => return Err(invalid()),
+            Instruction::LdAHlInc => vec![0x2a],
+            Instruction::LdHlIncA => vec![0x22],
+            Instruction::LdAHlDec => vec![0x3a],
+            Instruction::LdHlDecA => vec![0x32],
+            Instruction::LdAFromAddress(address) => word(0xfa, value(address)?),
+            Instruction::LdAddressA(address) => word(0xea, value(address)?),
+            Instruction::LdhAFromAddress(address) => vec![0xf0, high(value(address)?)?],
+            Instruction::LdhAddressA(address) => vec![0xe0, high(value(address)?)?],
+            Instruction::LdhAFromC => vec![0xf2],
+            Instruction::LdhCA => vec![0xe2],
+            Instruction::Ld16(reg, n) => word(0x01 | reg16_index(*reg) << 4, value(n)?),
+            Instruction::LdAddressSp(address) => word(0x08, value(address)?),
+            Instruction::LdSpHl => vec![0xf9],
+            Instruction::LdHlSp(e) => vec![0xf8, *e as u8],
+            Instruction::Inc(target) => vec![0x04 | target_index(*target) << 3],
+            Instruction::Dec(target) => vec![0x05 | target_index(*target) << 3],
+            Instruction::Inc16(reg) => vec![0x03 | reg16_index(*reg) << 4],
+            Instruction::Dec16(reg) => vec![0x0b | reg16_index(*reg) << 4],
+            Instruction::Alu(op, Source::Imm(n)) => vec![0xc6 | alu_index(*op) << 3, *n],
+            Instruction::Alu(op, source) => {
+                vec![0x80 | alu_index(*op) << 3 | source_index(*source)]
+            }
+            Instruction::AddHl(reg) => vec![0x09 | reg16_index(*reg) << 4],
+            Instruction::AddSp(e) => vec![0xe8, *e as u8],
+            Instruction::Daa => vec![0x27],
+            Instruction::Cpl => vec![0x2f],
+            Instruction::Scf => vec![0x37],
+            Instruction::Ccf => vec![0x3f],
+            Instruction::Rlca => vec![0x07],
+            Instruction::Rrca => vec![0x0f],
+            Instruction::Rla => vec![0x17],
+            Instruction::Rra => vec![0x1f],
+            Instruction::Shift(op, target) => {
+                vec![PREFIX, shift_index(*op) << 3 | target_index(*target)]
+            }
+            Instruction::Bit(n, target) => vec![PREFIX, 0x40 | bit(*n)? | target_index(*target)],
+            Instruction::Res(n, target) => vec![PREFIX, 0x80 | bit(*n)? | target_index(*target)],
+            Instruction::Set(n, target) => vec![PREFIX, 0xc0 | bit(*n)? | target_index(*target)],
+            Instruction::Push(reg) => vec![0xc5 | stack_index(*reg) << 4],
+            Instruction::Pop(reg) => vec![0xc1 | stack_index(*reg) << 4],
+            Instruction::Jp(None, target) => word(0xc3, value(target)?),
+            Instruction::Jp(Some(cc), target) => {
+                word(0xc2 | condition_index(*cc) << 3, value(target)?)
+            }
+            Instruction::JpHl => vec![0xe9],
+            Instruction::Jr(cc, target) => {
+                let opcode = match cc {
+                    None => 0x18,
+                    Some(cc) => 0x20 | condition_index(*cc) << 3,
+                };
+                let distance = i32::from(value(target)?) - (i32::from(address) + 2);
+                let offset = i8::try_from(distance).map_err(|_| Error::JumpOutOfRange {
+                    instruction: self.clone(),
+                    distance,
+                })?;
+                vec![opcode, offset as u8]
+            }
+            Instruction::Call(None, target) => word(0xcd, value(target)?),
+            Instruction::Call(Some(cc), target) => {
+                word(0xc4 | condition_index(*cc) << 3, value(target)?)
+            }
+            Instruction::Ret(None) => vec![0xc9],
+            Instruction::Ret(Some(cc)) => vec![0xc0 | condition_index(*cc) << 3],
+            Instruction::Reti => vec![0xd9],
+            Instruction::Rst(vector) if vector & !0x38 == 0 => vec![0xc7 | vector],
+            Instruction::Rst(_) => return Err(invalid()),
+        };
+        debug_assert_eq!(usize::from(self.size()), bytes.len());
+        Ok(bytes)
+    }
+}
+
+// Operand fields of the opcodes.
+
+fn reg_index(reg: Reg) -> u8 {
+    match reg {
+        Reg::B => 0,
+        Reg::C => 1,
+        Reg::D => 2,
+        Reg::E => 3,
+        Reg::H => 4,
+        Reg::L => 5,
+        Reg::A => 7,
+    }
+}
+
+fn target_index(target: Target) -> u8 {
+    match target {
+        Target::Reg(reg) => reg_index(reg),
+        Target::HlInd => 6,
+    }
+}
+
+fn source_index(source: Source) -> u8 {
+    match source {
+        Source::Reg(reg) => reg_index(reg),
+        Source::HlInd => 6,
+        Source::Imm(_) => unreachable!("Expected a register"),
+    }
+}
+
+fn reg16_index(reg: Reg16) -> u8 {
+    match reg {
+        Reg16::BC => 0,
+        Reg16::DE => 1,
+        Reg16::HL => 2,
+        Reg16::SP => 3,
+    }
+}
+
+fn stack_index(reg: StackReg) -> u8 {
+    match reg {
+        StackReg::BC => 0,
+        StackReg::DE => 1,
+        StackReg::HL => 2,
+        StackReg::AF => 3,
+    }
+}
+
+fn condition_index(condition: Condition) -> u8 {
+    match condition {
+        Condition::NZ => 0,
+        Condition::Z => 1,
+        Condition::NC => 2,
+        Condition::C => 3,
+    }
+}
+
+fn alu_index(op: Alu) -> u8 {
+    match op {
+        Alu::Add => 0,
+        Alu::Adc => 1,
+        Alu::Sub => 2,
+        Alu::Sbc => 3,
+        Alu::And => 4,
+        Alu::Xor => 5,
+        Alu::Or => 6,
+        Alu::Cp => 7,
+    }
+}
+
+fn shift_index(op: Shift) -> u8 {
+    match op {
+        Shift::Rlc => 0,
+        Shift::Rrc => 1,
+        Shift::Rl => 2,
+        Shift::Rr => 3,
+        Shift::Sla => 4,
+        Shift::Sra => 5,
+        Shift::Swap => 6,
+        Shift::Srl => 7,
+    }
+}
@@ -0,0 +1,342 @@
+//! Inline assembly (`asm { ... }` statements).
+//!
+//! The body of an `asm` block is [parsed](crate::asm::parse) like any assembly, except that
+//! operands can name the declarations of the program: `static`s, `let`s and parameters stand for
+//! their address (`NAME.field` for the address of a field), `const`s for their value, and `fn`s
+//! for their label. Names are [replaced](expand) with their values before parsing, and labels
+//! defined within a block are renamed so that every block has its own.
+//!
+//! ```
+//! use gb_lang::asm::inline::{expand, references};
+//!
+//! let input = "
+//! wait:
+//!     ldh a, [IO.LY]
+//!     cp LINES
+//!     jr nz, wait";
+//! let names: Vec<_> = references(input).iter().map(|r| r.name).collect();
+//! assert_eq!(vec!["IO.LY", "LINES"], names);
+//! let replacements = ["$FF44".to_string(), "144".to_string()];
+//! assert_eq!(
+//!     "__asm0.wait:\n    ldh a, [$FF44]\n    cp a, $90\n    jr nz, __asm0.wait\n",
+//!     expand(input, &replacements, "__asm0").unwrap().dump()
+//! );
+//! ```
+use crate::{
+    asm::{encode, parse, Assembly, Line, Value},
+    ast::{
+        statements::{Asm, Statement},
+        visit::{self, Visit, Walk},
+        Context, Program,
+    },
+    consts::Consts,
+    diagnostics::Diagnostic,
+    layout::Layouts,
+    resolve::{DeclarationKind, Resolution},
+};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("unknown instruction `{mnemonic}`")]
+    UnknownInstruction { line: usize, mnemonic: String },
+
+    #[error("invalid operands `{text}`")]
+    InvalidOperands { line: usize, text: String },
+
+    #[error("{error}")]
+    Invalid { line: usize, error: encode::Error },
+}
+
+impl Error {
+    /// Line of the block the error is on, starting from `1`.
+    pub fn line(&self) -> usize {
+        match self {
+            Error::UnknownInstruction { line, .. }
+            | Error::InvalidOperands { line, .. }
+            | Error::Invalid { line, .. } => *line,
+        }
+    }
+}
+
+/// Registers and conditions, which are never names of the program.
+const RESERVED: &[&str] = &[
+    "a", "b", "c", "d", "e", "h", "l", "af", "bc", "de", "hl", "sp", "hli", "hld", "z", "nz", "nc",
+];
+
+/// Name of the program used by an operand.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Reference<'a> {
+    /// Byte offset of the name in the block.
+    pub offset: usize,
+
+    /// Whole name, including the fields (`IO.LCDC`).
+    pub name: &'a str,
+}
+
+impl<'a> Reference<'a> {
+    /// Name of the declaration, without the fields.
+    pub fn base(&self) -> &'a str {
+        self.name.split('.').next().unwrap_or_default()
+    }
+
+    /// Path of fields that follows the declaration name.
+    pub fn fields(&self) -> Vec<&'a str> {
+        self.name.split('.').skip(1).collect()
+    }
+}
+
+/// Names used by the operands of a block, in order. Registers, conditions and labels defined
+/// within the block aren't references.
+pub fn references(input: &str) -> Vec<Reference<'_>> {
+    let labels = labels(input);
+    let mut references = Vec::new();
+    for (start, line) in lines(input) {
+        let (operands, offset) = operands(line);
+        let mut chars = operands.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let is_name = c.is_ascii_alphabetic() || c == '_' || c == '.';
+            if !is_name && !c.is_ascii_digit() && c != '$' && c != '%' {
+                continue;
+            }
+            let mut end = i + c.len_utf8();
+            while let Some((j, c)) = chars.peek().copied() {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                    end = j + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let name = &operands[i..end];
+            // numbers (`$FF`, `%10`, `42`) are skipped whole
+            if is_name
+                && !RESERVED
+                    .iter()
+                    .any(|reserved| name.eq_ignore_ascii_case(reserved))
+                && !labels.contains(name)
+            {
+                references.push(Reference {
+                    offset: start + offset + i,
+                    name,
+                });
+            }
+        }
+    }
+    references
+}
+
+/// Assemble a block, replacing every one of its [references](references) with the text of the
+/// same index in `replacements`. Labels defined within the block are prefixed with `prefix`.
+///
+/// Every instruction is checked to be encodable, and relative jumps within the block to be in
+/// range.
+pub fn expand(input: &str, replacements: &[String], prefix: &str) -> Result<Assembly, Error> {
+    let mut text = String::with_capacity(input.len());
+    let mut last = 0;
+    for (reference, replacement) in references(input).iter().zip(replacements) {
+        text.push_str(&input[last..reference.offset]);
+        text.push_str(replacement);
+        last = reference.offset + reference.name.len();
+    }
+    text.push_str(&input[last..]);
+
+    let labels = labels(input);
+    let rename = |label: &str| format!("{}.{}", prefix, label.trim_start_matches('.'));
+    let mut assembly = Assembly::default();
+    // source line of every line of the assembly
+    let mut numbers = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let parsed = parse::parse(line).map_err(|error| match error {
+            parse::Error::UnknownInstruction { mnemonic, .. } => Error::UnknownInstruction {
+                line: i + 1,
+                mnemonic,
+            },
+            parse::Error::InvalidOperands { text, .. } => {
+                Error::InvalidOperands { line: i + 1, text }
+            }
+        })?;
+        for mut line in parsed.lines {
+            match &mut line {
+                Line::Label(label) => *label = rename(label),
+                Line::Instruction(instruction) => {
+                    if let Some(Value::Label(label)) = instruction.value_mut() {
+                        if labels.contains(label.as_str()) {
+                            *label = rename(label);
+                        }
+                    }
+                }
+                Line::Data(_) => {}
+            }
+            numbers.push(i + 1);
+            assembly.lines.push(line);
+        }
+    }
+
+    // addresses relative to the start of the block, labels of the program resolving to the
+    // instruction itself
+    let mut addresses = HashMap::new();
+    let mut address = 0u16;
+    for (line, number) in assembly.lines.iter().zip(&numbers) {
+        match line {
+            Line::Label(label) => {
+                if addresses.insert(label.as_str(), address).is_some() {
+                    let error = encode::Error::DuplicateLabel(label.clone());
+                    return Err(Error::Invalid {
+                        line: *number,
+                        error,
+                    });
+                }
+            }
+            Line::Instruction(instruction) => address = address.wrapping_add(instruction.size()),
+            Line::Data(bytes) => address = address.wrapping_add(bytes.len() as u16),
+        }
+    }
+    let mut address = 0u16;
+    for (line, number) in assembly.lines.iter().zip(&numbers) {
+        match line {
+            Line::Label(_) => {}
+            Line::Instruction(instruction) => {
+                let resolve = |label: &str| Some(*addresses.get(label).unwrap_or(&address));
+                instruction
+                    .encode(address, resolve)
+                    .map_err(|error| Error::Invalid {
+                        line: *number,
+                        error,
+                    })?;
+                address = address.wrapping_add(instruction.size());
+            }
+            Line::Data(bytes) => address = address.wrapping_add(bytes.len() as u16),
+        }
+    }
+    Ok(assembly)
+}
+
+// Lines of a block, with the byte offset they start at.
+fn lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.split('\n').scan(0, |start, line| {
+        let offset = *start;
+        *start += line.len() + 1;
+        Some((offset, line))
+    })
+}
+
+// Operands of a line, and their byte offset within the line.
+fn operands(line: &str) -> (&str, usize) {
+    let code = line.split(';').next().unwrap_or_default();
+    let mut offset = 0;
+    if let Some((label, _)) = code.split_once(':') {
+        if parse::is_label(label.trim()) {
+            offset = label.len() + 1;
+            if code[offset..].starts_with(':') {
+                offset += 1;
+            }
+        }
+    }
+    let rest = &code[offset..];
+    let start = rest.len() - rest.trim_start().len();
+    // the mnemonic
+    let rest = rest.trim_start();
+    let mnemonic = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    (&rest[mnemonic..], offset + start + mnemonic)
+}
+
+// Labels defined within a block.
+fn labels(input: &str) -> HashSet<&str> {
+    input
+        .lines()
+        .filter_map(|line| {
+            let (label, _) = line.split(';').next()?.split_once(':')?;
+            Some(label.trim()).filter(|label| parse::is_label(label))
+        })
+        .collect()
+}
+
+/// Check the `asm` blocks of a program that has been resolved and laid out.
+///
+/// Errors are reported to the diagnostics of the context.
+pub fn check(
+    program: &Program<'_>,
+    resolution: &Resolution,
+    consts: &Consts,
+    layouts: &Layouts,
+    context: &mut Context,
+) {
+    let mut checker = Checker {
+        resolution,
+        consts,
+        layouts,
+        context,
+    };
+    program.accept(&mut checker);
+}
+
+/// Assemble an `asm` statement of a checked program, with the values of the names it uses.
+///
+/// Fails with a diagnostic, or with `None` if the block refers to declarations that have errors
+/// (which are reported by the pass that finds them).
+pub fn assemble(
+    asm: &Asm<'_>,
+    resolution: &Resolution,
+    consts: &Consts,
+    layouts: &Layouts,
+    prefix: &str,
+) -> Result<Assembly, Option<Diagnostic>> {
+    let input = asm.block.contents();
+    let mut replacements = Vec::new();
+    for (i, reference) in references(input).iter().enumerate() {
+        let span = asm.block.span_of(reference.offset, reference.name.len());
+        let id = resolution.asm_binding(asm.id, i).ok_or(None)?;
+        let kind = resolution.declaration(id).ok_or(None)?.kind;
+        let fields = reference.fields();
+        let replacement = match kind {
+            DeclarationKind::Let | DeclarationKind::Static | DeclarationKind::Param => {
+                let item = layouts.item(id).ok_or(None)?;
+                let offset = item.layout.offset_of(&fields).ok_or_else(|| {
+                    let message = format!("no field `{}` in `{}`", fields.join("."), item.name);
+                    Diagnostic::error(span, message)
+                })?;
+                format!("${:04X}", item.address + offset)
+            }
+            DeclarationKind::Const | DeclarationKind::Fn if !fields.is_empty() => {
+                let message = format!("`{}` has no fields", reference.base());
+                return Err(Some(Diagnostic::error(span, message)));
+            }
+            DeclarationKind::Const => consts.value(id).ok_or(None)?.to_string(),
+            DeclarationKind::Fn => resolution.label(id).unwrap_or(reference.name).to_string(),
+            DeclarationKind::Type => {
+                let message = format!("expected a value, found type `{}`", reference.name);
+                return Err(Some(Diagnostic::error(span, message)));
+            }
+        };
+        replacements.push(replacement);
+    }
+    expand(input, &replacements, prefix).map_err(|error| {
+        let (offset, line) = lines(input)
+            .nth(error.line() - 1)
+            .expect("Expected a line of the block");
+        let line = line.trim_end();
+        let indent = line.len() - line.trim_start().len();
+        let span = asm.block.span_of(offset + indent, line.len() - indent);
+        Some(Diagnostic::error(span, error.to_string()))
+    })
+}
+
+struct Checker<'a> {
+    resolution: &'a Resolution,
+    consts: &'a Consts,
+    layouts: &'a Layouts,
+    context: &'a mut Context,
+}
+
+impl<'input> Visit<'input> for Checker<'_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        if let Statement::Asm(asm) = statement {
+            let assembled = assemble(asm, self.resolution, self.consts, self.layouts, "__asm");
+            if let Err(Some(diagnostic)) = assembled {
+                self.context.diagnostics.emit(diagnostic);
+            }
+        }
+        visit::walk_statement(self, statement);
+    }
+}
@@ -0,0 +1,560 @@
+//! Interpreter of SM83 assembly.
+//!
+//! Runs an [`Assembly`] instruction by instruction, over a flat 64 KiB address space, until it
+//! executes a `halt`. Code isn't stored in memory: the program counter is the index of an
+//! instruction, and code labels (and return addresses on the stack) are instruction indices.
+//! Data lines are copied to memory starting at [`DATA_START`]. Instructions execute the same
+//! way on the [CPU emulator](crate::cpu), which runs machine code over any [`Bus`].
+//!
+//! ```
+//! use gb_lang::asm::{interp::Interpreter, Assembly, Instruction, Reg, Source, Value};
+//!
+//! let mut assembly = Assembly::default();
+//! assembly.push(Instruction::Ld(Reg::A, Source::Imm(42)));
+//! assembly.push(Instruction::LdAddressA(Value::Number(0xc000)));
+//! assembly.push(Instruction::Halt);
+//! let mut interpreter = Interpreter::new(&assembly).unwrap();
+//! interpreter.run(100).unwrap();
+//! assert_eq!(42, interpreter.memory[0xc000]);
+//! ```
+use crate::asm::{
+    Alu, Assembly, Condition, Instruction, Line, Reg, Reg16, Shift, Source, StackReg, Target, Value,
+};
+use std::collections::HashMap;
+
+/// Address of the first byte of data.
+pub const DATA_START: u16 = 0x4000;
+
+const Z: u8 = 0x80;
+const N: u8 = 0x40;
+const H: u8 = 0x20;
+const C: u8 = 0x10;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("undefined label `{0}`")]
+    UndefinedLabel(String),
+
+    #[error("label `{0}` is defined more than once")]
+    DuplicateLabel(String),
+
+    #[error("jumped outside of the program")]
+    OutOfBounds,
+
+    #[error("didn't halt after {0} instructions")]
+    Timeout(u64),
+}
+
+/// Register file.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+}
+
+impl Registers {
+    pub fn get(&self, reg: Reg) -> u8 {
+        match reg {
+            Reg::A => self.a,
+            Reg::B => self.b,
+            Reg::C => self.c,
+            Reg::D => self.d,
+            Reg::E => self.e,
+            Reg::H => self.h,
+            Reg::L => self.l,
+        }
+    }
+
+    pub fn set(&mut self, reg: Reg, value: u8) {
+        match reg {
+            Reg::A => self.a = value,
+            Reg::B => self.b = value,
+            Reg::C => self.c = value,
+            Reg::D => self.d = value,
+            Reg::E => self.e = value,
+            Reg::H => self.h = value,
+            Reg::L => self.l = value,
+        }
+    }
+
+    pub fn get16(&self, reg: Reg16) -> u16 {
+        match reg.halves() {
+            Some((high, low)) => u16::from_be_bytes([self.get(high), self.get(low)]),
+            None => self.sp,
+        }
+    }
+
+    pub fn set16(&mut self, reg: Reg16, value: u16) {
+        let [high_value, low_value] = value.to_be_bytes();
+        match reg.halves() {
+            Some((high, low)) => {
+                self.set(high, high_value);
+                self.set(low, low_value);
+            }
+            None => self.sp = value,
+        }
+    }
+
+    pub fn hl(&self) -> u16 {
+        self.get16(Reg16::HL)
+    }
+}
+
+/// Interpreter of an [`Assembly`].
+pub struct Interpreter<'a> {
+    pub registers: Registers,
+    pub memory: Box<[u8; 0x10000]>,
+    code: Vec<&'a Instruction>,
+    labels: HashMap<&'a str, u16>,
+    // index of the next instruction
+    pc: u16,
+    halted: bool,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Load a program, ready to run from its first instruction.
+    pub fn new(assembly: &'a Assembly) -> Result<Self, Error> {
+        let mut interpreter = Self {
+            registers: Registers {
+                sp: 0xfffe,
+                ..Registers::default()
+            },
+            memory: Box::new([0; 0x10000]),
+            code: Vec::new(),
+            labels: HashMap::new(),
+            pc: 0,
+            halted: false,
+        };
+        let mut pending = Vec::new();
+        let mut data = usize::from(DATA_START);
+        for line in &assembly.lines {
+            let value = match line {
+                Line::Label(label) => {
+                    pending.push(label.as_str());
+                    continue;
+                }
+                Line::Instruction(instruction) => {
+                    interpreter.code.push(instruction);
+                    interpreter.code.len() - 1
+                }
+                Line::Data(bytes) => {
+                    interpreter.memory[data..data + bytes.len()].copy_from_slice(bytes);
+                    data += bytes.len();
+                    data - bytes.len()
+                }
+            };
+            for label in pending.drain(..) {
+                if interpreter.labels.insert(label, value as u16).is_some() {
+                    return Err(Error::DuplicateLabel(label.to_string()));
+                }
+            }
+        }
+        for label in pending {
+            let end = interpreter.code.len() as u16;
+            if interpreter.labels.insert(label, end).is_some() {
+                return Err(Error::DuplicateLabel(label.to_string()));
+            }
+        }
+        Ok(interpreter)
+    }
+
+    /// Value of a label: the index of an instruction, or the address of some data.
+    pub fn label(&self, label: &str) -> Option<u16> {
+        self.labels.get(label).copied()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Run until the program halts, executing at most `limit` instructions. Returns the number
+    /// of instructions executed.
+    pub fn run(&mut self, limit: u64) -> Result<u64, Error> {
+        let mut count = 0;
+        while !self.halted {
+            if count == limit {
+                return Err(Error::Timeout(limit));
+            }
+            self.step()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Execute one instruction.
+    pub fn step(&mut self) -> Result<(), Error> {
+        let instruction = *self
+            .code
+            .get(usize::from(self.pc))
+            .ok_or(Error::OutOfBounds)?;
+        self.pc += 1;
+        let labels = &self.labels;
+        let mut core = Core {
+            registers: &mut self.registers,
+            bus: &mut *self.memory,
+            pc: &mut self.pc,
+            halted: &mut self.halted,
+        };
+        core.execute(instruction, |value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(label) => labels
+                .get(label.as_str())
+                .copied()
+                .ok_or_else(|| Error::UndefinedLabel(label.clone())),
+        })
+    }
+}
+
+/// Memory an SM83 core reads and writes.
+pub trait Bus {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+}
+
+impl Bus for [u8; 0x10000] {
+    fn read(&self, address: u16) -> u8 {
+        self[usize::from(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self[usize::from(address)] = value;
+    }
+}
+
+// Registers, memory and program counter that instructions execute on.
+pub(crate) struct Core<'a, B: Bus + ?Sized> {
+    pub registers: &'a mut Registers,
+    pub bus: &'a mut B,
+    // address (or index) of the next instruction
+    pub pc: &'a mut u16,
+    pub halted: &'a mut bool,
+}
+
+impl<B: Bus + ?Sized> Core<'_, B> {
+    // Execute an instruction, given the values of its labels. The program counter must already
+    // point to the next instruction.
+    pub fn execute(
+        &mut self,
+        instruction: &Instruction,
+        resolve: impl Fn(&Value) -> Result<u16, Error>,
+    ) -> Result<(), Error> {
+        let r = &mut *self.registers;
+        match instruction {
+            Instruction::Nop | Instruction::Di | Instruction::Ei => {}
+            Instruction::Halt | Instruction::Stop => *self.halted = true,
+            Instruction::Ld(reg, source) => {
+                let value = self.source(*source);
+                self.registers.set(*reg, value);
+            }
+            Instruction::LdHlInd(source) => {
+                let value = self.source(*source);
+                self.bus.write(self.registers.hl(), value);
+            }
+            Instruction::LdAFrom(reg) => r.a = self.bus.read(r.get16(*reg)),
+            Instruction::LdToA(reg) => self.bus.write(r.get16(*reg), r.a),
+            Instruction::LdAHlInc => {
+                r.a = self.bus.read(r.hl());
+                r.set16(Reg16::HL, r.hl().wrapping_add(1));
+            }
+            Instruction::LdHlIncA => {
+                self.bus.write(r.hl(), r.a);
+                r.set16(Reg16::HL, r.hl().wrapping_add(1));
+            }
+            Instruction::LdAHlDec => {
+                r.a = self.bus.read(r.hl());
+                r.set16(Reg16::HL, r.hl().wrapping_sub(1));
+            }
+            Instruction::LdHlDecA => {
+                self.bus.write(r.hl(), r.a);
+                r.set16(Reg16::HL, r.hl().wrapping_sub(1));
+            }
+            Instruction::LdhAFromC => r.a = self.bus.read(0xff00 | u16::from(r.c)),
+            Instruction::LdhCA => self.bus.write(0xff00 | u16::from(r.c), r.a),
+            Instruction::LdAFromAddress(address) | Instruction::LdhAFromAddress(address) => {
+                let address = resolve(address)?;
+                self.registers.a = self.bus.read(address);
+            }
+            Instruction::LdAddressA(address) | Instruction::LdhAddressA(address) => {
+                let address = resolve(address)?;
+                self.bus.write(address, self.registers.a);
+            }
+            Instruction::Ld16(reg, value) => {
+                let value = resolve(value)?;
+                self.registers.set16(*reg, value);
+            }
+            Instruction::LdAddressSp(address) => {
+                let address = resolve(address)?;
+                let [low, high] = self.registers.sp.to_le_bytes();
+                self.bus.write(address, low);
+                self.bus.write(address.wrapping_add(1), high);
+            }
+            Instruction::LdSpHl => r.sp = r.hl(),
+            Instruction::LdHlSp(e) => {
+                let value = self.sp_offset(*e);
+                self.registers.set16(Reg16::HL, value);
+            }
+            Instruction::Inc(target) => {
+                let value = self.target(*target).wrapping_add(1);
+                self.set_target(*target, value);
+                let r = &mut *self.registers;
+                r.f = (r.f & C) | zero(value) | flag(value & 0xf == 0, H);
+            }
+            Instruction::Dec(target) => {
+                let value = self.target(*target).wrapping_sub(1);
+                self.set_target(*target, value);
+                let r = &mut *self.registers;
+                r.f = (r.f & C) | zero(value) | N | flag(value & 0xf == 0xf, H);
+            }
+            Instruction::Inc16(reg) => r.set16(*reg, r.get16(*reg).wrapping_add(1)),
+            Instruction::Dec16(reg) => r.set16(*reg, r.get16(*reg).wrapping_sub(1)),
+            Instruction::Alu(op, source) => {
+                let value = self.source(*source);
+                self.alu(*op, value);
+            }
+            Instruction::AddHl(reg) => {
+                let (hl, value) = (r.hl(), r.get16(*reg));
+                let (result, carry) = hl.overflowing_add(value);
+                let half = (hl & 0xfff) + (value & 0xfff) > 0xfff;
+                r.set16(Reg16::HL, result);
+                r.f = (r.f & Z) | flag(half, H) | flag(carry, C);
+            }
+            Instruction::AddSp(e) => {
+                let sp = self.sp_offset(*e);
+                self.registers.sp = sp;
+            }
+            Instruction::Daa => {
+                let (mut a, mut carry) = (r.a, r.f & C != 0);
+                if r.f & N == 0 {
+                    if carry || a > 0x99 {
+                        a = a.wrapping_add(0x60);
+                        carry = true;
+                    }
+                    if r.f & H != 0 || a & 0xf > 9 {
+                        a = a.wrapping_add(0x06);
+                    }
+                } else {
+                    if carry {
+                        a = a.wrapping_sub(0x60);
+                    }
+                    if r.f & H != 0 {
+                        a = a.wrapping_sub(0x06);
+                    }
+                }
+                r.a = a;
+                r.f = zero(a) | (r.f & N) | flag(carry, C);
+            }
+            Instruction::Cpl => {
+                r.a = !r.a;
+                r.f |= N | H;
+            }
+            Instruction::Scf => r.f = (r.f & Z) | C,
+            Instruction::Ccf => r.f = (r.f & Z) | (!r.f & C),
+            Instruction::Rlca | Instruction::Rrca | Instruction::Rla | Instruction::Rra => {
+                let op = match instruction {
+                    Instruction::Rlca => Shift::Rlc,
+                    Instruction::Rrca => Shift::Rrc,
+                    Instruction::Rla => Shift::Rl,
+                    _ => Shift::Rr,
+                };
+                let (value, f) = shift(op, r.a, r.f);
+                r.a = value;
+                // unlike their CB-prefixed versions, these always reset the zero flag
+                r.f = f & C;
+            }
+            Instruction::Shift(op, target) => {
+                let (value, f) = shift(*op, self.target(*target), self.registers.f);
+                self.set_target(*target, value);
+                self.registers.f = f;
+            }
+            Instruction::Bit(bit, target) => {
+                let set = self.target(*target) & (1 << bit) != 0;
+                let r = &mut *self.registers;
+                r.f = (r.f & C) | flag(!set, Z) | H;
+            }
+            Instruction::Res(bit, target) => {
+                let value = self.target(*target) & !(1 << bit);
+                self.set_target(*target, value);
+            }
+            Instruction::Set(bit, target) => {
+                let value = self.target(*target) | 1 << bit;
+                self.set_target(*target, value);
+            }
+            Instruction::Push(reg) => {
+                let value = self.stack_reg(*reg);
+                self.push(value);
+            }
+            Instruction::Pop(reg) => {
+                let value = self.pop();
+                let r = &mut *self.registers;
+                match reg {
+                    StackReg::BC => r.set16(Reg16::BC, value),
+                    StackReg::DE => r.set16(Reg16::DE, value),
+                    StackReg::HL => r.set16(Reg16::HL, value),
+                    StackReg::AF => {
+                        let [a, f] = value.to_be_bytes();
+                        r.a = a;
+                        r.f = f & 0xf0;
+                    }
+                }
+            }
+            Instruction::Jp(condition, target) | Instruction::Jr(condition, target) => {
+                if self.condition(*condition) {
+                    *self.pc = resolve(target)?;
+                }
+            }
+            Instruction::Call(condition, target) => {
+                if self.condition(*condition) {
+                    let target = resolve(target)?;
+                    self.push(*self.pc);
+                    *self.pc = target;
+                }
+            }
+            Instruction::JpHl => *self.pc = r.hl(),
+            Instruction::Ret(condition) => {
+                if self.condition(*condition) {
+                    *self.pc = self.pop();
+                }
+            }
+            Instruction::Reti => *self.pc = self.pop(),
+            Instruction::Rst(vector) => {
+                self.push(*self.pc);
+                *self.pc = u16::from(*vector);
+            }
+        }
+        Ok(())
+    }
+
+    fn source(&self, source: Source) -> u8 {
+        match source {
+            Source::Reg(reg) => self.registers.get(reg),
+            Source::Imm(n) => n,
+            Source::HlInd => self.bus.read(self.registers.hl()),
+        }
+    }
+
+    fn target(&self, target: Target) -> u8 {
+        match target {
+            Target::Reg(reg) => self.registers.get(reg),
+            Target::HlInd => self.bus.read(self.registers.hl()),
+        }
+    }
+
+    fn set_target(&mut self, target: Target, value: u8) {
+        match target {
+            Target::Reg(reg) => self.registers.set(reg, value),
+            Target::HlInd => self.bus.write(self.registers.hl(), value),
+        }
+    }
+
+    // `sp + e`, setting the flags of `add sp, e` and `ld hl, sp + e`.
+    fn sp_offset(&mut self, e: i8) -> u16 {
+        let r = &mut *self.registers;
+        let (sp, value) = (r.sp, e as u8);
+        let half = (sp & 0xf) + u16::from(value & 0xf) > 0xf;
+        let carry = (sp & 0xff) + u16::from(value) > 0xff;
+        r.f = flag(half, H) | flag(carry, C);
+        sp.wrapping_add(e as u16)
+    }
+
+    fn stack_reg(&self, reg: StackReg) -> u16 {
+        let r = &self.registers;
+        match reg {
+            StackReg::BC => r.get16(Reg16::BC),
+            StackReg::DE => r.get16(Reg16::DE),
+            StackReg::HL => r.get16(Reg16::HL),
+            StackReg::AF => u16::from_be_bytes([r.a, r.f]),
+        }
+    }
+
+    fn condition(&self, condition: Option<Condition>) -> bool {
+        let f = self.registers.f;
+        match condition {
+            None => true,
+            Some(Condition::Z) => f & Z != 0,
+            Some(Condition::NZ) => f & Z == 0,
+            Some(Condition::C) => f & C != 0,
+            Some(Condition::NC) => f & C == 0,
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        let sp = self.registers.sp;
+        self.bus.write(sp.wrapping_sub(1), high);
+        self.bus.write(sp.wrapping_sub(2), low);
+        self.registers.sp = sp.wrapping_sub(2);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.registers.sp;
+        let low = self.bus.read(sp);
+        let high = self.bus.read(sp.wrapping_add(1));
+        self.registers.sp = sp.wrapping_add(2);
+        u16::from_be_bytes([high, low])
+    }
+
+    fn alu(&mut self, op: Alu, value: u8) {
+        let r = &mut *self.registers;
+        let a = r.a;
+        let carry = u8::from(r.f & C != 0);
+        let (result, f) = match op {
+            Alu::Add | Alu::Adc => {
+                let carry = if op == Alu::Adc { carry } else { 0 };
+                let sum = u16::from(a) + u16::from(value) + u16::from(carry);
+                let half = (a & 0xf) + (value & 0xf) + carry > 0xf;
+                (sum as u8, flag(half, H) | flag(sum > 0xff, C))
+            }
+            Alu::Sub | Alu::Sbc | Alu::Cp => {
+                let carry = if op == Alu::Sbc { carry } else { 0 };
+                let difference = i16::from(a) - i16::from(value) - i16::from(carry);
+                let half = i16::from(a & 0xf) - i16::from(value & 0xf) - i16::from(carry) < 0;
+                (
+                    difference as u8,
+                    N | flag(half, H) | flag(difference < 0, C),
+                )
+            }
+            Alu::And => (a & value, H),
+            Alu::Xor => (a ^ value, 0),
+            Alu::Or => (a | value, 0),
+        };
+        r.f = f | zero(result);
+        if op != Alu::Cp {
+            r.a = result;
+        }
+    }
+}
+
+// Rotate or shift a value, returning the result and the flags.
+fn shift(op: Shift, value: u8, f: u8) -> (u8, u8) {
+    let carry = u8::from(f & C != 0);
+    let (result, out) = match op {
+        Shift::Rlc => (value.rotate_left(1), value & 0x80),
+        Shift::Rrc => (value.rotate_right(1), value & 1),
+        Shift::Rl => (value << 1 | carry, value & 0x80),
+        Shift::Rr => (value >> 1 | carry << 7, value & 1),
+        Shift::Sla => (value << 1, value & 0x80),
+        Shift::Sra => (value >> 1 | value & 0x80, value & 1),
+        Shift::Swap => (value.rotate_left(4), 0),
+        Shift::Srl => (value >> 1, value & 1),
+    };
+    (result, zero(result) | flag(out != 0, C))
+}
+
+fn flag(set: bool, flag: u8) -> u8 {
+    if set {
+        flag
+    } else {
+        0
+    }
+}
+
+fn zero(value: u8) -> u8 {
+    flag(value == 0, Z)
+}
@@ -0,0 +1,338 @@
+//! Parser of SM83 assembly.
+//!
+//! Reads the syntax [`Assembly`] prints: one label (`name:`), instruction or `db` directive per
+//! line, in RGBDS syntax. Comments start with `;`. Numbers can be decimal, hexadecimal (`$FF`) or
+//! binary (`%1010`).
+//!
+//! ```
+//! use gb_lang::asm::parse::parse;
+//!
+//! let input = "main:
+//!     ld hl, $C000 ; start of WRAM
+//!     ld [hl], %1010
+//!     jr main
+//! ";
+//! assert_eq!(input.replace(" ; start of WRAM", "").replace("%1010", "$0A"), parse(input).unwrap().dump());
+//! ```
+use crate::asm::{
+    Alu, Assembly, Condition, Instruction, Line, Reg, Reg16, Shift, Source, StackReg, Target, Value,
+};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("{line}: unknown instruction `{mnemonic}`")]
+    UnknownInstruction { line: usize, mnemonic: String },
+
+    #[error("{line}: invalid operands `{text}`")]
+    InvalidOperands { line: usize, text: String },
+}
+
+const MNEMONICS: &[&str] = &[
+    "nop", "stop", "halt", "di", "ei", "ld", "ldh", "inc", "dec", "add", "adc", "sub", "sbc",
+    "and", "xor", "or", "cp", "daa", "cpl", "scf", "ccf", "rlca", "rrca", "rla", "rra", "rlc",
+    "rrc", "rl", "rr", "sla", "sra", "swap", "srl", "bit", "res", "set", "push", "pop", "jp", "jr",
+    "call", "ret", "reti", "rst", "db",
+];
+
+/// Parse a program. Lines are numbered from `1` in errors.
+pub fn parse(input: &str) -> Result<Assembly, Error> {
+    let mut assembly = Assembly::default();
+    for (i, line) in input.lines().enumerate() {
+        let mut text = line.split(';').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            if is_label(label) {
+                assembly.label(label);
+                // exported labels (`name::`)
+                text = rest.strip_prefix(':').unwrap_or(rest).trim();
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+            None => (text, ""),
+        };
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        if !MNEMONICS.contains(&mnemonic.as_str()) {
+            return Err(Error::UnknownInstruction {
+                line: i + 1,
+                mnemonic,
+            });
+        }
+        let operands: Vec<&str> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+        let line = if mnemonic == "db" {
+            let bytes: Option<Vec<u8>> = operands.iter().map(|n| byte(n)).collect();
+            bytes.filter(|bytes| !bytes.is_empty()).map(Line::Data)
+        } else {
+            instruction(&mnemonic, &operands).map(Line::Instruction)
+        };
+        match line {
+            Some(line) => assembly.lines.push(line),
+            None => {
+                return Err(Error::InvalidOperands {
+                    line: i + 1,
+                    text: text.to_string(),
+                })
+            }
+        }
+    }
+    Ok(assembly)
+}
+
+fn instruction(mnemonic: &str, operands: &[&str]) -> Option<Instruction> {
+    let alu = match mnemonic {
+        "add" => Some(Alu::Add),
+        "adc" => Some(Alu::Adc),
+        "sub" => Some(Alu::Sub),
+        "sbc" => Some(Alu::Sbc),
+        "and" => Some(Alu::And),
+        "xor" => Some(Alu::Xor),
+        "or" => Some(Alu::Or),
+        "cp" => Some(Alu::Cp),
+        _ => None,
+    };
+    let shift = match mnemonic {
+        "rlc" => Some(Shift::Rlc),
+        "rrc" => Some(Shift::Rrc),
+        "rl" => Some(Shift::Rl),
+        "rr" => Some(Shift::Rr),
+        "sla" => Some(Shift::Sla),
+        "sra" => Some(Shift::Sra),
+        "swap" => Some(Shift::Swap),
+        "srl" => Some(Shift::Srl),
+        _ => None,
+    };
+    let is = |operand: &str, name: &str| operand.eq_ignore_ascii_case(name);
+    let instruction = match (mnemonic, operands) {
+        ("nop", []) => Instruction::Nop,
+        ("stop", []) => Instruction::Stop,
+        ("halt", []) => Instruction::Halt,
+        ("di", []) => Instruction::Di,
+        ("ei", []) => Instruction::Ei,
+        ("ld", [dst, src]) => ld(dst, src)?,
+        ("ldh", [a, src]) if is(a, "a") => match indirect(src)? {
+            c if is(c, "c") => Instruction::LdhAFromC,
+            address => Instruction::LdhAFromAddress(high(address)?),
+        },
+        ("ldh", [dst, a]) if is(a, "a") => match indirect(dst)? {
+            c if is(c, "c") => Instruction::LdhCA,
+            address => Instruction::LdhAddressA(high(address)?),
+        },
+        ("inc", [operand]) => match reg16(operand) {
+            Some(reg) => Instruction::Inc16(reg),
+            None => Instruction::Inc(target(operand)?),
+        },
+        ("dec", [operand]) => match reg16(operand) {
+            Some(reg) => Instruction::Dec16(reg),
+            None => Instruction::Dec(target(operand)?),
+        },
+        ("add", [hl, reg]) if is(hl, "hl") => Instruction::AddHl(reg16(reg)?),
+        ("add", [sp, e]) if is(sp, "sp") => Instruction::AddSp(signed(e)?),
+        (_, [a, source]) if alu.is_some() && is(a, "a") => {
+            Instruction::Alu(alu?, self::source(source)?)
+        }
+        (_, [source]) if alu.is_some() => Instruction::Alu(alu?, self::source(source)?),
+        ("daa", []) => Instruction::Daa,
+        ("cpl", []) => Instruction::Cpl,
+        ("scf", []) => Instruction::Scf,
+        ("ccf", []) => Instruction::Ccf,
+        ("rlca", []) => Instruction::Rlca,
+        ("rrca", []) => Instruction::Rrca,
+        ("rla", []) => Instruction::Rla,
+        ("rra", []) => Instruction::Rra,
+        (_, [operand]) if shift.is_some() => Instruction::Shift(shift?, target(operand)?),
+        ("bit", [n, operand]) => Instruction::Bit(bit(n)?, target(operand)?),
+        ("res", [n, operand]) => Instruction::Res(bit(n)?, target(operand)?),
+        ("set", [n, operand]) => Instruction::Set(bit(n)?, target(operand)?),
+        ("push", [reg]) => Instruction::Push(stack_reg(reg)?),
+        ("pop", [reg]) => Instruction::Pop(stack_reg(reg)?),
+        ("jp", [hl]) if is(hl, "hl") => Instruction::JpHl,
+        ("jp", [target]) => Instruction::Jp(None, value(target)?),
+        ("jp", [cc, target]) => Instruction::Jp(Some(condition(cc)?), value(target)?),
+        ("jr", [target]) => Instruction::Jr(None, value(target)?),
+        ("jr", [cc, target]) => Instruction::Jr(Some(condition(cc)?), value(target)?),
+        ("call", [target]) => Instruction::Call(None, value(target)?),
+        ("call", [cc, target]) => Instruction::Call(Some(condition(cc)?), value(target)?),
+        ("ret", []) => Instruction::Ret(None),
+        ("ret", [cc]) => Instruction::Ret(Some(condition(cc)?)),
+        ("reti", []) => Instruction::Reti,
+        ("rst", [vector]) => match number(vector)? {
+            vector @ 0..=0x38 if vector % 8 == 0 => Instruction::Rst(vector as u8),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+fn ld(dst: &str, src: &str) -> Option<Instruction> {
+    let is = |operand: &str, name: &str| operand.eq_ignore_ascii_case(name);
+    let instruction = if let Some(reg) = reg(dst) {
+        match (reg, indirect(src)) {
+            (Reg::A, Some(bc)) if is(bc, "bc") => Instruction::LdAFrom(Reg16::BC),
+            (Reg::A, Some(de)) if is(de, "de") => Instruction::LdAFrom(Reg16::DE),
+            (Reg::A, Some(hl)) if is(hl, "hl+") || is(hl, "hli") => Instruction::LdAHlInc,
+            (Reg::A, Some(hl)) if is(hl, "hl-") || is(hl, "hld") => Instruction::LdAHlDec,
+            (_, Some(hl)) if is(hl, "hl") => Instruction::Ld(reg, Source::HlInd),
+            (Reg::A, Some(address)) => Instruction::LdAFromAddress(value(address)?),
+            (_, Some(_)) => return None,
+            (_, None) => Instruction::Ld(reg, source(src)?),
+        }
+    } else if let Some(address) = indirect(dst) {
+        match address.to_ascii_lowercase().as_str() {
+            "hl" => Instruction::LdHlInd(source(src).filter(|s| *s != Source::HlInd)?),
+            "bc" if is(src, "a") => Instruction::LdToA(Reg16::BC),
+            "de" if is(src, "a") => Instruction::LdToA(Reg16::DE),
+            "hl+" | "hli" if is(src, "a") => Instruction::LdHlIncA,
+            "hl-" | "hld" if is(src, "a") => Instruction::LdHlDecA,
+            _ if is(src, "a") => Instruction::LdAddressA(value(address)?),
+            _ if is(src, "sp") => Instruction::LdAddressSp(value(address)?),
+            _ => return None,
+        }
+    } else {
+        match reg16(dst)? {
+            Reg16::SP if is(src, "hl") => Instruction::LdSpHl,
+            Reg16::HL if src.get(..2).is_some_and(|sp| is(sp, "sp")) => {
+                let offset: String = src[2..].chars().filter(|c| !c.is_whitespace()).collect();
+                match offset.strip_prefix('+') {
+                    Some(offset) => Instruction::LdHlSp(signed(offset)?),
+                    None if offset.starts_with('-') => Instruction::LdHlSp(signed(&offset)?),
+                    None => Instruction::Ld16(Reg16::HL, value(src)?),
+                }
+            }
+            reg => Instruction::Ld16(reg, value(src)?),
+        }
+    };
+    Some(instruction)
+}
+
+pub(super) fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn number(text: &str) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text.trim_start()),
+        None => (false, text),
+    };
+    let n = if let Some(hex) = text.strip_prefix('$') {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = text.strip_prefix('%') {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn byte(text: &str) -> Option<u8> {
+    match number(text)? {
+        n @ -0x80..=0xff => Some(n as u8),
+        _ => None,
+    }
+}
+
+fn signed(text: &str) -> Option<i8> {
+    i8::try_from(number(text)?).ok()
+}
+
+fn bit(text: &str) -> Option<u8> {
+    byte(text).filter(|bit| *bit < 8)
+}
+
+fn value(text: &str) -> Option<Value> {
+    match number(text) {
+        Some(n @ -0x8000..=0xffff) => Some(Value::Number(n as u16)),
+        Some(_) => None,
+        None if is_label(text) => Some(Value::label(text)),
+        None => None,
+    }
+}
+
+// Address of `ldh`, which can be given as an offset from `$FF00`.
+fn high(text: &str) -> Option<Value> {
+    match value(text)? {
+        Value::Number(n) if n < 0x100 => Some(Value::Number(0xff00 | n)),
+        value => Some(value),
+    }
+}
+
+fn indirect(text: &str) -> Option<&str> {
+    let text = text.strip_prefix('[')?.strip_suffix(']')?;
+    Some(text.trim())
+}
+
+fn reg(text: &str) -> Option<Reg> {
+    let reg = match text.to_ascii_lowercase().as_str() {
+        "a" => Reg::A,
+        "b" => Reg::B,
+        "c" => Reg::C,
+        "d" => Reg::D,
+        "e" => Reg::E,
+        "h" => Reg::H,
+        "l" => Reg::L,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+fn reg16(text: &str) -> Option<Reg16> {
+    let reg = match text.to_ascii_lowercase().as_str() {
+        "bc" => Reg16::BC,
+        "de" => Reg16::DE,
+        "hl" => Reg16::HL,
+        "sp" => Reg16::SP,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+fn stack_reg(text: &str) -> Option<StackReg> {
+    let reg = match text.to_ascii_lowercase().as_str() {
+        "bc" => StackReg::BC,
+        "de" => StackReg::DE,
+        "hl" => StackReg::HL,
+        "af" => StackReg::AF,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+fn condition(text: &str) -> Option<Condition> {
+    let condition = match text.to_ascii_lowercase().as_str() {
+        "z" => Condition::Z,
+        "nz" => Condition::NZ,
+        "c" => Condition::C,
+        "nc" => Condition::NC,
+        _ => return None,
+    };
+    Some(condition)
+}
+
+fn target(text: &str) -> Option<Target> {
+    match indirect(text) {
+        Some(hl) if hl.eq_ignore_ascii_case("hl") => Some(Target::HlInd),
+        Some(_) => None,
+        None => reg(text).map(Target::Reg),
+    }
+}
+
+fn source(text: &str) -> Option<Source> {
+    match target(text) {
+        Some(Target::Reg(reg)) => Some(Source::Reg(reg)),
+        Some(Target::HlInd) => Some(Source::HlInd),
+        None => byte(text).map(Source::Imm),
+    }
+}
@@ -1,22 +1,42 @@
-use crate::lex::Tokenizer;
+use crate::{
+    ast::statements::Statement,
+    lex::{tokens, Tokenizer},
+    Spanned,
+};
+pub use context::{Context, Edition, Features, Interner, NodeId, Options, Symbol};
 pub use error::Error;
+pub use punctuated::Punctuated;
 use std::iter::Peekable;
+use visit::Walk;
 
+mod context;
 mod error;
 pub mod expressions;
+mod punctuated;
 pub mod statements;
 pub mod types;
+pub mod visit;
 
 pub fn parse<'input, G>(input: &'input str) -> Result<G, Error<'input>>
 where
     G: Grammar<'input>,
 {
-    let mut tokens = crate::lex::tokenize(input).peekable();
-    let mut context = Context::default();
-    G::parse(&mut tokens, &mut context)
+    parse_with_context(input, &mut Context::default())
 }
 
-pub trait Grammar<'input>: Sized {
+/// Parse the input using (and updating) an existing [`Context`].
+pub fn parse_with_context<'input, G>(
+    input: &'input str,
+    context: &mut Context,
+) -> Result<G, Error<'input>>
+where
+    G: Grammar<'input>,
+{
+    let mut tokens = crate::lex::tokenize_file(input, context.file).peekable();
+    G::parse(&mut tokens, context)
+}
+
+pub trait Grammar<'input>: Sized + Spanned + Walk<'input> {
     fn parse(
         tokens: &mut Peekable<Tokenizer<'input>>,
         context: &mut Context,
@@ -40,5 +60,10 @@ impl<'input> Grammar<'input> for () {
     }
 }
 
-#[derive(Default)]
-pub struct Context {}
+/// Whole input source.
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program<'input> {
+    pub statements: Vec<Statement<'input>>,
+    pub eof: tokens::EOF<'input>,
+}
@@ -0,0 +1,128 @@
+use crate::{
+    ast::{Error, Grammar},
+    diagnostics::Diagnostics,
+    lex::{tokens, Tokenizer},
+    loader::Files,
+    FileId, Span, Spanned,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    iter::Peekable,
+};
+
+/// State shared by the parser and the compiler passes that follow it.
+#[derive(Debug, Default)]
+pub struct Context {
+    pub options: Options,
+    pub interner: Interner,
+    pub diagnostics: Diagnostics,
+
+    /// Source files of the program, if it was [loaded](crate::loader) from several.
+    pub files: Files,
+
+    /// File of the input being parsed, which the spans of the parsed nodes refer to.
+    pub file: FileId,
+    next_id: u32,
+}
+
+impl Context {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Allocate a new [`NodeId`].
+    pub fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Interned name of an identifier token.
+    pub fn symbol(&mut self, identifier: &tokens::Identifier<'_>) -> Symbol {
+        self.interner.intern(identifier.as_str())
+    }
+}
+
+/// Parse options.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub edition: Edition,
+    pub features: Features,
+}
+
+/// Language edition.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Edition {
+    #[default]
+    Edition2021,
+}
+
+/// Set of enabled feature gates.
+#[derive(Debug, Default, Clone)]
+pub struct Features {
+    enabled: BTreeSet<String>,
+}
+
+impl Features {
+    pub fn enable(&mut self, feature: impl Into<String>) {
+        self.enabled.insert(feature.into());
+    }
+
+    pub fn is_enabled(&self, feature: &str) -> bool {
+        self.enabled.contains(feature)
+    }
+}
+
+/// Stable identifier of a syntax tree node, unique within a [`Context`].
+///
+/// Nodes get their ID when they are parsed, so fields of this type don't consume any input.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(u32);
+
+impl<'input> Grammar<'input> for NodeId {
+    fn parse(
+        _: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        Ok(context.next_id())
+    }
+}
+
+impl Spanned for NodeId {
+    fn span(&self) -> Span {
+        Span::default()
+    }
+}
+
+/// Interned string.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol(u32);
+
+/// String interner, mostly for identifiers.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<String, Symbol>,
+    strings: Vec<String>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(string.to_string());
+        self.symbols.insert(string.to_string(), symbol);
+        symbol
+    }
+
+    /// Interned string, or `None` if the symbol doesn't belong to this interner.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.strings.get(symbol.0 as usize).map(String::as_str)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{diagnostics::Diagnostic, lex::Token, Span, Spanned};
 use std::{
     fmt,
     fmt::{Display, Formatter},
@@ -9,14 +10,32 @@ pub enum Error<'input> {
     Lex(#[from] crate::lex::Error),
 
     /// Unexpected token error.
-    UnexpectedToken(crate::lex::Token<'input>),
+    UnexpectedToken(Token<'input>),
 
     /// Tokenizer ran out of tokens.
     TokenizerEmpty,
 }
 
 impl Display for Error<'_> {
-    fn fmt(&self, _: &mut Formatter<'_>) -> fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Lex(error) => error.fmt(f),
+            Error::UnexpectedToken(Token::EOF(_)) | Error::TokenizerEmpty => {
+                f.write_str("unexpected end of input")
+            }
+            Error::UnexpectedToken(token) => write!(f, "unexpected `{}`", token.as_str()),
+        }
+    }
+}
+
+impl Error<'_> {
+    /// Report the error as a [`Diagnostic`], at the unexpected token or input.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let span = match self {
+            Error::Lex(error) => error.span(),
+            Error::UnexpectedToken(token) => token.span(),
+            _ => Span::default(),
+        };
+        Diagnostic::error(span, self.to_string())
     }
 }
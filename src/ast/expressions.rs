@@ -1,5 +1,5 @@
 use crate::{
-    ast::{Context, Error, Grammar},
+    ast::{types::Type, Context, Error, Grammar, NodeId, Punctuated},
     lex::{tokens, tokens::Token, Tokenizer},
 };
 use std::iter::Peekable;
@@ -8,24 +8,256 @@ pub trait ExpressionGrammar<'input>: Grammar<'input> {}
 
 impl<'input, E: ExpressionGrammar<'input>> ExpressionGrammar<'input> for Box<E> {}
 
-#[derive(Debug)]
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression<'input> {
     Parenthesis(Parenthesis<'input, Box<Expression<'input>>>),
     Index(Index<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    FieldAccess(FieldAccess<'input, Box<Expression<'input>>>),
     Call(Call<'input, Box<Expression<'input>>>),
     Number(Number<'input>),
     Str(Str<'input>),
+    True(True<'input>),
+    False(False<'input>),
     Identifier(Identifier<'input>),
     Add(Add<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
     Subtract(Subtract<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
     Multiply(Multiply<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
     Divide(Divide<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    BitAnd(BitAnd<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    BitOr(BitOr<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    BitXor(BitXor<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    Equal(Equal<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    NotEqual(NotEqual<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    Less(Less<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    LessEqual(LessEqual<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    Greater(Greater<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    GreaterEqual(GreaterEqual<'input, Box<Expression<'input>>, Box<Expression<'input>>>),
+    BitNot(BitNot<'input, Box<Expression<'input>>>),
+    Negate(Negate<'input, Box<Expression<'input>>>),
+    Addr(Addr<'input, Box<Expression<'input>>>),
+    Deref(Deref<'input, Box<Expression<'input>>>),
+    Cast(Cast<'input, Box<Expression<'input>>>),
 }
 
-impl<'input> ExpressionGrammar<'input> for Expression<'input> {}
+impl<'input> Expression<'input> {
+    /// ID of the expression node.
+    pub fn id(&self) -> NodeId {
+        match self {
+            Expression::Parenthesis(node) => node.id,
+            Expression::Index(node) => node.id,
+            Expression::FieldAccess(node) => node.id,
+            Expression::Call(node) => node.id,
+            Expression::Number(node) => node.id,
+            Expression::Str(node) => node.id,
+            Expression::True(node) => node.id,
+            Expression::False(node) => node.id,
+            Expression::Identifier(node) => node.id,
+            Expression::Add(node) => node.id,
+            Expression::Subtract(node) => node.id,
+            Expression::Multiply(node) => node.id,
+            Expression::Divide(node) => node.id,
+            Expression::BitAnd(node) => node.id,
+            Expression::BitOr(node) => node.id,
+            Expression::BitXor(node) => node.id,
+            Expression::Equal(node) => node.id,
+            Expression::NotEqual(node) => node.id,
+            Expression::Less(node) => node.id,
+            Expression::LessEqual(node) => node.id,
+            Expression::Greater(node) => node.id,
+            Expression::GreaterEqual(node) => node.id,
+            Expression::BitNot(node) => node.id,
+            Expression::Negate(node) => node.id,
+            Expression::Addr(node) => node.id,
+            Expression::Deref(node) => node.id,
+            Expression::Cast(node) => node.id,
+        }
+    }
 
-impl<'input> Grammar<'input> for Expression<'input> {
-    fn parse(
+    // Parse an expression without comparisons outside of parentheses.
+    pub(crate) fn parse_length(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        Self::parse_binary(tokens, context, COMPARISON + 1)
+    }
+
+    // Binary operators are left associative, and parsed by precedence climbing.
+    fn parse_binary(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+        min_precedence: u8,
+    ) -> Result<Self, Error<'input>> {
+        let mut left = Self::parse_cast(tokens, context)?;
+        loop {
+            let precedence = match tokens.peek() {
+                Some(Ok(token)) => binary_precedence(token),
+                _ => None,
+            };
+            let precedence = match precedence {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => return Ok(left),
+            };
+            let id = context.next_id();
+            let operator = tokens.next().expect("Expected some token")?;
+            let left_ = Box::new(left);
+            let right = Box::new(Self::parse_binary(tokens, context, precedence + 1)?);
+            left = match operator {
+                Token::Plus(plus) => Expression::Add(Add {
+                    id,
+                    left: left_,
+                    plus,
+                    right,
+                }),
+                Token::Minus(minus) => Expression::Subtract(Subtract {
+                    id,
+                    left: left_,
+                    minus,
+                    right,
+                }),
+                Token::Star(star) => Expression::Multiply(Multiply {
+                    id,
+                    left: left_,
+                    star,
+                    right,
+                }),
+                Token::ForwardSlash(forward_slash) => Expression::Divide(Divide {
+                    id,
+                    left: left_,
+                    forward_slash,
+                    right,
+                }),
+                Token::And(and) => Expression::BitAnd(BitAnd {
+                    id,
+                    left: left_,
+                    and,
+                    right,
+                }),
+                Token::Or(or) => Expression::BitOr(BitOr {
+                    id,
+                    left: left_,
+                    or,
+                    right,
+                }),
+                Token::Xor(xor) => Expression::BitXor(BitXor {
+                    id,
+                    left: left_,
+                    xor,
+                    right,
+                }),
+                Token::EqualsEquals(equals_equals) => Expression::Equal(Equal {
+                    id,
+                    left: left_,
+                    equals_equals,
+                    right,
+                }),
+                Token::NotEquals(not_equals) => Expression::NotEqual(NotEqual {
+                    id,
+                    left: left_,
+                    not_equals,
+                    right,
+                }),
+                Token::LessThan(less_than) => Expression::Less(Less {
+                    id,
+                    left: left_,
+                    less_than,
+                    right,
+                }),
+                Token::LessEqualsThan(less_equals_than) => Expression::LessEqual(LessEqual {
+                    id,
+                    left: left_,
+                    less_equals_than,
+                    right,
+                }),
+                Token::GreaterThan(greater_than) => Expression::Greater(Greater {
+                    id,
+                    left: left_,
+                    greater_than,
+                    right,
+                }),
+                Token::GreaterEqualsThan(greater_equals_than) => {
+                    Expression::GreaterEqual(GreaterEqual {
+                        id,
+                        left: left_,
+                        greater_equals_than,
+                        right,
+                    })
+                }
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    // Casts bind tighter than binary operators, but not as tight as unary ones.
+    fn parse_cast(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        let mut expression = Self::parse_unary(tokens, context)?;
+        while let Some(Ok(Token::As(_))) = tokens.peek() {
+            expression = Expression::Cast(Cast {
+                id: context.next_id(),
+                inner: Box::new(expression),
+                as_: Grammar::parse(tokens, context)?,
+                type_: Grammar::parse(tokens, context)?,
+            });
+        }
+        Ok(expression)
+    }
+
+    fn parse_unary(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::Not(_))) => Ok(Expression::BitNot(BitNot {
+                id: context.next_id(),
+                not: Grammar::parse(tokens, context)?,
+                inner: Box::new(Self::parse_unary(tokens, context)?),
+            })),
+            Some(Ok(Token::Minus(_))) => Ok(Expression::Negate(Negate {
+                id: context.next_id(),
+                minus: Grammar::parse(tokens, context)?,
+                inner: Box::new(Self::parse_unary(tokens, context)?),
+            })),
+            _ => Self::parse_postfix(tokens, context),
+        }
+    }
+
+    // Indexing, field accesses and calls.
+    fn parse_postfix(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        let mut expression = Self::parse_primary(tokens, context)?;
+        loop {
+            expression = match tokens.peek() {
+                Some(Ok(Token::SquareLeft(_))) => Expression::Index(Index {
+                    id: context.next_id(),
+                    indexable: Box::new(expression),
+                    square_left: Grammar::parse(tokens, context)?,
+                    index: Grammar::parse(tokens, context)?,
+                    square_right: Grammar::parse(tokens, context)?,
+                }),
+                Some(Ok(Token::Dot(_))) => Expression::FieldAccess(FieldAccess {
+                    id: context.next_id(),
+                    inner: Box::new(expression),
+                    dot: Grammar::parse(tokens, context)?,
+                    field: Grammar::parse(tokens, context)?,
+                }),
+                Some(Ok(Token::ParLeft(_))) => Expression::Call(Call {
+                    id: context.next_id(),
+                    callable: Box::new(expression),
+                    par_left: Grammar::parse(tokens, context)?,
+                    arguments: Grammar::parse(tokens, context)?,
+                    par_right: Grammar::parse(tokens, context)?,
+                }),
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    fn parse_primary(
         tokens: &mut Peekable<Tokenizer<'input>>,
         context: &mut Context,
     ) -> Result<Self, Error<'input>> {
@@ -34,8 +266,12 @@ impl<'input> Grammar<'input> for Expression<'input> {
             None => Err(Error::TokenizerEmpty),
             Some(Ok(Token::Number(_))) => Ok(Number(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Str(_))) => Ok(Str(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::True(_))) => Ok(True(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::False(_))) => Ok(False(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Identifier(_))) => Ok(Identifier(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::ParLeft(_))) => Ok(Parenthesis(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Addr(_) | Token::Ptr(_))) => Ok(Addr(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Deref(_))) => Ok(Deref(Grammar::parse(tokens, context)?)),
             Some(Ok(_)) => Err(Error::UnexpectedToken(tokens.next().unwrap()?)),
             Some(Err(_)) => {
                 tokens.next().expect("Expected some token")?;
@@ -45,87 +281,397 @@ impl<'input> Grammar<'input> for Expression<'input> {
     }
 }
 
+impl<'input> Grammar<'input> for Option<Expression<'input>> {
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(token)) if starts_expression(token) => {
+                Ok(Some(Grammar::parse(tokens, context)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Whether an expression can start with the token.
+pub(crate) fn starts_expression(token: &Token<'_>) -> bool {
+    matches!(
+        token,
+        Token::Number(_)
+            | Token::Str(_)
+            | Token::True(_)
+            | Token::False(_)
+            | Token::Identifier(_)
+            | Token::ParLeft(_)
+            | Token::Addr(_)
+            | Token::Ptr(_)
+            | Token::Deref(_)
+            | Token::Not(_)
+            | Token::Minus(_)
+    )
+}
+
+// Precedence of comparisons, the loosest binary operators.
+const COMPARISON: u8 = 1;
+
+/// Precedence of a binary operator token (higher binds tighter).
+fn binary_precedence(token: &Token<'_>) -> Option<u8> {
+    match token {
+        Token::EqualsEquals(_)
+        | Token::NotEquals(_)
+        | Token::LessThan(_)
+        | Token::LessEqualsThan(_)
+        | Token::GreaterThan(_)
+        | Token::GreaterEqualsThan(_) => Some(COMPARISON),
+        Token::Or(_) => Some(2),
+        Token::Xor(_) => Some(3),
+        Token::And(_) => Some(4),
+        Token::Plus(_) | Token::Minus(_) => Some(5),
+        Token::Star(_) | Token::ForwardSlash(_) => Some(6),
+        _ => None,
+    }
+}
+
+impl<'input> Grammar<'input> for Expression<'input> {
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        Self::parse_binary(tokens, context, 0)
+    }
+}
+
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Parenthesis<'input, E>
 where
     E: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub par_left: tokens::ParLeft<'input>,
     pub inner: E,
-    pub par_right: tokens::ParLeft<'input>,
+    pub par_right: tokens::ParRight<'input>,
 }
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
-pub struct Number<'input>(pub tokens::Number<'input>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Number<'input> {
+    pub id: NodeId,
+    pub number: tokens::Number<'input>,
+}
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
-pub struct Str<'input>(pub tokens::Str<'input>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Str<'input> {
+    pub id: NodeId,
+    pub str: tokens::Str<'input>,
+}
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
-pub struct Identifier<'input>(pub tokens::Identifier<'input>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct True<'input> {
+    pub id: NodeId,
+    pub true_: tokens::True<'input>,
+}
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct False<'input> {
+    pub id: NodeId,
+    pub false_: tokens::False<'input>,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Identifier<'input> {
+    pub id: NodeId,
+    pub identifier: tokens::Identifier<'input>,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Add<'input, L, R>
 where
     L: ExpressionGrammar<'input>,
     R: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub left: L,
     pub plus: tokens::Plus<'input>,
     pub right: R,
 }
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Subtract<'input, L, R>
 where
     L: ExpressionGrammar<'input>,
     R: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub left: L,
     pub minus: tokens::Minus<'input>,
     pub right: R,
 }
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Multiply<'input, L, R>
 where
     L: ExpressionGrammar<'input>,
     R: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub left: L,
     pub star: tokens::Star<'input>,
     pub right: R,
 }
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Divide<'input, L, R>
 where
     L: ExpressionGrammar<'input>,
     R: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub left: L,
     pub forward_slash: tokens::ForwardSlash<'input>,
     pub right: R,
 }
 
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitAnd<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub and: tokens::And<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitOr<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub or: tokens::Or<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitXor<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub xor: tokens::Xor<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Equal<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub equals_equals: tokens::EqualsEquals<'input>,
+    pub right: R,
+}
+
+/// `<expression> ~= <expression>`, which is true when the operands are different.
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NotEqual<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub not_equals: tokens::NotEquals<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Less<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub less_than: tokens::LessThan<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LessEqual<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub less_equals_than: tokens::LessEqualsThan<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Greater<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub greater_than: tokens::GreaterThan<'input>,
+    pub right: R,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GreaterEqual<'input, L, R>
+where
+    L: ExpressionGrammar<'input>,
+    R: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub left: L,
+    pub greater_equals_than: tokens::GreaterEqualsThan<'input>,
+    pub right: R,
+}
+
+/// `~<expression>`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitNot<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub not: tokens::Not<'input>,
+    pub inner: E,
+}
+
+/// `-<expression>`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Negate<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub minus: tokens::Minus<'input>,
+    pub inner: E,
+}
+
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Index<'input, In, I>
 where
     In: ExpressionGrammar<'input>,
     I: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub indexable: In,
     pub square_left: tokens::SquareLeft<'input>,
     pub index: I,
     pub square_right: tokens::SquareRight<'input>,
 }
 
+/// `<expression>.<field>`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldAccess<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub inner: E,
+    pub dot: tokens::Dot<'input>,
+    pub field: tokens::Identifier<'input>,
+}
+
 #[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Call<'input, C>
 where
     C: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub callable: C,
     pub par_left: tokens::ParLeft<'input>,
+    pub arguments: Punctuated<Expression<'input>, tokens::Comma<'input>>,
+    pub par_right: tokens::ParRight<'input>,
+}
+
+/// `addr(<expression>)`, also spelled `ptr(<expression>)`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Addr<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    #[parse_with(addr_keyword)]
+    pub addr: tokens::Addr<'input>,
+    pub par_left: tokens::ParLeft<'input>,
+    pub inner: E,
     pub par_right: tokens::ParRight<'input>,
 }
+
+fn addr_keyword<'input>(
+    tokens: &mut Peekable<Tokenizer<'input>>,
+    context: &mut Context,
+) -> Result<tokens::Addr<'input>, Error<'input>> {
+    match tokens.peek() {
+        Some(Ok(Token::Ptr(_))) => tokens::Ptr::parse(tokens, context).map(Into::into),
+        _ => Grammar::parse(tokens, context),
+    }
+}
+
+/// `deref(<expression>)`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Deref<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub deref: tokens::Deref<'input>,
+    pub par_left: tokens::ParLeft<'input>,
+    pub inner: E,
+    pub par_right: tokens::ParRight<'input>,
+}
+
+/// `<expression> as <type>`
+#[derive(Debug, parse_derive::ExpressionGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cast<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub id: NodeId,
+    pub inner: E,
+    pub as_: tokens::As<'input>,
+    pub type_: Type<'input>,
+}
@@ -0,0 +1,100 @@
+use crate::{
+    ast::{
+        visit::{Visit, VisitMut, Walk},
+        Context, Error, Grammar,
+    },
+    lex::Tokenizer,
+    Span, Spanned,
+};
+use std::iter::Peekable;
+
+/// Sequence of `T`s separated by `P`s, with an optional trailing `P`.
+///
+/// Parsing stops at the first missing `T` or `P`.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Punctuated<T, P> {
+    pairs: Vec<(T, Option<P>)>,
+}
+
+impl<T, P> Punctuated<T, P> {
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.pairs.iter().map(|(item, _)| item)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.pairs.iter_mut().map(|(item, _)| item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns `true` if the last item is followed by a punctuation.
+    pub fn trailing_punct(&self) -> bool {
+        matches!(self.pairs.last(), Some((_, Some(_))))
+    }
+}
+
+impl<'input, T, P> Grammar<'input> for Punctuated<T, P>
+where
+    T: Spanned + Walk<'input>,
+    P: Spanned + Walk<'input>,
+    Option<T>: Grammar<'input>,
+    Option<P>: Grammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        let mut pairs = Vec::new();
+        while let Some(item) = Grammar::parse(tokens, context)? {
+            let punct: Option<P> = Grammar::parse(tokens, context)?;
+            let last = punct.is_none();
+            pairs.push((item, punct));
+            if last {
+                break;
+            }
+        }
+        Ok(Self { pairs })
+    }
+}
+
+impl<T: Spanned, P: Spanned> Spanned for Punctuated<T, P> {
+    fn span(&self) -> Span {
+        self.pairs
+            .iter()
+            .fold(Span::default(), |span, (item, punct)| {
+                span.join(item.span()).join(punct.span())
+            })
+    }
+}
+
+// Like other containers, punctuated sequences are transparent to visitors.
+impl<'input, T: Walk<'input>, P: Walk<'input>> Walk<'input> for Punctuated<T, P> {
+    fn accept<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        self.walk(visitor)
+    }
+
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        self.walk_mut(visitor)
+    }
+
+    fn walk<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        for (item, punct) in &self.pairs {
+            item.accept(visitor);
+            punct.accept(visitor);
+        }
+    }
+
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        for (item, punct) in &mut self.pairs {
+            item.accept_mut(visitor);
+            punct.accept_mut(visitor);
+        }
+    }
+}
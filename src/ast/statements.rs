@@ -1,10 +1,12 @@
 use crate::{
     ast::{
-        expressions::{Expression, ExpressionGrammar},
+        expressions::{starts_expression, Expression, ExpressionGrammar},
         types::{Type, TypeGrammar},
-        Context, Error, Grammar,
+        visit::Walk,
+        Context, Error, Grammar, NodeId, Punctuated,
     },
     lex::{tokens, tokens::Token, Tokenizer},
+    Spanned,
 };
 use std::iter::Peekable;
 
@@ -13,21 +15,59 @@ pub trait StatementGrammar<'input>: Grammar<'input> {}
 impl<'input, S> StatementGrammar<'input> for Box<S> where S: StatementGrammar<'input> {}
 impl<'input> StatementGrammar<'input> for () {}
 
-#[derive(Debug)]
+/// Body of an [`If`], [`Else`], [`Loop`] or [`While`], which is a single statement.
+pub trait BodyGrammar<'input>: Grammar<'input> {
+    fn parse_body(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>>;
+}
+
+impl<'input, S> BodyGrammar<'input> for S
+where
+    S: StatementGrammar<'input>,
+{
+    fn parse_body(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        Grammar::parse(tokens, context)
+    }
+}
+
+// Unlike the statements of a `Scope`, which go on until the closing `}`, a body holds the one
+// statement that follows the condition (usually a `Scope` itself).
+impl<'input> BodyGrammar<'input> for Vec<Statement<'input>> {
+    fn parse_body(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        Ok(vec![Grammar::parse(tokens, context)?])
+    }
+}
+
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement<'input> {
     Let(Let<'input, Type<'input>, Expression<'input>>),
-    Const(Const<'input, Type<'input>>),
+    Const(Const<'input, Type<'input>, Expression<'input>>),
     Static(Static<'input, Type<'input>, Expression<'input>>),
+    Fn(Fn<'input, Type<'input>, Scope<'input, Vec<Statement<'input>>>>),
+    TypeAlias(TypeAlias<'input, Type<'input>>),
     Scope(Scope<'input, Vec<Statement<'input>>>),
     If(If<'input, Expression<'input>, Vec<Statement<'input>>>),
     Loop(Loop<'input, Vec<Statement<'input>>>),
     While(While<'input, Expression<'input>, Vec<Statement<'input>>>),
     Continue(Continue<'input>),
     Break(Break<'input>),
+    Return(Return<'input>),
+    Asm(Asm<'input>),
+    Import(Import<'input>),
+    Use(Use<'input>),
+    Expression(ExpressionStatement<'input, Expression<'input>>),
+    Assign(Assign<'input, Expression<'input>>),
 }
 
-impl<'input> StatementGrammar<'input> for Statement<'input> {}
-
 impl<'input> Grammar<'input> for Statement<'input> {
     fn parse(
         tokens: &mut Peekable<Tokenizer<'input>>,
@@ -37,6 +77,8 @@ impl<'input> Grammar<'input> for Statement<'input> {
             Some(Ok(Token::Let(_))) => Ok(Statement::Let(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Const(_))) => Ok(Statement::Const(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Static(_))) => Ok(Statement::Static(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Fn(_))) => Ok(Statement::Fn(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Type(_))) => Ok(Statement::TypeAlias(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::CurlyLeft(_))) => Ok(Statement::Scope(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::If(_))) => Ok(Statement::If(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Loop(_))) => Ok(Statement::Loop(Grammar::parse(tokens, context)?)),
@@ -45,6 +87,26 @@ impl<'input> Grammar<'input> for Statement<'input> {
                 Ok(Statement::Continue(Grammar::parse(tokens, context)?))
             }
             Some(Ok(Token::Break(_))) => Ok(Statement::Break(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Return(_))) => Ok(Statement::Return(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Asm(_))) => Ok(Statement::Asm(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Import(_))) => Ok(Statement::Import(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Use(_))) => Ok(Statement::Use(Grammar::parse(tokens, context)?)),
+            // both start with an expression, and only the `=` that follows it tells them apart
+            Some(Ok(token)) if starts_expression(token) => {
+                let expression = Grammar::parse(tokens, context)?;
+                match tokens.peek() {
+                    Some(Ok(Token::Equals(_))) => Ok(Statement::Assign(Assign {
+                        place: expression,
+                        equals: Grammar::parse(tokens, context)?,
+                        expression: Grammar::parse(tokens, context)?,
+                        semi_colon: Grammar::parse(tokens, context)?,
+                    })),
+                    _ => Ok(Statement::Expression(ExpressionStatement {
+                        expression,
+                        semi_colon: Grammar::parse(tokens, context)?,
+                    })),
+                }
+            }
             Some(Ok(_)) => Err(Error::UnexpectedToken(tokens.next().unwrap()?)),
             Some(Err(_)) => {
                 tokens.next().expect("Expected some token")?;
@@ -55,43 +117,45 @@ impl<'input> Grammar<'input> for Statement<'input> {
     }
 }
 
-// TODO(german) this is a L1 parser so there shouldn't be code repetition here
 impl<'input> Grammar<'input> for Option<Statement<'input>> {
     fn parse(
         tokens: &mut Peekable<Tokenizer<'input>>,
         context: &mut Context,
     ) -> Result<Self, Error<'input>> {
         match tokens.peek() {
-            Some(Ok(Token::Let(_))) => Ok(Some(Statement::Let(Grammar::parse(tokens, context)?))),
-            Some(Ok(Token::Const(_))) => {
-                Ok(Some(Statement::Const(Grammar::parse(tokens, context)?)))
-            }
-            Some(Ok(Token::Static(_))) => {
-                Ok(Some(Statement::Static(Grammar::parse(tokens, context)?)))
-            }
-            Some(Ok(Token::CurlyLeft(_))) => {
-                Ok(Some(Statement::Scope(Grammar::parse(tokens, context)?)))
-            }
-            Some(Ok(Token::If(_))) => Ok(Some(Statement::If(Grammar::parse(tokens, context)?))),
-            Some(Ok(Token::Loop(_))) => Ok(Some(Statement::Loop(Grammar::parse(tokens, context)?))),
-            Some(Ok(Token::While(_))) => {
-                Ok(Some(Statement::While(Grammar::parse(tokens, context)?)))
+            Some(Ok(token)) if starts_statement(token) => {
+                Ok(Some(Grammar::parse(tokens, context)?))
             }
-            Some(Ok(Token::Continue(_))) => {
-                Ok(Some(Statement::Continue(Grammar::parse(tokens, context)?)))
-            }
-            Some(Ok(Token::Break(_))) => {
-                Ok(Some(Statement::Break(Grammar::parse(tokens, context)?)))
-            }
-            Some(Ok(Token::EOF(_))) => Ok(None),
             _ => Ok(None),
         }
     }
 }
 
+fn starts_statement(token: &Token<'_>) -> bool {
+    match token {
+        Token::Let(_)
+        | Token::Const(_)
+        | Token::Static(_)
+        | Token::Fn(_)
+        | Token::Type(_)
+        | Token::CurlyLeft(_)
+        | Token::If(_)
+        | Token::Loop(_)
+        | Token::While(_)
+        | Token::Continue(_)
+        | Token::Break(_)
+        | Token::Return(_)
+        | Token::Asm(_)
+        | Token::Import(_)
+        | Token::Use(_) => true,
+        token => starts_expression(token),
+    }
+}
+
 //impl<'input> StatementGrammar<'input> for Option<Statement<'input>> {}
 impl<'input, S> Grammar<'input> for Vec<S>
 where
+    S: Spanned + Walk<'input>,
     Option<S>: Grammar<'input>,
 {
     fn parse(
@@ -99,23 +163,21 @@ where
         context: &mut Context,
     ) -> Result<Self, Error<'input>> {
         let mut out = Vec::new();
-        loop {
-            if let Some(s) = Grammar::parse(tokens, context)? {
-                out.push(s);
-            } else {
-                break;
-            }
+        while let Some(s) = Grammar::parse(tokens, context)? {
+            out.push(s);
         }
         Ok(out)
     }
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Let<'input, T, E>
 where
     T: TypeGrammar<'input>,
     E: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub let_: tokens::Let<'input>,
     pub identifier: tokens::Identifier<'input>,
     pub colon_colon: tokens::ColonColon<'input>,
@@ -126,33 +188,183 @@ where
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
-pub struct Const<'input, T>
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Const<'input, T, E>
 where
     T: TypeGrammar<'input>,
+    E: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub const_: tokens::Const<'input>,
     pub identifier: tokens::Identifier<'input>,
     pub colon_colon: tokens::ColonColon<'input>,
     pub type_: T,
+    pub equals: tokens::Equals<'input>,
+    pub expression: E,
     pub semi_colon: tokens::SemiColon<'input>,
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Static<'input, T, E>
 where
     T: TypeGrammar<'input>,
     E: ExpressionGrammar<'input>,
 {
+    pub id: NodeId,
     pub static_: tokens::Static<'input>,
     pub identifier: tokens::Identifier<'input>,
+    pub placement: Option<Placement<'input>>,
     pub colon_colon: tokens::ColonColon<'input>,
     pub type_: T,
+    pub initializer: Option<Initializer<'input, E>>,
+
+    /// Optional, like after the `struct` statics of `example.ggb`.
+    pub semi_colon: Option<tokens::SemiColon<'input>>,
+}
+
+/// Fixed address of a [`Static`] (`@ 0x8000`).
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Placement<'input> {
+    pub at: tokens::At<'input>,
+
+    /// Constant expression, such as `VRAM_START + 0x1800`.
+    pub address: Box<Expression<'input>>,
+}
+
+impl<'input> Grammar<'input> for Option<Placement<'input>> {
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::At(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Initial value of a [`Static`].
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Initializer<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
     pub equals: tokens::Equals<'input>,
     pub expression: E,
+}
+
+impl<'input, E> Grammar<'input> for Option<Initializer<'input, E>>
+where
+    E: ExpressionGrammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::Equals(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// `type NAME = <type>;`
+///
+/// The name can be used in place of the type, including from its own definition as long as it is
+/// behind a `ptr<...>`.
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypeAlias<'input, T>
+where
+    T: TypeGrammar<'input>,
+{
+    pub id: NodeId,
+    pub type_: tokens::Type<'input>,
+    pub identifier: tokens::Identifier<'input>,
+    pub equals: tokens::Equals<'input>,
+    pub definition: T,
     pub semi_colon: tokens::SemiColon<'input>,
 }
 
+/// `fn NAME(PARAM::TYPE, ...) :: TYPE { ... }`
+///
+/// The return type is optional, for functions that don't return a value.
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fn<'input, T, B>
+where
+    T: TypeGrammar<'input>,
+    B: Grammar<'input>,
+{
+    pub id: NodeId,
+    pub fn_: tokens::Fn<'input>,
+    pub identifier: tokens::Identifier<'input>,
+    pub par_left: tokens::ParLeft<'input>,
+    pub params: Punctuated<Param<'input, T>, tokens::Comma<'input>>,
+    pub par_right: tokens::ParRight<'input>,
+    pub return_type: Option<ReturnType<'input, T>>,
+    pub body: B,
+}
+
+/// Parameter of a [`Fn`].
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Param<'input, T>
+where
+    T: TypeGrammar<'input>,
+{
+    pub id: NodeId,
+    pub identifier: tokens::Identifier<'input>,
+    pub colon_colon: tokens::ColonColon<'input>,
+    pub type_: T,
+}
+
+impl<'input, T> Grammar<'input> for Option<Param<'input, T>>
+where
+    T: TypeGrammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::Identifier(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Return type of a [`Fn`].
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReturnType<'input, T>
+where
+    T: TypeGrammar<'input>,
+{
+    pub colon_colon: tokens::ColonColon<'input>,
+    pub type_: T,
+}
+
+impl<'input, T> Grammar<'input> for Option<ReturnType<'input, T>>
+where
+    T: TypeGrammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::ColonColon(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scope<'input, I>
 where
     I: Grammar<'input>,
@@ -163,44 +375,179 @@ where
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct If<'input, E, I>
 where
     E: ExpressionGrammar<'input>,
-    I: Grammar<'input>,
+    I: BodyGrammar<'input>,
 {
     pub if_: tokens::If<'input>,
     pub expression: E,
+    #[parse_with(BodyGrammar::parse_body)]
+    pub inner: I,
+    pub else_: Option<Else<'input, I>>,
+}
+
+/// Else branch of an [`If`].
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Else<'input, I>
+where
+    I: BodyGrammar<'input>,
+{
+    pub else_: tokens::Else<'input>,
+    #[parse_with(BodyGrammar::parse_body)]
     pub inner: I,
 }
 
+impl<'input, I> Grammar<'input> for Option<Else<'input, I>>
+where
+    I: BodyGrammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::Else(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Loop<'input, I>
 where
-    I: Grammar<'input>,
+    I: BodyGrammar<'input>,
 {
     pub loop_: tokens::Loop<'input>,
+    #[parse_with(BodyGrammar::parse_body)]
     pub inner: I,
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct While<'input, E, I>
 where
     E: ExpressionGrammar<'input>,
-    I: Grammar<'input>,
+    I: BodyGrammar<'input>,
 {
     pub while_: tokens::While<'input>,
     pub expression: E,
+    #[parse_with(BodyGrammar::parse_body)]
     pub inner: I,
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Continue<'input> {
     pub continue_: tokens::Continue<'input>,
     pub semi_colon: tokens::SemiColon<'input>,
 }
 
 #[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Break<'input> {
     pub break_: tokens::Break<'input>,
     pub semi_colon: tokens::SemiColon<'input>,
 }
+
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Return<'input> {
+    pub return_: tokens::Return<'input>,
+    pub expression: Option<Expression<'input>>,
+    pub semi_colon: tokens::SemiColon<'input>,
+}
+
+/// `asm { ... }`
+///
+/// Inline SM83 assembly, emitted in place. Operands can refer to `static`s, `let`s and
+/// parameters (their address, or the address of a field with `NAME.field`), to `const`s (their
+/// value) and to `fn`s (their label).
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Asm<'input> {
+    pub id: NodeId,
+    pub asm_: tokens::Asm<'input>,
+    pub block: tokens::AsmBlock<'input>,
+}
+
+/// `import "PATH";`
+///
+/// Makes the [module](crate::loader) of another source file available to the file, under the
+/// name of the file (`import "gfx/sprites.ggb";` imports module `sprites`), or under the name
+/// given by an [`Alias`]. The path is relative to the importing file, or the name of a
+/// [built-in module](crate::prelude::MODULES). Only allowed at the top level of a file.
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Import<'input> {
+    pub id: NodeId,
+    pub import_: tokens::Import<'input>,
+    pub path: tokens::Str<'input>,
+    pub alias: Option<Alias<'input>>,
+    pub semi_colon: tokens::SemiColon<'input>,
+}
+
+/// Name of an [`Import`]ed module (`as NAME`), for modules whose files have the same name.
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alias<'input> {
+    pub as_: tokens::As<'input>,
+    pub identifier: tokens::Identifier<'input>,
+}
+
+impl<'input> Grammar<'input> for Option<Alias<'input>> {
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::As(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// `use MODULE::NAME;`
+///
+/// Brings a `const`, `static`, `type` or `fn` declared at the top level of an imported module
+/// into the scope of the file. Only allowed at the top level of a file.
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Use<'input> {
+    pub id: NodeId,
+    pub use_: tokens::Use<'input>,
+    pub module: tokens::Identifier<'input>,
+    pub colon_colon: tokens::ColonColon<'input>,
+    pub identifier: tokens::Identifier<'input>,
+    pub semi_colon: tokens::SemiColon<'input>,
+}
+
+/// Expression evaluated for its side effects (`foo();`).
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExpressionStatement<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub expression: E,
+    pub semi_colon: tokens::SemiColon<'input>,
+}
+
+/// `<place> = <expression>;`
+///
+/// The place is an expression that refers to memory: a variable, an element of an array, a field
+/// of a `struct` or `union`, or a dereferenced pointer.
+#[derive(Debug, parse_derive::StatementGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Assign<'input, E>
+where
+    E: ExpressionGrammar<'input>,
+{
+    pub place: E,
+    pub equals: tokens::Equals<'input>,
+    pub expression: E,
+    pub semi_colon: tokens::SemiColon<'input>,
+}
@@ -1,5 +1,5 @@
 use crate::{
-    ast::{Context, Error, Grammar},
+    ast::{expressions::Expression, Context, Error, Grammar, NodeId, Punctuated},
     lex::{tokens, tokens::Token, Tokenizer},
 };
 use std::iter::Peekable;
@@ -8,16 +8,21 @@ pub trait TypeGrammar<'input>: Grammar<'input> {}
 
 impl<'input, T: TypeGrammar<'input>> TypeGrammar<'input> for Box<T> {}
 
-#[derive(Debug)]
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type<'input> {
     U8(U8<'input>),
+    U16(U16<'input>),
+    I8(I8<'input>),
+    I16(I16<'input>),
+    Bool(Bool<'input>),
     Array(Array<'input, Box<Type<'input>>>),
     Ptr(Ptr<'input, Box<Type<'input>>>),
     Struct(Struct<'input>),
+    Union(Union<'input>),
+    Named(Named<'input>),
 }
 
-impl<'input> TypeGrammar<'input> for Type<'input> {}
-
 impl<'input> Grammar<'input> for Type<'input> {
     fn parse(
         tokens: &mut Peekable<Tokenizer<'input>>,
@@ -25,9 +30,15 @@ impl<'input> Grammar<'input> for Type<'input> {
     ) -> Result<Self, Error<'input>> {
         match tokens.peek() {
             Some(Ok(Token::U8(_))) => Ok(Type::U8(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::U16(_))) => Ok(Type::U16(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::I8(_))) => Ok(Type::I8(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::I16(_))) => Ok(Type::I16(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Bool(_))) => Ok(Type::Bool(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Array(_))) => Ok(Type::Array(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Ptr(_))) => Ok(Type::Ptr(Grammar::parse(tokens, context)?)),
             Some(Ok(Token::Struct(_))) => Ok(Type::Struct(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Union(_))) => Ok(Type::Union(Grammar::parse(tokens, context)?)),
+            Some(Ok(Token::Identifier(_))) => Ok(Type::Named(Grammar::parse(tokens, context)?)),
             Some(Ok(_)) => Err(Error::UnexpectedToken(tokens.next().unwrap()?)),
             Some(Err(_)) => {
                 tokens.next().expect("Expected some token")?;
@@ -39,9 +50,27 @@ impl<'input> Grammar<'input> for Type<'input> {
 }
 
 #[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct U8<'input>(pub tokens::U8<'input>);
 
 #[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct U16<'input>(pub tokens::U16<'input>);
+
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct I8<'input>(pub tokens::I8<'input>);
+
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct I16<'input>(pub tokens::I16<'input>);
+
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bool<'input>(pub tokens::Bool<'input>);
+
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Array<'input, T>
 where
     T: TypeGrammar<'input>,
@@ -50,11 +79,23 @@ where
     pub less_than: tokens::LessThan<'input>,
     pub type_: T,
     pub comma: tokens::Comma<'input>,
-    pub number: tokens::Number<'input>,
+
+    /// Constant expression, where comparisons must be parenthesized.
+    #[parse_with(length)]
+    pub length: Box<Expression<'input>>,
     pub greater_than: tokens::GreaterThan<'input>,
 }
 
+// Length of an `Array`, which the `>` after it would otherwise be parsed as a comparison of.
+fn length<'input>(
+    tokens: &mut Peekable<Tokenizer<'input>>,
+    context: &mut Context,
+) -> Result<Box<Expression<'input>>, Error<'input>> {
+    Expression::parse_length(tokens, context).map(Box::new)
+}
+
 #[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ptr<'input, T>
 where
     T: TypeGrammar<'input>,
@@ -66,8 +107,54 @@ where
 }
 
 #[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Struct<'input> {
     pub struct_: tokens::Struct<'input>,
     pub curly_left: tokens::CurlyLeft<'input>,
+    pub fields: Punctuated<Field<'input, Type<'input>>, tokens::Comma<'input>>,
+    pub curly_right: tokens::CurlyRight<'input>,
+}
+
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Union<'input> {
+    pub union: tokens::Union<'input>,
+    pub curly_left: tokens::CurlyLeft<'input>,
+    pub fields: Punctuated<Field<'input, Type<'input>>, tokens::Comma<'input>>,
     pub curly_right: tokens::CurlyRight<'input>,
 }
+
+/// Name of a type declared with a [`TypeAlias`](crate::ast::statements::TypeAlias).
+#[derive(Debug, parse_derive::TypeGrammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Named<'input> {
+    pub id: NodeId,
+    pub identifier: tokens::Identifier<'input>,
+}
+
+/// Field of a [`Struct`] or [`Union`].
+#[derive(Debug, parse_derive::Grammar)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Field<'input, T>
+where
+    T: TypeGrammar<'input>,
+{
+    pub identifier: tokens::Identifier<'input>,
+    pub colon_colon: tokens::ColonColon<'input>,
+    pub type_: T,
+}
+
+impl<'input, T> Grammar<'input> for Option<Field<'input, T>>
+where
+    T: TypeGrammar<'input>,
+{
+    fn parse(
+        tokens: &mut Peekable<Tokenizer<'input>>,
+        context: &mut Context,
+    ) -> Result<Self, Error<'input>> {
+        match tokens.peek() {
+            Some(Ok(Token::Identifier(_))) => Ok(Some(Grammar::parse(tokens, context)?)),
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Syntax tree traversal.
+//!
+//! Every node has a `visit_*` method in [`Visit`] (and a `visit_*_mut` one in [`VisitMut`]). The
+//! default implementations call the matching `walk_*` function, which visits the children of the
+//! node in source order. Overriding a method and calling the `walk_*` function from it is the way
+//! to hook into the traversal without having to stop it.
+//!
+//! ```
+//! use gb_lang::ast::{
+//!     statements::{Let, Statement},
+//!     types::TypeGrammar,
+//!     expressions::ExpressionGrammar,
+//!     visit::{self, Visit, Walk},
+//! };
+//!
+//! #[derive(Default)]
+//! struct CountLets(usize);
+//!
+//! impl<'input> Visit<'input> for CountLets {
+//!     fn visit_let<T, E>(&mut self, node: &Let<'input, T, E>)
+//!     where
+//!         T: TypeGrammar<'input>,
+//!         E: ExpressionGrammar<'input>,
+//!     {
+//!         self.0 += 1;
+//!         visit::walk_let(self, node);
+//!     }
+//! }
+//!
+//! let scope = gb_lang::parse::<Statement>("{ let a::u8 = 0; { let b::u8 = 1; } }").unwrap();
+//! let mut count = CountLets::default();
+//! scope.accept(&mut count);
+//! assert_eq!(2, count.0);
+//! ```
+use crate::ast::{
+    expressions::{
+        Add, Addr, BitAnd, BitNot, BitOr, BitXor, Call, Cast, Deref, Divide, Equal, Expression,
+        ExpressionGrammar, False, FieldAccess, Greater, GreaterEqual, Identifier, Index, Less,
+        LessEqual, Multiply, Negate, NotEqual, Number, Parenthesis, Str, Subtract, True,
+    },
+    statements::{
+        Alias, Asm, Assign, BodyGrammar, Break, Const, Continue, Else, ExpressionStatement, Fn, If,
+        Import, Initializer, Let, Loop, Param, Placement, Return, ReturnType, Scope, Statement,
+        Static, TypeAlias, Use, While,
+    },
+    types::{Array, Bool, Field, Named, Ptr, Struct, Type, TypeGrammar, Union, I16, I8, U16, U8},
+    Grammar, NodeId, Program, TokenGrammar,
+};
+
+/// Syntax tree traversal.
+///
+/// Implemented by every [`Grammar`], usually by the derive macros of the `parse_derive` crate.
+pub trait Walk<'input> {
+    /// Visit this node, by calling the matching method of the visitor.
+    fn accept<V: Visit<'input> + ?Sized>(&self, visitor: &mut V);
+
+    /// Visit this node, by calling the matching method of the mutable visitor.
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V);
+
+    /// Visit the children of this node, in source order.
+    fn walk<V: Visit<'input> + ?Sized>(&self, visitor: &mut V);
+
+    /// Visit the children of this node mutably, in source order.
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V);
+}
+
+macro_rules! visitor {
+    ($(
+        $(#[$($docs_meta:meta)+])*
+        fn $visit:ident, $visit_mut:ident, $walk:ident, $walk_mut:ident
+        ($node:ident <'input $(, $param:ident : $bound:ident)*>);
+    )*) => {
+        /// Syntax tree visitor.
+        pub trait Visit<'input> {
+            /// Called on every token of the syntax tree, in source order.
+            fn visit_token<T: TokenGrammar<'input>>(&mut self, _token: &T) {}
+
+            $(
+                $(#[$($docs_meta)+])*
+                fn $visit<$($param),*>(&mut self, node: &$node<'input $(, $param)*>)
+                where
+                    $($param: $bound<'input>,)*
+                {
+                    $walk(self, node)
+                }
+            )*
+        }
+
+        /// Mutable syntax tree visitor.
+        pub trait VisitMut<'input> {
+            /// Called on every token of the syntax tree, in source order.
+            fn visit_token_mut<T: TokenGrammar<'input>>(&mut self, _token: &mut T) {}
+
+            $(
+                $(#[$($docs_meta)+])*
+                fn $visit_mut<$($param),*>(&mut self, node: &mut $node<'input $(, $param)*>)
+                where
+                    $($param: $bound<'input>,)*
+                {
+                    $walk_mut(self, node)
+                }
+            )*
+        }
+
+        $(
+            /// Visit the children of the node.
+            pub fn $walk<'input, V $(, $param)*>(visitor: &mut V, node: &$node<'input $(, $param)*>)
+            where
+                V: Visit<'input> + ?Sized,
+                $($param: $bound<'input>,)*
+            {
+                node.walk(visitor)
+            }
+
+            /// Visit the children of the node mutably.
+            pub fn $walk_mut<'input, V $(, $param)*>(
+                visitor: &mut V,
+                node: &mut $node<'input $(, $param)*>,
+            ) where
+                V: VisitMut<'input> + ?Sized,
+                $($param: $bound<'input>,)*
+            {
+                node.walk_mut(visitor)
+            }
+        )*
+    };
+}
+
+visitor! {
+    fn visit_program, visit_program_mut, walk_program, walk_program_mut
+        (Program<'input>);
+
+    // statements
+
+    fn visit_statement, visit_statement_mut, walk_statement, walk_statement_mut
+        (Statement<'input>);
+    fn visit_let, visit_let_mut, walk_let, walk_let_mut
+        (Let<'input, T: TypeGrammar, E: ExpressionGrammar>);
+    fn visit_const, visit_const_mut, walk_const, walk_const_mut
+        (Const<'input, T: TypeGrammar, E: ExpressionGrammar>);
+    fn visit_static, visit_static_mut, walk_static, walk_static_mut
+        (Static<'input, T: TypeGrammar, E: ExpressionGrammar>);
+    fn visit_placement, visit_placement_mut, walk_placement, walk_placement_mut
+        (Placement<'input>);
+    fn visit_initializer, visit_initializer_mut, walk_initializer, walk_initializer_mut
+        (Initializer<'input, E: ExpressionGrammar>);
+    fn visit_type_alias, visit_type_alias_mut, walk_type_alias, walk_type_alias_mut
+        (TypeAlias<'input, T: TypeGrammar>);
+    fn visit_fn, visit_fn_mut, walk_fn, walk_fn_mut
+        (Fn<'input, T: TypeGrammar, B: Grammar>);
+    fn visit_param, visit_param_mut, walk_param, walk_param_mut
+        (Param<'input, T: TypeGrammar>);
+    fn visit_return_type, visit_return_type_mut, walk_return_type, walk_return_type_mut
+        (ReturnType<'input, T: TypeGrammar>);
+    fn visit_scope, visit_scope_mut, walk_scope, walk_scope_mut
+        (Scope<'input, I: Grammar>);
+    fn visit_if, visit_if_mut, walk_if, walk_if_mut
+        (If<'input, E: ExpressionGrammar, I: BodyGrammar>);
+    fn visit_else, visit_else_mut, walk_else, walk_else_mut
+        (Else<'input, I: BodyGrammar>);
+    fn visit_loop, visit_loop_mut, walk_loop, walk_loop_mut
+        (Loop<'input, I: BodyGrammar>);
+    fn visit_while, visit_while_mut, walk_while, walk_while_mut
+        (While<'input, E: ExpressionGrammar, I: BodyGrammar>);
+    fn visit_continue, visit_continue_mut, walk_continue, walk_continue_mut
+        (Continue<'input>);
+    fn visit_break, visit_break_mut, walk_break, walk_break_mut
+        (Break<'input>);
+    fn visit_return, visit_return_mut, walk_return, walk_return_mut
+        (Return<'input>);
+    fn visit_asm, visit_asm_mut, walk_asm, walk_asm_mut
+        (Asm<'input>);
+    fn visit_import, visit_import_mut, walk_import, walk_import_mut
+        (Import<'input>);
+    fn visit_alias, visit_alias_mut, walk_alias, walk_alias_mut
+        (Alias<'input>);
+    fn visit_use, visit_use_mut, walk_use, walk_use_mut
+        (Use<'input>);
+    fn visit_expression_statement, visit_expression_statement_mut,
+        walk_expression_statement, walk_expression_statement_mut
+        (ExpressionStatement<'input, E: ExpressionGrammar>);
+    fn visit_assign, visit_assign_mut, walk_assign, walk_assign_mut
+        (Assign<'input, E: ExpressionGrammar>);
+
+    // expressions
+
+    fn visit_expression, visit_expression_mut, walk_expression, walk_expression_mut
+        (Expression<'input>);
+    fn visit_parenthesis, visit_parenthesis_mut, walk_parenthesis, walk_parenthesis_mut
+        (Parenthesis<'input, E: ExpressionGrammar>);
+    fn visit_index, visit_index_mut, walk_index, walk_index_mut
+        (Index<'input, In: ExpressionGrammar, I: ExpressionGrammar>);
+    fn visit_field_access, visit_field_access_mut, walk_field_access, walk_field_access_mut
+        (FieldAccess<'input, E: ExpressionGrammar>);
+    fn visit_call, visit_call_mut, walk_call, walk_call_mut
+        (Call<'input, C: ExpressionGrammar>);
+    fn visit_number, visit_number_mut, walk_number, walk_number_mut
+        (Number<'input>);
+    fn visit_str, visit_str_mut, walk_str, walk_str_mut
+        (Str<'input>);
+    fn visit_true, visit_true_mut, walk_true, walk_true_mut
+        (True<'input>);
+    fn visit_false, visit_false_mut, walk_false, walk_false_mut
+        (False<'input>);
+    fn visit_identifier, visit_identifier_mut, walk_identifier, walk_identifier_mut
+        (Identifier<'input>);
+    fn visit_add, visit_add_mut, walk_add, walk_add_mut
+        (Add<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_subtract, visit_subtract_mut, walk_subtract, walk_subtract_mut
+        (Subtract<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_multiply, visit_multiply_mut, walk_multiply, walk_multiply_mut
+        (Multiply<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_divide, visit_divide_mut, walk_divide, walk_divide_mut
+        (Divide<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_bit_and, visit_bit_and_mut, walk_bit_and, walk_bit_and_mut
+        (BitAnd<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_bit_or, visit_bit_or_mut, walk_bit_or, walk_bit_or_mut
+        (BitOr<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_bit_xor, visit_bit_xor_mut, walk_bit_xor, walk_bit_xor_mut
+        (BitXor<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_equal, visit_equal_mut, walk_equal, walk_equal_mut
+        (Equal<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_not_equal, visit_not_equal_mut, walk_not_equal, walk_not_equal_mut
+        (NotEqual<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_less, visit_less_mut, walk_less, walk_less_mut
+        (Less<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_less_equal, visit_less_equal_mut, walk_less_equal, walk_less_equal_mut
+        (LessEqual<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_greater, visit_greater_mut, walk_greater, walk_greater_mut
+        (Greater<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_greater_equal, visit_greater_equal_mut, walk_greater_equal, walk_greater_equal_mut
+        (GreaterEqual<'input, L: ExpressionGrammar, R: ExpressionGrammar>);
+    fn visit_bit_not, visit_bit_not_mut, walk_bit_not, walk_bit_not_mut
+        (BitNot<'input, E: ExpressionGrammar>);
+    fn visit_negate, visit_negate_mut, walk_negate, walk_negate_mut
+        (Negate<'input, E: ExpressionGrammar>);
+    fn visit_addr, visit_addr_mut, walk_addr, walk_addr_mut
+        (Addr<'input, E: ExpressionGrammar>);
+    fn visit_deref, visit_deref_mut, walk_deref, walk_deref_mut
+        (Deref<'input, E: ExpressionGrammar>);
+    fn visit_cast, visit_cast_mut, walk_cast, walk_cast_mut
+        (Cast<'input, E: ExpressionGrammar>);
+
+    // types
+
+    fn visit_type, visit_type_mut, walk_type, walk_type_mut
+        (Type<'input>);
+    fn visit_u8, visit_u8_mut, walk_u8, walk_u8_mut
+        (U8<'input>);
+    fn visit_u16, visit_u16_mut, walk_u16, walk_u16_mut
+        (U16<'input>);
+    fn visit_i8, visit_i8_mut, walk_i8, walk_i8_mut
+        (I8<'input>);
+    fn visit_i16, visit_i16_mut, walk_i16, walk_i16_mut
+        (I16<'input>);
+    fn visit_bool, visit_bool_mut, walk_bool, walk_bool_mut
+        (Bool<'input>);
+    fn visit_array, visit_array_mut, walk_array, walk_array_mut
+        (Array<'input, T: TypeGrammar>);
+    fn visit_ptr, visit_ptr_mut, walk_ptr, walk_ptr_mut
+        (Ptr<'input, T: TypeGrammar>);
+    fn visit_struct, visit_struct_mut, walk_struct, walk_struct_mut
+        (Struct<'input>);
+    fn visit_union, visit_union_mut, walk_union, walk_union_mut
+        (Union<'input>);
+    fn visit_named, visit_named_mut, walk_named, walk_named_mut
+        (Named<'input>);
+    fn visit_field, visit_field_mut, walk_field, walk_field_mut
+        (Field<'input, T: TypeGrammar>);
+}
+
+impl<'input, W: Walk<'input>> Walk<'input> for Box<W> {
+    fn accept<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        W::accept(self, visitor)
+    }
+
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        W::accept_mut(self, visitor)
+    }
+
+    fn walk<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        W::walk(self, visitor)
+    }
+
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        W::walk_mut(self, visitor)
+    }
+}
+
+// Containers are transparent to visitors, so accepting a visitor is the same as walking them.
+
+impl<'input, W: Walk<'input>> Walk<'input> for Option<W> {
+    fn accept<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        self.walk(visitor)
+    }
+
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        self.walk_mut(visitor)
+    }
+
+    fn walk<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        if let Some(node) = self {
+            node.accept(visitor)
+        }
+    }
+
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        if let Some(node) = self {
+            node.accept_mut(visitor)
+        }
+    }
+}
+
+impl<'input, W: Walk<'input>> Walk<'input> for Vec<W> {
+    fn accept<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        self.walk(visitor)
+    }
+
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        self.walk_mut(visitor)
+    }
+
+    fn walk<V: Visit<'input> + ?Sized>(&self, visitor: &mut V) {
+        for node in self {
+            node.accept(visitor)
+        }
+    }
+
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, visitor: &mut V) {
+        for node in self {
+            node.accept_mut(visitor)
+        }
+    }
+}
+
+impl<'input> Walk<'input> for () {
+    fn accept<V: Visit<'input> + ?Sized>(&self, _: &mut V) {}
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, _: &mut V) {}
+    fn walk<V: Visit<'input> + ?Sized>(&self, _: &mut V) {}
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, _: &mut V) {}
+}
+
+impl<'input> Walk<'input> for NodeId {
+    fn accept<V: Visit<'input> + ?Sized>(&self, _: &mut V) {}
+    fn accept_mut<V: VisitMut<'input> + ?Sized>(&mut self, _: &mut V) {}
+    fn walk<V: Visit<'input> + ?Sized>(&self, _: &mut V) {}
+    fn walk_mut<V: VisitMut<'input> + ?Sized>(&mut self, _: &mut V) {}
+}
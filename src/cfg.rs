@@ -0,0 +1,285 @@
+//! Control-flow graphs of statement bodies.
+//!
+//! A [`Cfg`] splits a body (the statements of the program, or of a `fn`) into basic blocks of
+//! straight-line statements, connected by the jumps of `if`, `loop`, `while`, `break`,
+//! `continue` and `return`. Declarations that are never executed (`const`, `static`, `type` and
+//! `fn`) are not part of the graph.
+//!
+//! ```
+//! use gb_lang::{ast::Program, cfg::Cfg};
+//!
+//! let program = gb_lang::parse::<Program>("loop { break; } let a::u8 = 0; return;").unwrap();
+//! let cfg = Cfg::build(&program.statements);
+//! assert!(!cfg.is_reachable(cfg.exit()));
+//! ```
+use crate::{
+    ast::{
+        expressions::Expression,
+        statements::{Return, Statement},
+    },
+    Span, Spanned,
+};
+
+/// ID of a [`Block`] within its [`Cfg`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct BlockId(usize);
+
+impl BlockId {
+    /// Index of the block in [`Cfg::blocks`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Basic block.
+#[derive(Debug)]
+pub struct Block<'a, 'input> {
+    /// Straight-line statements (`let`s, assignments, `asm` blocks and expression statements), in
+    /// execution order.
+    pub statements: Vec<&'a Statement<'input>>,
+    pub terminator: Terminator<'a, 'input>,
+
+    /// Span of the first statement that starts in the block.
+    pub span: Option<Span>,
+
+    /// Span of the `break`, `continue`, `return` or infinite `loop` right before the block, if
+    /// the block can only be reached by jumping over it.
+    pub after: Option<Span>,
+}
+
+/// How control leaves a [`Block`].
+#[derive(Debug)]
+pub enum Terminator<'a, 'input> {
+    Goto(BlockId),
+    Branch {
+        condition: &'a Expression<'input>,
+        then: BlockId,
+        else_: BlockId,
+    },
+    Return(&'a Return<'input>),
+
+    /// End of the body, reached by running past its last statement.
+    Exit,
+}
+
+impl Terminator<'_, '_> {
+    /// Blocks control can jump to.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch { then, else_, .. } => vec![*then, *else_],
+            Terminator::Return(_) | Terminator::Exit => Vec::new(),
+        }
+    }
+}
+
+/// Control-flow graph of a statement body.
+#[derive(Debug)]
+pub struct Cfg<'a, 'input> {
+    blocks: Vec<Block<'a, 'input>>,
+    reachable: Vec<bool>,
+}
+
+impl<'a, 'input> Cfg<'a, 'input> {
+    /// Build the graph of a body.
+    ///
+    /// `break` and `continue` statements outside of a loop are ignored.
+    pub fn build(body: &'a [Statement<'input>]) -> Self {
+        let mut builder = Builder {
+            blocks: Vec::new(),
+            current: BlockId(0),
+            loops: Vec::new(),
+        };
+        builder.block();
+        builder.block();
+        for statement in body {
+            builder.statement(statement);
+        }
+        builder.terminate(Terminator::Goto(BlockId(1)));
+        let mut cfg = Self {
+            reachable: vec![false; builder.blocks.len()],
+            blocks: builder.blocks,
+        };
+        let mut stack = vec![cfg.entry()];
+        while let Some(id) = stack.pop() {
+            if !std::mem::replace(&mut cfg.reachable[id.0], true) {
+                stack.extend(cfg.blocks[id.0].terminator.successors());
+            }
+        }
+        cfg
+    }
+
+    /// Block where the body starts.
+    pub fn entry(&self) -> BlockId {
+        BlockId(0)
+    }
+
+    /// Block after the last statement of the body, terminated by [`Terminator::Exit`].
+    ///
+    /// It isn't reachable if every path through the body returns (or never ends).
+    pub fn exit(&self) -> BlockId {
+        BlockId(1)
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block<'a, 'input> {
+        &self.blocks[id.0]
+    }
+
+    /// Blocks of the graph, in the order they were created.
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &Block<'a, 'input>)> {
+        self.blocks.iter().enumerate().map(|(i, b)| (BlockId(i), b))
+    }
+
+    /// Whether there is a path from the entry to the block.
+    pub fn is_reachable(&self, id: BlockId) -> bool {
+        self.reachable[id.0]
+    }
+}
+
+struct Loop {
+    continue_: BlockId,
+    break_: BlockId,
+    broken: bool,
+}
+
+struct Builder<'a, 'input> {
+    blocks: Vec<Block<'a, 'input>>,
+    current: BlockId,
+    loops: Vec<Loop>,
+}
+
+impl<'a, 'input> Builder<'a, 'input> {
+    fn block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            statements: Vec::new(),
+            terminator: Terminator::Exit,
+            span: None,
+            after: None,
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    // Terminate the current block. The terminator of blocks that are never terminated is `Exit`.
+    fn terminate(&mut self, terminator: Terminator<'a, 'input>) {
+        self.blocks[self.current.0].terminator = terminator;
+    }
+
+    // Terminate the current block with a jump that diverges, and continue in a new block.
+    fn diverge(&mut self, terminator: Terminator<'a, 'input>, span: Span) {
+        self.terminate(terminator);
+        self.current = self.block();
+        self.blocks[self.current.0].after = Some(span);
+    }
+
+    fn statement(&mut self, statement: &'a Statement<'input>) {
+        if let Statement::Const(_)
+        | Statement::Static(_)
+        | Statement::TypeAlias(_)
+        | Statement::Fn(_)
+        | Statement::Import(_)
+        | Statement::Use(_) = statement
+        {
+            return;
+        }
+        let block = &mut self.blocks[self.current.0];
+        if block.span.is_none() {
+            block.span = Some(statement.span());
+        }
+        match statement {
+            Statement::Let(_)
+            | Statement::Asm(_)
+            | Statement::Expression(_)
+            | Statement::Assign(_) => block.statements.push(statement),
+            Statement::Scope(scope) => {
+                for statement in &scope.inner {
+                    self.statement(statement);
+                }
+            }
+            Statement::If(if_) => {
+                let then = self.block();
+                let else_ = if_.else_.as_ref().map(|_| self.block());
+                let join = self.block();
+                self.terminate(Terminator::Branch {
+                    condition: &if_.expression,
+                    then,
+                    else_: else_.unwrap_or(join),
+                });
+                self.current = then;
+                for statement in &if_.inner {
+                    self.statement(statement);
+                }
+                self.terminate(Terminator::Goto(join));
+                if let (Some(block), Some(else_)) = (else_, &if_.else_) {
+                    self.current = block;
+                    for statement in &else_.inner {
+                        self.statement(statement);
+                    }
+                    self.terminate(Terminator::Goto(join));
+                }
+                self.current = join;
+            }
+            Statement::Loop(loop_) => {
+                let body = self.block();
+                let exit = self.block();
+                self.terminate(Terminator::Goto(body));
+                self.current = body;
+                let broken = self.body(&loop_.inner, body, exit);
+                self.current = exit;
+                if !broken {
+                    self.blocks[exit.0].after = Some(statement.span());
+                }
+            }
+            Statement::While(while_) => {
+                let condition = self.block();
+                let body = self.block();
+                let exit = self.block();
+                self.terminate(Terminator::Goto(condition));
+                self.current = condition;
+                self.terminate(Terminator::Branch {
+                    condition: &while_.expression,
+                    then: body,
+                    else_: exit,
+                });
+                self.current = body;
+                self.body(&while_.inner, condition, exit);
+                self.current = exit;
+            }
+            Statement::Break(_) => {
+                if let Some(loop_) = self.loops.last_mut() {
+                    loop_.broken = true;
+                    let target = loop_.break_;
+                    self.diverge(Terminator::Goto(target), statement.span());
+                }
+            }
+            Statement::Continue(_) => {
+                if let Some(loop_) = self.loops.last() {
+                    let target = loop_.continue_;
+                    self.diverge(Terminator::Goto(target), statement.span());
+                }
+            }
+            Statement::Return(return_) => {
+                self.diverge(Terminator::Return(return_), statement.span());
+            }
+            Statement::Const(_)
+            | Statement::Static(_)
+            | Statement::TypeAlias(_)
+            | Statement::Fn(_)
+            | Statement::Import(_)
+            | Statement::Use(_) => unreachable!(),
+        }
+    }
+
+    // Lower the body of a loop, returning whether any `break` jumps out of it.
+    fn body(&mut self, body: &'a [Statement<'input>], continue_: BlockId, break_: BlockId) -> bool {
+        self.loops.push(Loop {
+            continue_,
+            break_,
+            broken: false,
+        });
+        for statement in body {
+            self.statement(statement);
+        }
+        self.terminate(Terminator::Goto(continue_));
+        self.loops.pop().expect("Expected an open loop").broken
+    }
+}
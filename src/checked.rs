@@ -0,0 +1,134 @@
+//! Queries on checked programs, shared by the [IR](crate::ir) lowering and the
+//! [interpreter](crate::interp), which both run after every check passed: the queries panic on
+//! programs with errors.
+use crate::{
+    ast::{
+        expressions::{Expression, FieldAccess},
+        statements::{Fn, Scope, Statement, Static},
+        types::Type,
+        NodeId,
+    },
+    consts::Consts,
+    ir::Size,
+    layout::{Layout, Layouts},
+    resolve::Resolution,
+    typeck::{Ty, Types},
+};
+
+pub(crate) type FnStatement<'input> =
+    Fn<'input, Type<'input>, Scope<'input, Vec<Statement<'input>>>>;
+pub(crate) type StaticStatement<'input> = Static<'input, Type<'input>, Expression<'input>>;
+
+/// Results of the checks of a program.
+#[derive(Clone, Copy)]
+pub(crate) struct Checked<'a> {
+    pub resolution: &'a Resolution,
+    pub consts: &'a Consts,
+    pub types: &'a Types,
+    pub layouts: &'a Layouts,
+}
+
+impl Checked<'_> {
+    pub fn declaration(&self, id: NodeId) -> Ty {
+        self.types
+            .declaration(id)
+            .cloned()
+            .expect("Expected a type checked declaration")
+    }
+
+    pub fn ty(&self, expression: &Expression<'_>) -> Ty {
+        self.types
+            .expression(expression.id())
+            .cloned()
+            .expect("Expected a type checked expression")
+    }
+
+    /// Size of a scalar expression.
+    pub fn size(&self, expression: &Expression<'_>) -> Size {
+        Size::of(&self.ty(expression)).expect("Expected a scalar type")
+    }
+
+    pub fn address(&self, declaration: NodeId) -> u16 {
+        let item = self
+            .layouts
+            .item(declaration)
+            .expect("Expected a laid out declaration");
+        item.address as u16
+    }
+
+    pub fn len(ty: &Ty) -> u16 {
+        Layout::of(ty).expect("Expected a type with a layout").size as u16
+    }
+
+    /// Offset of the accessed field from the start of its struct or union.
+    pub fn field_offset<'input>(
+        &self,
+        access: &FieldAccess<'input, Box<Expression<'input>>>,
+    ) -> u16 {
+        let layout = Layout::of(&self.ty(&access.inner)).expect("Expected a type with a layout");
+        let field = layout
+            .fields
+            .iter()
+            .find(|field| field.name == access.field.as_str())
+            .expect("Expected a field of the accessed type");
+        field.offset as u16
+    }
+
+    /// Declaration of the `fn` a call calls.
+    pub fn callee(&self, callable: &Expression<'_>) -> NodeId {
+        match callable {
+            Expression::Parenthesis(parenthesis) => self.callee(&parenthesis.inner),
+            Expression::Identifier(identifier) => self
+                .resolution
+                .binding(identifier.id)
+                .expect("Expected a call to a function"),
+            _ => unreachable!("Expected a call to a function"),
+        }
+    }
+}
+
+/// Collect the `fn`s and `static`s of a statement (and of the statements nested in it), in
+/// source order.
+pub(crate) fn collect<'a, 'input>(
+    statement: &'a Statement<'input>,
+    fns: &mut Vec<&'a FnStatement<'input>>,
+    statics: &mut Vec<&'a StaticStatement<'input>>,
+) {
+    let mut recurse = |statement| collect(statement, fns, statics);
+    match statement {
+        Statement::Static(static_) => statics.push(static_),
+        Statement::Fn(fn_) => {
+            fns.push(fn_);
+            fn_.body.inner.iter().for_each(|s| collect(s, fns, statics));
+        }
+        Statement::Scope(scope) => scope.inner.iter().for_each(recurse),
+        Statement::If(if_) => {
+            if_.inner.iter().for_each(&mut recurse);
+            if let Some(else_) = &if_.else_ {
+                else_.inner.iter().for_each(recurse);
+            }
+        }
+        Statement::Loop(loop_) => loop_.inner.iter().for_each(recurse),
+        Statement::While(while_) => while_.inner.iter().for_each(recurse),
+        _ => {}
+    }
+}
+
+/// Cast a scalar value: integers are sign or zero extended (following the type they are cast
+/// from) and wrapped around the size of the type they are cast to, and anything non-zero casts
+/// to `true`.
+pub(crate) fn cast(value: u16, from: &Ty, to: &Ty) -> u16 {
+    let (from_size, to_size) = match (Size::of(from), Size::of(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => unreachable!("Expected a cast between scalar types"),
+    };
+    let signed = from.width().is_some_and(|width| width.is_signed());
+    let value = match (from_size, signed) {
+        (Size::Byte, true) => i64::from(value as u8 as i8),
+        _ => i64::from(value),
+    };
+    match to {
+        Ty::Bool => u16::from(value != 0),
+        _ => to_size.wrap(value),
+    }
+}
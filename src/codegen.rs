@@ -0,0 +1,1210 @@
+//! SM83 code generation.
+//!
+//! Lowers an [IR program](crate::ir) to [assembly](crate::asm). The generated program starts
+//! at [`INIT`], which sets up the stack, calls the entry function and halts.
+//!
+//! Temporaries live in registers: `b`, `c`, `d` and `e` for bytes, `bc` and `de` for words.
+//! `a` and `hl` are the accumulators (and `hl` the address register) every instruction is
+//! computed in. Registers are allocated by a linear scan over each block, and temporaries that
+//! don't fit, that are used outside of their block, or that are live across a call (or inline
+//! assembly), are spilled to WRAM, right after the memory [laid out](crate::layout) for the
+//! program.
+//!
+//! Calls are caller-saved: arguments are passed in a shared WRAM area (which the callee copies
+//! out of before anything else), and results in `a` or `hl`. Variables live at fixed addresses,
+//! so a call that can reenter the caller (through a cycle of the call graph) pushes the caller's
+//! frame (its parameters, `let`s and spilled temporaries) on the stack, and pops it back after.
+//! Multiplication, division and copies call helper routines, which are only emitted when used.
+//!
+//! Comparisons set the flags with `cp` (or a 16-bit subtraction, and with the sign bits flipped
+//! for signed values). A comparison that decides the branch ending its block jumps on the flags
+//! directly, and one whose value is used is turned into a byte by a conditional jump.
+//!
+//! ```
+//! use gb_lang::asm::interp::Interpreter;
+//!
+//! let input = "fn double(a::u8) :: u8 { return a + a; } static A @ 0xc800 :: u8 = double(21);";
+//! let compiled = gb_lang::compile(input).unwrap();
+//! let mut interpreter = Interpreter::new(&compiled.assembly).unwrap();
+//! interpreter.run(1000).unwrap();
+//! assert_eq!(42, interpreter.memory[0xc800]);
+//! ```
+use crate::{
+    asm::{
+        Alu, Assembly, Condition, Instruction, Line, Reg, Reg16, Shift, Source, StackReg, Target,
+        Value,
+    },
+    ir::{
+        self, BinaryOp, BlockId, CompareOp, Function, FunctionId, Inst, Operand, Size, Temp,
+        Terminator,
+    },
+    layout::{Layouts, STACK_SIZE, STACK_TOP},
+    memory::Region,
+    Span,
+};
+use std::{
+    collections::{BTreeSet, HashSet},
+    ops::Range,
+};
+
+/// Label of the first instruction of the program.
+pub const INIT: &str = "__init";
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("not enough WRAM for the temporaries of the program (${0:04X} more bytes needed)")]
+    OutOfMemory(usize),
+}
+
+/// Generate the code of a program, using the WRAM its layout leaves free for temporaries.
+pub fn generate(program: &ir::Program, layouts: &Layouts) -> Result<Assembly, Error> {
+    let (base, args) = frame(program, layouts);
+    let mut next = base + args;
+
+    let mut assembly = Assembly::default();
+    let mut helpers = BTreeSet::new();
+    assembly.label(INIT);
+    assembly.push(Instruction::Ld16(Reg16::SP, Value::Number(STACK_TOP)));
+    let entry = &program.function(program.entry()).name;
+    assembly.push(Instruction::Call(None, Value::label(entry.as_str())));
+    assembly.label(HALT);
+    assembly.push(Instruction::Halt);
+    assembly.push(Instruction::Jp(None, Value::label(HALT)));
+    let components = components(program);
+    for (id, function) in program.functions() {
+        let homes = allocate(function, &mut next);
+        let mut generator = Generator {
+            program,
+            id,
+            function,
+            components: &components,
+            assembly: &mut assembly,
+            helpers: &mut helpers,
+            homes,
+            args: base as u16,
+            compares: 0,
+        };
+        generator.function();
+    }
+    assembly.span(Span::default());
+    for helper in helpers {
+        helper.emit(&mut assembly);
+    }
+    for (id, bytes) in program.all_data() {
+        assembly.label(data_label(id));
+        assembly.lines.push(Line::Data(bytes.to_vec()));
+    }
+
+    let stack = usize::from(STACK_TOP - STACK_SIZE);
+    if next > stack {
+        return Err(Error::OutOfMemory(next - stack));
+    }
+    Ok(assembly)
+}
+
+/// WRAM the generated code of a program uses for the arguments of calls and spilled temporaries,
+/// right after the statics. The stack takes the [`STACK_SIZE`] bytes below [`STACK_TOP`].
+pub fn scratch(program: &ir::Program, layouts: &Layouts) -> Range<usize> {
+    let (base, args) = frame(program, layouts);
+    let mut next = base + args;
+    for (_, function) in program.functions() {
+        allocate(function, &mut next);
+    }
+    base..next
+}
+
+// Start and size of the argument area (which the spilled temporaries follow).
+fn frame(program: &ir::Program, layouts: &Layouts) -> (usize, usize) {
+    let wram = Region::Wram.range();
+    let base = layouts
+        .items()
+        .filter(|item| item.region == Region::Wram)
+        .map(|item| item.address + item.layout.size)
+        .max()
+        .unwrap_or(usize::from(*wram.start()));
+    let args = program
+        .functions()
+        .map(|(_, function)| {
+            let bytes = function.params.iter().map(|p| function.size(*p).bytes());
+            bytes.map(usize::from).sum::<usize>()
+        })
+        .max()
+        .unwrap_or(0);
+    (base, args)
+}
+
+const HALT: &str = "__halt";
+
+// Strongly connected component of the call graph every function is in: calls between functions
+// of the same component can reenter the caller.
+fn components(program: &ir::Program) -> Vec<usize> {
+    struct Tarjan<'a> {
+        calls: Vec<Vec<usize>>,
+        // index and lowest reachable index of every visited function
+        indices: Vec<Option<(usize, usize)>>,
+        stack: Vec<usize>,
+        components: &'a mut Vec<usize>,
+        count: usize,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, function: usize) {
+            let index = self.indices.iter().flatten().count();
+            self.indices[function] = Some((index, index));
+            self.stack.push(function);
+            for callee in self.calls[function].clone() {
+                let low = match self.indices[callee] {
+                    None => {
+                        self.visit(callee);
+                        self.indices[callee].expect("Expected a visited function").1
+                    }
+                    Some((index, _)) if self.stack.contains(&callee) => index,
+                    Some(_) => continue,
+                };
+                let (index, lowest) = self.indices[function].expect("Expected a visited function");
+                self.indices[function] = Some((index, lowest.min(low)));
+            }
+            if let Some((index, lowest)) = self.indices[function] {
+                if index == lowest {
+                    while let Some(member) = self.stack.pop() {
+                        self.components[member] = self.count;
+                        if member == function {
+                            break;
+                        }
+                    }
+                    self.count += 1;
+                }
+            }
+        }
+    }
+
+    let calls = program
+        .functions()
+        .map(|(_, function)| {
+            let insts = function.blocks().flat_map(|(_, block)| &block.insts);
+            insts
+                .filter_map(|inst| match inst {
+                    Inst::Call { function, .. } => Some(function.index()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect();
+    let len = program.functions().count();
+    let mut components = vec![0; len];
+    let mut tarjan = Tarjan {
+        calls,
+        indices: vec![None; len],
+        stack: Vec::new(),
+        components: &mut components,
+        count: 0,
+    };
+    for function in 0..len {
+        if tarjan.indices[function].is_none() {
+            tarjan.visit(function);
+        }
+    }
+    components
+}
+
+fn data_label(id: ir::DataId) -> String {
+    format!("__data{}", id.index())
+}
+
+/// Where a temporary lives.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Home {
+    Reg(Reg),
+    Pair(Reg16),
+    /// Address of the WRAM slot of a spilled temporary.
+    Spill(u16),
+}
+
+const BYTE_REGS: [Reg; 4] = [Reg::B, Reg::C, Reg::D, Reg::E];
+const PAIRS: [Reg16; 2] = [Reg16::BC, Reg16::DE];
+
+// Allocate the temporaries of a function, spilling from `next` onwards.
+fn allocate(function: &Function, next: &mut usize) -> Vec<Home> {
+    // block and position of the definition of every temporary. Parameters are defined right
+    // before the first instruction.
+    let mut defs = vec![(0, -1); function.temps.len()];
+    let mut calls = Vec::new();
+    for (id, block) in function.blocks() {
+        for (i, inst) in block.insts.iter().enumerate() {
+            if let Some(dst) = inst.dst() {
+                defs[dst.index()] = (id.index(), i as isize);
+            }
+            // inline assembly is free to use any register
+            if let Inst::Call { .. } | Inst::Asm(_) = inst {
+                calls.push((id.index(), i as isize));
+            }
+        }
+    }
+    let mut last_uses = vec![None; function.temps.len()];
+    let mut spilled = vec![false; function.temps.len()];
+    for (id, block) in function.blocks() {
+        let operands = block
+            .insts
+            .iter()
+            .map(Inst::operands)
+            .chain(std::iter::once(block.terminator.operands()));
+        for (i, operands) in operands.enumerate() {
+            for operand in operands {
+                if let Operand::Temp(temp) = operand {
+                    if defs[temp.index()].0 == id.index() {
+                        last_uses[temp.index()] = Some(i as isize);
+                    } else {
+                        spilled[temp.index()] = true;
+                    }
+                }
+            }
+        }
+    }
+    for (temp, spilled) in spilled.iter_mut().enumerate() {
+        let (block, def) = defs[temp];
+        let last_use = last_uses[temp].unwrap_or(def);
+        *spilled |= calls
+            .iter()
+            .any(|&(id, call)| id == block && def < call && call < last_use);
+    }
+
+    let mut homes = vec![None; function.temps.len()];
+    for (id, _) in function.blocks() {
+        let mut defined: Vec<usize> = (0..function.temps.len())
+            .filter(|&temp| defs[temp].0 == id.index() && !spilled[temp])
+            .collect();
+        defined.sort_by_key(|&temp| defs[temp].1);
+        let mut active: Vec<(usize, isize)> = Vec::new();
+        let mut free: Vec<Reg> = BYTE_REGS.to_vec();
+        for temp in defined {
+            let def = defs[temp].1;
+            // registers of temporaries last used by the defining instruction are free, since
+            // operands are always read before the result is written
+            active.retain(|&(other, last_use)| {
+                if last_use <= def {
+                    free.extend(registers(homes[other].expect("Expected a register")));
+                }
+                last_use > def
+            });
+            let home = match function.temps[temp] {
+                Size::Byte => BYTE_REGS
+                    .iter()
+                    .find(|reg| free.contains(reg))
+                    .map(|reg| Home::Reg(*reg)),
+                Size::Word => PAIRS
+                    .iter()
+                    .find(|pair| {
+                        registers(Home::Pair(**pair))
+                            .iter()
+                            .all(|r| free.contains(r))
+                    })
+                    .map(|pair| Home::Pair(*pair)),
+            };
+            if let Some(home) = home {
+                free.retain(|reg| !registers(home).contains(reg));
+                homes[temp] = Some(home);
+                active.push((temp, last_uses[temp].unwrap_or(def)));
+            }
+        }
+    }
+    homes
+        .into_iter()
+        .enumerate()
+        .map(|(temp, home)| {
+            home.unwrap_or_else(|| {
+                let address = *next as u16;
+                *next += usize::from(function.temps[temp].bytes());
+                Home::Spill(address)
+            })
+        })
+        .collect()
+}
+
+// 8-bit registers taken by a home.
+fn registers(home: Home) -> Vec<Reg> {
+    match home {
+        Home::Reg(reg) => vec![reg],
+        Home::Pair(pair) => {
+            let (high, low) = pair.halves().expect("Expected a register pair");
+            vec![high, low]
+        }
+        Home::Spill(_) => Vec::new(),
+    }
+}
+
+/// Routine called by the generated code.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+enum Helper {
+    Mul16,
+    Udiv16,
+    Sdiv16,
+    Copy,
+}
+
+impl Helper {
+    fn label(self) -> &'static str {
+        match self {
+            Helper::Mul16 => "__mul16",
+            Helper::Udiv16 => "__udiv16",
+            Helper::Sdiv16 => "__sdiv16",
+            Helper::Copy => "__copy",
+        }
+    }
+
+    fn emit(self, assembly: &mut Assembly) {
+        use Instruction::*;
+        let local = |name: &str| format!("{}.{}", self.label(), name);
+        let jump = |condition, name: &str| Jp(condition, Value::label(local(name)));
+        assembly.label(self.label());
+        match self {
+            // hl = hl * de
+            Helper::Mul16 => {
+                for instruction in [
+                    Ld(Reg::B, Source::Reg(Reg::H)),
+                    Ld(Reg::C, Source::Reg(Reg::L)),
+                    Ld16(Reg16::HL, Value::Number(0)),
+                    Ld(Reg::A, Source::Imm(16)),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("loop"));
+                for instruction in [
+                    AddHl(Reg16::HL),
+                    Shift(self::Shift::Sla, Target::Reg(Reg::E)),
+                    Shift(self::Shift::Rl, Target::Reg(Reg::D)),
+                    jump(Some(Condition::NC), "next"),
+                    AddHl(Reg16::BC),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("next"));
+                for instruction in [
+                    Dec(Target::Reg(Reg::A)),
+                    jump(Some(Condition::NZ), "loop"),
+                    Ret(None),
+                ] {
+                    assembly.push(instruction);
+                }
+            }
+            // hl = hl / de, unsigned (dividing by zero gives $FFFF)
+            Helper::Udiv16 => {
+                for instruction in [
+                    Ld(Reg::B, Source::Reg(Reg::H)),
+                    Ld(Reg::C, Source::Reg(Reg::L)),
+                    Ld16(Reg16::HL, Value::Number(0)),
+                    Ld(Reg::A, Source::Imm(16)),
+                ] {
+                    assembly.push(instruction);
+                }
+                // shift the dividend (bc) into the remainder (hl), and the quotient into bc
+                assembly.label(local("loop"));
+                for instruction in [
+                    Push(StackReg::AF),
+                    Shift(self::Shift::Sla, Target::Reg(Reg::C)),
+                    Shift(self::Shift::Rl, Target::Reg(Reg::B)),
+                    Shift(self::Shift::Rl, Target::Reg(Reg::L)),
+                    Shift(self::Shift::Rl, Target::Reg(Reg::H)),
+                    jump(Some(Condition::C), "subtract"),
+                    Ld(Reg::A, Source::Reg(Reg::L)),
+                    Alu(self::Alu::Sub, Source::Reg(Reg::E)),
+                    Ld(Reg::A, Source::Reg(Reg::H)),
+                    Alu(self::Alu::Sbc, Source::Reg(Reg::D)),
+                    jump(Some(Condition::C), "next"),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("subtract"));
+                for instruction in [
+                    Ld(Reg::A, Source::Reg(Reg::L)),
+                    Alu(self::Alu::Sub, Source::Reg(Reg::E)),
+                    Ld(Reg::L, Source::Reg(Reg::A)),
+                    Ld(Reg::A, Source::Reg(Reg::H)),
+                    Alu(self::Alu::Sbc, Source::Reg(Reg::D)),
+                    Ld(Reg::H, Source::Reg(Reg::A)),
+                    Inc(Target::Reg(Reg::C)),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("next"));
+                for instruction in [
+                    Pop(StackReg::AF),
+                    Dec(Target::Reg(Reg::A)),
+                    jump(Some(Condition::NZ), "loop"),
+                    Ld(Reg::H, Source::Reg(Reg::B)),
+                    Ld(Reg::L, Source::Reg(Reg::C)),
+                    Ret(None),
+                ] {
+                    assembly.push(instruction);
+                }
+            }
+            // hl = hl / de, signed (rounding toward zero)
+            Helper::Sdiv16 => {
+                for instruction in [
+                    Ld(Reg::A, Source::Reg(Reg::H)),
+                    Alu(self::Alu::Xor, Source::Reg(Reg::D)),
+                    // bit 7 is the sign of the quotient
+                    Push(StackReg::AF),
+                    Bit(7, Target::Reg(Reg::H)),
+                    Call(Some(Condition::NZ), Value::label(local("negate"))),
+                    Bit(7, Target::Reg(Reg::D)),
+                    jump(Some(Condition::Z), "divide"),
+                    Ld(Reg::A, Source::Reg(Reg::E)),
+                    Cpl,
+                    Ld(Reg::E, Source::Reg(Reg::A)),
+                    Ld(Reg::A, Source::Reg(Reg::D)),
+                    Cpl,
+                    Ld(Reg::D, Source::Reg(Reg::A)),
+                    Inc16(Reg16::DE),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("divide"));
+                for instruction in [
+                    Call(None, Value::label(Helper::Udiv16.label())),
+                    Pop(StackReg::AF),
+                    Bit(7, Target::Reg(Reg::A)),
+                    Ret(Some(Condition::Z)),
+                ] {
+                    assembly.push(instruction);
+                }
+                // hl = -hl
+                assembly.label(local("negate"));
+                for instruction in [
+                    Ld(Reg::A, Source::Reg(Reg::L)),
+                    Cpl,
+                    Ld(Reg::L, Source::Reg(Reg::A)),
+                    Ld(Reg::A, Source::Reg(Reg::H)),
+                    Cpl,
+                    Ld(Reg::H, Source::Reg(Reg::A)),
+                    Inc16(Reg16::HL),
+                    Ret(None),
+                ] {
+                    assembly.push(instruction);
+                }
+            }
+            // copy bc bytes from hl to de
+            Helper::Copy => {
+                for instruction in [
+                    Ld(Reg::A, Source::Reg(Reg::B)),
+                    Alu(self::Alu::Or, Source::Reg(Reg::C)),
+                    Ret(Some(Condition::Z)),
+                ] {
+                    assembly.push(instruction);
+                }
+                assembly.label(local("loop"));
+                for instruction in [
+                    LdAHlInc,
+                    LdToA(Reg16::DE),
+                    Inc16(Reg16::DE),
+                    Dec16(Reg16::BC),
+                    Ld(Reg::A, Source::Reg(Reg::B)),
+                    Alu(self::Alu::Or, Source::Reg(Reg::C)),
+                    jump(Some(Condition::NZ), "loop"),
+                    Ret(None),
+                ] {
+                    assembly.push(instruction);
+                }
+            }
+        }
+    }
+}
+
+struct Generator<'a> {
+    program: &'a ir::Program,
+    id: FunctionId,
+    function: &'a Function,
+    // call graph component of every function
+    components: &'a [usize],
+    assembly: &'a mut Assembly,
+    helpers: &'a mut BTreeSet<Helper>,
+    homes: Vec<Home>,
+    // address of the argument area
+    args: u16,
+    // number of comparisons turned into bytes so far, which numbers their labels
+    compares: usize,
+}
+
+impl Generator<'_> {
+    fn emit(&mut self, instruction: Instruction) {
+        // `ld r, a` followed by `ld a, r` is common when a temporary is used right away
+        if let (Instruction::Ld(Reg::A, Source::Reg(from)), Some(Line::Instruction(last))) =
+            (&instruction, self.assembly.lines.last())
+        {
+            if *last == Instruction::Ld(*from, Source::Reg(Reg::A)) {
+                return;
+            }
+        }
+        self.assembly.push(instruction);
+    }
+
+    fn block_label(&self, id: BlockId) -> String {
+        format!("{}.bb{}", self.function.name, id.index())
+    }
+
+    fn function(&mut self) {
+        let function = self.function;
+        self.assembly.span(Span::default());
+        self.assembly.label(function.name.as_str());
+        let mut offset = self.args;
+        for param in &function.params {
+            match function.size(*param) {
+                Size::Byte => {
+                    self.emit(Instruction::LdAFromAddress(Value::Number(offset)));
+                    self.store_a(*param);
+                }
+                Size::Word => {
+                    self.load_word_at(offset);
+                    self.store_hl(*param);
+                }
+            }
+            offset += function.size(*param).bytes();
+        }
+        let targets: HashSet<BlockId> = function
+            .blocks()
+            .flat_map(|(_, block)| block.terminator.successors())
+            .collect();
+        let mut uses = vec![0; function.temps.len()];
+        for (_, block) in function.blocks() {
+            let operands = block.insts.iter().flat_map(Inst::operands);
+            for operand in operands.chain(block.terminator.operands()) {
+                if let Operand::Temp(temp) = operand {
+                    uses[temp.index()] += 1;
+                }
+            }
+        }
+        for (id, block) in function.blocks() {
+            if id.index() > 0 || targets.contains(&id) {
+                let label = self.block_label(id);
+                self.assembly.label(label);
+            }
+            // a comparison only used by the branch that follows it is left to the branch
+            let fused = match (block.insts.last(), &block.terminator) {
+                (
+                    Some(compare @ Inst::Compare { dst, .. }),
+                    Terminator::Branch {
+                        condition: Operand::Temp(condition),
+                        ..
+                    },
+                ) if dst == condition && uses[dst.index()] == 1 => Some(compare),
+                _ => None,
+            };
+            let insts = &block.insts[..block.insts.len() - usize::from(fused.is_some())];
+            for (inst, span) in insts.iter().zip(&block.spans) {
+                self.assembly.span(*span);
+                self.inst(inst);
+            }
+            self.assembly.span(block.span);
+            let next = id.index() + 1;
+            match &block.terminator {
+                Terminator::Jump(target) => self.jump(*target, next),
+                Terminator::Branch {
+                    size,
+                    condition,
+                    then,
+                    else_,
+                } => {
+                    let condition = match fused {
+                        Some(Inst::Compare {
+                            op,
+                            size,
+                            left,
+                            right,
+                            ..
+                        }) => self.compare(*op, *size, *left, *right),
+                        _ => {
+                            self.test(*size, *condition);
+                            Condition::NZ
+                        }
+                    };
+                    if then.index() == next {
+                        let else_ = Value::Label(self.block_label(*else_));
+                        self.emit(Instruction::Jp(Some(condition.negate()), else_));
+                    } else {
+                        let then = Value::Label(self.block_label(*then));
+                        self.emit(Instruction::Jp(Some(condition), then));
+                        self.jump(*else_, next);
+                    }
+                }
+                Terminator::Return(value) => {
+                    if let Some(value) = value {
+                        match function.ret.expect("Expected a return value") {
+                            Size::Byte => self.load_a(*value),
+                            Size::Word => self.load_hl(*value),
+                        }
+                    }
+                    self.emit(Instruction::Ret(None));
+                }
+            }
+        }
+    }
+
+    fn jump(&mut self, target: BlockId, next: usize) {
+        if target.index() != next {
+            let target = Value::Label(self.block_label(target));
+            self.emit(Instruction::Jp(None, target));
+        }
+    }
+
+    // Set the zero flag if an operand is zero.
+    fn test(&mut self, size: Size, operand: Operand) {
+        match size {
+            Size::Byte => {
+                self.load_a(operand);
+                self.emit(Instruction::Alu(Alu::Or, Source::Reg(Reg::A)));
+            }
+            Size::Word => {
+                self.load_hl(operand);
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                self.emit(Instruction::Alu(Alu::Or, Source::Reg(Reg::H)));
+            }
+        }
+    }
+
+    // Compare two operands, returning the condition under which the comparison holds (clobbering
+    // `a` and `hl`).
+    fn compare(&mut self, op: CompareOp, size: Size, left: Operand, right: Operand) -> Condition {
+        // `left <= right` is `!(right < left)`
+        let (left, right) = match op {
+            CompareOp::Le | CompareOp::SignedLe => (right, left),
+            _ => (left, right),
+        };
+        let signed = matches!(op, CompareOp::SignedLt | CompareOp::SignedLe);
+        match size {
+            // flipping the sign bits orders signed values like unsigned ones
+            Size::Byte if signed => {
+                self.load_a(right);
+                self.emit(Instruction::Alu(Alu::Xor, Source::Imm(0x80)));
+                self.emit(Instruction::Ld(Reg::H, Source::Reg(Reg::A)));
+                self.load_a(left);
+                self.emit(Instruction::Alu(Alu::Xor, Source::Imm(0x80)));
+                self.emit(Instruction::Alu(Alu::Cp, Source::Reg(Reg::H)));
+            }
+            Size::Byte => {
+                self.load_a(left);
+                let source = self.source(right);
+                self.emit(Instruction::Alu(Alu::Cp, source));
+            }
+            Size::Word => {
+                self.load_hl(left);
+                let (pair, restore) = if signed {
+                    self.emit(Instruction::Push(StackReg::DE));
+                    self.load_pair(Reg16::DE, right);
+                    for reg in [Reg::D, Reg::H] {
+                        self.emit(Instruction::Ld(Reg::A, Source::Reg(reg)));
+                        self.emit(Instruction::Alu(Alu::Xor, Source::Imm(0x80)));
+                        self.emit(Instruction::Ld(reg, Source::Reg(Reg::A)));
+                    }
+                    (Reg16::DE, true)
+                } else {
+                    self.pair(right)
+                };
+                // hl - pair, with the low byte of the difference kept for equality
+                let (high, low) = pair.halves().expect("Expected a register pair");
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                self.emit(Instruction::Alu(Alu::Sub, Source::Reg(low)));
+                self.emit(Instruction::Ld(Reg::L, Source::Reg(Reg::A)));
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::H)));
+                self.emit(Instruction::Alu(Alu::Sbc, Source::Reg(high)));
+                if matches!(op, CompareOp::Eq | CompareOp::Ne) {
+                    self.emit(Instruction::Alu(Alu::Or, Source::Reg(Reg::L)));
+                }
+                // popping leaves the flags alone
+                if restore {
+                    self.emit(Instruction::Pop(StackReg::DE));
+                }
+            }
+        }
+        match op {
+            CompareOp::Eq => Condition::Z,
+            CompareOp::Ne => Condition::NZ,
+            CompareOp::Lt | CompareOp::SignedLt => Condition::C,
+            CompareOp::Le | CompareOp::SignedLe => Condition::NC,
+        }
+    }
+
+    // Memory of the variables and spilled temporaries of the function, as the words (and odd
+    // bytes) that save it.
+    fn frame(&self) -> Vec<(u16, Size)> {
+        let spills = self.homes.iter().zip(&self.function.temps);
+        let spills = spills.filter_map(|(home, size)| match home {
+            Home::Spill(address) => Some(*address..address + size.bytes()),
+            _ => None,
+        });
+        let mut ranges: Vec<_> = self.function.frame.iter().cloned().chain(spills).collect();
+        ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<u16>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        let mut frame = Vec::new();
+        for range in merged {
+            let words = (range.start..range.end - 1).step_by(2);
+            frame.extend(words.map(|address| (address, Size::Word)));
+            if range.len() % 2 == 1 {
+                frame.push((range.end - 1, Size::Byte));
+            }
+        }
+        frame
+    }
+
+    fn home(&self, temp: Temp) -> Home {
+        self.homes[temp.index()]
+    }
+
+    // Load a byte operand into `a`.
+    fn load_a(&mut self, operand: Operand) {
+        let instruction = match operand {
+            Operand::Const(value) => Instruction::Ld(Reg::A, Source::Imm(value as u8)),
+            Operand::Temp(temp) => match self.home(temp) {
+                Home::Reg(reg) => Instruction::Ld(Reg::A, Source::Reg(reg)),
+                Home::Spill(address) => Instruction::LdAFromAddress(Value::Number(address)),
+                Home::Pair(_) => unreachable!("Expected a byte"),
+            },
+            Operand::Data(_) => unreachable!("Expected a byte"),
+        };
+        self.emit(instruction);
+    }
+
+    // Store `a` into a byte temporary.
+    fn store_a(&mut self, temp: Temp) {
+        let instruction = match self.home(temp) {
+            Home::Reg(reg) => Instruction::Ld(reg, Source::Reg(Reg::A)),
+            Home::Spill(address) => Instruction::LdAddressA(Value::Number(address)),
+            Home::Pair(_) => unreachable!("Expected a byte"),
+        };
+        self.emit(instruction);
+    }
+
+    // Load the word at an address into `hl` (clobbering `a`).
+    fn load_word_at(&mut self, address: u16) {
+        self.emit(Instruction::LdAFromAddress(Value::Number(address)));
+        self.emit(Instruction::Ld(Reg::L, Source::Reg(Reg::A)));
+        self.emit(Instruction::LdAFromAddress(Value::Number(address + 1)));
+        self.emit(Instruction::Ld(Reg::H, Source::Reg(Reg::A)));
+    }
+
+    // Load a word operand into a register pair (clobbering `a`).
+    fn load_pair(&mut self, pair: Reg16, operand: Operand) {
+        let (high, low) = pair.halves().expect("Expected a register pair");
+        match operand {
+            Operand::Const(value) => self.emit(Instruction::Ld16(pair, Value::Number(value))),
+            Operand::Data(id) => self.emit(Instruction::Ld16(pair, Value::Label(data_label(id)))),
+            Operand::Temp(temp) => match self.home(temp) {
+                Home::Pair(from) if from == pair => {}
+                Home::Pair(from) => {
+                    let (from_high, from_low) = from.halves().expect("Expected a register pair");
+                    self.emit(Instruction::Ld(high, Source::Reg(from_high)));
+                    self.emit(Instruction::Ld(low, Source::Reg(from_low)));
+                }
+                Home::Spill(address) => {
+                    self.emit(Instruction::LdAFromAddress(Value::Number(address)));
+                    self.emit(Instruction::Ld(low, Source::Reg(Reg::A)));
+                    self.emit(Instruction::LdAFromAddress(Value::Number(address + 1)));
+                    self.emit(Instruction::Ld(high, Source::Reg(Reg::A)));
+                }
+                Home::Reg(_) => unreachable!("Expected a word"),
+            },
+        }
+    }
+
+    fn load_hl(&mut self, operand: Operand) {
+        self.load_pair(Reg16::HL, operand);
+    }
+
+    // Store `hl` into a word temporary (clobbering `a`).
+    fn store_hl(&mut self, temp: Temp) {
+        match self.home(temp) {
+            Home::Pair(pair) => {
+                let (high, low) = pair.halves().expect("Expected a register pair");
+                self.emit(Instruction::Ld(high, Source::Reg(Reg::H)));
+                self.emit(Instruction::Ld(low, Source::Reg(Reg::L)));
+            }
+            Home::Spill(address) => {
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                self.emit(Instruction::LdAddressA(Value::Number(address)));
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::H)));
+                self.emit(Instruction::LdAddressA(Value::Number(address + 1)));
+            }
+            Home::Reg(_) => unreachable!("Expected a word"),
+        }
+    }
+
+    // Operand of an 8-bit operation with `a` (clobbering `hl`).
+    fn source(&mut self, operand: Operand) -> Source {
+        match operand {
+            Operand::Const(value) => Source::Imm(value as u8),
+            Operand::Temp(temp) => match self.home(temp) {
+                Home::Reg(reg) => Source::Reg(reg),
+                Home::Spill(address) => {
+                    self.emit(Instruction::Ld16(Reg16::HL, Value::Number(address)));
+                    Source::HlInd
+                }
+                Home::Pair(_) => unreachable!("Expected a byte"),
+            },
+            Operand::Data(_) => unreachable!("Expected a byte"),
+        }
+    }
+
+    // Register pair holding a word operand, loading it into `de` (saved on the stack) if it isn't
+    // in one already. Returns whether `de` has to be restored.
+    fn pair(&mut self, operand: Operand) -> (Reg16, bool) {
+        if let Operand::Temp(temp) = operand {
+            if let Home::Pair(pair) = self.home(temp) {
+                return (pair, false);
+            }
+        }
+        self.emit(Instruction::Push(StackReg::DE));
+        self.load_pair(Reg16::DE, operand);
+        (Reg16::DE, true)
+    }
+
+    // Call a helper with `hl` and `de` loaded from operands, preserving `bc` and `de`.
+    fn call_helper(&mut self, helper: Helper, hl: Operand, de: Operand, bc: Option<u16>) {
+        self.emit(Instruction::Push(StackReg::BC));
+        self.emit(Instruction::Push(StackReg::DE));
+        self.load_hl(hl);
+        self.load_pair(Reg16::DE, de);
+        if let Some(bc) = bc {
+            self.emit(Instruction::Ld16(Reg16::BC, Value::Number(bc)));
+        }
+        self.use_helper(helper);
+        self.emit(Instruction::Pop(StackReg::DE));
+        self.emit(Instruction::Pop(StackReg::BC));
+    }
+
+    fn use_helper(&mut self, helper: Helper) {
+        self.helpers.insert(helper);
+        if helper == Helper::Sdiv16 {
+            self.helpers.insert(Helper::Udiv16);
+        }
+        self.emit(Instruction::Call(None, Value::label(helper.label())));
+    }
+
+    // Extend a byte operand to a word in a register pair.
+    fn extend(&mut self, pair: Reg16, operand: Operand, signed: bool) {
+        let (high, low) = pair.halves().expect("Expected a register pair");
+        self.load_a(operand);
+        self.emit(Instruction::Ld(low, Source::Reg(Reg::A)));
+        if signed {
+            // a = $FF if bit 7 is set, $00 otherwise
+            self.emit(Instruction::Alu(Alu::Add, Source::Reg(Reg::A)));
+            self.emit(Instruction::Alu(Alu::Sbc, Source::Reg(Reg::A)));
+            self.emit(Instruction::Ld(high, Source::Reg(Reg::A)));
+        } else {
+            self.emit(Instruction::Ld(high, Source::Imm(0)));
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Load { dst, size, address } => match (size, address) {
+                (Size::Byte, Operand::Const(address)) => {
+                    self.emit(Instruction::LdAFromAddress(Value::Number(*address)));
+                    self.store_a(*dst);
+                }
+                (Size::Byte, address) => {
+                    self.load_hl(*address);
+                    self.emit(Instruction::Ld(Reg::A, Source::HlInd));
+                    self.store_a(*dst);
+                }
+                (Size::Word, address) => {
+                    self.load_hl(*address);
+                    self.emit(Instruction::LdAHlInc);
+                    self.emit(Instruction::Ld(Reg::H, Source::HlInd));
+                    self.emit(Instruction::Ld(Reg::L, Source::Reg(Reg::A)));
+                    self.store_hl(*dst);
+                }
+            },
+            Inst::Store {
+                size: Size::Byte,
+                address: Operand::Const(address),
+                value,
+            } => {
+                self.load_a(*value);
+                self.emit(Instruction::LdAddressA(Value::Number(*address)));
+            }
+            Inst::Store {
+                size: Size::Byte,
+                address,
+                value,
+            } => {
+                self.load_hl(*address);
+                let source = match value {
+                    Operand::Const(value) => Source::Imm(*value as u8),
+                    Operand::Temp(temp) => match self.home(*temp) {
+                        Home::Reg(reg) => Source::Reg(reg),
+                        _ => {
+                            self.load_a(*value);
+                            Source::Reg(Reg::A)
+                        }
+                    },
+                    Operand::Data(_) => unreachable!("Expected a byte"),
+                };
+                self.emit(Instruction::LdHlInd(source));
+            }
+            Inst::Store {
+                size: Size::Word,
+                address,
+                value,
+            } => {
+                self.load_hl(*address);
+                match value {
+                    Operand::Const(value) => {
+                        let [high, low] = value.to_be_bytes();
+                        self.emit(Instruction::LdHlInd(Source::Imm(low)));
+                        self.emit(Instruction::Inc16(Reg16::HL));
+                        self.emit(Instruction::LdHlInd(Source::Imm(high)));
+                    }
+                    Operand::Temp(temp) if matches!(self.home(*temp), Home::Spill(_)) => {
+                        let address = match self.home(*temp) {
+                            Home::Spill(address) => address,
+                            _ => unreachable!(),
+                        };
+                        self.emit(Instruction::LdAFromAddress(Value::Number(address)));
+                        self.emit(Instruction::LdHlIncA);
+                        self.emit(Instruction::LdAFromAddress(Value::Number(address + 1)));
+                        self.emit(Instruction::LdHlInd(Source::Reg(Reg::A)));
+                    }
+                    value => {
+                        let (pair, restore) = self.pair(*value);
+                        let (high, low) = pair.halves().expect("Expected a register pair");
+                        self.emit(Instruction::LdHlInd(Source::Reg(low)));
+                        self.emit(Instruction::Inc16(Reg16::HL));
+                        self.emit(Instruction::LdHlInd(Source::Reg(high)));
+                        if restore {
+                            self.emit(Instruction::Pop(StackReg::DE));
+                        }
+                    }
+                }
+            }
+            Inst::Copy { dst, src, len } => {
+                self.call_helper(Helper::Copy, *src, *dst, Some(*len));
+            }
+            Inst::Binary {
+                dst,
+                op,
+                size,
+                left,
+                right,
+            } => self.binary(*dst, *op, *size, *left, *right),
+            Inst::Compare {
+                dst,
+                op,
+                size,
+                left,
+                right,
+            } => {
+                let condition = self.compare(*op, *size, *left, *right);
+                let label = format!("{}.cmp{}", self.function.name, self.compares);
+                self.compares += 1;
+                // loads leave the flags alone
+                self.emit(Instruction::Ld(Reg::A, Source::Imm(1)));
+                self.emit(Instruction::Jp(
+                    Some(condition),
+                    Value::label(label.as_str()),
+                ));
+                self.emit(Instruction::Ld(Reg::A, Source::Imm(0)));
+                self.assembly.label(label);
+                self.store_a(*dst);
+            }
+            Inst::Not { dst, size, operand } => match size {
+                Size::Byte => {
+                    self.load_a(*operand);
+                    self.emit(Instruction::Cpl);
+                    self.store_a(*dst);
+                }
+                Size::Word => {
+                    self.load_hl(*operand);
+                    for reg in [Reg::L, Reg::H] {
+                        self.emit(Instruction::Ld(Reg::A, Source::Reg(reg)));
+                        self.emit(Instruction::Cpl);
+                        self.emit(Instruction::Ld(reg, Source::Reg(Reg::A)));
+                    }
+                    self.store_hl(*dst);
+                }
+            },
+            Inst::NonZero { dst, size, operand } => {
+                match size {
+                    Size::Byte => self.load_a(*operand),
+                    Size::Word => {
+                        self.load_hl(*operand);
+                        self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                        self.emit(Instruction::Alu(Alu::Or, Source::Reg(Reg::H)));
+                    }
+                }
+                // carry is set if `a` isn't zero, and rotated into an otherwise empty `a`
+                self.emit(Instruction::Alu(Alu::Add, Source::Imm(0xff)));
+                self.emit(Instruction::Ld(Reg::A, Source::Imm(0)));
+                self.emit(Instruction::Rla);
+                self.store_a(*dst);
+            }
+            Inst::Extend {
+                dst,
+                signed,
+                operand,
+            } => {
+                self.extend(Reg16::HL, *operand, *signed);
+                self.store_hl(*dst);
+            }
+            Inst::Truncate { dst, operand } => {
+                self.load_hl(*operand);
+                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                self.store_a(*dst);
+            }
+            Inst::Call {
+                dst,
+                function,
+                arguments,
+            } => {
+                let callee = self.program.function(*function);
+                let frame = if self.components[function.index()] == self.components[self.id.index()]
+                {
+                    self.frame()
+                } else {
+                    Vec::new()
+                };
+                for (address, size) in &frame {
+                    match size {
+                        Size::Byte => {
+                            self.emit(Instruction::LdAFromAddress(Value::Number(*address)));
+                            self.emit(Instruction::Push(StackReg::AF));
+                        }
+                        Size::Word => {
+                            self.load_word_at(*address);
+                            self.emit(Instruction::Push(StackReg::HL));
+                        }
+                    }
+                }
+                let mut offset = self.args;
+                for (param, argument) in callee.params.iter().zip(arguments) {
+                    match callee.size(*param) {
+                        Size::Byte => {
+                            self.load_a(*argument);
+                            self.emit(Instruction::LdAddressA(Value::Number(offset)));
+                        }
+                        Size::Word => {
+                            self.load_hl(*argument);
+                            self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                            self.emit(Instruction::LdAddressA(Value::Number(offset)));
+                            self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::H)));
+                            self.emit(Instruction::LdAddressA(Value::Number(offset + 1)));
+                        }
+                    }
+                    offset += callee.size(*param).bytes();
+                }
+                self.emit(Instruction::Call(None, Value::label(callee.name.as_str())));
+                if !frame.is_empty() {
+                    // the result waits in `de` (which holds no temporary across a call)
+                    let ret = dst.map(|dst| self.function.size(dst));
+                    match ret {
+                        Some(Size::Byte) => self.emit(Instruction::Ld(Reg::E, Source::Reg(Reg::A))),
+                        Some(Size::Word) => {
+                            self.emit(Instruction::Ld(Reg::D, Source::Reg(Reg::H)));
+                            self.emit(Instruction::Ld(Reg::E, Source::Reg(Reg::L)));
+                        }
+                        None => {}
+                    }
+                    for (address, size) in frame.iter().rev() {
+                        match size {
+                            Size::Byte => {
+                                self.emit(Instruction::Pop(StackReg::AF));
+                                self.emit(Instruction::LdAddressA(Value::Number(*address)));
+                            }
+                            Size::Word => {
+                                self.emit(Instruction::Pop(StackReg::HL));
+                                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                                self.emit(Instruction::LdAddressA(Value::Number(*address)));
+                                self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::H)));
+                                self.emit(Instruction::LdAddressA(Value::Number(address + 1)));
+                            }
+                        }
+                    }
+                    match ret {
+                        Some(Size::Byte) => self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::E))),
+                        Some(Size::Word) => {
+                            self.emit(Instruction::Ld(Reg::H, Source::Reg(Reg::D)));
+                            self.emit(Instruction::Ld(Reg::L, Source::Reg(Reg::E)));
+                        }
+                        None => {}
+                    }
+                }
+                if let Some(dst) = dst {
+                    match self.function.size(*dst) {
+                        Size::Byte => self.store_a(*dst),
+                        Size::Word => self.store_hl(*dst),
+                    }
+                }
+            }
+            // emitted verbatim, without going through the peephole optimizations of `emit`
+            Inst::Asm(assembly) => self.assembly.lines.extend(assembly.lines.iter().cloned()),
+        }
+    }
+
+    fn binary(&mut self, dst: Temp, op: BinaryOp, size: Size, left: Operand, right: Operand) {
+        let helper = match op {
+            BinaryOp::Mul => Some((Helper::Mul16, false)),
+            BinaryOp::Div => Some((Helper::Udiv16, false)),
+            BinaryOp::SignedDiv => Some((Helper::Sdiv16, true)),
+            _ => None,
+        };
+        if let Some((helper, signed)) = helper {
+            match size {
+                // bytes are extended to words, and the result truncated back
+                Size::Byte => {
+                    self.extend(Reg16::HL, left, signed);
+                    self.emit(Instruction::Push(StackReg::BC));
+                    self.emit(Instruction::Push(StackReg::DE));
+                    self.extend(Reg16::DE, right, signed);
+                    self.use_helper(helper);
+                    self.emit(Instruction::Pop(StackReg::DE));
+                    self.emit(Instruction::Pop(StackReg::BC));
+                    self.emit(Instruction::Ld(Reg::A, Source::Reg(Reg::L)));
+                    self.store_a(dst);
+                }
+                Size::Word => {
+                    self.call_helper(helper, left, right, None);
+                    self.store_hl(dst);
+                }
+            }
+            return;
+        }
+        let alu = match op {
+            BinaryOp::Add => Alu::Add,
+            BinaryOp::Sub => Alu::Sub,
+            BinaryOp::And => Alu::And,
+            BinaryOp::Or => Alu::Or,
+            BinaryOp::Xor => Alu::Xor,
+            _ => unreachable!(),
+        };
+        match size {
+            Size::Byte => {
+                self.load_a(left);
+                let source = self.source(right);
+                self.emit(Instruction::Alu(alu, source));
+                self.store_a(dst);
+            }
+            Size::Word => {
+                self.load_hl(left);
+                let (pair, restore) = self.pair(right);
+                if alu == Alu::Add {
+                    self.emit(Instruction::AddHl(pair));
+                } else {
+                    let (high, low) = pair.halves().expect("Expected a register pair");
+                    let carry = if alu == Alu::Sub { Alu::Sbc } else { alu };
+                    for (reg, alu, operand) in [(Reg::L, alu, low), (Reg::H, carry, high)] {
+                        self.emit(Instruction::Ld(Reg::A, Source::Reg(reg)));
+                        self.emit(Instruction::Alu(alu, Source::Reg(operand)));
+                        self.emit(Instruction::Ld(reg, Source::Reg(Reg::A)));
+                    }
+                }
+                if restore {
+                    self.emit(Instruction::Pop(StackReg::DE));
+                }
+                self.store_hl(dst);
+            }
+        }
+    }
+}
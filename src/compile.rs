@@ -0,0 +1,97 @@
+//! Compilation of a whole program.
+//!
+//! Runs every pass, from parsing to [code generation](crate::codegen), and stops at the first one
+//! that reports errors. The results of all the passes are kept, for the tools that need more than
+//! the assembly.
+//!
+//! ```
+//! let compiled = gb_lang::compile("static A::u8 = 1 + 2;").unwrap();
+//! assert!(compiled.warnings.is_empty());
+//! assert!(!compiled.assembly.lines.is_empty());
+//!
+//! let errors = gb_lang::compile("let a::u8 = b;").unwrap_err();
+//! assert_eq!(
+//!     "1:13: error: cannot find `b` in this scope",
+//!     errors.errors().next().unwrap().to_string(),
+//! );
+//! ```
+use crate::{
+    asm::Assembly,
+    ast::{Context, Program},
+    consts::Consts,
+    diagnostics::{Diagnostic, Diagnostics},
+    ir,
+    layout::Layouts,
+    resolve::Resolution,
+    typeck::Types,
+    Span,
+};
+
+/// Results of the passes of a program that compiled.
+#[derive(Debug)]
+pub struct Compiled<'input> {
+    pub program: Program<'input>,
+    pub resolution: Resolution,
+    pub consts: Consts,
+    pub types: Types,
+    pub layouts: Layouts,
+    pub ir: ir::Program,
+    pub assembly: Assembly,
+
+    /// Warnings reported by the passes.
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Compile a program from a single source input.
+///
+/// On failure, all the diagnostics reported up to the failing pass are returned, including
+/// warnings.
+pub fn compile(input: &str) -> Result<Compiled<'_>, Diagnostics> {
+    let mut context = Context::default();
+    match crate::ast::parse_with_context(input, &mut context) {
+        Ok(program) => compile_program(program, &mut context),
+        Err(error) => {
+            context.diagnostics.emit(error.diagnostic());
+            Err(context.diagnostics)
+        }
+    }
+}
+
+/// Compile a program that was already parsed with the given context, such as one
+/// [loaded](crate::loader) from several files.
+///
+/// Diagnostics are taken out of the context, which keeps the files to render them with.
+pub fn compile_program<'input>(
+    program: Program<'input>,
+    context: &mut Context,
+) -> Result<Compiled<'input>, Diagnostics> {
+    let resolution = crate::resolve::resolve(&program, context);
+    let consts = crate::consts::evaluate(&program, &resolution, context);
+    let types = crate::typeck::check(&program, &resolution, &consts, context);
+    let layouts = crate::layout::layout(&program, &consts, &types, context);
+    crate::asm::inline::check(&program, &resolution, &consts, &layouts, context);
+    crate::flow::check(&program, context);
+    if context.diagnostics.has_errors() {
+        return Err(std::mem::take(&mut context.diagnostics));
+    }
+
+    let ir = crate::ir::lower(&program, &resolution, &consts, &types, &layouts);
+    let assembly = match crate::codegen::generate(&ir, &layouts) {
+        Ok(assembly) => assembly,
+        Err(error) => {
+            let mut diagnostics = std::mem::take(&mut context.diagnostics);
+            diagnostics.emit(Diagnostic::error(Span::default(), error.to_string()));
+            return Err(diagnostics);
+        }
+    };
+    Ok(Compiled {
+        program,
+        resolution,
+        consts,
+        types,
+        layouts,
+        ir,
+        assembly,
+        warnings: context.diagnostics.take(),
+    })
+}
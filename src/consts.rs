@@ -0,0 +1,384 @@
+//! Compile-time evaluation of constant expressions.
+//!
+//! Constant expressions are the initializers of `const`s, the lengths of array types and the
+//! addresses of placed `static`s. They are made of number literals, references to other `const`s,
+//! parentheses, negation, the arithmetic and bitwise operators, and casts. Every intermediate
+//! value must fit in the width of the expression: the declared type of a `const` for its
+//! initializer, and `u16` for array lengths and addresses.
+//! A cast wraps its value around the width it casts to.
+//!
+//! ```
+//! use gb_lang::ast::{statements::Statement, Context};
+//!
+//! let mut context = Context::default();
+//! let program = gb_lang::ast::parse_with_context(
+//!     "const TILE_COUNT::u8 = 2 * SIZE; const SIZE::u8 = 0x40 | 4;",
+//!     &mut context,
+//! ).unwrap();
+//! let resolution = gb_lang::resolve::resolve(&program, &mut context);
+//! let consts = gb_lang::consts::evaluate(&program, &resolution, &mut context);
+//! match &program.statements[0] {
+//!     Statement::Const(const_) => assert_eq!(Some(0x88), consts.value(const_.id)),
+//!     _ => unreachable!(),
+//! }
+//! ```
+use crate::{
+    ast::{
+        expressions::{Expression, ExpressionGrammar},
+        statements::{Const, Placement, Statement},
+        types::{Array, Type, TypeGrammar},
+        visit::{self, Visit, Walk},
+        Context, NodeId, Program,
+    },
+    diagnostics::{Diagnostic, Diagnostics},
+    resolve::Resolution,
+    Span, Spanned,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+/// Width (and signedness) of an integer type.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Width {
+    U8,
+    U16,
+    I8,
+    I16,
+}
+
+impl Width {
+    /// Width of a syntactic type, or `None` if it isn't an integer type.
+    pub fn from_type(type_: &Type<'_>) -> Option<Width> {
+        match type_ {
+            Type::U8(_) => Some(Width::U8),
+            Type::U16(_) => Some(Width::U16),
+            Type::I8(_) => Some(Width::I8),
+            Type::I16(_) => Some(Width::I16),
+            _ => None,
+        }
+    }
+
+    /// Number of bits.
+    pub fn bits(self) -> u32 {
+        match self {
+            Width::U8 | Width::I8 => 8,
+            Width::U16 | Width::I16 => 16,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self, Width::I8 | Width::I16)
+    }
+
+    /// Smallest value of the width.
+    pub fn min(self) -> i64 {
+        if self.is_signed() {
+            -(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    /// Largest value of the width.
+    pub fn max(self) -> i64 {
+        if self.is_signed() {
+            (1 << (self.bits() - 1)) - 1
+        } else {
+            (1 << self.bits()) - 1
+        }
+    }
+
+    /// Whether the value is in the range of the width.
+    pub fn contains(self, value: i64) -> bool {
+        (self.min()..=self.max()).contains(&value)
+    }
+
+    /// Truncate a value to the width, wrapping it around in two's complement.
+    pub fn wrap(self, value: i64) -> i64 {
+        let value = value & ((1 << self.bits()) - 1);
+        if value > self.max() {
+            value - (1 << self.bits())
+        } else {
+            value
+        }
+    }
+}
+
+impl Display for Width {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Width::U8 => write!(f, "u8"),
+            Width::U16 => write!(f, "u16"),
+            Width::I8 => write!(f, "i8"),
+            Width::I16 => write!(f, "i16"),
+        }
+    }
+}
+
+/// Values of the constant expressions of a program.
+#[derive(Debug, Default)]
+pub struct Consts {
+    values: HashMap<NodeId, i64>,
+}
+
+impl Consts {
+    /// Value of a `const` declaration, or of a constant expression (such as an array length or
+    /// the address of a placement).
+    ///
+    /// Returns `None` if the evaluation failed, which has already been reported.
+    pub fn value(&self, id: NodeId) -> Option<i64> {
+        self.values.get(&id).copied()
+    }
+}
+
+/// Evaluate the `const`s, array lengths and placements of a program.
+///
+/// Errors are reported to the diagnostics of the context.
+pub fn evaluate(program: &Program<'_>, resolution: &Resolution, context: &mut Context) -> Consts {
+    let mut declarations = Vec::new();
+    for statement in &program.statements {
+        collect(statement, &mut declarations);
+    }
+    let mut evaluator = Evaluator {
+        resolution,
+        diagnostics: &mut context.diagnostics,
+        declarations: declarations.iter().map(|c| (c.id, *c)).collect(),
+        values: HashMap::new(),
+        evaluating: HashSet::new(),
+        consts: Consts::default(),
+    };
+    for const_ in &declarations {
+        evaluator.const_value(const_.id);
+    }
+    program.accept(&mut evaluator);
+    evaluator.consts
+}
+
+type ConstStatement<'input> = Const<'input, Type<'input>, Expression<'input>>;
+
+fn collect<'a, 'input>(
+    statement: &'a Statement<'input>,
+    out: &mut Vec<&'a ConstStatement<'input>>,
+) {
+    match statement {
+        Statement::Const(const_) => out.push(const_),
+        Statement::Fn(fn_) => fn_.body.inner.iter().for_each(|s| collect(s, out)),
+        Statement::Scope(scope) => scope.inner.iter().for_each(|s| collect(s, out)),
+        Statement::If(if_) => {
+            if_.inner.iter().for_each(|s| collect(s, out));
+            if let Some(else_) = &if_.else_ {
+                else_.inner.iter().for_each(|s| collect(s, out));
+            }
+        }
+        Statement::Loop(loop_) => loop_.inner.iter().for_each(|s| collect(s, out)),
+        Statement::While(while_) => while_.inner.iter().for_each(|s| collect(s, out)),
+        _ => {}
+    }
+}
+
+struct Evaluator<'a, 'input> {
+    resolution: &'a Resolution,
+    diagnostics: &'a mut Diagnostics,
+    declarations: HashMap<NodeId, &'a ConstStatement<'input>>,
+    // evaluated consts, `None` if the evaluation failed
+    values: HashMap<NodeId, Option<i64>>,
+    // consts being evaluated, to detect cycles
+    evaluating: HashSet<NodeId>,
+    consts: Consts,
+}
+
+impl Evaluator<'_, '_> {
+    fn const_value(&mut self, id: NodeId) -> Option<i64> {
+        if let Some(value) = self.values.get(&id) {
+            return *value;
+        }
+        let const_ = self.declarations[&id];
+        self.evaluating.insert(id);
+        let value = match Width::from_type(&const_.type_) {
+            Some(width) => self.expression(&const_.expression, width),
+            None => {
+                let diagnostic = Diagnostic::error(
+                    const_.type_.span(),
+                    "the type of a const must be an integer",
+                );
+                self.diagnostics.emit(diagnostic);
+                None
+            }
+        };
+        self.evaluating.remove(&id);
+        self.values.insert(id, value);
+        if let Some(value) = value {
+            self.consts.values.insert(id, value);
+        }
+        value
+    }
+
+    fn expression(&mut self, expression: &Expression<'_>, width: Width) -> Option<i64> {
+        match expression {
+            Expression::Number(number) => match number.number.value() {
+                Some(value) if value <= width.max() as u64 => Some(value as i64),
+                _ => self.error(
+                    expression.span(),
+                    format!("literal out of range for `{}`", width),
+                ),
+            },
+            Expression::Parenthesis(parenthesis) => self.expression(&parenthesis.inner, width),
+            Expression::Identifier(identifier) => {
+                // unbound names have already been reported by the resolver
+                let declaration = self.resolution.binding(identifier.id)?;
+                if !self.declarations.contains_key(&declaration) {
+                    let name = identifier.identifier.as_str();
+                    return self.error(expression.span(), format!("`{}` is not a const", name));
+                }
+                if self.evaluating.contains(&declaration) {
+                    let name = identifier.identifier.as_str();
+                    let span = self.declarations[&declaration].identifier.span();
+                    let diagnostic = Diagnostic::error(
+                        expression.span(),
+                        format!("cycle detected when evaluating `{}`", name),
+                    )
+                    .with_label(span, format!("`{}` declared here", name));
+                    self.diagnostics.emit(diagnostic);
+                    return None;
+                }
+                self.const_value(declaration)
+            }
+            Expression::Add(add) => {
+                let (left, right) = self.operands(&add.left, &add.right, width)?;
+                self.checked(expression, width, "add", left.checked_add(right))
+            }
+            Expression::Subtract(sub) => {
+                let (left, right) = self.operands(&sub.left, &sub.right, width)?;
+                self.checked(expression, width, "subtract", left.checked_sub(right))
+            }
+            Expression::Multiply(mul) => {
+                let (left, right) = self.operands(&mul.left, &mul.right, width)?;
+                self.checked(expression, width, "multiply", left.checked_mul(right))
+            }
+            Expression::Divide(div) => {
+                let (left, right) = self.operands(&div.left, &div.right, width)?;
+                if right == 0 {
+                    return self.error(expression.span(), "attempt to divide by zero");
+                }
+                self.checked(expression, width, "divide", left.checked_div(right))
+            }
+            Expression::BitAnd(and) => {
+                let (left, right) = self.operands(&and.left, &and.right, width)?;
+                Some(left & right)
+            }
+            Expression::BitOr(or) => {
+                let (left, right) = self.operands(&or.left, &or.right, width)?;
+                Some(left | right)
+            }
+            Expression::BitXor(xor) => {
+                let (left, right) = self.operands(&xor.left, &xor.right, width)?;
+                Some(left ^ right)
+            }
+            Expression::BitNot(not) => Some(width.wrap(!self.expression(&not.inner, width)?)),
+            Expression::Negate(negate) => {
+                let value = match &*negate.inner {
+                    // the literal of a negative number goes one further than the positive ones
+                    Expression::Number(number) => match number.number.value() {
+                        Some(value) if value <= (-width.min()) as u64 => value as i64,
+                        _ => {
+                            return self.error(
+                                expression.span(),
+                                format!("literal out of range for `{}`", width),
+                            )
+                        }
+                    },
+                    inner => self.expression(inner, width)?,
+                };
+                self.checked(expression, width, "negate", value.checked_neg())
+            }
+            Expression::True(_) => Some(1),
+            Expression::False(_) => Some(0),
+            Expression::Cast(cast) => {
+                let target = match Width::from_type(&cast.type_) {
+                    Some(target) => target,
+                    None => return self.error(expression.span(), "expression is not constant"),
+                };
+                let source = self.width(&cast.inner).unwrap_or(if target.is_signed() {
+                    Width::I16
+                } else {
+                    Width::U16
+                });
+                Some(target.wrap(self.expression(&cast.inner, source)?))
+            }
+            _ => self.error(expression.span(), "expression is not constant"),
+        }
+    }
+
+    // Width of an expression whose type doesn't depend on its context.
+    fn width(&self, expression: &Expression<'_>) -> Option<Width> {
+        match expression {
+            Expression::Parenthesis(parenthesis) => self.width(&parenthesis.inner),
+            Expression::Cast(cast) => Width::from_type(&cast.type_),
+            Expression::Identifier(identifier) => {
+                let declaration = self.resolution.binding(identifier.id)?;
+                Width::from_type(&self.declarations.get(&declaration)?.type_)
+            }
+            _ => None,
+        }
+    }
+
+    // Both operands are evaluated, so that all the errors get reported.
+    fn operands(
+        &mut self,
+        left: &Expression<'_>,
+        right: &Expression<'_>,
+        width: Width,
+    ) -> Option<(i64, i64)> {
+        let left = self.expression(left, width);
+        let right = self.expression(right, width);
+        Some((left?, right?))
+    }
+
+    fn checked(
+        &mut self,
+        expression: &Expression<'_>,
+        width: Width,
+        operation: &str,
+        value: Option<i64>,
+    ) -> Option<i64> {
+        match value {
+            Some(value) if width.contains(value) => Some(value),
+            _ => self.error(
+                expression.span(),
+                format!("attempt to {} with `{}` overflow", operation, width),
+            ),
+        }
+    }
+
+    fn error(&mut self, span: Span, message: impl Into<String>) -> Option<i64> {
+        self.diagnostics.emit(Diagnostic::error(span, message));
+        None
+    }
+}
+
+impl<'input> Visit<'input> for Evaluator<'_, 'input> {
+    fn visit_array<T: TypeGrammar<'input>>(&mut self, array: &Array<'input, T>) {
+        if let Some(length) = self.expression(&array.length, Width::U16) {
+            self.consts.values.insert(array.length.id(), length);
+        }
+        visit::walk_array(self, array);
+    }
+
+    fn visit_placement(&mut self, placement: &Placement<'input>) {
+        if let Some(address) = self.expression(&placement.address, Width::U16) {
+            self.consts.values.insert(placement.address.id(), address);
+        }
+        visit::walk_placement(self, placement);
+    }
+
+    // the expressions of consts have already been evaluated
+    fn visit_const<T, E>(&mut self, _: &Const<'input, T, E>)
+    where
+        T: TypeGrammar<'input>,
+        E: ExpressionGrammar<'input>,
+    {
+    }
+}
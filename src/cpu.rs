@@ -0,0 +1,284 @@
+//! SM83 CPU emulator.
+//!
+//! Runs machine code, like the [ROM image](crate::rom) of a program, until it halts. There is no
+//! PPU, audio, timer or interrupt: only the CPU and its memory, which is accessed through a
+//! [`Bus`]. [`Flat`] is 64 KiB of RAM, and [`Mbc`] maps the ROM and RAM banks of a cartridge the
+//! way its memory bank controller does. Instructions execute like in the
+//! [assembly interpreter](crate::asm::interp).
+//!
+//! ```
+//! use gb_lang::{
+//!     asm::parse::parse,
+//!     cpu::{Cpu, Flat},
+//!     rom::{build, Header},
+//! };
+//!
+//! let assembly = parse("ld a, $2A\nld [$C000], a\nhalt").unwrap();
+//! let rom = build(&assembly, &Header::default()).unwrap();
+//! let mut cpu = Cpu::new(Flat::new(&rom.bytes));
+//! cpu.run(100).unwrap();
+//! assert_eq!(0x2a, cpu.bus.memory[0xc000]);
+//! ```
+use crate::{
+    asm::{
+        decode::decode,
+        interp::{Core, Error as InterpError},
+        Value,
+    },
+    rom::{self, Cartridge, RamSize},
+};
+
+pub use crate::asm::interp::{Bus, Registers};
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("invalid instruction at ${address:04X} (opcode ${opcode:02X})")]
+    InvalidInstruction { address: u16, opcode: u8 },
+
+    #[error("didn't halt after {0} instructions")]
+    Timeout(u64),
+}
+
+/// SM83 CPU, with its memory.
+pub struct Cpu<B> {
+    pub registers: Registers,
+
+    /// Address of the next instruction.
+    pub pc: u16,
+    pub bus: B,
+    halted: bool,
+}
+
+impl<B: Bus> Cpu<B> {
+    /// CPU in the state the DMG boot ROM leaves it in, about to run the [entry point](rom::ENTRY)
+    /// of the cartridge.
+    pub fn new(bus: B) -> Self {
+        Cpu {
+            registers: Registers {
+                a: 0x01,
+                f: 0xb0,
+                b: 0x00,
+                c: 0x13,
+                d: 0x00,
+                e: 0xd8,
+                h: 0x01,
+                l: 0x4d,
+                sp: 0xfffe,
+            },
+            pc: rom::ENTRY,
+            bus,
+            halted: false,
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Run until the CPU halts, executing at most `limit` instructions. Returns the number of
+    /// instructions executed.
+    pub fn run(&mut self, limit: u64) -> Result<u64, Error> {
+        let mut count = 0;
+        while !self.halted {
+            if count == limit {
+                return Err(Error::Timeout(limit));
+            }
+            self.step()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Execute one instruction.
+    pub fn step(&mut self) -> Result<(), Error> {
+        let address = self.pc;
+        let bytes = [0, 1, 2].map(|i| self.bus.read(address.wrapping_add(i)));
+        let instruction = decode(&bytes, address).ok_or(Error::InvalidInstruction {
+            address,
+            opcode: bytes[0],
+        })?;
+        self.pc = address.wrapping_add(instruction.size());
+        let mut core = Core {
+            registers: &mut self.registers,
+            bus: &mut self.bus,
+            pc: &mut self.pc,
+            halted: &mut self.halted,
+        };
+        core.execute(&instruction, |value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(label) => Err(InterpError::UndefinedLabel(label.clone())),
+        })
+        .expect("Expected decoded instructions to only use numbers");
+        Ok(())
+    }
+}
+
+/// 64 KiB of RAM, starting with the bytes of a program.
+pub struct Flat {
+    pub memory: Box<[u8; 0x10000]>,
+}
+
+impl Flat {
+    /// Memory with a program at address `$0000` (cut off at the end of the address space).
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut memory = Box::new([0; 0x10000]);
+        let len = bytes.len().min(memory.len());
+        memory[..len].copy_from_slice(&bytes[..len]);
+        Flat { memory }
+    }
+}
+
+impl Bus for Flat {
+    fn read(&self, address: u16) -> u8 {
+        self.memory[usize::from(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[usize::from(address)] = value;
+    }
+}
+
+// Size of a bank of cartridge RAM.
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Memory of a cartridge with a memory bank controller, and of the rest of the Game Boy.
+///
+/// Writes to the ROM set the registers of the controller: `$0A` in `$0000..=$1FFF` enables the
+/// cartridge RAM, `$2000..=$3FFF` selects the ROM bank at `$4000..=$7FFF` and `$4000..=$5FFF`
+/// the RAM bank at `$A000..=$BFFF` (or, on MBC1, the upper bits of the ROM bank). The RTC
+/// registers of the MBC3 read as `$FF`. Echo RAM mirrors WRAM.
+pub struct Mbc {
+    cartridge: Cartridge,
+    rom: Vec<u8>,
+
+    /// Cartridge RAM, every bank.
+    pub ram: Vec<u8>,
+
+    /// Memory outside of the cartridge: VRAM, WRAM, OAM, IO registers and HRAM.
+    pub memory: Box<[u8; 0x10000]>,
+    ram_enabled: bool,
+    // ROM bank register (the lower 5 bits of the bank on MBC1)
+    rom_bank: u16,
+    // RAM bank register (the upper 2 bits of the ROM bank on MBC1)
+    ram_bank: u8,
+    // MBC1 banking mode: whether the RAM bank register also applies to `$0000..=$3FFF` and RAM
+    advanced: bool,
+}
+
+impl Mbc {
+    pub fn new(rom: Vec<u8>, cartridge: Cartridge, ram_size: RamSize) -> Self {
+        let ram = vec![0; usize::from(ram_size.banks()) * RAM_BANK_SIZE];
+        Mbc {
+            cartridge,
+            rom,
+            ram,
+            memory: Box::new([0; 0x10000]),
+            ram_enabled: cartridge == Cartridge::RomOnly,
+            rom_bank: 1,
+            ram_bank: 0,
+            advanced: false,
+        }
+    }
+
+    /// ROM bank mapped at `$4000..=$7FFF`.
+    pub fn rom_bank(&self) -> usize {
+        let bank = usize::from(self.rom_bank);
+        match self.cartridge {
+            Cartridge::RomOnly => 1,
+            Cartridge::Mbc1 { .. } => usize::from(self.ram_bank) << 5 | bank.max(1),
+            Cartridge::Mbc3 { .. } => bank.max(1),
+            Cartridge::Mbc5 { .. } => bank,
+        }
+    }
+
+    /// RAM bank mapped at `$A000..=$BFFF`.
+    pub fn ram_bank(&self) -> usize {
+        match self.cartridge {
+            Cartridge::Mbc1 { .. } if !self.advanced => 0,
+            _ => usize::from(self.ram_bank),
+        }
+    }
+
+    // ROM bank mapped at `$0000..=$3FFF`.
+    fn rom0_bank(&self) -> usize {
+        match self.cartridge {
+            Cartridge::Mbc1 { .. } if self.advanced => usize::from(self.ram_bank) << 5,
+            _ => 0,
+        }
+    }
+
+    fn read_rom(&self, bank: usize, offset: u16) -> u8 {
+        if self.rom.is_empty() {
+            return rom::PADDING;
+        }
+        self.rom[(bank * rom::BANK_SIZE + usize::from(offset)) % self.rom.len()]
+    }
+
+    // Index of an address of `$A000..=$BFFF` in the cartridge RAM, if it is mapped.
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        let rtc = matches!(self.cartridge, Cartridge::Mbc3 { .. }) && self.ram_bank > 3;
+        if !self.ram_enabled || self.ram.is_empty() || rtc {
+            return None;
+        }
+        let index = self.ram_bank() * RAM_BANK_SIZE + usize::from(address - 0xa000);
+        Some(index % self.ram.len())
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match (self.cartridge, address) {
+            (Cartridge::RomOnly, _) => {}
+            (_, 0x0000..=0x1fff) => self.ram_enabled = value & 0x0f == 0x0a,
+            (Cartridge::Mbc5 { .. }, 0x2000..=0x2fff) => {
+                self.rom_bank = self.rom_bank & 0x100 | u16::from(value);
+            }
+            (Cartridge::Mbc5 { .. }, 0x3000..=0x3fff) => {
+                self.rom_bank = self.rom_bank & 0xff | u16::from(value & 1) << 8;
+            }
+            (Cartridge::Mbc1 { .. }, 0x2000..=0x3fff) => self.rom_bank = u16::from(value & 0x1f),
+            (_, 0x2000..=0x3fff) => self.rom_bank = u16::from(value & 0x7f),
+            (Cartridge::Mbc1 { .. }, 0x4000..=0x5fff) => self.ram_bank = value & 0x03,
+            // the rumble motor takes bit 3
+            (Cartridge::Mbc5 { rumble: true, .. }, 0x4000..=0x5fff) => {
+                self.ram_bank = value & 0x07;
+            }
+            (_, 0x4000..=0x5fff) => self.ram_bank = value & 0x0f,
+            (Cartridge::Mbc1 { .. }, _) => self.advanced = value & 1 != 0,
+            // latching the clock of the MBC3 isn't emulated
+            _ => {}
+        }
+    }
+}
+
+// Address in WRAM of an address in echo RAM.
+fn mirror(address: u16) -> u16 {
+    match address {
+        0xe000..=0xfdff => address - 0x2000,
+        _ => address,
+    }
+}
+
+impl Bus for Mbc {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3fff => self.read_rom(self.rom0_bank(), address),
+            0x4000..=0x7fff => self.read_rom(self.rom_bank(), address - 0x4000),
+            0xa000..=0xbfff => match self.ram_index(address) {
+                Some(index) => self.ram[index],
+                None => 0xff,
+            },
+            _ => self.memory[usize::from(mirror(address))],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7fff => self.write_register(address, value),
+            0xa000..=0xbfff => {
+                if let Some(index) = self.ram_index(address) {
+                    self.ram[index] = value;
+                }
+            }
+            _ => self.memory[usize::from(mirror(address))] = value,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Debug information of linked programs.
+//!
+//! [`sym`] writes the symbol file (`.sym`) that emulators like BGB, SameBoy and Emulicious load
+//! next to a ROM: one `bank:address name` line per label (functions, statics, and the labels of
+//! hand-written assembly). [`source_map`] writes the span of the source every range of ROM bytes
+//! was generated from, one `bank:start-end path:line:column-line:column` line per range (with
+//! inclusive ends). The path of the file is left out for spans of unknown files, such as the ones
+//! of programs compiled from a single input.
+//!
+//! ```
+//! use gb_lang::{
+//!     asm::parse::parse,
+//!     debug::sym,
+//!     link::{link, Kind, Section},
+//!     rom::Header,
+//! };
+//!
+//! let main = parse("__init:\n    halt").unwrap();
+//! let linked = link(&[Section::new("main", Kind::Rom0, main)], &Header::default()).unwrap();
+//! assert_eq!("00:0150 __init\n", sym(&linked.symbols));
+//! ```
+use crate::{
+    link::{Mapping, Symbol},
+    loader::Files,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Symbol file of the labels of a program, by bank and address.
+pub fn sym(symbols: &BTreeMap<String, Symbol>) -> String {
+    let mut symbols: Vec<_> = symbols.iter().collect();
+    symbols.sort_by_key(|(name, symbol)| (symbol.bank, symbol.address, *name));
+    let mut output = String::new();
+    for (name, symbol) in symbols {
+        writeln!(
+            output,
+            "{:02X}:{:04X} {}",
+            symbol.bank.number, symbol.address, name
+        )
+        .expect("Expected to write to a string");
+    }
+    output
+}
+
+/// Map from ranges of ROM addresses to the spans of the source they were generated from, in the
+/// given files.
+pub fn source_map(mappings: &[Mapping], files: &Files) -> String {
+    let mut output = String::new();
+    for mapping in mappings {
+        let end = mapping.address + (mapping.size - 1);
+        let [min, max] = [mapping.span.min, mapping.span.max];
+        let path = match files.get(mapping.span.file) {
+            Some(file) => format!("{}:", file.path.display()),
+            None => String::new(),
+        };
+        writeln!(
+            output,
+            "{:02X}:{:04X}-{:04X} {}{}:{}-{}:{}",
+            mapping.bank.number, mapping.address, end, path, min[0], min[1], max[0], max[1]
+        )
+        .expect("Expected to write to a string");
+    }
+    output
+}
@@ -0,0 +1,124 @@
+use crate::{loader::Files, Span};
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+/// Severity of a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// Message about the input source, reported by any of the compiler passes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+
+    /// Primary location of the diagnostic.
+    pub span: Span,
+
+    /// Secondary locations, each with a short description.
+    pub labels: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warning,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.labels.push((span, label.into()));
+        self
+    }
+
+    /// Like the [`Display`] output, but every location starts with the path of its file
+    /// (`path:line:column`), for programs [loaded](crate::loader) from several files. Locations in
+    /// files that aren't in `files` have no path.
+    pub fn render(&self, files: &Files) -> String {
+        let location = |span: &Span| {
+            let [line, column] = span.min;
+            match files.get(span.file) {
+                Some(file) => format!("{}:{}:{}", file.path.display(), line, column),
+                None => format!("{}:{}", line, column),
+            }
+        };
+        self.write(location)
+    }
+
+    fn write(&self, location: impl Fn(&Span) -> String) -> String {
+        let level = match self.level {
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        let mut output = format!("{}: {}: {}", location(&self.span), level, self.message);
+        for (span, label) in &self.labels {
+            output.push_str(&format!("\n  {}: {}", location(span), label));
+        }
+        output
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let location = |span: &Span| format!("{}:{}", span.min[0], span.min[1]);
+        f.write_str(&self.write(location))
+    }
+}
+
+/// Diagnostics sink.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|d| d.level == Level::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|d| d.level == Level::Warning)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Remove and return all the emitted diagnostics.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
@@ -0,0 +1,113 @@
+//! Control flow checks.
+//!
+//! Rejects `break` and `continue` outside of a `loop` or `while`, and `return` outside of a
+//! `fn`. Using the [control-flow graph](crate::cfg) of every body, it also warns about statements
+//! that can never run, and rejects functions with a return type that can run past the end of
+//! their body.
+//!
+//! ```
+//! use gb_lang::ast::Context;
+//!
+//! let mut context = Context::default();
+//! let input = "fn f(a::u8) :: u8 { if a { return 1; } }";
+//! let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+//! gb_lang::flow::check(&program, &mut context);
+//! let error = context.diagnostics.errors().next().unwrap();
+//! assert_eq!(
+//!     "1:4: error: function `f` doesn't return a value on all paths\n  1:40: the function can reach its end here",
+//!     error.to_string(),
+//! );
+//! ```
+use crate::{
+    ast::{
+        statements::Statement,
+        visit::{self, Visit, Walk},
+        Context, Program,
+    },
+    cfg::Cfg,
+    diagnostics::{Diagnostic, Diagnostics},
+    Spanned,
+};
+
+/// Check the control flow of a program.
+///
+/// Errors and warnings are reported to the diagnostics of the context.
+pub fn check(program: &Program<'_>, context: &mut Context) {
+    let mut checker = Checker {
+        diagnostics: &mut context.diagnostics,
+        loops: 0,
+        fns: 0,
+    };
+    checker.unreachable(&Cfg::build(&program.statements));
+    program.accept(&mut checker);
+}
+
+struct Checker<'a> {
+    diagnostics: &'a mut Diagnostics,
+    // number of enclosing loops (within the enclosing function) and functions
+    loops: usize,
+    fns: usize,
+}
+
+impl Checker<'_> {
+    // Warn once about every stretch of statements that follows a diverging statement.
+    fn unreachable(&mut self, cfg: &Cfg<'_, '_>) {
+        for (id, block) in cfg.blocks() {
+            if let (Some(span), Some(after), false) =
+                (block.span, block.after, cfg.is_reachable(id))
+            {
+                let diagnostic = Diagnostic::warning(span, "unreachable statement")
+                    .with_label(after, "any code following this is unreachable");
+                self.diagnostics.emit(diagnostic);
+            }
+        }
+    }
+}
+
+impl<'input> Visit<'input> for Checker<'_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        match statement {
+            Statement::Fn(fn_) => {
+                let cfg = Cfg::build(&fn_.body.inner);
+                self.unreachable(&cfg);
+                if fn_.return_type.is_some() && cfg.is_reachable(cfg.exit()) {
+                    let message = format!(
+                        "function `{}` doesn't return a value on all paths",
+                        fn_.identifier.as_str()
+                    );
+                    let diagnostic = Diagnostic::error(fn_.identifier.span(), message).with_label(
+                        fn_.body.curly_right.span(),
+                        "the function can reach its end here",
+                    );
+                    self.diagnostics.emit(diagnostic);
+                }
+                let loops = std::mem::replace(&mut self.loops, 0);
+                self.fns += 1;
+                visit::walk_statement(self, statement);
+                self.fns -= 1;
+                self.loops = loops;
+            }
+            Statement::Loop(_) | Statement::While(_) => {
+                self.loops += 1;
+                visit::walk_statement(self, statement);
+                self.loops -= 1;
+            }
+            Statement::Break(_) if self.loops == 0 => {
+                let diagnostic = Diagnostic::error(statement.span(), "`break` outside of a loop");
+                self.diagnostics.emit(diagnostic);
+            }
+            Statement::Continue(_) if self.loops == 0 => {
+                let diagnostic =
+                    Diagnostic::error(statement.span(), "`continue` outside of a loop");
+                self.diagnostics.emit(diagnostic);
+            }
+            Statement::Return(_) if self.fns == 0 => {
+                let diagnostic =
+                    Diagnostic::error(statement.span(), "`return` outside of a function");
+                self.diagnostics.emit(diagnostic);
+                visit::walk_statement(self, statement);
+            }
+            _ => visit::walk_statement(self, statement),
+        }
+    }
+}
@@ -0,0 +1,521 @@
+//! Canonical formatting of gb-lang source.
+//!
+//! ```
+//! let program = gb_lang::parse("let  foo::array< u8,4 > =bar ;").unwrap();
+//! let formatted = gb_lang::fmt::format(&program, &Default::default());
+//! assert_eq!("let foo::array<u8, 4> = bar;\n", formatted);
+//! ```
+use crate::{
+    ast::{
+        expressions::Expression,
+        statements::{Asm, Else, If, Scope, Statement},
+        types::{Field, Type},
+        Program, Punctuated,
+    },
+    lex::{tokens, Token, Tokenizer},
+    Spanned,
+};
+use std::collections::VecDeque;
+
+/// Formatting options.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Maximum width of a line, before struct and union types are broken into multiple lines.
+    pub max_width: usize,
+
+    /// Number of spaces of each indentation level.
+    pub indent: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_width: 100,
+            indent: 4,
+        }
+    }
+}
+
+/// Format a program.
+///
+/// The syntax tree doesn't hold any comments, so they are lost. Use [`format_with_trivia`] to
+/// keep them.
+pub fn format(program: &Program<'_>, options: &Options) -> String {
+    let mut printer = Printer::new(options, VecDeque::new());
+    printer.program(program);
+    printer.out
+}
+
+/// Format a program, keeping the comments of a trivia-preserving token stream.
+///
+/// The token stream must come from [`crate::lex::tokenize_with_trivia`] over the same input
+/// the program was parsed from. Expressions are written on a single line, so the comments inside
+/// them are moved to the lines after their statement (or to the start of the body, for the
+/// conditions of `if`s and `while`s).
+pub fn format_with_trivia<'input>(
+    program: &Program<'input>,
+    tokens: Tokenizer<'input>,
+    options: &Options,
+) -> String {
+    let comments = tokens
+        .map_while(Result::ok)
+        .filter_map(|token| match token {
+            Token::Comment(comment) => Some(comment),
+            _ => None,
+        })
+        .collect();
+    let mut printer = Printer::new(options, comments);
+    printer.program(program);
+    printer.out
+}
+
+struct Printer<'a, 'input> {
+    options: &'a Options,
+    // pending comments, in source order
+    comments: VecDeque<tokens::Comment<'input>>,
+    out: String,
+    indent: usize,
+    column: usize,
+    // last source line emitted within the current block
+    last_line: Option<usize>,
+}
+
+impl<'a, 'input> Printer<'a, 'input> {
+    fn new(options: &'a Options, comments: VecDeque<tokens::Comment<'input>>) -> Self {
+        Self {
+            options,
+            comments,
+            out: String::new(),
+            indent: 0,
+            column: 0,
+            last_line: None,
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        if self.column == 0 && !s.is_empty() {
+            let indent = self.indent * self.options.indent;
+            self.out.extend(std::iter::repeat_n(' ', indent));
+            self.column = indent;
+        }
+        self.out.push_str(s);
+        self.column += s.chars().count();
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.column = 0;
+    }
+
+    // Keep (at most) one blank line between items that were separated by blank lines.
+    fn separate(&mut self, line: usize) {
+        if matches!(self.last_line, Some(last) if line > last + 1) {
+            self.newline();
+        }
+    }
+
+    /// Emit the comments that come before the given `[line, column]` location, each on its own
+    /// line.
+    fn comments_before(&mut self, location: [usize; 2]) {
+        while matches!(self.comments.front(), Some(c) if c.span().min < location) {
+            let comment = self.comments.pop_front().unwrap();
+            self.separate(comment.span().min[0]);
+            self.write(comment.as_str().trim_end());
+            self.newline();
+            // comments moved out of an item come from lines before its end
+            let line = comment.span().max[0];
+            self.last_line = Some(self.last_line.map_or(line, |last| last.max(line)));
+        }
+    }
+
+    /// Emit the comment that follows an item on its last line. The comments still pending from
+    /// earlier lines are inside the item, and are left for the lines after it.
+    fn trailing_comment(&mut self, line: usize) {
+        let inner = self
+            .comments
+            .iter()
+            .take_while(|c| c.span().min[0] < line)
+            .count();
+        if matches!(self.comments.get(inner), Some(c) if c.span().min[0] == line) {
+            let comment = self.comments.remove(inner).unwrap();
+            self.write(" ");
+            self.write(comment.as_str().trim_end());
+        }
+    }
+
+    fn has_comments_before(&self, location: [usize; 2]) -> bool {
+        matches!(self.comments.front(), Some(c) if c.span().min < location)
+    }
+
+    fn program(&mut self, program: &Program<'input>) {
+        self.statements(&program.statements);
+        self.comments_before([usize::MAX; 2]);
+    }
+
+    fn statements(&mut self, statements: &[Statement<'input>]) {
+        for statement in statements {
+            let span = statement.span();
+            self.comments_before(span.min);
+            self.separate(span.min[0]);
+            self.statement(statement);
+            self.trailing_comment(span.max[0]);
+            self.newline();
+            self.last_line = Some(span.max[0]);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement<'input>) {
+        match statement {
+            Statement::Let(let_) => {
+                self.write("let ");
+                self.write(let_.identifier.as_str());
+                self.write("::");
+                self.type_(&let_.type_);
+                self.write(" = ");
+                self.expression(&let_.expression);
+                self.write(";");
+            }
+            Statement::Const(const_) => {
+                self.write("const ");
+                self.write(const_.identifier.as_str());
+                self.write("::");
+                self.type_(&const_.type_);
+                self.write(" = ");
+                self.expression(&const_.expression);
+                self.write(";");
+            }
+            Statement::Static(static_) => {
+                self.write("static ");
+                self.write(static_.identifier.as_str());
+                match &static_.placement {
+                    Some(placement) => {
+                        self.write(" @ ");
+                        self.expression(&placement.address);
+                        self.write(" :: ");
+                    }
+                    None => self.write("::"),
+                }
+                self.type_(&static_.type_);
+                if let Some(initializer) = &static_.initializer {
+                    self.write(" = ");
+                    self.expression(&initializer.expression);
+                }
+                self.write(";");
+            }
+            Statement::TypeAlias(alias) => {
+                self.write("type ");
+                self.write(alias.identifier.as_str());
+                self.write(" = ");
+                self.type_(&alias.definition);
+                self.write(";");
+            }
+            Statement::Fn(fn_) => {
+                self.write("fn ");
+                self.write(fn_.identifier.as_str());
+                self.write("(");
+                for (i, param) in fn_.params.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.write(param.identifier.as_str());
+                    self.write("::");
+                    self.type_(&param.type_);
+                }
+                self.write(")");
+                if let Some(return_type) = &fn_.return_type {
+                    self.write(" :: ");
+                    self.type_(&return_type.type_);
+                }
+                self.write(" ");
+                self.scope(&fn_.body);
+            }
+            Statement::Scope(scope) => self.scope(scope),
+            Statement::If(if_) => self.if_(if_),
+            Statement::Loop(loop_) => {
+                self.write("loop");
+                self.body(&loop_.inner);
+            }
+            Statement::While(while_) => {
+                self.write("while ");
+                self.expression(&while_.expression);
+                self.body(&while_.inner);
+            }
+            Statement::Continue(_) => self.write("continue;"),
+            Statement::Break(_) => self.write("break;"),
+            Statement::Return(return_) => match &return_.expression {
+                Some(expression) => {
+                    self.write("return ");
+                    self.expression(expression);
+                    self.write(";");
+                }
+                None => self.write("return;"),
+            },
+            Statement::Asm(asm) => self.asm(asm),
+            Statement::Import(import) => {
+                self.write("import ");
+                self.write(import.path.as_str());
+                if let Some(alias) = &import.alias {
+                    self.write(" as ");
+                    self.write(alias.identifier.as_str());
+                }
+                self.write(";");
+            }
+            Statement::Use(use_) => {
+                self.write("use ");
+                self.write(use_.module.as_str());
+                self.write("::");
+                self.write(use_.identifier.as_str());
+                self.write(";");
+            }
+            Statement::Expression(statement) => {
+                self.expression(&statement.expression);
+                self.write(";");
+            }
+            Statement::Assign(assign) => {
+                self.expression(&assign.place);
+                self.write(" = ");
+                self.expression(&assign.expression);
+                self.write(";");
+            }
+        }
+    }
+
+    // One line of assembly per line, without the blank ones.
+    fn asm(&mut self, asm: &Asm<'input>) {
+        let mut lines = asm
+            .block
+            .contents()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .peekable();
+        if lines.peek().is_none() {
+            self.write("asm {}");
+            return;
+        }
+        self.write("asm {");
+        self.newline();
+        self.indent += 1;
+        for line in lines {
+            self.write(line);
+            self.newline();
+        }
+        self.indent -= 1;
+        self.write("}");
+    }
+
+    fn if_(&mut self, if_: &If<'input, Expression<'input>, Vec<Statement<'input>>>) {
+        self.write("if ");
+        self.expression(&if_.expression);
+        self.body(&if_.inner);
+        if let Some(Else { inner, .. }) = &if_.else_ {
+            if let [Statement::Scope(_)] = if_.inner[..] {
+                self.write(" ");
+            } else {
+                self.newline();
+            }
+            self.write("else");
+            match &inner[..] {
+                [Statement::If(if_)] => {
+                    self.write(" ");
+                    self.if_(if_);
+                }
+                inner => self.body(inner),
+            }
+        }
+    }
+
+    // Scopes open on the same line, any other statement goes on its own (indented) line.
+    fn body(&mut self, body: &[Statement<'input>]) {
+        if let [Statement::Scope(scope)] = body {
+            self.write(" ");
+            self.scope(scope);
+        } else {
+            self.indent += 1;
+            for statement in body {
+                self.newline();
+                self.comments_before(statement.span().min);
+                self.statement(statement);
+            }
+            self.indent -= 1;
+        }
+    }
+
+    fn scope(&mut self, scope: &Scope<'input, Vec<Statement<'input>>>) {
+        let end = scope.curly_right.span().min;
+        if scope.inner.is_empty() && !self.has_comments_before(end) {
+            self.write("{}");
+            return;
+        }
+        let last_line = self.last_line.take();
+        self.write("{");
+        self.newline();
+        self.indent += 1;
+        self.statements(&scope.inner);
+        self.comments_before(end);
+        self.indent -= 1;
+        self.write("}");
+        self.last_line = last_line;
+    }
+
+    fn type_(&mut self, type_: &Type<'input>) {
+        match type_ {
+            Type::U8(_) => self.write("u8"),
+            Type::U16(_) => self.write("u16"),
+            Type::I8(_) => self.write("i8"),
+            Type::I16(_) => self.write("i16"),
+            Type::Bool(_) => self.write("bool"),
+            Type::Array(array) => {
+                self.write("array<");
+                self.type_(&array.type_);
+                self.write(", ");
+                self.expression(&array.length);
+                self.write(">");
+            }
+            Type::Ptr(ptr) => {
+                self.write("ptr<");
+                self.type_(&ptr.type_);
+                self.write(">");
+            }
+            Type::Struct(struct_) => {
+                self.fields("struct", &struct_.fields, struct_.curly_right.span().min)
+            }
+            Type::Union(union) => self.fields("union", &union.fields, union.curly_right.span().min),
+            Type::Named(named) => self.write(named.identifier.as_str()),
+        }
+    }
+
+    // Fields go on a single line if they fit, otherwise one per line with a trailing comma.
+    fn fields(
+        &mut self,
+        keyword: &str,
+        fields: &Punctuated<Field<'input, Type<'input>>, tokens::Comma<'input>>,
+        end: [usize; 2],
+    ) {
+        self.write(keyword);
+        if fields.is_empty() && !self.has_comments_before(end) {
+            self.write(" {}");
+            return;
+        }
+        let mut inline = Printer::new(self.options, VecDeque::new());
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                inline.write(", ");
+            }
+            inline.field(field);
+        }
+        let width = self.column + " {  }".len() + inline.out.chars().count();
+        if width <= self.options.max_width && !self.has_comments_before(end) {
+            self.write(" { ");
+            self.write(&inline.out);
+            self.write(" }");
+            return;
+        }
+        let last_line = self.last_line.take();
+        self.write(" {");
+        self.newline();
+        self.indent += 1;
+        for field in fields.iter() {
+            let span = field.span();
+            self.comments_before(span.min);
+            self.separate(span.min[0]);
+            self.field(field);
+            self.write(",");
+            self.trailing_comment(span.max[0]);
+            self.newline();
+            self.last_line = Some(span.max[0]);
+        }
+        self.comments_before(end);
+        self.indent -= 1;
+        self.write("}");
+        self.last_line = last_line;
+    }
+
+    fn field(&mut self, field: &Field<'input, Type<'input>>) {
+        self.write(field.identifier.as_str());
+        self.write("::");
+        self.type_(&field.type_);
+    }
+
+    fn expression(&mut self, expression: &Expression<'input>) {
+        match expression {
+            Expression::Parenthesis(parenthesis) => {
+                self.write("(");
+                self.expression(&parenthesis.inner);
+                self.write(")");
+            }
+            Expression::Index(index) => {
+                self.expression(&index.indexable);
+                self.write("[");
+                self.expression(&index.index);
+                self.write("]");
+            }
+            Expression::FieldAccess(access) => {
+                self.expression(&access.inner);
+                self.write(".");
+                self.write(access.field.as_str());
+            }
+            Expression::Call(call) => {
+                self.expression(&call.callable);
+                self.write("(");
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expression(argument);
+                }
+                self.write(")");
+            }
+            Expression::Number(number) => self.write(number.number.as_str()),
+            Expression::Str(str) => self.write(str.str.as_str()),
+            Expression::True(_) => self.write("true"),
+            Expression::False(_) => self.write("false"),
+            Expression::Identifier(identifier) => self.write(identifier.identifier.as_str()),
+            Expression::Add(add) => self.binary(&add.left, "+", &add.right),
+            Expression::Subtract(sub) => self.binary(&sub.left, "-", &sub.right),
+            Expression::Multiply(mul) => self.binary(&mul.left, "*", &mul.right),
+            Expression::Divide(div) => self.binary(&div.left, "/", &div.right),
+            Expression::BitAnd(and) => self.binary(&and.left, "&", &and.right),
+            Expression::BitOr(or) => self.binary(&or.left, "|", &or.right),
+            Expression::BitXor(xor) => self.binary(&xor.left, "^", &xor.right),
+            Expression::Equal(eq) => self.binary(&eq.left, "==", &eq.right),
+            Expression::NotEqual(ne) => self.binary(&ne.left, "~=", &ne.right),
+            Expression::Less(lt) => self.binary(&lt.left, "<", &lt.right),
+            Expression::LessEqual(le) => self.binary(&le.left, "<=", &le.right),
+            Expression::Greater(gt) => self.binary(&gt.left, ">", &gt.right),
+            Expression::GreaterEqual(ge) => self.binary(&ge.left, ">=", &ge.right),
+            Expression::BitNot(not) => {
+                self.write("~");
+                self.expression(&not.inner);
+            }
+            Expression::Negate(negate) => {
+                self.write("-");
+                self.expression(&negate.inner);
+            }
+            Expression::Addr(addr) => {
+                self.write(addr.addr.as_str());
+                self.write("(");
+                self.expression(&addr.inner);
+                self.write(")");
+            }
+            Expression::Deref(deref) => {
+                self.write("deref(");
+                self.expression(&deref.inner);
+                self.write(")");
+            }
+            Expression::Cast(cast) => {
+                self.expression(&cast.inner);
+                self.write(" as ");
+                self.type_(&cast.type_);
+            }
+        }
+    }
+
+    fn binary(&mut self, left: &Expression<'input>, operator: &str, right: &Expression<'input>) {
+        self.expression(left);
+        self.write(" ");
+        self.write(operator);
+        self.write(" ");
+        self.expression(right);
+    }
+}
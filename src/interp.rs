@@ -0,0 +1,588 @@
+//! Interpreter of checked programs.
+//!
+//! Runs a program straight from its syntax tree, with the semantics of the generated code: a flat
+//! 64 KiB address space, variables (`static`s, `let`s and parameters) at the addresses they were
+//! [laid out](crate::layout) at (the [frame](Layouts::frame) of a reentered function is given back
+//! when it returns), and arithmetic that wraps around the size of its type. Like in the
+//! [IR](crate::ir), the initializers of every `static` run first, then the top-level statements.
+//! Values of aggregate types are handled by address, string literals are stored from
+//! [`DATA_START`], and inline assembly runs on the [assembly interpreter](crate::asm::interp) (with
+//! its own registers, and without calls to functions).
+//!
+//! It makes a reference for the [code generator](crate::codegen), and runs game logic without
+//! building a ROM.
+//!
+//! ```
+//! use gb_lang::interp::Interpreter;
+//!
+//! let input = "static A::u8 = 0; fn add(a::u8, b::u8) :: u8 { return a + b; } static B::u8 = add(A, 3);";
+//! let compiled = gb_lang::compile(input).unwrap();
+//! let mut interpreter = Interpreter::new(
+//!     &compiled.program,
+//!     &compiled.resolution,
+//!     &compiled.consts,
+//!     &compiled.types,
+//!     &compiled.layouts,
+//! );
+//! interpreter.run(1000).unwrap();
+//! assert_eq!(Some(&[3][..]), interpreter.variable("B"));
+//! assert_eq!(Ok(Some(0x01)), interpreter.call("add", &[0xff, 2], 1000));
+//! ```
+use crate::{
+    asm::{self, inline, Assembly, Instruction},
+    ast::{expressions::Expression, statements::Statement, NodeId, Program},
+    checked::{self, collect, Checked, FnStatement, StaticStatement},
+    consts::Consts,
+    ir::Size,
+    layout::{Layouts, STACK_SIZE},
+    resolve::{DeclarationKind, Resolution},
+    typeck::{Ty, Types},
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Address of the first string literal.
+pub const DATA_START: u16 = asm::interp::DATA_START;
+
+/// Deepest nesting of calls: every call takes at least the two bytes of its return address on
+/// the stack of the generated code.
+pub const MAX_DEPTH: usize = STACK_SIZE as usize / 2;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("didn't finish after {0} steps")]
+    Timeout(u64),
+
+    #[error("calls nested more than {MAX_DEPTH} deep")]
+    StackOverflow,
+
+    #[error("no function named `{0}`")]
+    UndefinedFunction(String),
+
+    #[error("`{name}` takes {expected} argument(s) but {found} were given")]
+    Arguments {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("inline assembly: {0}")]
+    Asm(#[from] asm::interp::Error),
+}
+
+// How control leaves a statement.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<u16>),
+}
+
+/// Interpreter of a checked program.
+pub struct Interpreter<'a, 'input> {
+    pub memory: Box<[u8; 0x10000]>,
+    program: &'a Program<'input>,
+    checked: Checked<'a>,
+    fns: Vec<&'a FnStatement<'input>>,
+    functions: HashMap<NodeId, &'a FnStatement<'input>>,
+    statics: Vec<&'a StaticStatement<'input>>,
+    // addresses of the string literals evaluated so far, and of the next one
+    data: HashMap<NodeId, u16>,
+    next_data: u16,
+    steps: u64,
+    limit: u64,
+    // functions being run, innermost last
+    calls: Vec<NodeId>,
+}
+
+impl<'a, 'input> Interpreter<'a, 'input> {
+    /// Load a program, with its memory cleared.
+    ///
+    /// The program must have been resolved, type checked, laid out and had its
+    /// [inline assembly](inline::check) checked without errors: running a program with errors
+    /// panics.
+    pub fn new(
+        program: &'a Program<'input>,
+        resolution: &'a Resolution,
+        consts: &'a Consts,
+        types: &'a Types,
+        layouts: &'a Layouts,
+    ) -> Self {
+        let mut fns = Vec::new();
+        let mut statics = Vec::new();
+        for statement in &program.statements {
+            collect(statement, &mut fns, &mut statics);
+        }
+        Interpreter {
+            memory: Box::new([0; 0x10000]),
+            program,
+            checked: Checked {
+                resolution,
+                consts,
+                types,
+                layouts,
+            },
+            functions: fns.iter().map(|fn_| (fn_.id, *fn_)).collect(),
+            fns,
+            statics,
+            data: HashMap::new(),
+            next_data: DATA_START,
+            steps: 0,
+            limit: 0,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Run the initializers of the `static`s, then the top-level statements, taking at most
+    /// `limit` steps (one per statement and per loop condition). Returns the number of steps
+    /// taken.
+    pub fn run(&mut self, limit: u64) -> Result<u64, Error> {
+        self.start(limit);
+        for static_ in self.statics.clone() {
+            if let Some(initializer) = &static_.initializer {
+                self.step()?;
+                let address = self.checked.address(static_.id);
+                let ty = self.checked.declaration(static_.id);
+                self.init(address, &ty, &initializer.expression)?;
+            }
+        }
+        self.block(&self.program.statements)?;
+        Ok(self.steps)
+    }
+
+    /// Call a function by name (or by [label](Resolution::label)), taking at most `limit` steps. Arguments of aggregate types are
+    /// addresses. Returns the result of the call, if any.
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: &[u16],
+        limit: u64,
+    ) -> Result<Option<u16>, Error> {
+        let fn_ = self
+            .fns
+            .iter()
+            .find(|fn_| {
+                fn_.identifier.as_str() == name
+                    || self.checked.resolution.label(fn_.id) == Some(name)
+            })
+            .copied()
+            .ok_or_else(|| Error::UndefinedFunction(name.to_string()))?;
+        if fn_.params.len() != arguments.len() {
+            return Err(Error::Arguments {
+                name: name.to_string(),
+                expected: fn_.params.len(),
+                found: arguments.len(),
+            });
+        }
+        self.start(limit);
+        self.invoke(fn_, arguments)
+    }
+
+    /// Memory of the variable declared with a name (the first one, if there are several).
+    pub fn variable(&self, name: &str) -> Option<&[u8]> {
+        let item = self
+            .checked
+            .layouts
+            .items()
+            .find(|item| item.name == name)?;
+        self.memory
+            .get(item.address..item.address + item.layout.size)
+    }
+
+    fn start(&mut self, limit: u64) {
+        self.steps = 0;
+        self.limit = limit;
+        self.calls.clear();
+    }
+
+    fn step(&mut self) -> Result<(), Error> {
+        if self.steps == self.limit {
+            return Err(Error::Timeout(self.limit));
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn invoke(
+        &mut self,
+        fn_: &'a FnStatement<'input>,
+        arguments: &[u16],
+    ) -> Result<Option<u16>, Error> {
+        if self.calls.len() == MAX_DEPTH {
+            return Err(Error::StackOverflow);
+        }
+        // a function that is reentered gives the variables of the run it interrupted back when it
+        // returns, like the generated code does by saving them around the calls that reenter it
+        let frame: Vec<_> = if self.calls.contains(&fn_.id) {
+            self.checked.layouts.frame(fn_.id).collect()
+        } else {
+            Vec::new()
+        };
+        let saved: Vec<_> = frame
+            .iter()
+            .map(|item| self.memory[item.address..item.address + item.layout.size].to_vec())
+            .collect();
+        self.calls.push(fn_.id);
+        for (param, argument) in fn_.params.iter().zip(arguments) {
+            let address = self.checked.address(param.id);
+            let ty = self.checked.declaration(param.id);
+            self.store(address, &ty, *argument);
+        }
+        let result = self.block(&fn_.body.inner);
+        self.calls.pop();
+        for (item, bytes) in frame.iter().zip(saved) {
+            self.memory[item.address..item.address + item.layout.size].copy_from_slice(&bytes);
+        }
+        match result? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    fn block(&mut self, statements: &'a [Statement<'input>]) -> Result<Flow, Error> {
+        for statement in statements {
+            match self.statement(statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &'a Statement<'input>) -> Result<Flow, Error> {
+        match statement {
+            // declarations (the initializers of statics have already run)
+            Statement::Const(_)
+            | Statement::Static(_)
+            | Statement::Fn(_)
+            | Statement::TypeAlias(_)
+            | Statement::Import(_)
+            | Statement::Use(_) => return Ok(Flow::Next),
+            Statement::Scope(scope) => return self.block(&scope.inner),
+            _ => {}
+        }
+        self.step()?;
+        match statement {
+            Statement::Let(let_) => {
+                let address = self.checked.address(let_.id);
+                let ty = self.checked.declaration(let_.id);
+                self.init(address, &ty, &let_.expression)?;
+            }
+            Statement::Assign(assign) => {
+                let address = self.place(&assign.place)?;
+                let ty = self.checked.ty(&assign.place);
+                self.init(address, &ty, &assign.expression)?;
+            }
+            Statement::If(if_) => {
+                if self.value(&if_.expression)? != 0 {
+                    return self.block(&if_.inner);
+                } else if let Some(else_) = &if_.else_ {
+                    return self.block(&else_.inner);
+                }
+            }
+            Statement::Loop(loop_) => loop {
+                match self.block(&loop_.inner)? {
+                    Flow::Break => break,
+                    Flow::Return(value) => return Ok(Flow::Return(value)),
+                    Flow::Next | Flow::Continue => self.step()?,
+                }
+            },
+            Statement::While(while_) => {
+                while self.value(&while_.expression)? != 0 {
+                    match self.block(&while_.inner)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => self.step()?,
+                    }
+                }
+            }
+            Statement::Continue(_) => return Ok(Flow::Continue),
+            Statement::Break(_) => return Ok(Flow::Break),
+            Statement::Return(return_) => {
+                let value = match &return_.expression {
+                    Some(expression) => Some(self.value(expression)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Asm(asm) => {
+                let mut assembly = inline::assemble(
+                    asm,
+                    self.checked.resolution,
+                    self.checked.consts,
+                    self.checked.layouts,
+                    "__asm",
+                )
+                .expect("Expected checked inline assembly");
+                assembly.push(Instruction::Halt);
+                self.asm(&assembly)?;
+            }
+            Statement::Expression(statement) => {
+                self.value(&statement.expression)?;
+            }
+            Statement::Const(_)
+            | Statement::Static(_)
+            | Statement::Fn(_)
+            | Statement::TypeAlias(_)
+            | Statement::Import(_)
+            | Statement::Use(_)
+            | Statement::Scope(_) => unreachable!(),
+        }
+        Ok(Flow::Next)
+    }
+
+    // Run a block of inline assembly on the memory of the program, one step per instruction.
+    fn asm(&mut self, assembly: &Assembly) -> Result<(), Error> {
+        let mut interpreter = asm::interp::Interpreter::new(assembly)?;
+        std::mem::swap(&mut interpreter.memory, &mut self.memory);
+        let result = interpreter.run(self.limit - self.steps);
+        std::mem::swap(&mut interpreter.memory, &mut self.memory);
+        match result {
+            Ok(count) => {
+                self.steps += count;
+                Ok(())
+            }
+            Err(asm::interp::Error::Timeout(_)) => Err(Error::Timeout(self.limit)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn load(&self, address: u16, size: Size) -> u16 {
+        let low = self.memory[usize::from(address)];
+        match size {
+            Size::Byte => u16::from(low),
+            Size::Word => {
+                let high = self.memory[usize::from(address.wrapping_add(1))];
+                u16::from_le_bytes([low, high])
+            }
+        }
+    }
+
+    // Store a value of type `ty` (the address of an aggregate) at `address`.
+    fn store(&mut self, address: u16, ty: &Ty, value: u16) {
+        match Size::of(ty) {
+            Some(size) => {
+                let [low, high] = value.to_le_bytes();
+                self.memory[usize::from(address)] = low;
+                if size == Size::Word {
+                    self.memory[usize::from(address.wrapping_add(1))] = high;
+                }
+            }
+            None => self.copy(address, value, Checked::len(ty)),
+        }
+    }
+
+    // Copy bytes one at a time, from the first one (like the copy routine of the generated code).
+    fn copy(&mut self, dst: u16, src: u16, len: u16) {
+        for i in 0..len {
+            let byte = self.memory[usize::from(src.wrapping_add(i))];
+            self.memory[usize::from(dst.wrapping_add(i))] = byte;
+        }
+    }
+
+    // Initialize the memory at `address` with the value of an expression.
+    fn init(
+        &mut self,
+        address: u16,
+        ty: &Ty,
+        expression: &'a Expression<'input>,
+    ) -> Result<(), Error> {
+        let value = self.value(expression)?;
+        match Size::of(ty) {
+            Some(_) => self.store(address, ty, value),
+            // string literals may be shorter than the array they initialize
+            None => self.copy(address, value, Checked::len(&self.checked.ty(expression))),
+        }
+        Ok(())
+    }
+
+    // Value of an expression, or its address if it has an aggregate type.
+    fn value(&mut self, expression: &'a Expression<'input>) -> Result<u16, Error> {
+        let ty = self.checked.ty(expression);
+        let value = match expression {
+            Expression::Parenthesis(parenthesis) => self.value(&parenthesis.inner)?,
+            Expression::Number(number) => {
+                let value = number.number.value().expect("Expected a valid literal");
+                let size = Size::of(&ty).expect("Expected an integer literal");
+                size.wrap(value as i64)
+            }
+            Expression::True(_) => 1,
+            Expression::False(_) => 0,
+            Expression::Str(str) => match self.data.get(&str.id) {
+                Some(address) => *address,
+                None => {
+                    let address = self.next_data;
+                    let bytes = str.str.contents().as_bytes();
+                    for (i, byte) in bytes.iter().enumerate() {
+                        self.memory[usize::from(address.wrapping_add(i as u16))] = *byte;
+                    }
+                    self.next_data = address.wrapping_add(bytes.len() as u16);
+                    self.data.insert(str.id, address);
+                    address
+                }
+            },
+            Expression::Identifier(identifier) => {
+                let id = self
+                    .checked
+                    .resolution
+                    .binding(identifier.id)
+                    .expect("Expected a resolved identifier");
+                let kind = self.checked.resolution.declaration(id).map(|d| d.kind);
+                if kind == Some(DeclarationKind::Const) {
+                    let value = self
+                        .checked
+                        .consts
+                        .value(id)
+                        .expect("Expected a const value");
+                    let size = Size::of(&ty).expect("Expected an integer const");
+                    return Ok(size.wrap(value));
+                }
+                let address = self.place(expression)?;
+                self.read(address, &ty)
+            }
+            Expression::Index(_) | Expression::FieldAccess(_) | Expression::Deref(_) => {
+                let address = self.place(expression)?;
+                self.read(address, &ty)
+            }
+            Expression::Call(call) => {
+                let fn_ = self.callee(&call.callable);
+                let mut arguments = Vec::with_capacity(call.arguments.len());
+                for argument in call.arguments.iter() {
+                    arguments.push(self.value(argument)?);
+                }
+                // calls that don't return a value are only used as statements
+                self.invoke(fn_, &arguments)?.unwrap_or(0)
+            }
+            Expression::Add(add) => {
+                let (left, right) = (self.value(&add.left)?, self.value(&add.right)?);
+                left.wrapping_add(right)
+            }
+            Expression::Subtract(sub) => {
+                let (left, right) = (self.value(&sub.left)?, self.value(&sub.right)?);
+                left.wrapping_sub(right)
+            }
+            Expression::Multiply(mul) => {
+                let (left, right) = (self.value(&mul.left)?, self.value(&mul.right)?);
+                left.wrapping_mul(right)
+            }
+            Expression::Divide(div) => {
+                let (left, right) = (self.value(&div.left)?, self.value(&div.right)?);
+                let size = Size::of(&ty).expect("Expected a scalar type");
+                match ty.width() {
+                    Some(width) if width.is_signed() => signed_divide(size, left, right),
+                    _ => divide(left, right),
+                }
+            }
+            Expression::BitAnd(and) => self.value(&and.left)? & self.value(&and.right)?,
+            Expression::BitOr(or) => self.value(&or.left)? | self.value(&or.right)?,
+            Expression::BitXor(xor) => self.value(&xor.left)? ^ self.value(&xor.right)?,
+            Expression::Equal(eq) => u16::from(self.value(&eq.left)? == self.value(&eq.right)?),
+            Expression::NotEqual(ne) => u16::from(self.value(&ne.left)? != self.value(&ne.right)?),
+            Expression::Less(lt) => u16::from(self.order(&lt.left, &lt.right)?.is_lt()),
+            Expression::LessEqual(le) => u16::from(self.order(&le.left, &le.right)?.is_le()),
+            Expression::Greater(gt) => u16::from(self.order(&gt.left, &gt.right)?.is_gt()),
+            Expression::GreaterEqual(ge) => u16::from(self.order(&ge.left, &ge.right)?.is_ge()),
+            Expression::BitNot(not) => !self.value(&not.inner)?,
+            Expression::Negate(negate) => 0u16.wrapping_sub(self.value(&negate.inner)?),
+            Expression::Cast(cast) => {
+                let from = self.checked.ty(&cast.inner);
+                let value = self.value(&cast.inner)?;
+                return Ok(checked::cast(value, &from, &ty));
+            }
+            Expression::Addr(addr) => return self.place(&addr.inner),
+        };
+        Ok(match Size::of(&ty) {
+            Some(size) => size.wrap(i64::from(value)),
+            None => value,
+        })
+    }
+
+    // Order of the values of two integer expressions of the same type.
+    fn order(
+        &mut self,
+        left: &'a Expression<'input>,
+        right: &'a Expression<'input>,
+    ) -> Result<Ordering, Error> {
+        let ty = self.checked.ty(left);
+        let (left, right) = (self.value(left)?, self.value(right)?);
+        Ok(match (ty.width(), Size::of(&ty)) {
+            (Some(width), Some(Size::Byte)) if width.is_signed() => {
+                (left as u8 as i8).cmp(&(right as u8 as i8))
+            }
+            (Some(width), _) if width.is_signed() => (left as i16).cmp(&(right as i16)),
+            _ => left.cmp(&right),
+        })
+    }
+
+    // Read the value of type `ty` stored at an address (aggregates are left as addresses).
+    fn read(&self, address: u16, ty: &Ty) -> u16 {
+        match Size::of(ty) {
+            Some(size) => self.load(address, size),
+            None => address,
+        }
+    }
+
+    // Address of the memory an expression refers to.
+    fn place(&mut self, expression: &'a Expression<'input>) -> Result<u16, Error> {
+        match expression {
+            Expression::Parenthesis(parenthesis) => self.place(&parenthesis.inner),
+            Expression::Identifier(identifier) => {
+                let id = self
+                    .checked
+                    .resolution
+                    .binding(identifier.id)
+                    .expect("Expected a resolved identifier");
+                Ok(self.checked.address(id))
+            }
+            Expression::Index(index) => {
+                let base = match self.checked.ty(&index.indexable) {
+                    Ty::Ptr(_) => self.value(&index.indexable)?,
+                    _ => self.place(&index.indexable)?,
+                };
+                let element = Checked::len(&self.checked.ty(expression));
+                let i = self.value(&index.index)?;
+                let i = match self.checked.size(&index.index) {
+                    Size::Byte => i & 0xff,
+                    Size::Word => i,
+                };
+                Ok(base.wrapping_add(i.wrapping_mul(element)))
+            }
+            Expression::FieldAccess(access) => {
+                let base = self.place(&access.inner)?;
+                Ok(base.wrapping_add(self.checked.field_offset(access)))
+            }
+            Expression::Deref(deref) => self.value(&deref.inner),
+            // aggregate values are already addresses
+            _ => self.value(expression),
+        }
+    }
+
+    fn callee(&self, callable: &Expression<'_>) -> &'a FnStatement<'input> {
+        self.functions[&self.checked.callee(callable)]
+    }
+}
+
+// Unsigned division of words (or bytes extended to words). Dividing by zero gives $FFFF.
+fn divide(left: u16, right: u16) -> u16 {
+    left.checked_div(right).unwrap_or(0xffff)
+}
+
+// Signed division, rounding toward zero. The magnitudes are divided like unsigned values, so
+// dividing by zero gives -1 for non-negative dividends, and 1 for negative ones.
+fn signed_divide(size: Size, left: u16, right: u16) -> u16 {
+    let extend = |value: u16| match size {
+        Size::Byte => value as u8 as i8 as u16,
+        Size::Word => value,
+    };
+    let (left, right) = (extend(left), extend(right));
+    let negative = (left ^ right) & 0x8000 != 0;
+    let magnitude = |value: u16| match value & 0x8000 {
+        0 => value,
+        _ => value.wrapping_neg(),
+    };
+    let quotient = divide(magnitude(left), magnitude(right));
+    if negative {
+        quotient.wrapping_neg()
+    } else {
+        quotient
+    }
+}
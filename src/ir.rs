@@ -0,0 +1,1104 @@
+//! Intermediate representation of lowered programs.
+//!
+//! The IR is a typed, three-address code that sits between the AST and machine code. Every
+//! function is a list of basic blocks of [instructions](Inst) that operate on 8 and 16-bit
+//! [temporaries](Temp), ended by a [`Terminator`]. Temporaries are assigned exactly once, and
+//! there are no registers: variables live in memory, at the address [laid out](crate::layout)
+//! for them, and are read and written with explicit loads and stores.
+//!
+//! The statements at the top level of the program make up the [entry](Program::entry)
+//! function, which also runs the initializers of every `static`. Values of aggregate types
+//! (structs, unions and arrays) are handled by address, and copied with [`Inst::Copy`].
+//!
+//! ```
+//! use gb_lang::ast::Context;
+//!
+//! let mut context = Context::default();
+//! let input = "static A @ 0xc000 :: u8; let b::u8 = A + 1;";
+//! let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+//! let resolution = gb_lang::resolve::resolve(&program, &mut context);
+//! let consts = gb_lang::consts::evaluate(&program, &resolution, &mut context);
+//! let types = gb_lang::typeck::check(&program, &resolution, &consts, &mut context);
+//! let layouts = gb_lang::layout::layout(&program, &consts, &types, &mut context);
+//! let ir = gb_lang::ir::lower(&program, &resolution, &consts, &types, &layouts);
+//! assert_eq!(
+//!     "fn __start() {
+//! bb0:
+//!     %0 = load i8 $C000
+//!     %1 = add i8 %0, $1
+//!     store i8 $C001, %1
+//!     ret
+//! }
+//! ",
+//!     ir.dump(),
+//! );
+//! ```
+use crate::{
+    asm::{inline, Assembly},
+    ast::{expressions::Expression, statements::Statement, NodeId},
+    cfg::{self, Cfg},
+    checked::{self, collect, Checked},
+    consts::Consts,
+    layout::Layouts,
+    resolve::{DeclarationKind, Resolution},
+    typeck::{Ty, Types},
+    Span, Spanned,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    fmt::{Display, Formatter},
+    ops::Range,
+};
+
+/// Name of the [entry](Program::entry) function.
+pub const ENTRY: &str = "__start";
+
+/// Size of a value.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Size {
+    Byte,
+    Word,
+}
+
+impl Size {
+    /// Size of the values of a type, if they fit in a temporary.
+    ///
+    /// `bool`s are bytes, and pointers are words.
+    pub fn of(ty: &Ty) -> Option<Size> {
+        match ty {
+            Ty::U8 | Ty::I8 | Ty::Bool => Some(Size::Byte),
+            Ty::U16 | Ty::I16 | Ty::Ptr(_) => Some(Size::Word),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(self) -> u16 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+        }
+    }
+
+    /// Wrap a value around the size.
+    pub fn wrap(self, value: i64) -> u16 {
+        match self {
+            Size::Byte => value as u8 as u16,
+            Size::Word => value as u16,
+        }
+    }
+}
+
+impl Display for Size {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Size::Byte => write!(f, "i8"),
+            Size::Word => write!(f, "i16"),
+        }
+    }
+}
+
+/// Temporary value of a [`Function`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Temp(usize);
+
+impl Temp {
+    /// Index of the temporary in [`Function::temps`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Display for Temp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+/// ID of a [`Block`] within its [`Function`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct BlockId(usize);
+
+impl BlockId {
+    /// Index of the block in [`Function::blocks`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// ID of a [`Function`] within its [`Program`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct FunctionId(usize);
+
+impl FunctionId {
+    /// Index of the function in [`Program::functions`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// ID of a read-only piece of data (the bytes of a string literal).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct DataId(usize);
+
+impl DataId {
+    /// Index of the data in [`Program::data`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Display for DataId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "data{}", self.0)
+    }
+}
+
+/// Input of an instruction.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Operand {
+    Temp(Temp),
+    Const(u16),
+
+    /// Address of a piece of data, known once the program is placed in ROM.
+    Data(DataId),
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Temp(temp) => write!(f, "{}", temp),
+            Operand::Const(value) => write!(f, "${:X}", value),
+            Operand::Data(data) => write!(f, "{}", data),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// Unsigned division.
+    Div,
+    /// Signed division.
+    SignedDiv,
+    And,
+    Or,
+    Xor,
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "udiv",
+            BinaryOp::SignedDiv => "sdiv",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Comparison of two values of the same size. `>` and `>=` are `<` and `<=` with their operands
+/// swapped.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    /// Unsigned `<`.
+    Lt,
+    /// Unsigned `<=`.
+    Le,
+    SignedLt,
+    SignedLe,
+}
+
+impl Display for CompareOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompareOp::Eq => "eq",
+            CompareOp::Ne => "ne",
+            CompareOp::Lt => "ult",
+            CompareOp::Le => "ule",
+            CompareOp::SignedLt => "slt",
+            CompareOp::SignedLe => "sle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Inst {
+    /// `dst = [address]`
+    Load {
+        dst: Temp,
+        size: Size,
+        address: Operand,
+    },
+
+    /// `[address] = value`
+    Store {
+        size: Size,
+        address: Operand,
+        value: Operand,
+    },
+
+    /// Copy `len` bytes from `src` to `dst` (both addresses).
+    Copy {
+        dst: Operand,
+        src: Operand,
+        len: u16,
+    },
+
+    /// `dst = left op right`, wrapping around the size.
+    Binary {
+        dst: Temp,
+        op: BinaryOp,
+        size: Size,
+        left: Operand,
+        right: Operand,
+    },
+
+    /// `dst = left op right`, as a byte (`1` if the comparison holds, `0` otherwise).
+    Compare {
+        dst: Temp,
+        op: CompareOp,
+        size: Size,
+        left: Operand,
+        right: Operand,
+    },
+
+    /// `dst = ~operand`
+    Not {
+        dst: Temp,
+        size: Size,
+        operand: Operand,
+    },
+
+    /// `dst = operand != 0`, as a byte.
+    NonZero {
+        dst: Temp,
+        size: Size,
+        operand: Operand,
+    },
+
+    /// Extend a byte to a word, with or without its sign.
+    Extend {
+        dst: Temp,
+        signed: bool,
+        operand: Operand,
+    },
+
+    /// Low byte of a word.
+    Truncate { dst: Temp, operand: Operand },
+
+    /// Call a function, with one argument per parameter. Arguments and results of aggregate
+    /// types are passed by address.
+    Call {
+        dst: Option<Temp>,
+        function: FunctionId,
+        arguments: Vec<Operand>,
+    },
+
+    /// Inline assembly, with the names it uses replaced by their values.
+    Asm(Assembly),
+}
+
+/// How control leaves a [`Block`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+
+    /// Jump to `then` if the condition isn't zero, to `else_` otherwise.
+    Branch {
+        size: Size,
+        condition: Operand,
+        then: BlockId,
+        else_: BlockId,
+    },
+    Return(Option<Operand>),
+}
+
+impl Inst {
+    /// Temporary assigned by the instruction.
+    pub fn dst(&self) -> Option<Temp> {
+        match self {
+            Inst::Load { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::Compare { dst, .. }
+            | Inst::Not { dst, .. }
+            | Inst::NonZero { dst, .. }
+            | Inst::Extend { dst, .. }
+            | Inst::Truncate { dst, .. } => Some(*dst),
+            Inst::Call { dst, .. } => *dst,
+            Inst::Store { .. } | Inst::Copy { .. } | Inst::Asm(_) => None,
+        }
+    }
+
+    /// Inputs of the instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Inst::Load { address, .. } => vec![*address],
+            Inst::Store { address, value, .. } => vec![*address, *value],
+            Inst::Copy { dst, src, .. } => vec![*dst, *src],
+            Inst::Binary { left, right, .. } | Inst::Compare { left, right, .. } => {
+                vec![*left, *right]
+            }
+            Inst::Not { operand, .. }
+            | Inst::NonZero { operand, .. }
+            | Inst::Extend { operand, .. }
+            | Inst::Truncate { operand, .. } => vec![*operand],
+            Inst::Call { arguments, .. } => arguments.clone(),
+            Inst::Asm(_) => Vec::new(),
+        }
+    }
+}
+
+impl Terminator {
+    /// Inputs of the terminator.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+
+    /// Blocks control can jump to.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then, else_, .. } => vec![*then, *else_],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+}
+
+/// Basic block.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+
+    /// Span of the statement every instruction was lowered from.
+    pub spans: Vec<Span>,
+
+    /// Span of the condition or `return` that ends the block, if any.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+
+    /// Temporaries holding the arguments, on entry.
+    pub params: Vec<Temp>,
+
+    /// Size of the returned value (if any).
+    pub ret: Option<Size>,
+
+    /// Size of every temporary.
+    pub temps: Vec<Size>,
+
+    /// Blocks of the function. The first one is where it starts.
+    pub blocks: Vec<Block>,
+
+    /// Memory of the parameters and `let`s of the function, which it saves around the calls
+    /// that can reenter it.
+    pub frame: Vec<Range<u16>>,
+}
+
+impl Function {
+    pub fn size(&self, temp: Temp) -> Size {
+        self.temps[temp.0]
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    /// Blocks of the function, in order.
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &Block)> {
+        self.blocks.iter().enumerate().map(|(i, b)| (BlockId(i), b))
+    }
+}
+
+/// Lowered program.
+#[derive(Debug, Clone)]
+pub struct Program {
+    functions: Vec<Function>,
+    data: Vec<Vec<u8>>,
+}
+
+impl Program {
+    /// Function that runs the top-level statements of the program.
+    pub fn entry(&self) -> FunctionId {
+        FunctionId(0)
+    }
+
+    pub fn function(&self, id: FunctionId) -> &Function {
+        &self.functions[id.0]
+    }
+
+    /// Functions of the program: the entry, followed by every `fn` in source order.
+    pub fn functions(&self) -> impl Iterator<Item = (FunctionId, &Function)> {
+        self.functions
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (FunctionId(i), f))
+    }
+
+    pub fn data(&self, id: DataId) -> &[u8] {
+        &self.data[id.0]
+    }
+
+    /// Read-only data of the program.
+    pub fn all_data(&self) -> impl Iterator<Item = (DataId, &[u8])> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (DataId(i), d.as_slice()))
+    }
+
+    /// Textual form of the program.
+    pub fn dump(&self) -> String {
+        self.to_string()
+    }
+
+    fn write_inst(&self, f: &mut Formatter<'_>, inst: &Inst) -> fmt::Result {
+        match inst {
+            Inst::Load { dst, size, address } => {
+                write!(f, "{} = load {} {}", dst, size, address)
+            }
+            Inst::Store {
+                size,
+                address,
+                value,
+            } => write!(f, "store {} {}, {}", size, address, value),
+            Inst::Copy { dst, src, len } => write!(f, "copy {}, {}, {}", dst, src, len),
+            Inst::Binary {
+                dst,
+                op,
+                size,
+                left,
+                right,
+            } => write!(f, "{} = {} {} {}, {}", dst, op, size, left, right),
+            Inst::Compare {
+                dst,
+                op,
+                size,
+                left,
+                right,
+            } => write!(f, "{} = cmp {} {} {}, {}", dst, op, size, left, right),
+            Inst::Not { dst, size, operand } => write!(f, "{} = not {} {}", dst, size, operand),
+            Inst::NonZero { dst, size, operand } => {
+                write!(f, "{} = nonzero {} {}", dst, size, operand)
+            }
+            Inst::Extend {
+                dst,
+                signed,
+                operand,
+            } => {
+                let op = if *signed { "sext" } else { "zext" };
+                write!(f, "{} = {} {}", dst, op, operand)
+            }
+            Inst::Truncate { dst, operand } => write!(f, "{} = trunc {}", dst, operand),
+            Inst::Call {
+                dst,
+                function,
+                arguments,
+            } => {
+                if let Some(dst) = dst {
+                    write!(f, "{} = ", dst)?;
+                }
+                write!(f, "call @{}(", self.function(*function).name)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Inst::Asm(assembly) => {
+                write!(f, "asm {{")?;
+                for line in &assembly.lines {
+                    write!(f, "\n    {}", line)?;
+                }
+                write!(f, "\n    }}")
+            }
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (id, data) in self.all_data() {
+            writeln!(f, "{} = {:?}", id, String::from_utf8_lossy(data))?;
+        }
+        if !self.data.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "fn {}(", function.name)?;
+            for (i, param) in function.params.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {}", param, function.size(*param))?;
+            }
+            write!(f, ")")?;
+            if let Some(ret) = function.ret {
+                write!(f, " -> {}", ret)?;
+            }
+            writeln!(f, " {{")?;
+            for (id, block) in function.blocks() {
+                writeln!(f, "{}:", id)?;
+                for inst in &block.insts {
+                    write!(f, "    ")?;
+                    self.write_inst(f, inst)?;
+                    writeln!(f)?;
+                }
+                match &block.terminator {
+                    Terminator::Jump(target) => writeln!(f, "    jmp {}", target)?,
+                    Terminator::Branch {
+                        size,
+                        condition,
+                        then,
+                        else_,
+                    } => writeln!(f, "    br {} {}, {}, {}", size, condition, then, else_)?,
+                    Terminator::Return(None) => writeln!(f, "    ret")?,
+                    Terminator::Return(Some(value)) => writeln!(f, "    ret {}", value)?,
+                }
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lower a checked program.
+///
+/// The program must have been resolved, type checked, laid out and had its
+/// [inline assembly](inline::check) checked without errors: lowering a program with errors panics.
+pub fn lower(
+    program: &crate::ast::Program<'_>,
+    resolution: &Resolution,
+    consts: &Consts,
+    types: &Types,
+    layouts: &Layouts,
+) -> Program {
+    let mut fns = Vec::new();
+    let mut statics = Vec::new();
+    for statement in &program.statements {
+        collect(statement, &mut fns, &mut statics);
+    }
+    let mut lower = Lower {
+        checked: Checked {
+            resolution,
+            consts,
+            types,
+            layouts,
+        },
+        functions: fns
+            .iter()
+            .enumerate()
+            .map(|(i, fn_)| (fn_.id, FunctionId(i + 1)))
+            .collect(),
+        data: Vec::new(),
+        asm_blocks: 0,
+    };
+    let mut functions = Vec::with_capacity(fns.len() + 1);
+    let mut entry = Builder::default();
+    for static_ in statics {
+        if let Some(initializer) = &static_.initializer {
+            let ty = lower.checked.declaration(static_.id);
+            let address = lower.address(static_.id);
+            lower.init(&mut entry, address, &ty, &initializer.expression);
+            entry.mark(static_.span());
+        }
+    }
+    functions.push(lower.function(ENTRY, entry, None, &program.statements));
+    for fn_ in fns {
+        let frame = layouts.frame(fn_.id);
+        let mut builder = Builder {
+            frame: frame
+                .map(|item| item.address as u16..(item.address + item.layout.size) as u16)
+                .collect(),
+            ..Builder::default()
+        };
+        for param in fn_.params.iter() {
+            let ty = lower.checked.declaration(param.id);
+            let temp = builder.temp(Size::of(&ty).unwrap_or(Size::Word));
+            builder.params.push(temp);
+            let address = lower.address(param.id);
+            lower.init_from(&mut builder, address, &ty, Operand::Temp(temp));
+            builder.mark(param.span());
+        }
+        let ret = match lower.checked.declaration(fn_.id) {
+            Ty::Fn(_, ret) if *ret == Ty::Unit => None,
+            Ty::Fn(_, ret) => Some(Size::of(&ret).unwrap_or(Size::Word)),
+            _ => unreachable!("Expected a function type"),
+        };
+        let name = resolution
+            .label(fn_.id)
+            .unwrap_or_else(|| fn_.identifier.as_str());
+        functions.push(lower.function(name, builder, ret, &fn_.body.inner));
+    }
+    Program {
+        functions,
+        data: lower.data,
+    }
+}
+
+// Function being lowered.
+#[derive(Default)]
+struct Builder {
+    params: Vec<Temp>,
+    frame: Vec<Range<u16>>,
+    temps: Vec<Size>,
+    // instructions of the current block
+    insts: Vec<Inst>,
+    // span of every instruction of the current block, up to the last one marked
+    spans: Vec<Span>,
+}
+
+impl Builder {
+    fn temp(&mut self, size: Size) -> Temp {
+        self.temps.push(size);
+        Temp(self.temps.len() - 1)
+    }
+
+    // Mark the instructions pushed since the last mark as lowered from a span.
+    fn mark(&mut self, span: Span) {
+        self.spans.resize(self.insts.len(), span);
+    }
+
+    fn push(&mut self, size: Size, inst: impl FnOnce(Temp) -> Inst) -> Operand {
+        let dst = self.temp(size);
+        self.insts.push(inst(dst));
+        Operand::Temp(dst)
+    }
+}
+
+struct Lower<'a> {
+    checked: Checked<'a>,
+    functions: HashMap<NodeId, FunctionId>,
+    data: Vec<Vec<u8>>,
+    // number of `asm` blocks lowered so far, which prefixes their labels
+    asm_blocks: usize,
+}
+
+impl Lower<'_> {
+    fn function(
+        &mut self,
+        name: &str,
+        mut builder: Builder,
+        ret: Option<Size>,
+        body: &[Statement<'_>],
+    ) -> Function {
+        let cfg = Cfg::build(body);
+        // only reachable blocks are lowered, keeping their order. The exit block is always
+        // empty, so jumps to it return right away.
+        let lowered = |id: &cfg::BlockId| cfg.is_reachable(*id) && *id != cfg.exit();
+        let mut ids = HashMap::new();
+        for (id, _) in cfg.blocks().filter(|(id, _)| lowered(id)) {
+            ids.insert(id, BlockId(ids.len()));
+        }
+        let mut blocks = Vec::with_capacity(ids.len());
+        for (id, block) in cfg.blocks().filter(|(id, _)| lowered(id)) {
+            for statement in &block.statements {
+                match statement {
+                    Statement::Let(let_) => {
+                        let ty = self.checked.declaration(let_.id);
+                        let address = self.address(let_.id);
+                        self.init(&mut builder, address, &ty, &let_.expression);
+                    }
+                    Statement::Expression(statement) => {
+                        self.value(&mut builder, &statement.expression);
+                    }
+                    Statement::Assign(assign) => {
+                        let ty = self.checked.ty(&assign.place);
+                        let address = self.place(&mut builder, &assign.place);
+                        self.init(&mut builder, address, &ty, &assign.expression);
+                    }
+                    Statement::Asm(asm) => {
+                        let prefix = format!("__asm{}", self.asm_blocks);
+                        self.asm_blocks += 1;
+                        let assembly = inline::assemble(
+                            asm,
+                            self.checked.resolution,
+                            self.checked.consts,
+                            self.checked.layouts,
+                            &prefix,
+                        )
+                        .expect("Expected checked inline assembly");
+                        builder.insts.push(Inst::Asm(assembly));
+                    }
+                    _ => unreachable!("Expected a straight-line statement"),
+                }
+                builder.mark(statement.span());
+            }
+            let span = match &block.terminator {
+                cfg::Terminator::Branch { condition, .. } => condition.span(),
+                cfg::Terminator::Return(return_) => return_.span(),
+                cfg::Terminator::Goto(_) | cfg::Terminator::Exit => Span::default(),
+            };
+            let terminator = match &block.terminator {
+                cfg::Terminator::Goto(target) if *target == cfg.exit() => Terminator::Return(None),
+                cfg::Terminator::Goto(target) => Terminator::Jump(ids[target]),
+                cfg::Terminator::Branch {
+                    condition,
+                    then,
+                    else_,
+                } => Terminator::Branch {
+                    size: self.checked.size(condition),
+                    condition: self.value(&mut builder, condition),
+                    then: ids[then],
+                    else_: ids[else_],
+                },
+                cfg::Terminator::Return(return_) => Terminator::Return(
+                    return_
+                        .expression
+                        .as_ref()
+                        .map(|expression| self.value(&mut builder, expression)),
+                ),
+                cfg::Terminator::Exit => Terminator::Return(None),
+            };
+            builder.mark(span);
+            debug_assert_eq!(ids[&id].0, blocks.len());
+            blocks.push(Block {
+                insts: std::mem::take(&mut builder.insts),
+                terminator,
+                spans: std::mem::take(&mut builder.spans),
+                span,
+            });
+        }
+        Function {
+            name: name.to_string(),
+            params: builder.params,
+            ret,
+            temps: builder.temps,
+            blocks,
+            frame: builder.frame,
+        }
+    }
+
+    fn address(&self, declaration: NodeId) -> Operand {
+        Operand::Const(self.checked.address(declaration))
+    }
+
+    fn init(&mut self, b: &mut Builder, address: Operand, ty: &Ty, expression: &Expression<'_>) {
+        let value = self.value(b, expression);
+        match Size::of(ty) {
+            Some(size) => b.insts.push(Inst::Store {
+                size,
+                address,
+                value,
+            }),
+            // string literals may be shorter than the array they initialize
+            None => b.insts.push(Inst::Copy {
+                dst: address,
+                src: value,
+                len: Checked::len(&self.checked.ty(expression)),
+            }),
+        }
+    }
+
+    // Initialize the memory at `address` with a value of type `ty` (the address of an aggregate).
+    fn init_from(&mut self, b: &mut Builder, address: Operand, ty: &Ty, value: Operand) {
+        match Size::of(ty) {
+            Some(size) => b.insts.push(Inst::Store {
+                size,
+                address,
+                value,
+            }),
+            None => b.insts.push(Inst::Copy {
+                dst: address,
+                src: value,
+                len: Checked::len(ty),
+            }),
+        }
+    }
+
+    /// Value of an expression, or its address if it has an aggregate type.
+    fn value(&mut self, b: &mut Builder, expression: &Expression<'_>) -> Operand {
+        let ty = self.checked.ty(expression);
+        match expression {
+            Expression::Parenthesis(parenthesis) => self.value(b, &parenthesis.inner),
+            Expression::Number(number) => {
+                let value = number.number.value().expect("Expected a valid literal");
+                let size = Size::of(&ty).expect("Expected an integer literal");
+                Operand::Const(size.wrap(value as i64))
+            }
+            Expression::True(_) => Operand::Const(1),
+            Expression::False(_) => Operand::Const(0),
+            Expression::Str(str) => {
+                self.data.push(str.str.contents().as_bytes().to_vec());
+                Operand::Data(DataId(self.data.len() - 1))
+            }
+            Expression::Identifier(identifier) => {
+                let id = self
+                    .checked
+                    .resolution
+                    .binding(identifier.id)
+                    .expect("Expected a resolved identifier");
+                let kind = self.checked.resolution.declaration(id).map(|d| d.kind);
+                if kind == Some(DeclarationKind::Const) {
+                    let value = self
+                        .checked
+                        .consts
+                        .value(id)
+                        .expect("Expected a const value");
+                    let size = Size::of(&ty).expect("Expected an integer const");
+                    return Operand::Const(size.wrap(value));
+                }
+                let address = self.place(b, expression);
+                self.load(b, address, &ty)
+            }
+            Expression::Index(_) | Expression::FieldAccess(_) | Expression::Deref(_) => {
+                let address = self.place(b, expression);
+                self.load(b, address, &ty)
+            }
+            Expression::Call(call) => {
+                let function = self.callee(&call.callable);
+                let arguments = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.value(b, argument))
+                    .collect();
+                let dst = match ty {
+                    Ty::Unit => None,
+                    ty => Some(b.temp(Size::of(&ty).unwrap_or(Size::Word))),
+                };
+                b.insts.push(Inst::Call {
+                    dst,
+                    function,
+                    arguments,
+                });
+                // calls that don't return a value are only used as statements
+                dst.map(Operand::Temp).unwrap_or(Operand::Const(0))
+            }
+            Expression::Add(add) => self.binary(b, &ty, BinaryOp::Add, &add.left, &add.right),
+            Expression::Subtract(sub) => self.binary(b, &ty, BinaryOp::Sub, &sub.left, &sub.right),
+            Expression::Multiply(mul) => self.binary(b, &ty, BinaryOp::Mul, &mul.left, &mul.right),
+            Expression::Divide(div) => {
+                let op = match ty.width() {
+                    Some(width) if width.is_signed() => BinaryOp::SignedDiv,
+                    _ => BinaryOp::Div,
+                };
+                self.binary(b, &ty, op, &div.left, &div.right)
+            }
+            Expression::BitAnd(and) => self.binary(b, &ty, BinaryOp::And, &and.left, &and.right),
+            Expression::BitOr(or) => self.binary(b, &ty, BinaryOp::Or, &or.left, &or.right),
+            Expression::BitXor(xor) => self.binary(b, &ty, BinaryOp::Xor, &xor.left, &xor.right),
+            Expression::Equal(eq) => self.compare(b, CompareOp::Eq, &eq.left, &eq.right, false),
+            Expression::NotEqual(ne) => self.compare(b, CompareOp::Ne, &ne.left, &ne.right, false),
+            Expression::Less(lt) => self.compare(b, CompareOp::Lt, &lt.left, &lt.right, false),
+            Expression::LessEqual(le) => self.compare(b, CompareOp::Le, &le.left, &le.right, false),
+            Expression::Greater(gt) => self.compare(b, CompareOp::Lt, &gt.left, &gt.right, true),
+            Expression::GreaterEqual(ge) => {
+                self.compare(b, CompareOp::Le, &ge.left, &ge.right, true)
+            }
+            Expression::BitNot(not) => {
+                let operand = self.value(b, &not.inner);
+                let size = Size::of(&ty).expect("Expected a scalar type");
+                b.push(size, |dst| Inst::Not { dst, size, operand })
+            }
+            Expression::Negate(negate) => match &*negate.inner {
+                Expression::Number(number) => {
+                    let value = number.number.value().expect("Expected a valid literal");
+                    let size = Size::of(&ty).expect("Expected an integer literal");
+                    Operand::Const(size.wrap(-(value as i64)))
+                }
+                inner => {
+                    let operand = self.value(b, inner);
+                    let size = Size::of(&ty).expect("Expected a scalar type");
+                    b.push(size, |dst| Inst::Binary {
+                        dst,
+                        op: BinaryOp::Sub,
+                        size,
+                        left: Operand::Const(0),
+                        right: operand,
+                    })
+                }
+            },
+            Expression::Cast(cast) => {
+                let from = self.checked.ty(&cast.inner);
+                let operand = self.value(b, &cast.inner);
+                self.cast(b, operand, &from, &ty)
+            }
+            Expression::Addr(addr) => self.place(b, &addr.inner),
+        }
+    }
+
+    // Read the value of type `ty` stored at an address (aggregates are left as addresses).
+    fn load(&mut self, b: &mut Builder, address: Operand, ty: &Ty) -> Operand {
+        match Size::of(ty) {
+            Some(size) => b.push(size, |dst| Inst::Load { dst, size, address }),
+            None => address,
+        }
+    }
+
+    /// Address of the memory an expression refers to.
+    fn place(&mut self, b: &mut Builder, expression: &Expression<'_>) -> Operand {
+        match expression {
+            Expression::Parenthesis(parenthesis) => self.place(b, &parenthesis.inner),
+            Expression::Identifier(identifier) => {
+                let id = self
+                    .checked
+                    .resolution
+                    .binding(identifier.id)
+                    .expect("Expected a resolved identifier");
+                self.address(id)
+            }
+            Expression::Index(index) => {
+                let base = match self.checked.ty(&index.indexable) {
+                    Ty::Ptr(_) => self.value(b, &index.indexable),
+                    _ => self.place(b, &index.indexable),
+                };
+                let element = Checked::len(&self.checked.ty(expression));
+                let i = self.value(b, &index.index);
+                let i = match (self.checked.size(&index.index), i) {
+                    (Size::Byte, Operand::Temp(_)) => b.push(Size::Word, |dst| Inst::Extend {
+                        dst,
+                        signed: false,
+                        operand: i,
+                    }),
+                    _ => i,
+                };
+                self.offset(b, base, i, element)
+            }
+            Expression::FieldAccess(access) => {
+                let base = self.place(b, &access.inner);
+                let offset = self.checked.field_offset(access);
+                self.offset(b, base, Operand::Const(offset), 1)
+            }
+            Expression::Deref(deref) => self.value(b, &deref.inner),
+            // aggregate values are already addresses
+            _ => self.value(b, expression),
+        }
+    }
+
+    // Address of the `index`th element of `size` bytes after `base`.
+    fn offset(&mut self, b: &mut Builder, base: Operand, index: Operand, size: u16) -> Operand {
+        let offset = match index {
+            Operand::Const(index) => Operand::Const(index.wrapping_mul(size)),
+            _ if size == 1 => index,
+            _ => b.push(Size::Word, |dst| Inst::Binary {
+                dst,
+                op: BinaryOp::Mul,
+                size: Size::Word,
+                left: index,
+                right: Operand::Const(size),
+            }),
+        };
+        match (base, offset) {
+            (base, Operand::Const(0)) => base,
+            (Operand::Const(base), Operand::Const(offset)) => {
+                Operand::Const(base.wrapping_add(offset))
+            }
+            (base, offset) => b.push(Size::Word, |dst| Inst::Binary {
+                dst,
+                op: BinaryOp::Add,
+                size: Size::Word,
+                left: base,
+                right: offset,
+            }),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        b: &mut Builder,
+        ty: &Ty,
+        op: BinaryOp,
+        left: &Expression<'_>,
+        right: &Expression<'_>,
+    ) -> Operand {
+        let left = self.value(b, left);
+        let right = self.value(b, right);
+        let size = Size::of(ty).expect("Expected a scalar type");
+        b.push(size, |dst| Inst::Binary {
+            dst,
+            op,
+            size,
+            left,
+            right,
+        })
+    }
+
+    // Compare two operands (`<` and `<=` are signed for signed integers), which are swapped after
+    // being evaluated if `swap` is set.
+    fn compare(
+        &mut self,
+        b: &mut Builder,
+        op: CompareOp,
+        left: &Expression<'_>,
+        right: &Expression<'_>,
+        swap: bool,
+    ) -> Operand {
+        let ty = self.checked.ty(left);
+        let op = match (op, ty.width()) {
+            (CompareOp::Lt, Some(width)) if width.is_signed() => CompareOp::SignedLt,
+            (CompareOp::Le, Some(width)) if width.is_signed() => CompareOp::SignedLe,
+            (op, _) => op,
+        };
+        let left = self.value(b, left);
+        let right = self.value(b, right);
+        let (left, right) = if swap { (right, left) } else { (left, right) };
+        let size = Size::of(&ty).expect("Expected a scalar type");
+        b.push(Size::Byte, |dst| Inst::Compare {
+            dst,
+            op,
+            size,
+            left,
+            right,
+        })
+    }
+
+    fn cast(&mut self, b: &mut Builder, operand: Operand, from: &Ty, to: &Ty) -> Operand {
+        let (from_size, to_size) = match (Size::of(from), Size::of(to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => unreachable!("Expected a cast between scalar types"),
+        };
+        if let Operand::Const(value) = operand {
+            return Operand::Const(checked::cast(value, from, to));
+        }
+        let signed = from.width().is_some_and(|width| width.is_signed());
+        if *to == Ty::Bool && *from != Ty::Bool {
+            return b.push(Size::Byte, |dst| Inst::NonZero {
+                dst,
+                size: from_size,
+                operand,
+            });
+        }
+        match (from_size, to_size) {
+            (Size::Byte, Size::Word) => b.push(Size::Word, |dst| Inst::Extend {
+                dst,
+                signed,
+                operand,
+            }),
+            (Size::Word, Size::Byte) => b.push(Size::Byte, |dst| Inst::Truncate { dst, operand }),
+            _ => operand,
+        }
+    }
+
+    fn callee(&self, callable: &Expression<'_>) -> FunctionId {
+        self.functions[&self.checked.callee(callable)]
+    }
+}
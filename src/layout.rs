@@ -0,0 +1,463 @@
+//! Memory layout of types.
+//!
+//! The SM83 has no alignment requirements, so every type is byte aligned: structs lay their
+//! fields out back to back, unions overlap them all at offset `0`, and pointers take two bytes.
+//!
+//! ```
+//! use gb_lang::{layout::Layout, typeck::Ty};
+//!
+//! // array<array<u8, 0x400>, 2>
+//! let ty = Ty::Array(Box::new(Ty::Array(Box::new(Ty::U8), 0x400)), 2);
+//! assert_eq!(0x800, Layout::of(&ty).unwrap().size);
+//! ```
+use crate::{
+    ast::{
+        statements::{Placement, Statement},
+        visit::{self, Visit, Walk},
+        Context, NodeId, Program,
+    },
+    consts::Consts,
+    diagnostics::Diagnostic,
+    lex::tokens,
+    memory::Region,
+    typeck::{Ty, Types},
+    Span, Spanned,
+};
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+/// Size of the address space.
+pub const ADDRESS_SPACE: usize = 0x10000;
+
+/// Initial value of the stack pointer of the generated code (the end of WRAM).
+pub const STACK_TOP: u16 = 0xe000;
+
+/// Bytes of WRAM (below [`STACK_TOP`]) kept free for the stack, where nothing is laid out.
+pub const STACK_SIZE: u16 = 0x100;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("type `{0}` doesn't fit in the address space")]
+    TooLarge(Ty),
+
+    #[error("values of type `{0}` aren't stored in memory")]
+    NotStored(Ty),
+
+    /// The type contains [`Ty::Error`].
+    #[error("type has errors")]
+    Invalid,
+}
+
+/// Size, alignment and field offsets of a type.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Layout {
+    pub size: usize,
+    pub align: usize,
+
+    /// Fields of struct and union types, in declaration order.
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Layout of a struct or union field.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: Ty,
+
+    /// Offset from the start of the struct or union.
+    pub offset: usize,
+    pub layout: Layout,
+}
+
+impl Layout {
+    /// Compute the layout of a type.
+    pub fn of(ty: &Ty) -> Result<Layout, Error> {
+        let layout = match ty {
+            Ty::U8 | Ty::I8 | Ty::Bool => Layout::scalar(1),
+            Ty::U16 | Ty::I16 => Layout::scalar(2),
+            Ty::Ptr(_) => Layout::scalar(2),
+            Ty::Unit => Layout::scalar(0),
+            Ty::Fn(..) => return Err(Error::NotStored(ty.clone())),
+            Ty::Array(element, len) => {
+                let element = Layout::of(element)?;
+                Layout {
+                    size: element
+                        .size
+                        .checked_mul(*len)
+                        .ok_or_else(|| Error::TooLarge(ty.clone()))?,
+                    align: element.align,
+                    fields: Vec::new(),
+                }
+            }
+            Ty::Struct(fields) => {
+                let mut layout = Layout::scalar(0);
+                for field in fields {
+                    let field_layout = Layout::of(&field.ty)?;
+                    let offset = align_to(layout.size, field_layout.align);
+                    layout.size = offset + field_layout.size;
+                    layout.align = layout.align.max(field_layout.align);
+                    layout.fields.push(FieldLayout {
+                        name: field.name.clone(),
+                        ty: field.ty.clone(),
+                        offset,
+                        layout: field_layout,
+                    });
+                }
+                layout.size = align_to(layout.size, layout.align);
+                layout
+            }
+            Ty::Union(fields) => {
+                let mut layout = Layout::scalar(0);
+                for field in fields {
+                    let field_layout = Layout::of(&field.ty)?;
+                    layout.size = layout.size.max(field_layout.size);
+                    layout.align = layout.align.max(field_layout.align);
+                    layout.fields.push(FieldLayout {
+                        name: field.name.clone(),
+                        ty: field.ty.clone(),
+                        offset: 0,
+                        layout: field_layout,
+                    });
+                }
+                layout.size = align_to(layout.size, layout.align);
+                layout
+            }
+            // named types are only kept behind pointers, which have a layout of their own
+            Ty::Named(..) | Ty::Error => return Err(Error::Invalid),
+        };
+        if layout.size > ADDRESS_SPACE {
+            return Err(Error::TooLarge(ty.clone()));
+        }
+        Ok(layout)
+    }
+
+    fn scalar(size: usize) -> Layout {
+        Layout {
+            size,
+            align: 1,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Field with the given name.
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Offset of a nested field, following a path of field names.
+    pub fn offset_of(&self, path: &[&str]) -> Option<usize> {
+        let mut layout = self;
+        let mut offset = 0;
+        for name in path {
+            let field = layout.field(name)?;
+            offset += field.offset;
+            layout = &field.layout;
+        }
+        Some(offset)
+    }
+}
+
+fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+/// Laid out declaration (a `let`, a `static` or a parameter).
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub id: NodeId,
+    pub name: String,
+    pub ty: Ty,
+
+    /// Address of the first byte of the item.
+    pub address: usize,
+    pub region: Region,
+    pub layout: Layout,
+
+    /// `fn` the item is a parameter or a `let` of (not counting the `fn`s nested in it), if any.
+    pub function: Option<NodeId>,
+}
+
+/// Layouts of all the declarations of a program that take up memory.
+#[derive(Debug, Default)]
+pub struct Layouts {
+    items: Vec<Item>,
+}
+
+impl Layouts {
+    /// Laid out declarations, in source order.
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter()
+    }
+
+    /// Laid out declaration with the given node ID.
+    pub fn item(&self, id: NodeId) -> Option<&Item> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// Parameters and `let`s of a `fn`, which make up its frame.
+    pub fn frame(&self, function: NodeId) -> impl Iterator<Item = &Item> {
+        self.items
+            .iter()
+            .filter(move |item| item.function == Some(function))
+    }
+
+    /// Report of the address of every declaration and field.
+    pub fn dump(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Layouts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            writeln!(
+                f,
+                "{} @ ${:04X} in {}, size ${:04X}",
+                item.name, item.address, item.region, item.layout.size
+            )?;
+            write_fields(f, item.address, "", &item.layout)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_fields(
+    f: &mut Formatter<'_>,
+    address: usize,
+    prefix: &str,
+    layout: &Layout,
+) -> fmt::Result {
+    for field in &layout.fields {
+        let address = address + field.offset;
+        let path = format!("{}{}", prefix, field.name);
+        let ty = match &field.ty {
+            Ty::Struct(_) => "struct".to_string(),
+            Ty::Union(_) => "union".to_string(),
+            ty => ty.to_string(),
+        };
+        writeln!(f, "  ${:04X} {}: {}", address, path, ty)?;
+        write_fields(f, address, &format!("{}.", path), &field.layout)?;
+    }
+    Ok(())
+}
+
+/// Lay out the `let` and `static` declarations (and `fn` parameters) of a type checked program.
+///
+/// Statics with a fixed address (`static VRAM @ 0x8000 :: ...`) are checked against the
+/// [memory map](crate::memory), against the stack and against each other so that no two of them
+/// overlap. Every other declaration is allocated in WRAM, around the fixed ones and below the
+/// stack. Errors are reported to the diagnostics of the context.
+pub fn layout(
+    program: &Program<'_>,
+    consts: &Consts,
+    types: &Types,
+    context: &mut Context,
+) -> Layouts {
+    let mut collect = Collect {
+        consts,
+        types,
+        context,
+        entries: Vec::new(),
+        function: None,
+    };
+    program.accept(&mut collect);
+    let entries = collect.entries;
+    place(entries, context)
+}
+
+struct Entry {
+    id: NodeId,
+    name: String,
+    ty: Ty,
+    layout: Layout,
+    // span of the declared name
+    span: Span,
+    // fixed address (`None` if its evaluation failed) and its span
+    placement: Option<(Option<i64>, Span)>,
+    function: Option<NodeId>,
+}
+
+impl Entry {
+    fn end(&self, address: usize) -> usize {
+        address + self.layout.size
+    }
+}
+
+fn place(entries: Vec<Entry>, context: &mut Context) -> Layouts {
+    let stack = usize::from(STACK_TOP - STACK_SIZE)..usize::from(STACK_TOP);
+    let mut addresses: Vec<Option<usize>> = vec![None; entries.len()];
+    let mut placed: Vec<usize> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let (address, span) = match &entry.placement {
+            Some(placement) => *placement,
+            None => continue,
+        };
+        // addresses that failed to evaluate have already been reported
+        let address = match address {
+            Some(address) => address as usize,
+            None => continue,
+        };
+        let region = Region::of(address as u16);
+        // the ROM can't be written to, so statics don't belong there
+        if region == Region::Rom || !region.is_usable() {
+            let message = format!("`{}` can't be placed in {}", entry.name, region);
+            context.diagnostics.emit(Diagnostic::error(span, message));
+            continue;
+        }
+        let region_end = usize::from(*region.range().end()) + 1;
+        if entry.end(address) > region_end {
+            let message = format!(
+                "`{}` doesn't fit in {} (${:04X}..=${:04X})",
+                entry.name,
+                region,
+                region.range().start(),
+                region.range().end()
+            );
+            let label = format!("`{}` takes ${:04X} bytes", entry.name, entry.layout.size);
+            let diagnostic = Diagnostic::error(span, message).with_label(entry.span, label);
+            context.diagnostics.emit(diagnostic);
+            continue;
+        }
+        if address < stack.end && stack.start < entry.end(address) {
+            let message = format!(
+                "`{}` overlaps with the stack (${:04X}..${:04X})",
+                entry.name, stack.start, stack.end
+            );
+            context.diagnostics.emit(Diagnostic::error(span, message));
+            continue;
+        }
+        for &j in &placed {
+            let other = &entries[j];
+            let other_address = addresses[j].expect("Expected a placed item");
+            if address < other.end(other_address) && other_address < entry.end(address) {
+                let message = format!("`{}` overlaps with `{}`", entry.name, other.name);
+                let label = format!("`{}` is placed here", other.name);
+                let diagnostic =
+                    Diagnostic::error(span, message).with_label(other.placement.unwrap().1, label);
+                context.diagnostics.emit(diagnostic);
+            }
+        }
+        addresses[i] = Some(address);
+        placed.push(i);
+    }
+
+    // everything else goes to WRAM below the stack, skipping over the fixed items
+    let wram_start = usize::from(*Region::Wram.range().start());
+    let wram_end = stack.start;
+    let mut next = wram_start;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.placement.is_some() {
+            continue;
+        }
+        while let Some(end) = placed.iter().find_map(|&j| {
+            let address = addresses[j]?;
+            let end = entries[j].end(address);
+            (next < end && address < entry.end(next)).then_some(end)
+        }) {
+            next = end;
+        }
+        if entry.end(next) > wram_end {
+            let message = format!(
+                "not enough WRAM for `{}` (${:04X} bytes)",
+                entry.name, entry.layout.size
+            );
+            context
+                .diagnostics
+                .emit(Diagnostic::error(entry.span, message));
+            continue;
+        }
+        addresses[i] = Some(next);
+        next = entry.end(next);
+    }
+
+    let items = entries
+        .into_iter()
+        .zip(addresses)
+        .filter_map(|(entry, address)| {
+            let address = address?;
+            Some(Item {
+                id: entry.id,
+                name: entry.name,
+                ty: entry.ty,
+                address,
+                region: Region::of(address as u16),
+                layout: entry.layout,
+                function: entry.function,
+            })
+        })
+        .collect();
+    Layouts { items }
+}
+
+struct Collect<'a> {
+    consts: &'a Consts,
+    types: &'a Types,
+    context: &'a mut Context,
+    entries: Vec<Entry>,
+    // `fn` being visited
+    function: Option<NodeId>,
+}
+
+impl Collect<'_> {
+    fn push(
+        &mut self,
+        id: NodeId,
+        identifier: &tokens::Identifier<'_>,
+        type_: Span,
+        placement: Option<&Placement<'_>>,
+        function: Option<NodeId>,
+    ) {
+        let ty = match self.types.declaration(id) {
+            Some(ty) => ty.clone(),
+            None => return,
+        };
+        match Layout::of(&ty) {
+            Ok(layout) => self.entries.push(Entry {
+                id,
+                name: identifier.as_str().to_string(),
+                ty,
+                layout,
+                span: identifier.span(),
+                placement: placement.map(|p| (self.consts.value(p.address.id()), p.span())),
+                function,
+            }),
+            Err(Error::Invalid) => {}
+            Err(error) => {
+                let diagnostic = Diagnostic::error(type_, error.to_string());
+                self.context.diagnostics.emit(diagnostic);
+            }
+        }
+    }
+}
+
+impl<'input> Visit<'input> for Collect<'_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        match statement {
+            Statement::Let(let_) => {
+                let type_ = let_.type_.span();
+                self.push(let_.id, &let_.identifier, type_, None, self.function);
+            }
+            Statement::Static(static_) => self.push(
+                static_.id,
+                &static_.identifier,
+                static_.type_.span(),
+                static_.placement.as_ref(),
+                None,
+            ),
+            // parameters are allocated like any `let`. Reentered functions save their frame on
+            // the stack.
+            Statement::Fn(fn_) => {
+                let function = self.function.replace(fn_.id);
+                for param in fn_.params.iter() {
+                    let type_ = param.type_.span();
+                    self.push(param.id, &param.identifier, type_, None, Some(fn_.id));
+                }
+                visit::walk_statement(self, statement);
+                self.function = function;
+            }
+            _ => visit::walk_statement(self, statement),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Span;
+use crate::{FileId, Span};
 pub use error::Error;
 use std::{borrow::Cow, iter::Peekable, str::Chars};
 pub use tokens::Token;
@@ -12,47 +12,83 @@ pub fn tokenize(input: &str) -> Tokenizer<'_> {
         // an EOF token is always returned at the very end, so even if the input string is empty,
         // the iterator hasn't ended yet.
         ended: false,
+        trivia: false,
+        asm: false,
         input,
         chars: input.chars().peekable(),
-        begin: [0; 2],
-        fi: [0; 2],
+        offset: 0,
+        begin_offset: 0,
+        cursor: [1, 1],
+        begin: [1, 1],
+        fi: [1, 1],
+        file: FileId::default(),
+    }
+}
+
+/// Like [`tokenize`], but the spans of the returned tokens refer to the given file.
+pub fn tokenize_file(input: &str, file: FileId) -> Tokenizer<'_> {
+    Tokenizer {
+        file,
+        ..tokenize(input)
+    }
+}
+
+/// Like [`tokenize`], but the returned tokens include the trivia ([`Token::Comment`]) which is
+/// otherwise skipped.
+///
+/// The parser doesn't expect trivia, so this is meant for tools that need to reproduce the input
+/// source, like [`crate::fmt`].
+pub fn tokenize_with_trivia(input: &str) -> Tokenizer<'_> {
+    Tokenizer {
+        trivia: true,
+        ..tokenize(input)
     }
 }
 
 #[derive(Debug)]
 pub struct Tokenizer<'input> {
     ended: bool,
+    trivia: bool,
+    // the last token was the `asm` keyword, so a `{` opens an inline assembly block
+    asm: bool,
     input: &'input str,
     chars: Peekable<Chars<'input>>,
+    // byte offset of the next char, and of the first char of the current token.
+    offset: usize,
+    begin_offset: usize,
+    // `[line, column]` of the next char, of the first char of the current token, and of the last
+    // consumed char.
+    cursor: [usize; 2],
     begin: [usize; 2],
     fi: [usize; 2],
+    file: FileId,
 }
 
 macro_rules! handle_non_alphanum {
     ($s:ident ,) => { return None; };
     ($s:ident , $c0:expr => { $t0:ident }, $($tt:tt)*) => {
         if $s.chars.peek() == Some(&$c0) {
-            $s.chars.next().unwrap();
+            $s.bump();
             return Some(Ok(tokens::Token::$t0($crate::lex::tokens::$t0 {
-                inner: std::borrow::Cow::Borrowed($s.input),
-                span: $crate::Span::default(),
+                inner: $s.text(),
+                span: $s.span(),
             })));
         }
         handle_non_alphanum! { $s , $($tt)* }
     };
     ($s:ident , $c0:expr => { $t0:ident, $c1:expr => $t1:ident }, $($tt:tt)*) => {
         if $s.chars.peek() == Some(&$c0) {
-            $s.chars.next().unwrap();
+            $s.bump();
             return if $s.chars.peek() == Some(&$c1) {
-                $s.chars.next().unwrap();
+                $s.bump();
                 Some(Ok(tokens::Token::$t1($crate::lex::tokens::$t1 {
-                    inner: std::borrow::Cow::Borrowed($s.input),
-                    span: Default::default(),
+                    inner: $s.text(),
+                    span: $s.span(),
                 })))
             } else {
                 Some(Ok(tokens::Token::$t0($crate::lex::tokens::$t0 {
-                    inner: std::borrow::Cow::Borrowed($s.input),
-                    span: $crate::Span::default(),
+                    inner: $s.text(),
+                    span: $s.span(),
                 })))
             };
         }
@@ -62,49 +98,78 @@ macro_rules! handle_non_alphanum {
 
 macro_rules! handle_alpha {
     ($s:ident , $($e:expr => { $t0:ident } ,)*) => {
-        // TODO(german): remove alloc
-        let mut aux = String::new();
-        loop {
-            match $s.chars.peek().copied() {
-                None => break,
-                Some(c) if c.is_alphanumeric() || c == '_' => {
-                    $s.chars.next().unwrap();
-                    aux.push(c)
-                },
-                _ => break,
+        while let Some(c) = $s.chars.peek().copied() {
+            if c.is_alphanumeric() || c == '_' {
+                $s.bump();
+            } else {
+                break;
             }
         }
-        match aux.as_str() {
+        match &$s.input[$s.begin_offset..$s.offset] {
             $($e => Ok(Token::$t0(tokens::$t0 {
-                inner: Cow::Borrowed($s.input),
-                span: Span::default(),
+                inner: $s.text(),
+                span: $s.span(),
             })),)*
             _ => Ok(Token::Identifier(tokens::Identifier {
-                inner: Cow::Borrowed($s.input),
-                span: Span::default(),
+                inner: $s.text(),
+                span: $s.span(),
             })),
         }
     };
 }
 
 impl<'input> Tokenizer<'input> {
+    /// Consume the next char, keeping track of its location in the input.
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        self.fi = self.cursor;
+        if c == '\n' {
+            self.cursor = [self.cursor[0] + 1, 1];
+        } else {
+            self.cursor[1] += 1;
+        }
+        Some(c)
+    }
+
+    /// Mark the next char as the beginning of a new token.
+    fn begin_token(&mut self) {
+        self.begin_offset = self.offset;
+        self.begin = self.cursor;
+        self.fi = self.cursor;
+    }
+
+    /// Source text of the current token.
+    fn text(&self) -> Cow<'input, str> {
+        Cow::Borrowed(&self.input[self.begin_offset..self.offset])
+    }
+
+    /// Span of the current token.
+    fn span(&self) -> Span {
+        Span {
+            min: self.begin,
+            max: self.fi,
+            file: self.file,
+        }
+    }
+
     fn skip_whitespace(&mut self) -> Result<(), Error> {
-        loop {
-            match self.chars.peek() {
-                Some(c) if c.is_whitespace() => {
-                    let _ = self.chars.next().unwrap();
-                }
-                _ => return Ok(()),
+        while let Some(c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
             }
         }
+        Ok(())
     }
 
     fn next_token_eof(&mut self) -> Option<Result<Token<'input>, Error>> {
         if self.chars.peek().is_none() {
             self.ended = true;
             Some(Ok(Token::EOF(tokens::EOF {
-                inner: Cow::Borrowed(self.input),
-                span: Default::default(),
+                inner: self.text(),
+                span: self.span(),
             })))
         } else {
             None
@@ -120,6 +185,7 @@ impl<'input> Tokenizer<'input> {
             ',' => { Comma },
             '{' => { CurlyLeft },
             '}' => { CurlyRight },
+            '.' => { Dot },
             '=' => { Equals, '=' => EqualsEquals },
             '/' => { ForwardSlash, '=' => ForwardSlashEquals },
             '>' => { GreaterThan, '=' => GreaterEqualsThan },
@@ -143,76 +209,168 @@ impl<'input> Tokenizer<'input> {
             self,
             "addr" => { Addr },
             "array" => { Array },
+            "as" => { As },
             "asm" => { Asm },
+            "bool" => { Bool },
             "break" => { Break },
             "const" => { Const },
             "continue" => { Continue },
             "deref" => { Deref },
             "else" => { Else },
+            "false" => { False },
+            "fn" => { Fn },
+            "i8" => { I8 },
+            "i16" => { I16 },
             "if" => { If },
+            "import" => { Import },
             "let" => { Let },
             "loop" => { Loop },
             "ptr" => { Ptr },
+            "return" => { Return },
             "static" => { Static },
             "struct" => { Struct },
+            "true" => { True },
+            "type" => { Type },
             "union" => { Union },
             "u8" => { U8 },
+            "u16" => { U16 },
+            "use" => { Use },
             "while" => { While },
         }
     }
 
     fn next_token_num(&mut self) -> Result<Token<'input>, Error> {
-        loop {
-            // TODO(german) handle proper radix (decimal, octal, binary)
-            match self.chars.peek() {
-                #[rustfmt::skip]
-                Some('0'..='9') => { self.chars.next().unwrap(); }
-                _ => break,
+        let radix = match self.input[self.offset..].get(..2) {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            Some("0o") => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.bump();
+            self.bump();
+        }
+        let mut digits = 0;
+        while let Some(c) = self.chars.peek() {
+            if c.is_digit(radix) {
+                self.bump();
+                digits += 1;
+            } else {
+                break;
+            }
+        }
+        // numbers can't be immediately followed by identifier chars (`0x`, `12ab`, `0b102`, ...)
+        let mut invalid = digits == 0;
+        while let Some(c) = self.chars.peek() {
+            if c.is_alphanumeric() || *c == '_' {
+                self.bump();
+                invalid = true;
+            } else {
+                break;
             }
         }
-        return Ok(Token::Number(tokens::Number {
-            inner: Cow::Borrowed(self.input),
-            span: Span::default(),
-        }));
+        if invalid {
+            return Err(Error::InvalidNumberToken(self.span()));
+        }
+        Ok(Token::Number(tokens::Number {
+            inner: self.text(),
+            span: self.span(),
+        }))
     }
 
     fn next_token_alphanum(&mut self) -> Result<Token<'input>, Error> {
-        let next = self.chars.peek().expect("Expected character");
+        let next = *self.chars.peek().expect("Expected character");
         if next.is_numeric() {
             self.next_token_num()
-        } else {
+        } else if next.is_alphabetic() || next == '_' {
             self.next_token_alpha()
+        } else {
+            self.bump();
+            Err(Error::UnexpectedChar(next, self.span()))
+        }
+    }
+
+    fn next_token_comment(&mut self) -> Option<Token<'input>> {
+        if self.input[self.offset..].starts_with("//") {
+            while let Some(c) = self.chars.peek() {
+                if *c == '\n' {
+                    break;
+                }
+                self.bump();
+            }
+            Some(Token::Comment(tokens::Comment {
+                inner: self.text(),
+                span: self.span(),
+            }))
+        } else {
+            None
         }
     }
 
     fn next_token_string(&mut self) -> Option<Result<Token<'input>, Error>> {
         match self.chars.peek() {
             Some('"') => {
-                self.chars.next().unwrap();
+                self.bump();
                 loop {
-                    match self.chars.next() {
+                    match self.bump() {
                         Some('"') => {
                             return Some(Ok(Token::Str(tokens::Str {
-                                inner: Cow::Borrowed(self.input),
-                                span: Span::default(),
+                                inner: self.text(),
+                                span: self.span(),
                             })));
                         }
                         Some(_) => {}
-                        None => return Some(Err(Error::OpenEndedStringToken)),
+                        None => return Some(Err(Error::OpenEndedStringToken(self.span()))),
                     }
                 }
             }
-            _ => return None,
+            _ => None,
+        }
+    }
+
+    // The body of an inline assembly block isn't gb-lang, so it is lexed as a single token, up
+    // to the first `}` outside of an assembly comment.
+    fn next_token_asm_block(&mut self) -> Option<Result<Token<'input>, Error>> {
+        if !std::mem::take(&mut self.asm) || self.chars.peek() != Some(&'{') {
+            return None;
+        }
+        self.bump();
+        let mut comment = false;
+        loop {
+            match self.bump() {
+                Some('}') if !comment => {
+                    return Some(Ok(Token::AsmBlock(tokens::AsmBlock {
+                        inner: self.text(),
+                        span: self.span(),
+                    })));
+                }
+                Some(';') => comment = true,
+                Some('\n') => comment = false,
+                Some(_) => {}
+                None => return Some(Err(Error::OpenEndedAsmBlock(self.span()))),
+            }
         }
     }
 
     fn next_token(&mut self) -> Result<Token<'input>, Error> {
         assert!(!self.ended);
         self.skip_whitespace()?;
-        self.next_token_eof()
+        self.begin_token();
+        while let Some(comment) = self.next_token_comment() {
+            if self.trivia {
+                return Ok(comment);
+            }
+            self.skip_whitespace()?;
+            self.begin_token();
+        }
+        let token = self
+            .next_token_eof()
+            .or_else(|| self.next_token_asm_block())
             .or_else(|| self.next_token_non_alphanum())
             .or_else(|| self.next_token_string())
-            .unwrap_or_else(|| self.next_token_alphanum())
+            .unwrap_or_else(|| self.next_token_alphanum());
+        self.asm = matches!(token, Ok(Token::Asm(_)));
+        token
     }
 }
 
@@ -1,19 +1,43 @@
+use crate::{Span, Spanned};
 use std::{
     fmt,
     fmt::{Display, Formatter},
 };
 
+/// Tokenization error, spanning the offending input.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// Triggered when the input source ends with an open-ended string token.
-    OpenEndedStringToken,
+    OpenEndedStringToken(Span),
+
+    /// Triggered when the input source ends with an open-ended `asm { ... }` block.
+    OpenEndedAsmBlock(Span),
 
     /// Invalid number format.
-    InvalidNumberToken,
+    InvalidNumberToken(Span),
+
+    /// Char that doesn't begin any token.
+    UnexpectedChar(char, Span),
 }
 
 impl Display for Error {
-    fn fmt(&self, _: &mut Formatter<'_>) -> fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OpenEndedStringToken(_) => f.write_str("unterminated string"),
+            Error::OpenEndedAsmBlock(_) => f.write_str("unterminated `asm` block"),
+            Error::InvalidNumberToken(_) => f.write_str("invalid number"),
+            Error::UnexpectedChar(c, _) => write!(f, "unexpected character `{}`", c),
+        }
+    }
+}
+
+impl Spanned for Error {
+    fn span(&self) -> Span {
+        match self {
+            Error::OpenEndedStringToken(span)
+            | Error::OpenEndedAsmBlock(span)
+            | Error::InvalidNumberToken(span)
+            | Error::UnexpectedChar(_, span) => *span,
+        }
     }
 }
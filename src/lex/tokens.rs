@@ -5,8 +5,10 @@ macro_rules! tokens {
         // token structs
         $(
             #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+            #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
             $(#[$($docs_meta)+])*
             pub struct $token_name<'input> {
+                #[cfg_attr(feature = "serde", serde(rename = "text"))]
                 pub(super) inner: std::borrow::Cow<'input, str>,
                 pub(super) span: crate::Span,
             }
@@ -25,14 +27,44 @@ macro_rules! tokens {
                 }
             }
 
+            impl<'input> $token_name<'input> {
+                /// Source text of the token.
+                pub fn as_str(&self) -> &str {
+                    &self.inner
+                }
+            }
+
+            impl<'input> crate::ast::TokenGrammar<'input> for $token_name<'input> {}
+
+            impl<'input> crate::ast::visit::Walk<'input> for $token_name<'input> {
+                fn accept<V>(&self, visitor: &mut V)
+                where
+                    V: crate::ast::visit::Visit<'input> + ?Sized,
+                {
+                    visitor.visit_token(self)
+                }
+
+                fn accept_mut<V>(&mut self, visitor: &mut V)
+                where
+                    V: crate::ast::visit::VisitMut<'input> + ?Sized,
+                {
+                    visitor.visit_token_mut(self)
+                }
+
+                fn walk<V: crate::ast::visit::Visit<'input> + ?Sized>(&self, _: &mut V) {}
+
+                fn walk_mut<V: crate::ast::visit::VisitMut<'input> + ?Sized>(&mut self, _: &mut V) {}
+            }
+
             impl<'input> crate::ast::Grammar<'input> for Option<$token_name<'input>> {
                 fn parse(
                     tokens: &mut std::iter::Peekable<crate::lex::Tokenizer<'input>>,
-                    _: &mut crate::ast::Context,
+                    context: &mut crate::ast::Context,
                 ) -> Result<Self, crate::ast::Error<'input>> {
-                    match tokens.next() {
-                        Some(Ok(Token::$token_name(t))) => Ok(Some(t)),
-                        // TODO(german): consider if returning buffered Err from tokenizer makes sense.
+                    match tokens.peek() {
+                        Some(Ok(Token::$token_name(_))) => {
+                            Ok(Some(crate::ast::Grammar::parse(tokens, context)?))
+                        }
                         _ => Ok(None),
                     }
                 }
@@ -40,13 +72,36 @@ macro_rules! tokens {
         )*
 
         #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum Token<'input> {
             $($(#[$($docs_meta)+])* $token_name ($token_name<'input>),)*
         }
 
         // span trait
-        $(impl crate::Spanned for $token_name<'_> {})*
-        impl crate::Spanned for Token<'_> {}
+        $(
+            impl crate::Spanned for $token_name<'_> {
+                fn span(&self) -> crate::Span {
+                    self.span
+                }
+            }
+        )*
+
+        impl crate::Spanned for Token<'_> {
+            fn span(&self) -> crate::Span {
+                match self {
+                    $(Token::$token_name(t) => t.span,)*
+                }
+            }
+        }
+
+        impl Token<'_> {
+            /// Source text of the token.
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Token::$token_name(t) => t.as_str(),)*
+                }
+            }
+        }
     }
 }
 
@@ -59,6 +114,14 @@ tokens! {
     pub struct Number;
     /// `"Hello, world!"`
     pub struct Str;
+    /// `// ...`
+    ///
+    /// Trivia, only returned by [`crate::lex::tokenize_with_trivia`].
+    pub struct Comment;
+    /// `{ ld a, [hl] }`
+    ///
+    /// Body of an `asm` block, which is only lexed right after the `asm` keyword.
+    pub struct AsmBlock;
 
     // keywords
 
@@ -66,8 +129,12 @@ tokens! {
     pub struct Addr;
     /// `array`
     pub struct Array;
+    /// `as`
+    pub struct As;
     /// `asm`
     pub struct Asm;
+    /// `bool`
+    pub struct Bool;
     /// `break`
     pub struct Break;
     /// `const`
@@ -78,22 +145,42 @@ tokens! {
     pub struct Deref;
     /// `else`
     pub struct Else;
+    /// `false`
+    pub struct False;
+    /// `fn`
+    pub struct Fn;
+    /// `i8`
+    pub struct I8;
+    /// `i16`
+    pub struct I16;
     /// `if`
     pub struct If;
+    /// `import`
+    pub struct Import;
     /// `let`
     pub struct Let;
     /// `loop`
     pub struct Loop;
     /// `ptr`
     pub struct Ptr;
+    /// `return`
+    pub struct Return;
     /// `static`
     pub struct Static;
     /// `struct`
     pub struct Struct;
+    /// `true`
+    pub struct True;
+    /// `type`
+    pub struct Type;
     /// `union`
     pub struct Union;
     /// 'u8'
     pub struct U8;
+    /// `u16`
+    pub struct U16;
+    /// `use`
+    pub struct Use;
     /// `while`
     pub struct While;
 
@@ -138,6 +225,8 @@ tokens! {
     pub struct CurlyLeft;
     /// `}`
     pub struct CurlyRight;
+    /// `.`
+    pub struct Dot;
     /// `=`
     pub struct Equals;
     /// `/`
@@ -169,3 +258,70 @@ tokens! {
     /// `^`
     pub  struct Xor;
 }
+
+// `ptr(<expression>)` takes the address of an expression, like `addr(<expression>)`.
+impl<'input> From<Ptr<'input>> for Addr<'input> {
+    fn from(ptr: Ptr<'input>) -> Self {
+        Addr {
+            inner: ptr.inner,
+            span: ptr.span,
+        }
+    }
+}
+
+impl Number<'_> {
+    /// Value of the literal, or `None` if it doesn't fit in a `u64`.
+    pub fn value(&self) -> Option<u64> {
+        let text = self.as_str();
+        let (radix, digits) = match text.get(..2) {
+            Some("0x") => (16, &text[2..]),
+            Some("0b") => (2, &text[2..]),
+            Some("0o") => (8, &text[2..]),
+            _ => (10, text),
+        };
+        u64::from_str_radix(digits, radix).ok()
+    }
+}
+
+impl AsmBlock<'_> {
+    /// Assembly source of the block, without the curly braces.
+    pub fn contents(&self) -> &str {
+        &self.inner[1..self.inner.len() - 1]
+    }
+
+    /// Span of `len` bytes of the [contents](Self::contents), starting at byte `offset`.
+    pub fn span_of(&self, offset: usize, len: usize) -> crate::Span {
+        // the contents start right after the opening curly brace
+        let mut location = [self.span.min[0], self.span.min[1] + 1];
+        let mut min = location;
+        for (i, c) in self.contents().char_indices() {
+            if i == offset {
+                min = location;
+            }
+            if i + c.len_utf8() >= offset + len {
+                return crate::Span {
+                    min,
+                    max: location,
+                    file: self.span.file,
+                };
+            }
+            if c == '\n' {
+                location = [location[0] + 1, 1];
+            } else {
+                location[1] += 1;
+            }
+        }
+        crate::Span {
+            min,
+            max: min,
+            file: self.span.file,
+        }
+    }
+}
+
+impl Str<'_> {
+    /// Contents of the literal, without the quotes.
+    pub fn contents(&self) -> &str {
+        &self.inner[1..self.inner.len() - 1]
+    }
+}
@@ -1,25 +1,121 @@
 pub use ast::parse;
+pub use compile::{compile, compile_program, Compiled};
 pub use lex::tokenize;
 
+pub mod asm;
 pub mod ast;
+pub mod cfg;
+mod checked;
+pub mod codegen;
+pub mod compile;
+pub mod consts;
+pub mod cpu;
+pub mod debug;
+pub mod diagnostics;
+pub mod flow;
+pub mod fmt;
+pub mod interp;
+pub mod ir;
+pub mod layout;
 pub mod lex;
+pub mod link;
+pub mod loader;
+pub mod memory;
+pub mod prelude;
+pub mod resolve;
+pub mod rgbds;
+pub mod rom;
+#[cfg(feature = "serde")]
+pub mod sexp;
+pub mod typeck;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+/// Identifier of a source file, among the files [loaded](loader) for a program.
+///
+/// The [`Default`] ID is the one of the first file, which is the only one when the input isn't
+/// split across files.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub u32);
+
+impl FileId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    #[cfg(feature = "serde")]
+    fn is_default(&self) -> bool {
+        *self == FileId::default()
+    }
+}
+
+/// Region of the source input.
+///
+/// Lines and columns start at `1`, so the [`Default`] span (`[0, 0]`) never refers to any real
+/// location. It is used by nodes that don't consume any input.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     /// Location of upper-left-most char `[line, column]`.
     pub min: [usize; 2],
 
     /// Location of bottom-right-most char `[line, column]`.
     pub max: [usize; 2],
+
+    /// File the lines and columns refer to.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "FileId::is_default")
+    )]
+    pub file: FileId,
 }
 
-impl Default for Span {
-    fn default() -> Self {
-        Self {
-            min: [0, 0],
-            max: [0, 0],
+impl Span {
+    /// Smallest span that covers both `self` and `other`.
+    ///
+    /// Spans that don't refer to any location are ignored, so they can be used as the identity.
+    /// Spans of different files can't be joined: the result is `self`.
+    pub fn join(self, other: Span) -> Span {
+        if self.min == [0, 0] {
+            other
+        } else if other.min == [0, 0] || other.file != self.file {
+            self
+        } else {
+            Span {
+                min: self.min.min(other.min),
+                max: self.max.max(other.max),
+                file: self.file,
+            }
         }
     }
 }
 
-pub trait Spanned {}
+/// Syntax elements that map to a region of the source input.
+pub trait Spanned {
+    fn span(&self) -> Span;
+}
+
+impl<S: Spanned + ?Sized> Spanned for Box<S> {
+    fn span(&self) -> Span {
+        S::span(self)
+    }
+}
+
+impl<S: Spanned> Spanned for Option<S> {
+    fn span(&self) -> Span {
+        self.as_ref().map(Spanned::span).unwrap_or_default()
+    }
+}
+
+impl<S: Spanned> Spanned for Vec<S> {
+    fn span(&self) -> Span {
+        self.iter()
+            .map(Spanned::span)
+            .fold(Span::default(), Span::join)
+    }
+}
+
+impl Spanned for () {
+    fn span(&self) -> Span {
+        Span::default()
+    }
+}
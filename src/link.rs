@@ -0,0 +1,841 @@
+//! Linker.
+//!
+//! Places [sections](Section) of code, data and variables in the memory map, and builds the ROM
+//! image of the program. A section belongs to a [`Kind`] of memory, and can be given a bank and
+//! an address. The others are placed by the linker, in the first bank with enough free space
+//! (and at the lowest address that fits), in the order they are given. Sections with a fixed
+//! address are placed first, then sections with a fixed bank. Floating `ROM0` sections start
+//! after the cartridge header, which leaves the interrupt vectors to fixed sections.
+//!
+//! Only one `ROMX` bank is mapped at a time, so the linker generates a banking runtime for
+//! cartridges with a memory bank controller. Calls to a `ROMX` bank that isn't the caller's go
+//! through a trampoline in `ROM0` (`__far.NAME`), which switches banks around the call. The
+//! current bank is kept at the top of HRAM ([`CURRENT_BANK`], at [`BANKING`]), past the end of
+//! the `HRAM` of the [hardware module](crate::prelude). Trampolines keep the results of the call
+//! (in `a` and `hl`), but not `bc` and `de`. Other references to labels in a different `ROMX`
+//! bank are errors.
+//!
+//! The program starts at [`INIT`]. Variables are laid out by [`layout`](crate::layout), and can
+//! be added as [sections](variables) to be checked against the rest of memory (and to give
+//! `static`s symbols), along with the [scratch] area and stack of the generated code.
+//! The symbols and the [spans](crate::asm::Assembly::spans) of the sections make up the
+//! [debug information](crate::debug) of the program.
+//!
+//! ```
+//! use gb_lang::{
+//!     asm::parse::parse,
+//!     link::{link, Kind, Section},
+//!     rom::{Cartridge, Header},
+//! };
+//!
+//! let main = parse("__init:\n    call far\n    halt").unwrap();
+//! let far = parse("far:\n    ret").unwrap();
+//! let sections = [
+//!     Section::new("main", Kind::Rom0, main),
+//!     Section::new("far", Kind::Romx, far).in_bank(3),
+//! ];
+//! let header = Header {
+//!     cartridge: Cartridge::Mbc1 { ram: false, battery: false },
+//!     ..Default::default()
+//! };
+//! let linked = link(&sections, &header).unwrap();
+//! assert_eq!(0x10000, linked.rom.len());
+//! assert_eq!("ROMX[3]", linked.symbols["far"].bank.to_string());
+//! assert!(linked.symbols.contains_key("__far.far"));
+//! ```
+use crate::{
+    asm::{
+        encode, Alu, Assembly, Instruction, Line, Reg, Reg16, Shift, Source, StackReg, Target,
+        Value,
+    },
+    codegen::{self, INIT},
+    ir,
+    layout::{Layouts, STACK_SIZE, STACK_TOP},
+    memory::Region,
+    resolve::{DeclarationKind, Resolution},
+    rom::{self, Cartridge, Cgb, Header},
+    Span,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fmt::{Display, Formatter},
+    ops::RangeInclusive,
+};
+
+/// Label of the HRAM byte that holds the current `ROMX` bank (the high bit of MBC5 banks is in
+/// the next byte).
+pub const CURRENT_BANK: &str = "__rom_bank";
+
+/// Address of the HRAM bytes of the banking runtime: [`CURRENT_BANK`], and the high bit of MBC5
+/// banks.
+pub const BANKING: u16 = 0xfffd;
+
+/// Label of the routine that switches to the `ROMX` bank in `de`.
+pub const SWITCH_BANK: &str = "__switch_bank";
+
+/// Label of the first instruction of cartridges with a memory bank controller, which maps the
+/// first `ROMX` bank before jumping to [`INIT`].
+pub const ENTRY: &str = "__entry";
+
+// MBC registers that select the ROM bank.
+const ROM_BANK_LOW: u16 = 0x2000;
+const ROM_BANK_HIGH: u16 = 0x3000;
+const MBC1_BANK_HIGH: u16 = 0x4000;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("section `{section}` holds instructions, which can't be placed in {kind}")]
+    CodeInRam { section: String, kind: Kind },
+
+    #[error("section `{section}` can't be placed in {bank}, which the cartridge doesn't have")]
+    InvalidBank { section: String, bank: Bank },
+
+    #[error("section `{section}` can't be placed at ${address:04X}, outside of {kind}")]
+    InvalidAddress {
+        section: String,
+        kind: Kind,
+        address: u16,
+    },
+
+    #[error("section `{section}` overlaps section `{other}` in {bank}")]
+    Overlap {
+        section: String,
+        other: String,
+        bank: Bank,
+    },
+
+    #[error("section `{section}` (${size:04X} bytes) doesn't fit in {}", location(*.kind, *.bank))]
+    Overflow {
+        section: String,
+        size: usize,
+        kind: Kind,
+        bank: Option<u16>,
+
+        /// Usage of memory when the section was being placed.
+        map: Map,
+    },
+
+    #[error("label `{0}` is defined more than once")]
+    DuplicateLabel(String),
+
+    #[error("`{label}` is in {bank}, which isn't mapped from section `{section}` (only calls can cross ROM banks)")]
+    FarReference {
+        section: String,
+        label: String,
+        bank: Bank,
+    },
+
+    #[error("undefined entry point `{0}`")]
+    MissingEntry(String),
+
+    #[error("in section `{section}`: {error}")]
+    Encode {
+        section: String,
+        error: encode::Error,
+    },
+
+    #[error(transparent)]
+    Rom(#[from] rom::Error),
+}
+
+fn location(kind: Kind, bank: Option<u16>) -> String {
+    match bank {
+        Some(number) => Bank { kind, number }.to_string(),
+        None if kind.is_banked() => format!("any {} bank", kind),
+        None => kind.to_string(),
+    }
+}
+
+/// Kind of memory a section is placed in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    /// ROM bank 0, always mapped at `$0000-$3FFF`.
+    Rom0,
+    /// Switchable ROM banks, mapped at `$4000-$7FFF`.
+    Romx,
+    /// Cartridge RAM banks.
+    Sram,
+    /// Work RAM bank 0, at `$C000-$CFFF`.
+    Wram0,
+    /// Work RAM bank 1, at `$D000-$DFFF` (or banks 1 to 7, on the Game Boy Color).
+    Wramx,
+    /// High RAM.
+    Hram,
+}
+
+impl Kind {
+    /// Addresses of the kind of memory.
+    pub fn range(self) -> RangeInclusive<u16> {
+        match self {
+            Kind::Rom0 => 0x0000..=0x3fff,
+            Kind::Romx => 0x4000..=0x7fff,
+            Kind::Sram => Region::Sram.range(),
+            Kind::Wram0 => 0xc000..=0xcfff,
+            Kind::Wramx => 0xd000..=0xdfff,
+            Kind::Hram => Region::Hram.range(),
+        }
+    }
+
+    /// Whether the kind of memory is in the cartridge ROM.
+    pub fn is_rom(self) -> bool {
+        matches!(self, Kind::Rom0 | Kind::Romx)
+    }
+
+    /// Whether the kind of memory has switchable banks.
+    pub fn is_banked(self) -> bool {
+        matches!(self, Kind::Romx | Kind::Sram | Kind::Wramx)
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Rom0 => "ROM0",
+            Kind::Romx => "ROMX",
+            Kind::Sram => "SRAM",
+            Kind::Wram0 => "WRAM0",
+            Kind::Wramx => "WRAMX",
+            Kind::Hram => "HRAM",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Bank of a kind of memory. Kinds that aren't banked only have bank `0`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Bank {
+    pub kind: Kind,
+    pub number: u16,
+}
+
+impl Display for Bank {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.kind.is_banked() {
+            write!(f, "{}[{}]", self.kind, self.number)
+        } else {
+            write!(f, "{}", self.kind)
+        }
+    }
+}
+
+/// Code, data or variables, placed as a whole.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: Kind,
+
+    /// Bank the section must be placed in, or `None` to let the linker pick one.
+    pub bank: Option<u16>,
+
+    /// Address the section must start at, or `None` to let the linker pick one.
+    pub address: Option<u16>,
+
+    /// Contents of the section. RAM sections only hold labels and data, which reserves space
+    /// (but isn't written anywhere).
+    pub assembly: Assembly,
+}
+
+impl Section {
+    pub fn new(name: impl Into<String>, kind: Kind, assembly: Assembly) -> Self {
+        Section {
+            name: name.into(),
+            kind,
+            bank: None,
+            address: None,
+            assembly,
+        }
+    }
+
+    /// Place the section in a given bank.
+    pub fn in_bank(mut self, bank: u16) -> Self {
+        self.bank = Some(bank);
+        self
+    }
+
+    /// Place the section at a given address.
+    pub fn at(mut self, address: u16) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Number of bytes the section takes up.
+    pub fn size(&self) -> usize {
+        self.assembly
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Label(_) => 0,
+                Line::Instruction(instruction) => usize::from(instruction.size()),
+                Line::Data(bytes) => bytes.len(),
+            })
+            .sum()
+    }
+}
+
+/// Sections of the variables of a program (`static`s, `let`s and parameters), at the addresses
+/// of their layout, in WRAM, HRAM and cartridge RAM (bank 0). Every section is named after its
+/// variable, and the sections of `static`s start with a label of the same name (qualified by
+/// their [module](crate::loader::Files::label)). A variable that
+/// spans both WRAM banks is split in two sections.
+pub fn variables(layouts: &Layouts, resolution: &Resolution) -> Vec<Section> {
+    let mut sections = Vec::new();
+    for item in layouts.items() {
+        let declaration = resolution.declaration(item.id);
+        let label =
+            declaration.map(|declaration| declaration.kind) == Some(DeclarationKind::Static);
+        let name = resolution.label(item.id).unwrap_or(&item.name);
+        sections.extend(reserve(name, label, item.address, item.layout.size));
+    }
+    sections
+}
+
+/// Sections of the WRAM the generated code of a program uses besides its variables: the
+/// [scratch area](codegen::scratch) of arguments and spilled temporaries (`__scratch`), and the
+/// stack (`__stack`).
+pub fn scratch(program: &ir::Program, layouts: &Layouts) -> Vec<Section> {
+    let scratch = codegen::scratch(program, layouts);
+    let stack = usize::from(STACK_TOP - STACK_SIZE);
+    let mut sections = reserve("__scratch", false, scratch.start, scratch.len());
+    sections.extend(reserve("__stack", false, stack, usize::from(STACK_SIZE)));
+    sections
+}
+
+// Sections reserving `size` bytes of RAM at `address` (split at the end of WRAM0), starting with
+// a label named after them if `label` is set.
+fn reserve(name: &str, label: bool, address: usize, size: usize) -> Vec<Section> {
+    let (kind, bank) = match Region::of(address as u16) {
+        _ if size == 0 => return Vec::new(),
+        Region::Wram if address < 0xd000 => (Kind::Wram0, 0),
+        Region::Wram => (Kind::Wramx, 1),
+        Region::Hram => (Kind::Hram, 0),
+        Region::Sram => (Kind::Sram, 0),
+        // memory mapped hardware takes no room in the sections of the program
+        Region::Vram | Region::Oam | Region::Io | Region::Ie => return Vec::new(),
+        region => unreachable!("Expected the layout to reject items in {}", region),
+    };
+    let mut assembly = Assembly::default();
+    if label {
+        assembly.label(name);
+    }
+    let end = usize::from(*kind.range().end()) + 1;
+    let head = size.min(end.saturating_sub(address));
+    assembly.lines.push(Line::Data(vec![0; head]));
+    let section = Section::new(name, kind, assembly)
+        .in_bank(bank)
+        .at(address as u16);
+    let mut sections = vec![section];
+    if kind == Kind::Wram0 && head < size {
+        sections.extend(reserve(name, false, end, size - head));
+    }
+    sections
+}
+
+/// Address of a label, and the bank it is in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Symbol {
+    pub bank: Bank,
+    pub address: u16,
+}
+
+/// Bytes of the ROM generated from a span of the source.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Mapping {
+    pub bank: Bank,
+    pub address: u16,
+    pub size: u16,
+    pub span: Span,
+}
+
+/// Section placed in a bank.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Placement {
+    pub name: String,
+    pub address: u16,
+    pub size: usize,
+}
+
+/// Sections placed in a bank, by address.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Usage {
+    pub bank: Bank,
+    pub sections: Vec<Placement>,
+}
+
+impl Usage {
+    /// Number of bytes of the bank taken up by sections.
+    pub fn used(&self) -> usize {
+        self.sections.iter().map(|section| section.size).sum()
+    }
+
+    /// Number of bytes of the bank left free.
+    pub fn free(&self) -> usize {
+        let range = self.bank.kind.range();
+        usize::from(range.end() - range.start()) + 1 - self.used()
+    }
+}
+
+/// Usage of every bank that holds sections.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Map {
+    pub banks: Vec<Usage>,
+}
+
+impl Map {
+    /// Usage of a bank, if anything is placed in it.
+    pub fn usage(&self, bank: Bank) -> Option<&Usage> {
+        self.banks.iter().find(|usage| usage.bank == bank)
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for usage in &self.banks {
+            writeln!(
+                f,
+                "{}: ${:04X} bytes used, ${:04X} free",
+                usage.bank,
+                usage.used(),
+                usage.free()
+            )?;
+            for section in &usage.sections {
+                writeln!(
+                    f,
+                    "    ${:04X} (${:04X} bytes) {}",
+                    section.address, section.size, section.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Linked program.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Linked {
+    /// ROM image, with its header.
+    pub rom: Vec<u8>,
+
+    /// Every label of the sections (and of the banking runtime).
+    pub symbols: BTreeMap<String, Symbol>,
+    pub map: Map,
+
+    /// Bytes of the ROM generated from the source, by bank and address.
+    pub source_map: Vec<Mapping>,
+}
+
+/// Link sections into the ROM image of a cartridge. The banks that exist are given by the
+/// cartridge type, the RAM size and the Game Boy Color support of the header.
+pub fn link(sections: &[Section], header: &Header) -> Result<Linked, Error> {
+    let mut linker = Linker {
+        header,
+        sections: sections.to_vec(),
+        placements: vec![None; sections.len()],
+        banks: BTreeMap::new(),
+        symbols: BTreeMap::new(),
+    };
+    let rom0 = Bank {
+        kind: Kind::Rom0,
+        number: 0,
+    };
+    let header_placement = Placement {
+        name: "header".to_string(),
+        address: rom::ENTRY,
+        size: usize::from(rom::CODE - rom::ENTRY),
+    };
+    linker.banks.insert(rom0, vec![header_placement]);
+
+    for section in &linker.sections {
+        linker.check(section)?;
+    }
+    linker.place_all()?;
+    linker.define(0)?;
+    let banked = header.cartridge != Cartridge::RomOnly;
+    if banked {
+        let far = linker.far_calls()?;
+        let (code, variables) = runtime(header.cartridge, &far, &linker.symbols);
+        let start = linker.sections.len();
+        linker.sections.extend([code, variables]);
+        linker.placements.extend([None, None]);
+        linker.place_all()?;
+        linker.define(start)?;
+    }
+
+    let in_rom0 = |label: &str| match linker.symbols.get(label) {
+        Some(symbol) if symbol.bank == rom0 => Ok(symbol.address),
+        _ => Err(Error::MissingEntry(label.to_string())),
+    };
+    in_rom0(INIT)?;
+    let entry = in_rom0(if banked { ENTRY } else { INIT })?;
+    let (mut bytes, mut source_map) = linker.encode()?;
+    source_map.sort_by_key(|mapping| (mapping.bank, mapping.address));
+    let start = usize::from(rom::ENTRY);
+    bytes[start..start + 4].copy_from_slice(&rom::jump(entry));
+    rom::write_header(&mut bytes, header)?;
+    Ok(Linked {
+        rom: bytes,
+        map: linker.map(),
+        symbols: linker.symbols,
+        source_map,
+    })
+}
+
+struct Linker<'a> {
+    header: &'a Header,
+    sections: Vec<Section>,
+    placements: Vec<Option<(Bank, u16)>>,
+    banks: BTreeMap<Bank, Vec<Placement>>,
+    symbols: BTreeMap<String, Symbol>,
+}
+
+impl Linker<'_> {
+    /// Banks of a kind of memory that the cartridge has.
+    fn banks_of(&self, kind: Kind) -> Vec<u16> {
+        match kind {
+            Kind::Rom0 | Kind::Wram0 | Kind::Hram => vec![0],
+            Kind::Romx => {
+                let cartridge = self.header.cartridge;
+                let mbc1 = matches!(cartridge, Cartridge::Mbc1 { .. });
+                // MBC1 maps banks $20, $40 and $60 to the bank after them
+                (1..cartridge.rom_banks())
+                    .filter(|bank| !(mbc1 && bank % 0x20 == 0))
+                    .collect()
+            }
+            Kind::Sram => (0..self.header.ram_size.banks()).collect(),
+            Kind::Wramx if self.header.cgb == Cgb::None => vec![1],
+            Kind::Wramx => (1..=7).collect(),
+        }
+    }
+
+    fn check(&self, section: &Section) -> Result<(), Error> {
+        let has_code = section
+            .assembly
+            .lines
+            .iter()
+            .any(|line| matches!(line, Line::Instruction(_)));
+        if has_code && !section.kind.is_rom() {
+            return Err(Error::CodeInRam {
+                section: section.name.clone(),
+                kind: section.kind,
+            });
+        }
+        if let Some(number) = section.bank {
+            if !self.banks_of(section.kind).contains(&number) {
+                let bank = Bank {
+                    kind: section.kind,
+                    number,
+                };
+                return Err(Error::InvalidBank {
+                    section: section.name.clone(),
+                    bank,
+                });
+            }
+        }
+        if let Some(address) = section.address {
+            if !section.kind.range().contains(&address) {
+                return Err(Error::InvalidAddress {
+                    section: section.name.clone(),
+                    kind: section.kind,
+                    address,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Place the sections that haven't been yet: the ones with a fixed address first, then the
+    /// ones with a fixed bank, then the others.
+    fn place_all(&mut self) -> Result<(), Error> {
+        let mut order: Vec<_> = (0..self.sections.len())
+            .filter(|i| self.placements[*i].is_none())
+            .collect();
+        order.sort_by_key(|i| {
+            let section = &self.sections[*i];
+            (section.address.is_none(), section.bank.is_none())
+        });
+        for i in order {
+            let placement = self.place(i)?;
+            self.placements[i] = Some(placement);
+        }
+        Ok(())
+    }
+
+    fn place(&mut self, i: usize) -> Result<(Bank, u16), Error> {
+        let section = &self.sections[i];
+        let kind = section.kind;
+        let size = section.size();
+        let candidates = match section.bank {
+            Some(number) => vec![number],
+            None => self.banks_of(kind),
+        };
+        let range = kind.range();
+        let end = usize::from(*range.end()) + 1;
+        let first = match kind {
+            Kind::Rom0 => rom::CODE,
+            _ => *range.start(),
+        };
+        for number in &candidates {
+            let bank = Bank {
+                kind,
+                number: *number,
+            };
+            let placed = self.banks.get(&bank).map(Vec::as_slice).unwrap_or(&[]);
+            let overlap = |start: usize| {
+                placed.iter().find(|other| {
+                    let other_start = usize::from(other.address);
+                    start < other_start + other.size && other_start < start + size
+                })
+            };
+            let address = match section.address {
+                Some(address) => {
+                    let address = usize::from(address);
+                    match overlap(address) {
+                        Some(other) if candidates.len() == 1 => {
+                            return Err(Error::Overlap {
+                                section: section.name.clone(),
+                                other: other.name.clone(),
+                                bank,
+                            })
+                        }
+                        Some(_) => continue,
+                        None => address,
+                    }
+                }
+                None => {
+                    let mut address = usize::from(first);
+                    while let Some(other) = overlap(address) {
+                        address = usize::from(other.address) + other.size;
+                    }
+                    address
+                }
+            };
+            if address + size <= end {
+                let placement = Placement {
+                    name: section.name.clone(),
+                    address: address as u16,
+                    size,
+                };
+                let placed = self.banks.entry(bank).or_default();
+                placed.push(placement);
+                placed.sort_by_key(|placement| placement.address);
+                return Ok((bank, address as u16));
+            }
+        }
+        Err(Error::Overflow {
+            section: section.name.clone(),
+            size,
+            kind,
+            bank: section.bank,
+            map: self.map(),
+        })
+    }
+
+    /// Define the labels of the sections from the `start`th one.
+    fn define(&mut self, start: usize) -> Result<(), Error> {
+        for (section, placement) in self.sections.iter().zip(&self.placements).skip(start) {
+            let (bank, mut address) = placement.expect("Expected a placed section");
+            for line in &section.assembly.lines {
+                match line {
+                    Line::Label(label) => {
+                        let symbol = Symbol { bank, address };
+                        if self.symbols.insert(label.clone(), symbol).is_some() {
+                            return Err(Error::DuplicateLabel(label.clone()));
+                        }
+                    }
+                    Line::Instruction(instruction) => {
+                        address = address.wrapping_add(instruction.size())
+                    }
+                    Line::Data(bytes) => address = address.wrapping_add(bytes.len() as u16),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Redirect calls to other `ROMX` banks to trampolines, and return the labels they call.
+    fn far_calls(&mut self) -> Result<BTreeSet<String>, Error> {
+        let mut far = BTreeSet::new();
+        for (section, placement) in self.sections.iter_mut().zip(&self.placements) {
+            let (bank, _) = placement.expect("Expected a placed section");
+            if !bank.kind.is_rom() {
+                continue;
+            }
+            for line in &mut section.assembly.lines {
+                let instruction = match line {
+                    Line::Instruction(instruction) => instruction,
+                    _ => continue,
+                };
+                let is_call = matches!(instruction, Instruction::Call(..));
+                let label = match instruction.value_mut() {
+                    Some(Value::Label(label)) => label,
+                    _ => continue,
+                };
+                let target = match self.symbols.get(label.as_str()) {
+                    Some(symbol) if symbol.bank.kind == Kind::Romx && symbol.bank != bank => {
+                        symbol.bank
+                    }
+                    _ => continue,
+                };
+                if !is_call {
+                    return Err(Error::FarReference {
+                        section: section.name.clone(),
+                        label: label.clone(),
+                        bank: target,
+                    });
+                }
+                far.insert(label.clone());
+                *label = far_label(label);
+            }
+        }
+        Ok(far)
+    }
+
+    /// Bytes of every ROM bank, up to the last one that holds sections, and the spans of the
+    /// source they come from.
+    fn encode(&self) -> Result<(Vec<u8>, Vec<Mapping>), Error> {
+        let banks = self
+            .placements
+            .iter()
+            .flatten()
+            .filter(|(bank, _)| bank.kind.is_rom())
+            .map(|(bank, _)| usize::from(bank.number) + 1)
+            .max()
+            .unwrap_or(0)
+            .max(2);
+        let mut bytes = vec![rom::PADDING; banks * rom::BANK_SIZE];
+        let mut mappings: Vec<Mapping> = Vec::new();
+        for (section, placement) in self.sections.iter().zip(&self.placements) {
+            let (bank, mut address) = placement.expect("Expected a placed section");
+            if !bank.kind.is_rom() {
+                continue;
+            }
+            let resolve = |label: &str| self.symbols.get(label).map(|symbol| symbol.address);
+            let mut offset = usize::from(bank.number) * rom::BANK_SIZE
+                + usize::from(address - bank.kind.range().start());
+            for (i, line) in section.assembly.lines.iter().enumerate() {
+                let encoded = match line {
+                    Line::Label(_) => continue,
+                    Line::Instruction(instruction) => instruction
+                        .encode(address, resolve)
+                        .map_err(|error| Error::Encode {
+                            section: section.name.clone(),
+                            error,
+                        })?,
+                    Line::Data(bytes) => bytes.clone(),
+                };
+                bytes[offset..offset + encoded.len()].copy_from_slice(&encoded);
+                let span = section.assembly.span_of(i);
+                match mappings.last_mut() {
+                    _ if span == Span::default() || encoded.is_empty() => {}
+                    Some(last)
+                        if last.span == span
+                            && last.bank == bank
+                            && last.address.wrapping_add(last.size) == address =>
+                    {
+                        last.size += encoded.len() as u16
+                    }
+                    _ => mappings.push(Mapping {
+                        bank,
+                        address,
+                        size: encoded.len() as u16,
+                        span,
+                    }),
+                }
+                offset += encoded.len();
+                address = address.wrapping_add(encoded.len() as u16);
+            }
+        }
+        Ok((bytes, mappings))
+    }
+
+    fn map(&self) -> Map {
+        let banks = self
+            .banks
+            .iter()
+            .map(|(bank, sections)| Usage {
+                bank: *bank,
+                sections: sections.clone(),
+            })
+            .collect();
+        Map { banks }
+    }
+}
+
+/// Label of the trampoline that calls a label in another `ROMX` bank.
+pub fn far_label(label: &str) -> String {
+    format!("__far.{}", label)
+}
+
+/// Banking runtime: the entry point, the bank switching routine and the trampolines (in `ROM0`),
+/// and the current bank (at [`BANKING`]).
+fn runtime(
+    cartridge: Cartridge,
+    far: &BTreeSet<String>,
+    symbols: &BTreeMap<String, Symbol>,
+) -> (Section, Section) {
+    use Instruction::*;
+    let mbc5 = matches!(cartridge, Cartridge::Mbc5 { .. });
+    let high = format!("{}.high", CURRENT_BANK);
+    let mut code = Assembly::default();
+
+    code.label(ENTRY);
+    code.push(Ld16(Reg16::DE, Value::Number(1)));
+    code.push(Call(None, Value::label(SWITCH_BANK)));
+    code.push(Jp(None, Value::label(INIT)));
+
+    // switch to the bank in `de`, only using `a`
+    code.label(SWITCH_BANK);
+    code.push(Ld(Reg::A, Source::Reg(Reg::E)));
+    code.push(LdAddressA(Value::label(CURRENT_BANK)));
+    code.push(LdAddressA(Value::Number(ROM_BANK_LOW)));
+    match cartridge {
+        Cartridge::Mbc1 { .. } => {
+            // bits 5 and 6 of the bank go to the other register
+            code.push(Shift(self::Shift::Swap, Target::Reg(Reg::A)));
+            code.push(Rrca);
+            code.push(Alu(self::Alu::And, Source::Imm(0x03)));
+            code.push(LdAddressA(Value::Number(MBC1_BANK_HIGH)));
+        }
+        Cartridge::Mbc5 { .. } => {
+            code.push(Ld(Reg::A, Source::Reg(Reg::D)));
+            code.push(LdAddressA(Value::label(high.as_str())));
+            code.push(LdAddressA(Value::Number(ROM_BANK_HIGH)));
+        }
+        _ => {}
+    }
+    code.push(Ret(None));
+
+    for label in far {
+        let bank = symbols[label].bank.number;
+        code.label(far_label(label));
+        code.push(LdAFromAddress(Value::label(CURRENT_BANK)));
+        code.push(Ld(Reg::E, Source::Reg(Reg::A)));
+        if mbc5 {
+            code.push(LdAFromAddress(Value::label(high.as_str())));
+            code.push(Ld(Reg::D, Source::Reg(Reg::A)));
+        }
+        code.push(Push(StackReg::DE));
+        code.push(Ld16(Reg16::DE, Value::Number(bank)));
+        code.push(Call(None, Value::label(SWITCH_BANK)));
+        code.push(Call(None, Value::label(label.as_str())));
+        code.push(Ld(Reg::B, Source::Reg(Reg::A)));
+        code.push(Pop(StackReg::DE));
+        code.push(Call(None, Value::label(SWITCH_BANK)));
+        code.push(Ld(Reg::A, Source::Reg(Reg::B)));
+        code.push(Ret(None));
+    }
+
+    let mut variables = Assembly::default();
+    variables.label(CURRENT_BANK);
+    variables.lines.push(Line::Data(vec![0]));
+    if mbc5 {
+        variables.label(high);
+        variables.lines.push(Line::Data(vec![0]));
+    }
+    (
+        Section::new("__banking", Kind::Rom0, code),
+        Section::new("__banking", Kind::Hram, variables)
+            .in_bank(0)
+            .at(BANKING),
+    )
+}
@@ -0,0 +1,354 @@
+//! Module loader.
+//!
+//! Programs can be split across source files. Every file is a module, named after the file (its
+//! stem), which [imports](crate::ast::statements::Import) other modules by path, relative to its
+//! own directory: `import "gfx/sprites.ggb";` in `src/main.ggb` loads `src/gfx/sprites.ggb` as
+//! module `sprites` (and `import "gfx/sprites.ggb" as gfx;` as module `gfx`). Paths that name a
+//! [built-in module](crate::prelude::MODULES) load it instead. [`load`] reads the root file of a
+//! program and every file it imports (each one once, even if several files import it), and
+//! [`Sources::parse`] parses all of them into a single [`Program`], imported files first, so
+//! their top-level statements run before the ones of their importers.
+//!
+//! Every file has its own namespace: the names declared at its top level are only visible from
+//! the file, and from the files that [`use`](crate::ast::statements::Use) them. The files of the
+//! program are recorded in the [`Context`], and the [spans](crate::Span) of their nodes refer to
+//! them by [`FileId`], so diagnostics can be [rendered](Diagnostic::render) with their paths. The
+//! `fn`s and `static`s of imported files get [labels](Files::label) prefixed by the name of their
+//! module, so they don't clash with the ones of other files.
+//!
+//! Imports that can't be read, and imports that form a cycle, are reported to the diagnostics of
+//! the context.
+//!
+//! ```
+//! use gb_lang::{ast::Context, loader};
+//! use std::{collections::HashMap, io, path::Path};
+//!
+//! let files = HashMap::from([
+//!     ("main.ggb", "import \"lib/math.ggb\";\nuse math::double;\nlet x::u8 = double(21);"),
+//!     ("lib/math.ggb", "fn double(x::u8) :: u8 { return x + x; }"),
+//! ]);
+//! let mut context = Context::default();
+//! let sources = loader::load_with(Path::new("main.ggb"), &mut context, |path| {
+//!     let file = files.get(path.to_str().unwrap());
+//!     file.map(|file| file.to_string()).ok_or_else(|| io::ErrorKind::NotFound.into())
+//! })
+//! .unwrap();
+//! let program = sources.parse(&mut context).unwrap();
+//! gb_lang::resolve::resolve(&program, &mut context);
+//! assert!(context.diagnostics.is_empty());
+//! assert_eq!("math", context.files.get(gb_lang::FileId(1)).unwrap().name);
+//! ```
+use crate::{
+    ast::{self, statements::Statement, Context, NodeId, Program},
+    diagnostics::Diagnostic,
+    lex::{tokenize_file, Token},
+    prelude, FileId, Span, Spanned,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Source files of a program, and the files its `import`s load.
+#[derive(Debug, Default)]
+pub struct Files {
+    files: Vec<File>,
+    // `import` statement -> imported file
+    imports: HashMap<NodeId, FileId>,
+}
+
+/// Source file of a program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct File {
+    /// Path of the file (`<NAME>` for built-in modules).
+    pub path: PathBuf,
+
+    /// Name of the module of the file, which importers refer to it by.
+    pub name: String,
+}
+
+impl Files {
+    pub fn add(&mut self, path: PathBuf, name: impl Into<String>) -> FileId {
+        self.files.push(File {
+            path,
+            name: name.into(),
+        });
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&File> {
+        self.files.get(file.index())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &File)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (FileId(i as u32), file))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Record the file an `import` statement loads.
+    pub fn bind(&mut self, import: NodeId, file: FileId) {
+        self.imports.insert(import, file);
+    }
+
+    /// File an `import` statement loads, or `None` if it wasn't loaded.
+    pub fn import(&self, import: NodeId) -> Option<FileId> {
+        self.imports.get(&import).copied()
+    }
+
+    /// Assembly label of a `fn` or `static` declared in a file: its name in the root file (the
+    /// first one) and in unknown files, and `MODULE__NAME` in the others. Modules with the same
+    /// name are told apart by the ID of their file (`MODULE_2__NAME`).
+    pub fn label(&self, file: FileId, name: &str) -> String {
+        let module = match self.get(file) {
+            Some(module) if file != FileId::default() => module,
+            _ => return name.to_string(),
+        };
+        let prefix = prefix(&module.name);
+        if self.files[..file.index()]
+            .iter()
+            .any(|other| self::prefix(&other.name) == prefix)
+        {
+            format!("{}_{}__{}", prefix, file.0, name)
+        } else {
+            format!("{}__{}", prefix, name)
+        }
+    }
+}
+
+// Name of a module as the start of a label.
+fn prefix(name: &str) -> String {
+    let prefix: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", prefix)
+    } else {
+        prefix
+    }
+}
+
+/// Loaded source files of a program.
+#[derive(Debug)]
+pub struct Sources {
+    // imported files first, root file last
+    files: Vec<Source>,
+}
+
+#[derive(Debug)]
+struct Source {
+    id: FileId,
+    text: Cow<'static, str>,
+    // span of the path of every `import` of the file that was loaded -> imported file
+    imports: Vec<(Span, FileId)>,
+}
+
+/// Error parsing one of the loaded files.
+#[derive(Debug)]
+pub struct ParseError<'input> {
+    pub file: FileId,
+    pub error: ast::Error<'input>,
+}
+
+impl Sources {
+    /// Source text of a loaded file.
+    pub fn text(&self, file: FileId) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|source| source.id == file)
+            .map(|source| &*source.text)
+    }
+
+    /// Parse every loaded file, as a single program.
+    ///
+    /// The statements of imported files come before the statements of their importers, and the
+    /// `import` statements are [bound](Files::bind) to the files they load.
+    pub fn parse<'a>(&'a self, context: &mut Context) -> Result<Program<'a>, ParseError<'a>> {
+        let file = context.file;
+        let mut statements = Vec::new();
+        let mut eof = None;
+        for source in &self.files {
+            context.file = source.id;
+            let program: Program<'a> =
+                ast::parse_with_context(&source.text, context).map_err(|error| {
+                    context.file = file;
+                    ParseError {
+                        file: source.id,
+                        error,
+                    }
+                })?;
+            for statement in &program.statements {
+                if let Statement::Import(import) = statement {
+                    let span = import.path.span();
+                    if let Some((_, target)) = source.imports.iter().find(|(s, _)| *s == span) {
+                        context.files.bind(import.id, *target);
+                    }
+                }
+            }
+            statements.extend(program.statements);
+            eof = Some(program.eof);
+        }
+        context.file = file;
+        Ok(Program {
+            statements,
+            eof: eof.expect("Expected the root file to be loaded"),
+        })
+    }
+}
+
+/// Load the root file of a program, and every file it imports, from the file system.
+///
+/// Fails if the root file can't be read. The files are added to the [files](Files) of the
+/// context, the root file first.
+pub fn load(root: &Path, context: &mut Context) -> io::Result<Sources> {
+    load_with(root, context, |path| std::fs::read_to_string(path))
+}
+
+/// Like [`load`], but files are read by the given function.
+pub fn load_with(
+    root: &Path,
+    context: &mut Context,
+    mut read: impl FnMut(&Path) -> io::Result<String>,
+) -> io::Result<Sources> {
+    let text = read(root)?;
+    let root = normalize(root);
+    let name = module_name(&root);
+    let id = context.files.add(root.clone(), name);
+    let mut loader = Loader {
+        context,
+        read: &mut read,
+        paths: HashMap::new(),
+        stack: Vec::new(),
+        files: Vec::new(),
+    };
+    loader.paths.insert(root.clone(), id);
+    loader.visit(id, &root, Cow::Owned(text), Span::default());
+    Ok(Sources {
+        files: loader.files,
+    })
+}
+
+struct Loader<'a, R> {
+    context: &'a mut Context,
+    read: &'a mut R,
+    // path of every loaded file
+    paths: HashMap<PathBuf, FileId>,
+    // files being loaded, with the span of the import that loads them
+    stack: Vec<(FileId, Span)>,
+    files: Vec<Source>,
+}
+
+impl<R: FnMut(&Path) -> io::Result<String>> Loader<'_, R> {
+    // Load the files imported by a file (depth first), then the file itself.
+    fn visit(&mut self, id: FileId, path: &Path, text: Cow<'static, str>, span: Span) {
+        self.stack.push((id, span));
+        let mut imports = Vec::new();
+        for (span, literal) in scan(&text, id) {
+            let builtin = prelude::module(&literal);
+            let target = match builtin {
+                Some(_) => PathBuf::from(format!("<{}>", literal)),
+                None => normalize(&path.parent().unwrap_or(Path::new("")).join(&literal)),
+            };
+            if let Some(&loaded) = self.paths.get(&target) {
+                if let Some(i) = self.stack.iter().position(|(file, _)| *file == loaded) {
+                    self.cycle(span, &literal, i);
+                }
+                imports.push((span, loaded));
+                continue;
+            }
+            let text = match builtin {
+                Some(text) => Cow::Borrowed(text),
+                None => match (self.read)(&target) {
+                    Ok(text) => Cow::Owned(text),
+                    Err(error) => {
+                        let message = format!("cannot read `{}`: {}", target.display(), error);
+                        self.context
+                            .diagnostics
+                            .emit(Diagnostic::error(span, message));
+                        continue;
+                    }
+                },
+            };
+            let name = match builtin {
+                Some(_) => literal.clone(),
+                None => module_name(&target),
+            };
+            let loaded = self.context.files.add(target.clone(), name);
+            self.paths.insert(target.clone(), loaded);
+            imports.push((span, loaded));
+            self.visit(loaded, &target, text, span);
+        }
+        self.stack.pop();
+        self.files.push(Source { id, text, imports });
+    }
+
+    // Report an import of the `i`th file being loaded.
+    fn cycle(&mut self, span: Span, literal: &str, i: usize) {
+        let mut diagnostic = Diagnostic::error(span, format!("cyclic import of `{}`", literal));
+        for (file, span) in &self.stack[i + 1..] {
+            let path = &self.context.files.get(*file).expect("Expected a file").path;
+            diagnostic =
+                diagnostic.with_label(*span, format!("`{}` imported here", path.display()));
+        }
+        self.context.diagnostics.emit(diagnostic);
+    }
+}
+
+// Path (and its span) of every `import` at the top level of a file. Errors are left to the
+// parser.
+fn scan(text: &str, file: FileId) -> Vec<(Span, String)> {
+    let mut imports = Vec::new();
+    let mut depth = 0usize;
+    let mut tokens = tokenize_file(text, file).peekable();
+    while let Some(Ok(token)) = tokens.next() {
+        match token {
+            Token::CurlyLeft(_) => depth += 1,
+            Token::CurlyRight(_) => depth = depth.saturating_sub(1),
+            Token::Import(_) if depth == 0 => {
+                if let Some(Ok(Token::Str(path))) = tokens.peek() {
+                    imports.push((path.span(), path.contents().to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    imports
+}
+
+// Path without `.` components, and with `..` components applied where possible.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
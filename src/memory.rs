@@ -0,0 +1,95 @@
+//! Game Boy memory map.
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+    ops::RangeInclusive,
+};
+
+/// Region of the 16-bit address space.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Region {
+    /// Cartridge ROM.
+    Rom,
+    /// Video RAM.
+    Vram,
+    /// Cartridge (external) RAM.
+    Sram,
+    /// Work RAM.
+    Wram,
+    /// Mirror of work RAM.
+    Echo,
+    /// Object attribute memory (sprites).
+    Oam,
+    /// Prohibited area after OAM.
+    Unusable,
+    /// I/O registers.
+    Io,
+    /// High RAM.
+    Hram,
+    /// Interrupt enable register.
+    Ie,
+}
+
+impl Region {
+    /// Every region, in address order.
+    pub const ALL: [Region; 10] = [
+        Region::Rom,
+        Region::Vram,
+        Region::Sram,
+        Region::Wram,
+        Region::Echo,
+        Region::Oam,
+        Region::Unusable,
+        Region::Io,
+        Region::Hram,
+        Region::Ie,
+    ];
+
+    /// Addresses of the region.
+    pub fn range(self) -> RangeInclusive<u16> {
+        match self {
+            Region::Rom => 0x0000..=0x7fff,
+            Region::Vram => 0x8000..=0x9fff,
+            Region::Sram => 0xa000..=0xbfff,
+            Region::Wram => 0xc000..=0xdfff,
+            Region::Echo => 0xe000..=0xfdff,
+            Region::Oam => 0xfe00..=0xfe9f,
+            Region::Unusable => 0xfea0..=0xfeff,
+            Region::Io => 0xff00..=0xff7f,
+            Region::Hram => 0xff80..=0xfffe,
+            Region::Ie => 0xffff..=0xffff,
+        }
+    }
+
+    /// Region an address belongs to.
+    pub fn of(address: u16) -> Region {
+        Region::ALL
+            .iter()
+            .copied()
+            .find(|region| region.range().contains(&address))
+            .expect("Expected the regions to cover the address space")
+    }
+
+    /// Whether data can be placed in the region.
+    pub fn is_usable(self) -> bool {
+        !matches!(self, Region::Echo | Region::Unusable)
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Region::Rom => "ROM",
+            Region::Vram => "VRAM",
+            Region::Sram => "SRAM",
+            Region::Wram => "WRAM",
+            Region::Echo => "echo RAM",
+            Region::Oam => "OAM",
+            Region::Unusable => "unusable memory",
+            Region::Io => "IO",
+            Region::Hram => "HRAM",
+            Region::Ie => "IE",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,37 @@
+//! Built-in modules.
+//!
+//! [`HARDWARE`] (`hardware`) declares the memory map of the Game Boy: every DMG and CGB IO
+//! register as a `u8` static at its address, OAM as an array of `Sprite`s, the tile data and
+//! tile maps of VRAM, and `const`s for the bits of LCDC, STAT, IE and IF (and of sprite flags,
+//! the joypad and the timer). HRAM is an array of bytes, which stops short of the two bytes the
+//! [banking runtime](crate::link::BANKING) keeps at its top.
+//!
+//! Programs [loaded](crate::loader) from files import built-in modules by name
+//! (`import "hardware";`). Single-file programs can include their source instead.
+//!
+//! ```
+//! use gb_lang::{ast::Context, prelude};
+//!
+//! let input = format!(
+//!     "{}\nlet on::u8 = LCDC & LCDC_ON;",
+//!     prelude::module("hardware").unwrap()
+//! );
+//! let mut context = Context::default();
+//! let program = gb_lang::ast::parse_with_context(&input, &mut context).unwrap();
+//! gb_lang::resolve::resolve(&program, &mut context);
+//! assert!(context.diagnostics.is_empty());
+//! ```
+
+/// Source of the `hardware` module.
+pub const HARDWARE: &str = include_str!("prelude/hardware.ggb");
+
+/// Every built-in module, by name.
+pub const MODULES: [(&str, &str); 1] = [("hardware", HARDWARE)];
+
+/// Source of a built-in module.
+pub fn module(name: &str) -> Option<&'static str> {
+    MODULES
+        .iter()
+        .find(|(module, _)| *module == name)
+        .map(|(_, source)| *source)
+}
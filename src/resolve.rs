@@ -0,0 +1,452 @@
+//! Name resolution.
+//!
+//! Binds every identifier expression (and named type, and name used by an `asm` block) to the
+//! `let`, `const`, `static`, `fn`, parameter or `type` it refers to. Every
+//! [`Scope`](crate::ast::statements::Scope) opens a new scope, and so do the bodies of `if`,
+//! `else`, `loop` and `while`, even when they aren't wrapped in curly braces.
+//!
+//! `const`, `static`, `type` and `fn` declarations are visible from anywhere within the scope
+//! that declares them. The parameters of a `fn` are visible from its body. A `let` is only
+//! visible after its declaration (but not from its own initializer).
+//!
+//! The top level of every [file](crate::loader) is a scope of its own. The `const`s, `static`s,
+//! `type`s and `fn`s declared there can be brought into the top-level scope of an importing file
+//! with a `use`. Two modules imported by the same file can't have the same name (an `import`
+//! can rename a module with `as`).
+//!
+//! ```
+//! use gb_lang::ast::Context;
+//!
+//! let mut context = Context::default();
+//! let input = "let a::u8 = FOO; const FOO::u8 = 1;";
+//! let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+//! let resolution = gb_lang::resolve::resolve(&program, &mut context);
+//! assert_eq!(2, resolution.declarations().count());
+//! assert!(context.diagnostics.is_empty());
+//! ```
+use crate::{
+    asm::inline::references,
+    ast::{
+        expressions::Identifier,
+        statements::{Import, Statement, Use},
+        types::Named,
+        visit::{self, Visit, Walk},
+        Context, NodeId, Program, Symbol,
+    },
+    diagnostics::Diagnostic,
+    lex::tokens,
+    FileId, Span, Spanned,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Kind of a named declaration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DeclarationKind {
+    Let,
+    Const,
+    Static,
+    Fn,
+    Param,
+    Type,
+}
+
+/// Named declaration.
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub kind: DeclarationKind,
+    pub name: Symbol,
+
+    /// Span of the declared name.
+    pub span: Span,
+}
+
+/// Output of the name resolution pass.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    // identifier expression (or `use` statement) -> declaration
+    bindings: HashMap<NodeId, NodeId>,
+    // `asm` statement -> declaration of every reference (`None` if it isn't resolved)
+    asm_bindings: HashMap<NodeId, Vec<Option<NodeId>>>,
+    declarations: HashMap<NodeId, Declaration>,
+    // `fn` or `static` -> assembly label
+    labels: HashMap<NodeId, String>,
+}
+
+impl Resolution {
+    /// ID of the declaration an identifier expression refers to, or `None` if the name couldn't
+    /// be resolved.
+    pub fn binding(&self, identifier: NodeId) -> Option<NodeId> {
+        self.bindings.get(&identifier).copied()
+    }
+
+    /// ID of the declaration a `use` statement brings into scope, or `None` if it couldn't be
+    /// resolved.
+    pub fn use_binding(&self, use_: NodeId) -> Option<NodeId> {
+        self.bindings.get(&use_).copied()
+    }
+
+    /// ID of the declaration the `index`th [reference](crate::asm::inline::references) of an
+    /// `asm` statement refers to, or `None` if the name couldn't be resolved.
+    pub fn asm_binding(&self, asm: NodeId, index: usize) -> Option<NodeId> {
+        self.asm_bindings.get(&asm)?.get(index).copied().flatten()
+    }
+
+    /// Declaration node with the given ID.
+    pub fn declaration(&self, id: NodeId) -> Option<&Declaration> {
+        self.declarations.get(&id)
+    }
+
+    /// Assembly label of a `fn` or `static` declaration, qualified by its
+    /// [module](crate::loader::Files::label).
+    pub fn label(&self, id: NodeId) -> Option<&str> {
+        self.labels.get(&id).map(String::as_str)
+    }
+
+    /// All the declarations of the program, in no particular order.
+    pub fn declarations(&self) -> impl Iterator<Item = (NodeId, &Declaration)> {
+        self.declarations.iter().map(|(id, d)| (*id, d))
+    }
+}
+
+/// Resolve the names of a program.
+///
+/// Errors are reported to the diagnostics of the context. Names that fail to resolve are left
+/// unbound.
+pub fn resolve(program: &Program<'_>, context: &mut Context) -> Resolution {
+    let mut resolver = Resolver {
+        context,
+        scopes: Vec::new(),
+        resolution: Resolution::default(),
+        modules: HashMap::new(),
+        imports: HashMap::new(),
+    };
+    let mut files = BTreeMap::<_, Vec<_>>::new();
+    for statement in &program.statements {
+        files
+            .entry(statement.span().file)
+            .or_default()
+            .push(statement);
+    }
+    for (file, statements) in files {
+        resolver.enter(statements);
+        let module = resolver.scopes.pop().expect("Expected an open scope");
+        resolver.modules.insert(file, module);
+    }
+    // every module declares its names before they are used by other modules
+    for statement in &program.statements {
+        match statement {
+            Statement::Import(import) => resolver.import(import),
+            Statement::Use(use_) => resolver.use_(use_),
+            _ => {}
+        }
+    }
+    for statement in &program.statements {
+        let file = statement.span().file;
+        let module = resolver.modules.remove(&file).unwrap_or_default();
+        resolver.scopes.push(module);
+        statement.accept(&mut resolver);
+        let module = resolver.scopes.pop().expect("Expected an open scope");
+        resolver.modules.insert(file, module);
+    }
+    resolver.resolution
+}
+
+#[derive(Default)]
+struct Scope {
+    // declarations that are visible from the current point of the scope
+    names: HashMap<Symbol, NodeId>,
+    // `let`s of the scope that haven't been reached yet
+    pending: Vec<(Symbol, NodeId, Span)>,
+}
+
+struct Resolver<'a> {
+    context: &'a mut Context,
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+    // top-level scope of every file, except the one being resolved
+    modules: HashMap<FileId, Scope>,
+    // (importing file, module name) -> imported file, and the span of the import that names it
+    imports: HashMap<(FileId, Symbol), (FileId, Span)>,
+}
+
+impl Resolver<'_> {
+    /// Open the scope of the given statements, declaring their `const`s, `static`s, `type`s and
+    /// `fn`s.
+    fn enter<'s, 'input: 's>(
+        &mut self,
+        statements: impl IntoIterator<Item = &'s Statement<'input>>,
+    ) {
+        self.scopes.push(Scope::default());
+        for statement in statements {
+            match statement {
+                Statement::Let(let_) => {
+                    let name = self.context.symbol(&let_.identifier);
+                    let span = let_.identifier.span();
+                    self.scope().pending.push((name, let_.id, span));
+                }
+                Statement::Const(const_) => {
+                    self.declare(const_.id, DeclarationKind::Const, &const_.identifier)
+                }
+                Statement::Static(static_) => {
+                    self.declare(static_.id, DeclarationKind::Static, &static_.identifier)
+                }
+                Statement::Fn(fn_) => self.declare(fn_.id, DeclarationKind::Fn, &fn_.identifier),
+                Statement::TypeAlias(alias) => {
+                    self.declare(alias.id, DeclarationKind::Type, &alias.identifier)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn exit(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("Expected an open scope")
+    }
+
+    fn declare(&mut self, id: NodeId, kind: DeclarationKind, identifier: &tokens::Identifier<'_>) {
+        let name = self.context.symbol(identifier);
+        let span = identifier.span();
+        self.resolution
+            .declarations
+            .insert(id, Declaration { kind, name, span });
+        if let DeclarationKind::Fn | DeclarationKind::Static = kind {
+            let label = self.context.files.label(span.file, identifier.as_str());
+            self.resolution.labels.insert(id, label);
+        }
+        let scope = self.scopes.last_mut().expect("Expected an open scope");
+        scope.pending.retain(|(_, pending, _)| *pending != id);
+        match scope.names.get(&name) {
+            Some(previous) => {
+                let previous = &self.resolution.declarations[previous];
+                let diagnostic = Diagnostic::error(
+                    span,
+                    format!("`{}` is defined multiple times", identifier.as_str()),
+                )
+                .with_label(
+                    previous.span,
+                    format!("previous definition of `{}` here", identifier.as_str()),
+                );
+                self.context.diagnostics.emit(diagnostic);
+            }
+            None => {
+                scope.names.insert(name, id);
+            }
+        }
+    }
+
+    // Make the module of an imported file available to the importing file, by name (its alias,
+    // or the name of the file).
+    fn import(&mut self, import: &Import<'_>) {
+        let path = &import.path;
+        let span = path.span();
+        let file = match self.context.files.import(import.id) {
+            Some(file) => file,
+            None => {
+                let message = format!("unresolved import {}", path.as_str());
+                self.context
+                    .diagnostics
+                    .emit(Diagnostic::error(span, message));
+                return;
+            }
+        };
+        let (name, span) = match &import.alias {
+            Some(alias) => (
+                alias.identifier.as_str().to_string(),
+                alias.identifier.span(),
+            ),
+            None => {
+                let name = self.context.files.get(file).map(|file| file.name.clone());
+                (name.unwrap_or_default(), span)
+            }
+        };
+        let symbol = self.context.interner.intern(&name);
+        match self.imports.get(&(span.file, symbol)) {
+            // importing the same file twice is harmless
+            Some((previous, _)) if *previous == file => {}
+            Some((_, previous)) => {
+                let message = format!("module `{}` is imported multiple times", name);
+                let diagnostic = Diagnostic::error(span, message)
+                    .with_label(*previous, format!("previous import of `{}` here", name));
+                self.context.diagnostics.emit(diagnostic);
+            }
+            None => {
+                self.imports.insert((span.file, symbol), (file, span));
+            }
+        }
+    }
+
+    // Declare a name of an imported module in the top-level scope of the file.
+    fn use_(&mut self, use_: &Use<'_>) {
+        let span = use_.identifier.span();
+        let file = use_.span().file;
+        let module = self.context.symbol(&use_.module);
+        let imported = match self.imports.get(&(file, module)) {
+            Some((imported, _)) => *imported,
+            None => {
+                let message = format!("unresolved module `{}`", use_.module.as_str());
+                let diagnostic = Diagnostic::error(use_.module.span(), message);
+                self.context.diagnostics.emit(diagnostic);
+                return;
+            }
+        };
+        let name = self.context.symbol(&use_.identifier);
+        let declaration = self
+            .modules
+            .get(&imported)
+            .and_then(|scope| scope.names.get(&name))
+            .copied();
+        let declaration = match declaration {
+            Some(declaration) => declaration,
+            None => {
+                let message = format!(
+                    "no `{}` in module `{}`",
+                    use_.identifier.as_str(),
+                    use_.module.as_str()
+                );
+                self.context
+                    .diagnostics
+                    .emit(Diagnostic::error(span, message));
+                return;
+            }
+        };
+        self.resolution.bindings.insert(use_.id, declaration);
+        let scope = self.modules.entry(file).or_default();
+        match scope.names.get(&name) {
+            Some(previous) => {
+                let name = use_.identifier.as_str();
+                let diagnostic =
+                    Diagnostic::error(span, format!("`{}` is defined multiple times", name));
+                let diagnostic = match self.resolution.declarations.get(previous) {
+                    Some(previous) => diagnostic.with_label(
+                        previous.span,
+                        format!("previous definition of `{}` here", name),
+                    ),
+                    None => diagnostic,
+                };
+                self.context.diagnostics.emit(diagnostic);
+            }
+            None => {
+                scope.names.insert(name, declaration);
+            }
+        }
+    }
+
+    fn lookup(&mut self, id: NodeId, identifier: &tokens::Identifier<'_>) {
+        if let Some(declaration) = self.find(identifier.as_str(), identifier.span()) {
+            self.resolution.bindings.insert(id, declaration);
+        }
+    }
+
+    // Declaration a name refers to, reporting the names that can't be resolved.
+    fn find(&mut self, text: &str, span: Span) -> Option<NodeId> {
+        let name = self.context.interner.intern(text);
+        if let Some(declaration) = self.scopes.iter().rev().find_map(|s| s.names.get(&name)) {
+            return Some(*declaration);
+        }
+        let pending = self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .pending
+                .iter()
+                .find(|(pending, _, _)| *pending == name)
+        });
+        let diagnostic = match pending {
+            Some((_, _, declared)) => {
+                Diagnostic::error(span, format!("`{}` used before its declaration", text))
+                    .with_label(*declared, format!("`{}` declared here", text))
+            }
+            None => Diagnostic::error(span, format!("cannot find `{}` in this scope", text)),
+        };
+        self.context.diagnostics.emit(diagnostic);
+        None
+    }
+
+    /// Resolve the body of an `if`, `else`, `loop` or `while`, which always opens a new scope.
+    fn body(&mut self, body: &[Statement<'_>]) {
+        let scoped = !matches!(body, [Statement::Scope(_)]);
+        if scoped {
+            self.enter(body);
+        }
+        for statement in body {
+            self.visit_statement(statement);
+        }
+        if scoped {
+            self.exit();
+        }
+    }
+}
+
+impl<'input> Visit<'input> for Resolver<'_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        match statement {
+            Statement::Let(let_) => {
+                let_.type_.accept(self);
+                let_.expression.accept(self);
+                self.declare(let_.id, DeclarationKind::Let, &let_.identifier);
+            }
+            Statement::Fn(fn_) => {
+                fn_.return_type.accept(self);
+                self.scopes.push(Scope::default());
+                for param in fn_.params.iter() {
+                    param.type_.accept(self);
+                    self.declare(param.id, DeclarationKind::Param, &param.identifier);
+                }
+                self.enter(&fn_.body.inner);
+                visit::walk_scope(self, &fn_.body);
+                self.exit();
+                self.exit();
+            }
+            Statement::Scope(scope) => {
+                self.enter(&scope.inner);
+                visit::walk_scope(self, scope);
+                self.exit();
+            }
+            Statement::If(if_) => {
+                if_.expression.accept(self);
+                self.body(&if_.inner);
+                if let Some(else_) = &if_.else_ {
+                    self.body(&else_.inner);
+                }
+            }
+            Statement::Loop(loop_) => self.body(&loop_.inner),
+            Statement::While(while_) => {
+                while_.expression.accept(self);
+                self.body(&while_.inner);
+            }
+            Statement::Asm(asm) => {
+                let bindings = references(asm.block.contents())
+                    .iter()
+                    .map(|reference| {
+                        let span = asm.block.span_of(reference.offset, reference.base().len());
+                        self.find(reference.base(), span)
+                    })
+                    .collect();
+                self.resolution.asm_bindings.insert(asm.id, bindings);
+            }
+            // resolved before any other statement, but only allowed at the top level of a file
+            Statement::Import(_) | Statement::Use(_) if self.scopes.len() > 1 => {
+                let keyword = match statement {
+                    Statement::Import(_) => "import",
+                    _ => "use",
+                };
+                let message = format!("`{}` is only allowed at the top level of a file", keyword);
+                let diagnostic = Diagnostic::error(statement.span(), message);
+                self.context.diagnostics.emit(diagnostic);
+            }
+            Statement::Import(_) | Statement::Use(_) => {}
+            // `const`s, `static`s, `type`s and `fn`s are declared when their scope is entered
+            _ => visit::walk_statement(self, statement),
+        }
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier<'input>) {
+        self.lookup(identifier.id, &identifier.identifier);
+    }
+
+    fn visit_named(&mut self, named: &Named<'input>) {
+        self.lookup(named.id, &named.identifier);
+    }
+}
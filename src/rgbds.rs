@@ -0,0 +1,183 @@
+//! Export to RGBDS assembly.
+//!
+//! [`export`] writes [sections](crate::link::Section) as an `rgbasm` source file, so the code
+//! generated for a program can be linked with `rgblink` alongside hand-written assembly instead of
+//! by the [linker](crate::link) of this crate. Every section gets a `SECTION` directive (with its
+//! bank and address, if fixed), ROM data is written with `db` and RAM sections only reserve space
+//! with `ds`. Global labels are exported (`NAME::`), so hand-written code can call functions and
+//! use statics by name.
+//!
+//! RGBDS local labels (`parent.name`) can only be defined in the scope of their parent, and have a
+//! single level. Labels with a dot that don't fit (like the labels of inline assembly,
+//! `__asmN.label`) are renamed into the current scope, and so are the references to them.
+//!
+//! [`constants`] writes the `const`s of a program as an include file of `DEF NAME EQU value`
+//! lines, which only defines symbols (and so can be `INCLUDE`d by any source file). Like the `fn`s
+//! and `static`s, the consts of imported files are [qualified](Files::label) by their module.
+//!
+//! ```
+//! use gb_lang::{
+//!     asm::parse::parse,
+//!     link::{Kind, Section},
+//!     rgbds::export,
+//! };
+//!
+//! let main = parse("main:\n    call wait\n    ret").unwrap();
+//! let section = Section::new("main", Kind::Romx, main).in_bank(2);
+//! assert_eq!(
+//!     "SECTION \"main\", ROMX, BANK[2]\nmain::\n    call wait\n    ret\n",
+//!     export(&[section])
+//! );
+//! ```
+use crate::{
+    asm::{Line, Value},
+    ast::{
+        expressions::ExpressionGrammar,
+        statements::Const,
+        types::TypeGrammar,
+        visit::{self, Visit, Walk},
+        Program,
+    },
+    consts::Consts,
+    link::Section,
+    loader::Files,
+    Spanned,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+/// RGBDS source of a list of sections.
+///
+/// Sections with the same name are numbered (`name.1`, `name.2`…), since `rgbasm` would merge
+/// them otherwise.
+pub fn export(sections: &[Section]) -> String {
+    let renames = renames(sections);
+    let rename = |label: &str| {
+        renames
+            .get(label)
+            .cloned()
+            .unwrap_or_else(|| label.to_string())
+    };
+
+    let mut output = String::new();
+    let mut names = HashMap::new();
+    for (i, section) in sections.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        let count = names.entry(section.name.as_str()).or_insert(0);
+        let name = match *count {
+            0 => section.name.clone(),
+            n => format!("{}.{}", section.name, n),
+        };
+        *count += 1;
+        write!(output, "SECTION \"{}\", {}", name, section.kind)
+            .expect("Expected to write to a string");
+        if let Some(address) = section.address {
+            write!(output, "[${:04X}]", address).expect("Expected to write to a string");
+        }
+        match section.bank {
+            Some(bank) if section.kind.is_banked() => writeln!(output, ", BANK[{}]", bank),
+            _ => writeln!(output),
+        }
+        .expect("Expected to write to a string");
+
+        for line in &section.assembly.lines {
+            match line {
+                Line::Label(label) => {
+                    let label = rename(label);
+                    let colons = if label.contains('.') { ":" } else { "::" };
+                    writeln!(output, "{}{}", label, colons)
+                }
+                Line::Instruction(instruction) => {
+                    let mut instruction = instruction.clone();
+                    if let Some(Value::Label(label)) = instruction.value_mut() {
+                        *label = rename(label);
+                    }
+                    writeln!(output, "    {}", instruction)
+                }
+                Line::Data(bytes) if section.kind.is_rom() => {
+                    writeln!(output, "{}", Line::Data(bytes.clone()))
+                }
+                Line::Data(bytes) if bytes.is_empty() => Ok(()),
+                Line::Data(bytes) => writeln!(output, "    ds {}", bytes.len()),
+            }
+            .expect("Expected to write to a string");
+        }
+    }
+    output
+}
+
+// New names of the labels that aren't valid RGBDS local labels where they are defined: they
+// become local labels of the current scope (or global labels, outside of any), with their dots
+// replaced by underscores.
+fn renames(sections: &[Section]) -> HashMap<String, String> {
+    let mut renames = HashMap::new();
+    for section in sections {
+        let mut scope = None;
+        for line in &section.assembly.lines {
+            let label = match line {
+                Line::Label(label) => label,
+                _ => continue,
+            };
+            match label.split_once('.') {
+                None => scope = Some(label.as_str()),
+                Some((parent, name)) if Some(parent) == scope && !name.contains('.') => {}
+                Some(_) => {
+                    let name = label.replace('.', "_");
+                    let name = match scope {
+                        Some(scope) => format!("{}.{}", scope, name),
+                        None => name,
+                    };
+                    renames.insert(label.clone(), name);
+                }
+            }
+        }
+    }
+    renames
+}
+
+/// RGBDS include file defining the value of every `const` of a program, in source order.
+///
+/// Consts are named by the [label](Files::label) of their module in the given files. Consts that
+/// failed to evaluate are left out, and so are the consts that have the same label as an earlier
+/// one (a local const shadowing a global one of the same file, for instance).
+pub fn constants(program: &Program<'_>, consts: &Consts, files: &Files) -> String {
+    let mut collector = Collector {
+        consts,
+        files,
+        names: HashSet::new(),
+        output: String::new(),
+    };
+    program.accept(&mut collector);
+    collector.output
+}
+
+struct Collector<'a> {
+    consts: &'a Consts,
+    files: &'a Files,
+    names: HashSet<String>,
+    output: String,
+}
+
+impl<'input> Visit<'input> for Collector<'_> {
+    fn visit_const<T, E>(&mut self, const_: &Const<'input, T, E>)
+    where
+        T: TypeGrammar<'input>,
+        E: ExpressionGrammar<'input>,
+    {
+        let identifier = &const_.identifier;
+        let name = self
+            .files
+            .label(identifier.span().file, identifier.as_str());
+        if let Some(value) = self.consts.value(const_.id) {
+            if self.names.insert(name.clone()) {
+                writeln!(self.output, "DEF {} EQU {}", name, value)
+                    .expect("Expected to write to a string");
+            }
+        }
+        visit::walk_const(self, const_);
+    }
+}
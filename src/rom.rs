@@ -0,0 +1,370 @@
+//! Game Boy ROM images.
+//!
+//! A ROM starts with 256 bytes of interrupt vectors, followed by the cartridge header at
+//! `$0100..=$014F`. The header begins with the entry point (a jump to the program, which is
+//! placed right after it at [`CODE`]), and holds the Nintendo logo and header checksum that the
+//! boot ROM checks before running anything. Images are padded to a valid ROM size (32 KiB times
+//! a power of two).
+//!
+//! [`build`] makes an image of a program that fits in the first two banks. Larger programs are
+//! split into sections and placed in banks by the [linker](crate::link).
+//!
+//! ```
+//! use gb_lang::{asm::parse::parse, rom};
+//!
+//! let assembly = parse("main:\n    halt\n    jr main").unwrap();
+//! let header = rom::Header {
+//!     title: "HELLO".to_string(),
+//!     ..Default::default()
+//! };
+//! let image = rom::build(&assembly, &header).unwrap();
+//! assert_eq!(0x8000, image.bytes.len());
+//! assert_eq!([0x00, 0xc3, 0x50, 0x01], image.bytes[0x100..0x104]);
+//! assert_eq!(Ok(()), rom::verify(&image.bytes));
+//! ```
+use crate::asm::{
+    encode::{self, assemble},
+    Assembly,
+};
+use std::collections::BTreeMap;
+
+/// Size of a ROM bank.
+pub const BANK_SIZE: usize = 0x4000;
+
+/// Address of the entry point, where the boot ROM jumps to.
+pub const ENTRY: u16 = 0x0100;
+
+/// Address of the first byte after the header, where the program is placed.
+pub const CODE: u16 = 0x0150;
+
+/// Largest ROM size (in bytes) the header can describe.
+pub const MAX_SIZE: usize = 0x80_0000;
+
+/// Byte of the unused parts of the image.
+pub const PADDING: u8 = 0xff;
+
+/// Logo the boot ROM compares the header against.
+pub const LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+// Offsets of the header fields.
+const LOGO_START: usize = 0x0104;
+const TITLE: usize = 0x0134;
+const CGB: usize = 0x0143;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION: usize = 0x014a;
+const VERSION: usize = 0x014c;
+const HEADER_CHECKSUM: usize = 0x014d;
+const GLOBAL_CHECKSUM: usize = 0x014e;
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("invalid title `{title}` (expected at most {max} printable ASCII characters)")]
+    InvalidTitle { title: String, max: usize },
+
+    #[error(transparent)]
+    Assemble(#[from] encode::Error),
+
+    #[error("program ends at ${0:04X}, past the end of the ROM banks at $0000..=$7FFF")]
+    TooLarge(usize),
+
+    #[error("image of ${0:X} bytes is smaller than the header")]
+    Truncated(usize),
+
+    #[error("the header doesn't hold the Nintendo logo")]
+    InvalidLogo,
+
+    #[error("header checksum is ${found:02X}, expected ${expected:02X}")]
+    HeaderChecksum { expected: u8, found: u8 },
+
+    #[error("global checksum is ${found:04X}, expected ${expected:04X}")]
+    GlobalChecksum { expected: u16, found: u16 },
+
+    #[error("image of ${size:X} bytes doesn't match the ROM size code ${code:02X}")]
+    InvalidSize { code: u8, size: usize },
+
+    #[error("no cartridge type has the hardware of {0:?}")]
+    UnsupportedCartridge(Cartridge),
+}
+
+/// Game Boy Color support (`$0143`).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Cgb {
+    /// Runs on the original Game Boy only.
+    #[default]
+    None,
+    /// Runs on both, with Game Boy Color features.
+    Compatible,
+    /// Requires a Game Boy Color.
+    Only,
+}
+
+impl Cgb {
+    pub fn code(self) -> u8 {
+        match self {
+            Cgb::None => 0x00,
+            Cgb::Compatible => 0x80,
+            Cgb::Only => 0xc0,
+        }
+    }
+}
+
+/// Memory bank controller of the cartridge, and its hardware (`$0147`).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Cartridge {
+    /// 32 KiB of ROM, without banking.
+    #[default]
+    RomOnly,
+    Mbc1 {
+        ram: bool,
+        battery: bool,
+    },
+    Mbc3 {
+        ram: bool,
+        battery: bool,
+        timer: bool,
+    },
+    Mbc5 {
+        ram: bool,
+        battery: bool,
+        rumble: bool,
+    },
+}
+
+impl Cartridge {
+    /// Largest number of ROM banks the memory bank controller can switch between (including
+    /// bank 0).
+    pub fn rom_banks(self) -> u16 {
+        match self {
+            Cartridge::RomOnly => 2,
+            Cartridge::Mbc1 { .. } | Cartridge::Mbc3 { .. } => 128,
+            Cartridge::Mbc5 { .. } => 512,
+        }
+    }
+
+    /// Cartridge type code, or `None` if no cartridge type has its hardware: the timer of an
+    /// MBC3 only comes with a battery (which keeps it running). A battery without RAM (or
+    /// timer) has nothing to keep, so it is ignored.
+    pub fn code(self) -> Option<u8> {
+        let code = match self {
+            Cartridge::RomOnly => 0x00,
+            Cartridge::Mbc1 { ram: false, .. } => 0x01,
+            Cartridge::Mbc1 { battery: false, .. } => 0x02,
+            Cartridge::Mbc1 { .. } => 0x03,
+            Cartridge::Mbc3 {
+                ram: false,
+                timer: true,
+                battery: true,
+            } => 0x0f,
+            Cartridge::Mbc3 {
+                ram: true,
+                timer: true,
+                battery: true,
+            } => 0x10,
+            Cartridge::Mbc3 { timer: true, .. } => return None,
+            Cartridge::Mbc3 { ram: false, .. } => 0x11,
+            Cartridge::Mbc3 { battery: false, .. } => 0x12,
+            Cartridge::Mbc3 { .. } => 0x13,
+            Cartridge::Mbc5 {
+                rumble: false,
+                ram: false,
+                ..
+            } => 0x19,
+            Cartridge::Mbc5 {
+                rumble: false,
+                battery: false,
+                ..
+            } => 0x1a,
+            Cartridge::Mbc5 { rumble: false, .. } => 0x1b,
+            Cartridge::Mbc5 { ram: false, .. } => 0x1c,
+            Cartridge::Mbc5 { battery: false, .. } => 0x1d,
+            Cartridge::Mbc5 { .. } => 0x1e,
+        };
+        Some(code)
+    }
+}
+
+/// Size of the cartridge RAM (`$0149`).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum RamSize {
+    #[default]
+    None,
+    /// 8 KiB, a single bank.
+    Kib8,
+    /// 32 KiB, 4 banks.
+    Kib32,
+    /// 128 KiB, 16 banks.
+    Kib128,
+    /// 64 KiB, 8 banks.
+    Kib64,
+}
+
+impl RamSize {
+    /// Number of 8 KiB banks.
+    pub fn banks(self) -> u16 {
+        match self {
+            RamSize::None => 0,
+            RamSize::Kib8 => 1,
+            RamSize::Kib32 => 4,
+            RamSize::Kib64 => 8,
+            RamSize::Kib128 => 16,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            RamSize::None => 0x00,
+            RamSize::Kib8 => 0x02,
+            RamSize::Kib32 => 0x03,
+            RamSize::Kib128 => 0x04,
+            RamSize::Kib64 => 0x05,
+        }
+    }
+}
+
+/// Contents of the cartridge header. The entry point, logo, ROM size and checksums are
+/// computed.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Header {
+    /// Up to 16 characters (15 with Game Boy Color support), padded with zeros.
+    pub title: String,
+    pub cgb: Cgb,
+    pub cartridge: Cartridge,
+    pub ram_size: RamSize,
+
+    /// Whether the game is sold in Japan (`$014A`).
+    pub japanese: bool,
+    pub version: u8,
+}
+
+/// ROM image.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+
+    /// Address of every label of the program.
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// Build the ROM image of a program, which starts running with its first instruction.
+pub fn build(assembly: &Assembly, header: &Header) -> Result<Rom, Error> {
+    let object = assemble(assembly, CODE)?;
+    let end = usize::from(CODE) + object.bytes.len();
+    if end > 2 * BANK_SIZE {
+        return Err(Error::TooLarge(end));
+    }
+    let mut bytes = vec![PADDING; end];
+    let entry = usize::from(ENTRY);
+    bytes[entry..entry + 4].copy_from_slice(&jump(CODE));
+    bytes[usize::from(CODE)..end].copy_from_slice(&object.bytes);
+    write_header(&mut bytes, header)?;
+    Ok(Rom {
+        bytes,
+        labels: object.labels,
+    })
+}
+
+/// Instructions of the entry point: `nop`, then a jump to `target`.
+pub fn jump(target: u16) -> [u8; 4] {
+    let [low, high] = target.to_le_bytes();
+    [0x00, 0xc3, low, high]
+}
+
+/// Pad an image (of at most [`MAX_SIZE`] bytes) to a valid ROM size, and fill in its header
+/// (after the entry point) and checksums. The rest of the image must already be in place.
+///
+/// Fails if the title is invalid, or if the cartridge has no [type code](Cartridge::code).
+pub fn write_header(bytes: &mut Vec<u8>, header: &Header) -> Result<(), Error> {
+    let max = if header.cgb == Cgb::None { 16 } else { 15 };
+    let printable = |c: char| c.is_ascii_graphic() || c == ' ';
+    if header.title.len() > max || !header.title.chars().all(printable) {
+        return Err(Error::InvalidTitle {
+            title: header.title.clone(),
+            max,
+        });
+    }
+    let cartridge = header
+        .cartridge
+        .code()
+        .ok_or(Error::UnsupportedCartridge(header.cartridge))?;
+    let size = bytes.len().max(2 * BANK_SIZE).next_power_of_two();
+    debug_assert!(size <= MAX_SIZE, "Expected at most 512 banks");
+    bytes.resize(size, PADDING);
+
+    bytes[LOGO_START..LOGO_START + LOGO.len()].copy_from_slice(&LOGO);
+    bytes[TITLE..CGB + 1].fill(0);
+    bytes[TITLE..TITLE + header.title.len()].copy_from_slice(header.title.as_bytes());
+    if header.cgb != Cgb::None {
+        bytes[CGB] = header.cgb.code();
+    }
+    // no licensee, no Super Game Boy support
+    bytes[CGB + 1..CARTRIDGE_TYPE].fill(0);
+    bytes[CARTRIDGE_TYPE] = cartridge;
+    bytes[ROM_SIZE] = size_code(size);
+    bytes[RAM_SIZE] = header.ram_size.code();
+    bytes[DESTINATION] = u8::from(!header.japanese);
+    bytes[DESTINATION + 1] = 0x00;
+    bytes[VERSION] = header.version;
+    bytes[HEADER_CHECKSUM] = header_checksum(bytes);
+    let global = global_checksum(bytes);
+    bytes[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global.to_be_bytes());
+    Ok(())
+}
+
+/// Check an image the way the boot ROM and emulators do: the logo, the checksums, and that the
+/// size matches the header.
+pub fn verify(bytes: &[u8]) -> Result<(), Error> {
+    if bytes.len() < usize::from(CODE) {
+        return Err(Error::Truncated(bytes.len()));
+    }
+    if bytes[LOGO_START..LOGO_START + LOGO.len()] != LOGO {
+        return Err(Error::InvalidLogo);
+    }
+    let expected = header_checksum(bytes);
+    if bytes[HEADER_CHECKSUM] != expected {
+        return Err(Error::HeaderChecksum {
+            expected,
+            found: bytes[HEADER_CHECKSUM],
+        });
+    }
+    let code = bytes[ROM_SIZE];
+    if code > 8 || (2 * BANK_SIZE) << code != bytes.len() {
+        return Err(Error::InvalidSize {
+            code,
+            size: bytes.len(),
+        });
+    }
+    let expected = global_checksum(bytes);
+    let found = u16::from_be_bytes([bytes[GLOBAL_CHECKSUM], bytes[GLOBAL_CHECKSUM + 1]]);
+    if found != expected {
+        return Err(Error::GlobalChecksum { expected, found });
+    }
+    Ok(())
+}
+
+/// ROM size code of an image size, which must be 32 KiB times a power of two.
+pub fn size_code(size: usize) -> u8 {
+    debug_assert!(size.is_power_of_two() && (2 * BANK_SIZE..=MAX_SIZE).contains(&size));
+    (size / (2 * BANK_SIZE)).trailing_zeros() as u8
+}
+
+/// Checksum of the header bytes `$0134..=$014C`, checked by the boot ROM.
+pub fn header_checksum(bytes: &[u8]) -> u8 {
+    bytes[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+/// Sum of every byte of the image except the global checksum itself.
+pub fn global_checksum(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2).contains(i))
+        .fold(0u16, |sum, (_, byte)| sum.wrapping_add(u16::from(*byte)))
+}
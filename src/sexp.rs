@@ -0,0 +1,440 @@
+//! Compact S-expression dumps, for golden tests.
+//!
+//! Nodes are written as `(Name child...)`, sequences as `[item...]` and tokens as their source
+//! text. Spans, node IDs and missing optional nodes are left out.
+//!
+//! The dump is written by [serializing](Serialize) the nodes, so this module is only available
+//! with the `serde` feature.
+//!
+//! ```
+//! use gb_lang::ast::statements::Statement;
+//!
+//! let let_ = gb_lang::parse::<Statement>("let foo::array<u8, 2> = \"hi\";").unwrap();
+//! assert_eq!(
+//!     r#"(Let let foo :: (Array array < (U8 u8) , (Number 2) >) = (Str "\"hi\"") ;)"#,
+//!     gb_lang::sexp::to_string(&let_).unwrap(),
+//! );
+//! ```
+use serde::{ser, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Error reported by a [`Serialize`] impl.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// Dump any serializable value (tokens, nodes, programs...) as an S-expression.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    let mut serializer = Serializer {
+        out: String::new(),
+        opened: true,
+    };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+struct Serializer {
+    out: String,
+    // true right after opening a delimiter, so the next item isn't preceded by a space.
+    opened: bool,
+}
+
+impl Serializer {
+    fn separate(&mut self) {
+        if !self.opened {
+            self.out.push(' ');
+        }
+        self.opened = false;
+    }
+
+    fn atom(&mut self, atom: &str) {
+        self.separate();
+        let bare = !atom.is_empty()
+            && !atom
+                .chars()
+                .any(|c| c.is_whitespace() || "()[]\"\\".contains(c));
+        if bare {
+            self.out.push_str(atom);
+        } else {
+            self.out.push('"');
+            for c in atom.chars() {
+                if c == '"' || c == '\\' {
+                    self.out.push('\\');
+                }
+                self.out.push(c);
+            }
+            self.out.push('"');
+        }
+    }
+
+    fn open(&mut self, delimiter: char, name: Option<&str>) {
+        self.separate();
+        self.out.push(delimiter);
+        self.opened = true;
+        if let Some(name) = name {
+            self.out.push_str(name);
+            self.opened = false;
+        }
+    }
+
+    fn close(&mut self, delimiter: char) {
+        self.out.push(delimiter);
+        self.opened = false;
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Node<'a>;
+    type SerializeStructVariant = Node<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.atom(if v { "true" } else { "false" });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.atom(&v.to_string());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.atom(&v.to_string());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.atom(&v.to_string());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.atom(v.encode_utf8(&mut [0; 4]));
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.atom(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        use ser::SerializeSeq;
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for byte in v {
+            seq.serialize_element(byte)?;
+        }
+        seq.end()
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), Error> {
+        self.atom(name);
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.atom(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.open('(', Some(name));
+        value.serialize(&mut *self)?;
+        self.close(')');
+        Ok(())
+    }
+
+    // enum variants wrap a node of the same name (`Expression::Add(Add { .. })`), so they are
+    // transparent.
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Compound<'a>, Error> {
+        self.open('[', None);
+        Ok(Compound {
+            serializer: self,
+            close: Some(']'),
+        })
+    }
+
+    // tuples are written inline, into the enclosing sequence or node
+    fn serialize_tuple(self, _: usize) -> Result<Compound<'a>, Error> {
+        Ok(Compound {
+            serializer: self,
+            close: None,
+        })
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, _: usize) -> Result<Compound<'a>, Error> {
+        self.open('(', Some(name));
+        Ok(Compound {
+            serializer: self,
+            close: Some(')'),
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, Error> {
+        self.open('(', Some(variant));
+        Ok(Compound {
+            serializer: self,
+            close: Some(')'),
+        })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Compound<'a>, Error> {
+        self.open('{', None);
+        Ok(Compound {
+            serializer: self,
+            close: Some('}'),
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, _: usize) -> Result<Node<'a>, Error> {
+        Ok(Node {
+            serializer: self,
+            name,
+            state: NodeState::Empty,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Node<'a>, Error> {
+        self.serialize_struct(variant, 0)
+    }
+}
+
+struct Compound<'a> {
+    serializer: &'a mut Serializer,
+    close: Option<char>,
+}
+
+impl Compound<'_> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut *self.serializer)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        if let Some(close) = self.close {
+            self.serializer.close(close);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Compound::end(self)
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Compound::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Compound::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Compound::end(self)
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.element(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Compound::end(self)
+    }
+}
+
+enum NodeState {
+    Empty,
+    Open,
+    Token,
+}
+
+/// Struct serializer. Tokens (structs with a `text` field) are written as their text, and
+/// any other struct as a node.
+struct Node<'a> {
+    serializer: &'a mut Serializer,
+    name: &'static str,
+    state: NodeState,
+}
+
+impl ser::SerializeStruct for Node<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        match (key, &self.state) {
+            ("span" | "id", _) => Ok(()),
+            ("text", NodeState::Empty) => {
+                self.state = NodeState::Token;
+                value.serialize(&mut *self.serializer)
+            }
+            (_, NodeState::Empty) => {
+                self.serializer.open('(', Some(self.name));
+                self.state = NodeState::Open;
+                value.serialize(&mut *self.serializer)
+            }
+            _ => value.serialize(&mut *self.serializer),
+        }
+    }
+
+    fn end(self) -> Result<(), Error> {
+        match self.state {
+            NodeState::Empty => {
+                self.serializer.open('(', Some(self.name));
+                self.serializer.close(')');
+            }
+            NodeState::Open => self.serializer.close(')'),
+            NodeState::Token => {}
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for Node<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        ser::SerializeStruct::end(self)
+    }
+}
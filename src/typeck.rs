@@ -0,0 +1,1026 @@
+//! Type checking.
+//!
+//! Assigns a [`Ty`] to every declaration and expression of a program, and checks initializers
+//! against the type of their declaration. Requires the names of the program to be
+//! [resolved](crate::resolve), and its constants [evaluated](crate::consts) first.
+//!
+//! ```
+//! use gb_lang::{ast::Context, typeck::Ty};
+//!
+//! let mut context = Context::default();
+//! let program = gb_lang::ast::parse_with_context(
+//!     "let a::array<u8, 4> = \"abcde\";",
+//!     &mut context,
+//! ).unwrap();
+//! let resolution = gb_lang::resolve::resolve(&program, &mut context);
+//! let consts = gb_lang::consts::evaluate(&program, &resolution, &mut context);
+//! gb_lang::typeck::check(&program, &resolution, &consts, &mut context);
+//! let error = context.diagnostics.errors().next().unwrap();
+//! assert_eq!(
+//!     "1:23: error: string literal of 5 bytes doesn't fit in `array<u8, 4>`\n  1:8: expected due to this type",
+//!     error.to_string(),
+//! );
+//! ```
+use crate::{
+    ast::{
+        expressions::{Cast, Expression, ExpressionGrammar},
+        statements::{Placement, Statement, TypeAlias},
+        types::{Array, Named, Type, TypeGrammar},
+        visit::{self, Visit, Walk},
+        Context, NodeId, Program, Punctuated,
+    },
+    consts::{Consts, Width},
+    diagnostics::{Diagnostic, Diagnostics},
+    lex::tokens,
+    resolve::{DeclarationKind, Resolution},
+    Span, Spanned,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    fmt::{Display, Formatter},
+};
+
+/// Resolved type.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Ty {
+    U8,
+    U16,
+    I8,
+    I16,
+    Bool,
+    Array(Box<Ty>, usize),
+    Ptr(Box<Ty>),
+    Struct(Vec<Field>),
+    Union(Vec<Field>),
+
+    /// Function taking parameters of the given types.
+    Fn(Vec<Ty>, Box<Ty>),
+
+    /// Type of the calls to functions that don't return a value.
+    Unit,
+
+    /// Type declared with `type`, referred to by the ID of its declaration.
+    ///
+    /// Named types are expanded to their definition, except behind a `ptr<...>` (which is how
+    /// recursive types are kept finite). [`Types::alias`] expands them.
+    Named(NodeId, String),
+
+    /// Type of ill-typed expressions.
+    ///
+    /// It is compatible with every other type, so a single error doesn't cascade into many.
+    Error,
+}
+
+/// Field of a struct or union type.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Field {
+    pub name: String,
+    pub ty: Ty,
+}
+
+impl Ty {
+    /// Width of an integer type, or `None` if the type isn't an integer.
+    pub fn width(&self) -> Option<Width> {
+        match self {
+            Ty::U8 => Some(Width::U8),
+            Ty::U16 => Some(Width::U16),
+            Ty::I8 => Some(Width::I8),
+            Ty::I16 => Some(Width::I16),
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.width().is_some()
+    }
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::U8 => write!(f, "u8"),
+            Ty::U16 => write!(f, "u16"),
+            Ty::I8 => write!(f, "i8"),
+            Ty::I16 => write!(f, "i16"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Array(ty, len) => write!(f, "array<{}, {}>", ty, len),
+            Ty::Ptr(ty) => write!(f, "ptr<{}>", ty),
+            Ty::Struct(fields) => write_fields(f, "struct", fields),
+            Ty::Union(fields) => write_fields(f, "union", fields),
+            Ty::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                match &**ret {
+                    Ty::Unit => write!(f, ")"),
+                    ret => write!(f, ") :: {}", ret),
+                }
+            }
+            Ty::Unit => write!(f, "()"),
+            Ty::Named(_, name) => write!(f, "{}", name),
+            Ty::Error => write!(f, "{{error}}"),
+        }
+    }
+}
+
+fn write_fields(f: &mut Formatter<'_>, keyword: &str, fields: &[Field]) -> fmt::Result {
+    if fields.is_empty() {
+        return write!(f, "{} {{}}", keyword);
+    }
+    write!(f, "{} {{ ", keyword)?;
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}::{}", field.name, field.ty)?;
+    }
+    write!(f, " }}")
+}
+
+/// Output of the type checker.
+#[derive(Debug, Default)]
+pub struct Types {
+    expressions: HashMap<NodeId, Ty>,
+    declarations: HashMap<NodeId, Ty>,
+    aliases: HashMap<NodeId, Ty>,
+    // target types of casts
+    casts: HashMap<NodeId, Ty>,
+}
+
+impl Types {
+    /// Type of an expression node.
+    pub fn expression(&self, id: NodeId) -> Option<&Ty> {
+        self.expressions.get(&id)
+    }
+
+    /// Declared type of a `let`, `const`, `static`, `fn` or parameter node.
+    pub fn declaration(&self, id: NodeId) -> Option<&Ty> {
+        self.declarations.get(&id)
+    }
+
+    /// Definition of a `type` declaration.
+    pub fn alias(&self, id: NodeId) -> Option<&Ty> {
+        self.aliases.get(&id)
+    }
+
+    /// Expand a [`Ty::Named`] to its definition. Other types are returned as they are.
+    pub fn expand(&self, ty: Ty) -> Ty {
+        match ty {
+            Ty::Named(id, _) => self.aliases.get(&id).cloned().unwrap_or(Ty::Error),
+            ty => ty,
+        }
+    }
+
+    /// Whether values of type `found` can be used where an `expected` is expected.
+    ///
+    /// Types are compared by structure, expanding named types as needed. [`Ty::Error`] is
+    /// compatible with every type.
+    pub fn is_compatible(&self, expected: &Ty, found: &Ty) -> bool {
+        self.same(expected, found, &mut Vec::new())
+    }
+
+    // Named types that are being compared are assumed to be the same, so that comparing
+    // recursive types terminates.
+    fn same(&self, a: &Ty, b: &Ty, assumed: &mut Vec<(NodeId, NodeId)>) -> bool {
+        match (a, b) {
+            (Ty::Error, _) | (_, Ty::Error) => true,
+            (Ty::Named(a, _), Ty::Named(b, _)) if a == b || assumed.contains(&(*a, *b)) => true,
+            (Ty::Named(id_a, _), Ty::Named(id_b, _)) => {
+                assumed.push((*id_a, *id_b));
+                let same = self.same(&self.expand(a.clone()), &self.expand(b.clone()), assumed);
+                assumed.pop();
+                same
+            }
+            (Ty::Named(..), other) => self.same(&self.expand(a.clone()), other, assumed),
+            (other, Ty::Named(..)) => self.same(other, &self.expand(b.clone()), assumed),
+            (Ty::Array(a, len_a), Ty::Array(b, len_b)) => {
+                len_a == len_b && self.same(a, b, assumed)
+            }
+            (Ty::Ptr(a), Ty::Ptr(b)) => self.same(a, b, assumed),
+            (Ty::Struct(a), Ty::Struct(b)) | (Ty::Union(a), Ty::Union(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|(a, b)| a.name == b.name && self.same(&a.ty, &b.ty, assumed))
+            }
+            (Ty::Fn(params_a, ret_a), Ty::Fn(params_b, ret_b)) => {
+                params_a.len() == params_b.len()
+                    && params_a
+                        .iter()
+                        .zip(params_b)
+                        .all(|(a, b)| self.same(a, b, assumed))
+                    && self.same(ret_a, ret_b, assumed)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// Type check a program.
+///
+/// Errors are reported to the diagnostics of the context.
+pub fn check(
+    program: &Program<'_>,
+    resolution: &Resolution,
+    consts: &Consts,
+    context: &mut Context,
+) -> Types {
+    let mut aliases = Vec::new();
+    for statement in &program.statements {
+        collect(statement, &mut aliases);
+    }
+    let mut lower = Lower {
+        consts,
+        resolution,
+        diagnostics: &mut context.diagnostics,
+        aliases: aliases.iter().map(|alias| (alias.id, *alias)).collect(),
+        expanding: Vec::new(),
+        types: Types::default(),
+    };
+    // every alias is expanded, even if unused, so that infinitely sized ones get reported
+    for alias in &aliases {
+        lower.alias(alias.id, alias.identifier.span());
+    }
+    program.accept(&mut lower);
+    let mut checker = Checker {
+        resolution,
+        diagnostics: lower.diagnostics,
+        types: lower.types,
+        returns: Vec::new(),
+    };
+    program.accept(&mut checker);
+    program.accept(&mut Constants {
+        consts,
+        checker: &mut checker,
+    });
+    checker.types
+}
+
+type TypeAliasStatement<'input> = TypeAlias<'input, Type<'input>>;
+
+fn collect<'a, 'input>(
+    statement: &'a Statement<'input>,
+    out: &mut Vec<&'a TypeAliasStatement<'input>>,
+) {
+    match statement {
+        Statement::TypeAlias(alias) => out.push(alias),
+        Statement::Fn(fn_) => fn_.body.inner.iter().for_each(|s| collect(s, out)),
+        Statement::Scope(scope) => scope.inner.iter().for_each(|s| collect(s, out)),
+        Statement::If(if_) => {
+            if_.inner.iter().for_each(|s| collect(s, out));
+            if let Some(else_) = &if_.else_ {
+                else_.inner.iter().for_each(|s| collect(s, out));
+            }
+        }
+        Statement::Loop(loop_) => loop_.inner.iter().for_each(|s| collect(s, out)),
+        Statement::While(while_) => while_.inner.iter().for_each(|s| collect(s, out)),
+        _ => {}
+    }
+}
+
+// Lowers syntactic types, and the types of all the declarations before any of them is used.
+struct Lower<'a, 'input> {
+    consts: &'a Consts,
+    resolution: &'a Resolution,
+    diagnostics: &'a mut Diagnostics,
+    aliases: HashMap<NodeId, &'a TypeAliasStatement<'input>>,
+    // aliases being expanded, to detect infinitely sized types
+    expanding: Vec<NodeId>,
+    types: Types,
+}
+
+impl Lower<'_, '_> {
+    /// Lower a syntactic type.
+    ///
+    /// Array lengths whose evaluation failed (and has already been reported) lower to
+    /// [`Ty::Error`].
+    fn ty(&mut self, type_: &Type<'_>) -> Ty {
+        match type_ {
+            Type::U8(_) => Ty::U8,
+            Type::U16(_) => Ty::U16,
+            Type::I8(_) => Ty::I8,
+            Type::I16(_) => Ty::I16,
+            Type::Bool(_) => Ty::Bool,
+            Type::Array(array) => {
+                let ty = self.ty(&array.type_);
+                match self.consts.value(array.length.id()) {
+                    Some(len) => Ty::Array(Box::new(ty), len as usize),
+                    None => Ty::Error,
+                }
+            }
+            Type::Ptr(ptr) => match &*ptr.type_ {
+                // named types behind pointers are expanded lazily
+                Type::Named(named) => match self.named(named) {
+                    Some(id) => Ty::Ptr(Box::new(Ty::Named(
+                        id,
+                        named.identifier.as_str().to_string(),
+                    ))),
+                    None => Ty::Ptr(Box::new(Ty::Error)),
+                },
+                type_ => Ty::Ptr(Box::new(self.ty(type_))),
+            },
+            Type::Struct(struct_) => Ty::Struct(self.fields(&struct_.fields)),
+            Type::Union(union) => Ty::Union(self.fields(&union.fields)),
+            Type::Named(named) => match self.named(named) {
+                Some(id) => self.alias(id, named.span()),
+                None => Ty::Error,
+            },
+        }
+    }
+
+    fn fields<'input>(
+        &mut self,
+        fields: &Punctuated<crate::ast::types::Field<'input, Type<'input>>, tokens::Comma<'input>>,
+    ) -> Vec<Field> {
+        fields
+            .iter()
+            .map(|field| Field {
+                name: field.identifier.as_str().to_string(),
+                ty: self.ty(&field.type_),
+            })
+            .collect()
+    }
+
+    /// ID of the `type` declaration a named type refers to.
+    fn named(&mut self, named: &Named<'_>) -> Option<NodeId> {
+        // unbound names have already been reported by the resolver
+        let id = self.resolution.binding(named.id)?;
+        if self.aliases.contains_key(&id) {
+            return Some(id);
+        }
+        let message = format!("expected type, found `{}`", named.identifier.as_str());
+        let declaration = self
+            .resolution
+            .declaration(id)
+            .expect("Expected a declaration");
+        let label = format!("`{}` declared here", named.identifier.as_str());
+        let diagnostic =
+            Diagnostic::error(named.span(), message).with_label(declaration.span, label);
+        self.diagnostics.emit(diagnostic);
+        None
+    }
+
+    /// Expanded definition of a `type` declaration, used from the given span.
+    fn alias(&mut self, id: NodeId, span: Span) -> Ty {
+        if let Some(ty) = self.types.aliases.get(&id) {
+            return ty.clone();
+        }
+        let alias = self.aliases[&id];
+        if self.expanding.contains(&id) {
+            let message = format!(
+                "recursive type `{}` has infinite size",
+                alias.identifier.as_str()
+            );
+            let diagnostic = Diagnostic::error(alias.identifier.span(), message)
+                .with_label(span, "recursive without indirection (use a `ptr<...>`)");
+            self.diagnostics.emit(diagnostic);
+            return Ty::Error;
+        }
+        self.expanding.push(id);
+        let ty = self.ty(&alias.definition);
+        self.expanding.pop();
+        self.types.aliases.insert(id, ty.clone());
+        ty
+    }
+}
+
+impl<'input> Visit<'input> for Lower<'_, '_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        let (id, type_) = match statement {
+            Statement::Let(let_) => (let_.id, &let_.type_),
+            Statement::Const(const_) => (const_.id, &const_.type_),
+            Statement::Static(static_) => (static_.id, &static_.type_),
+            Statement::Fn(fn_) => {
+                let mut params = Vec::new();
+                for param in fn_.params.iter() {
+                    let ty = self.ty(&param.type_);
+                    self.types.declarations.insert(param.id, ty.clone());
+                    params.push(ty);
+                }
+                let ret = match &fn_.return_type {
+                    Some(return_type) => self.ty(&return_type.type_),
+                    None => Ty::Unit,
+                };
+                let ty = Ty::Fn(params, Box::new(ret));
+                self.types.declarations.insert(fn_.id, ty);
+                return fn_.body.accept(self);
+            }
+            _ => return visit::walk_statement(self, statement),
+        };
+        let ty = self.ty(type_);
+        self.types.declarations.insert(id, ty);
+        visit::walk_statement(self, statement);
+    }
+
+    // types of casts are lowered with the rest
+    fn visit_cast<E: ExpressionGrammar<'input>>(&mut self, cast: &Cast<'input, E>) {
+        let ty = self.ty(&cast.type_);
+        self.types.casts.insert(cast.id, ty);
+        visit::walk_cast(self, cast);
+    }
+}
+
+// Array lengths and placements are constant expressions, which may mix the integer types of the
+// `const`s they refer to. Those that failed to evaluate have already been reported.
+struct Constants<'a, 'b> {
+    consts: &'a Consts,
+    checker: &'a mut Checker<'b>,
+}
+
+impl Constants<'_, '_> {
+    fn constant(&mut self, expression: &Expression<'_>, label: (Span, &str)) {
+        if self.consts.value(expression.id()).is_none() {
+            return;
+        }
+        let ty = self.checker.expression(expression);
+        if !ty.is_integer() && ty != Ty::Error {
+            let message = format!("mismatched types: expected an integer, found `{}`", ty);
+            let diagnostic =
+                Diagnostic::error(expression.span(), message).with_label(label.0, label.1);
+            self.checker.diagnostics.emit(diagnostic);
+        }
+    }
+}
+
+impl<'input> Visit<'input> for Constants<'_, '_> {
+    fn visit_array<T: TypeGrammar<'input>>(&mut self, array: &Array<'input, T>) {
+        let label = (array.array.span(), "expected due to this array type");
+        self.constant(&array.length, label);
+        array.type_.accept(self);
+    }
+
+    fn visit_placement(&mut self, placement: &Placement<'input>) {
+        let label = (placement.at.span(), "expected due to this placement");
+        self.constant(&placement.address, label);
+    }
+}
+
+struct Checker<'a> {
+    resolution: &'a Resolution,
+    diagnostics: &'a mut Diagnostics,
+    types: Types,
+    // return type of the enclosing functions, and the span that explains it
+    returns: Vec<(Ty, Span)>,
+}
+
+impl Checker<'_> {
+    /// Check an expression against the type it is expected to have. The label explains where
+    /// the expectation comes from.
+    fn expect(&mut self, expression: &Expression<'_>, expected: &Ty, label: (Span, &str)) {
+        // string literals initialize any array of bytes that is large enough to hold them
+        if let (Expression::Str(str), Ty::Array(ty, len)) = (expression, expected) {
+            if **ty == Ty::U8 {
+                let bytes = str.str.contents().len();
+                self.types
+                    .expressions
+                    .insert(str.id, Ty::Array(Box::new(Ty::U8), bytes));
+                if bytes > *len {
+                    let message = format!(
+                        "string literal of {} bytes doesn't fit in `{}`",
+                        bytes, expected
+                    );
+                    let diagnostic =
+                        Diagnostic::error(expression.span(), message).with_label(label.0, label.1);
+                    self.diagnostics.emit(diagnostic);
+                }
+                return;
+            }
+        }
+        let found = self.typed(expression, Some(expected));
+        if !self.types.is_compatible(expected, &found) {
+            let message = format!(
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            );
+            let diagnostic =
+                Diagnostic::error(expression.span(), message).with_label(label.0, label.1);
+            self.diagnostics.emit(diagnostic);
+        }
+    }
+
+    /// Type of an expression.
+    fn expression(&mut self, expression: &Expression<'_>) -> Ty {
+        self.typed(expression, None)
+    }
+
+    /// Type of an expression, where integer literals take the type of the `hint` (if it is an
+    /// integer type).
+    fn typed(&mut self, expression: &Expression<'_>, hint: Option<&Ty>) -> Ty {
+        let ty = match expression {
+            Expression::Parenthesis(parenthesis) => self.typed(&parenthesis.inner, hint),
+            Expression::Index(index) => {
+                let indexable = self.expression(&index.indexable);
+                let ty = self.expression(&index.index);
+                if !matches!(ty, Ty::U8 | Ty::U16 | Ty::Error) {
+                    let message =
+                        format!("mismatched types: expected `u8` or `u16`, found `{}`", ty);
+                    let diagnostic = Diagnostic::error(index.index.span(), message)
+                        .with_label(index.indexable.span(), "indexed value");
+                    self.diagnostics.emit(diagnostic);
+                }
+                match indexable {
+                    Ty::Array(ty, _) | Ty::Ptr(ty) => self.types.expand(*ty),
+                    Ty::Error => Ty::Error,
+                    ty => {
+                        let message = format!("cannot index into a value of type `{}`", ty);
+                        let diagnostic = Diagnostic::error(index.indexable.span(), message)
+                            .with_label(index.square_left.span(), "indexed here");
+                        self.diagnostics.emit(diagnostic);
+                        Ty::Error
+                    }
+                }
+            }
+            Expression::FieldAccess(access) => match self.expression(&access.inner) {
+                Ty::Error => Ty::Error,
+                ty => {
+                    let name = access.field.as_str();
+                    let field = match &ty {
+                        Ty::Struct(fields) | Ty::Union(fields) => {
+                            fields.iter().find(|field| field.name == name)
+                        }
+                        _ => None,
+                    };
+                    match field {
+                        Some(field) => self.types.expand(field.ty.clone()),
+                        None => {
+                            let message = format!("no field `{}` on type `{}`", name, ty);
+                            let diagnostic = Diagnostic::error(access.field.span(), message)
+                                .with_label(access.inner.span(), "accessed value");
+                            self.diagnostics.emit(diagnostic);
+                            Ty::Error
+                        }
+                    }
+                }
+            },
+            Expression::Call(call) => match self.expression(&call.callable) {
+                Ty::Fn(params, ret) => {
+                    if params.len() != call.arguments.len() {
+                        let message = format!(
+                            "this function takes {} argument{} but {} {} supplied",
+                            params.len(),
+                            if params.len() == 1 { "" } else { "s" },
+                            call.arguments.len(),
+                            if call.arguments.len() == 1 {
+                                "was"
+                            } else {
+                                "were"
+                            },
+                        );
+                        let diagnostic = Diagnostic::error(expression.span(), message)
+                            .with_label(call.callable.span(), "called here");
+                        self.diagnostics.emit(diagnostic);
+                    }
+                    let label = (call.callable.span(), "argument of this call");
+                    for (i, argument) in call.arguments.iter().enumerate() {
+                        match params.get(i) {
+                            Some(param) => self.expect(argument, param, label),
+                            None => {
+                                self.expression(argument);
+                            }
+                        }
+                    }
+                    *ret
+                }
+                ty => {
+                    if ty != Ty::Error {
+                        let message = format!("values of type `{}` can't be called", ty);
+                        let diagnostic = Diagnostic::error(call.callable.span(), message)
+                            .with_label(call.par_left.span(), "called here");
+                        self.diagnostics.emit(diagnostic);
+                    }
+                    for argument in call.arguments.iter() {
+                        self.expression(argument);
+                    }
+                    Ty::Error
+                }
+            },
+            Expression::Number(number) => {
+                let value = number.number.value();
+                let ty = match hint {
+                    Some(ty) if ty.is_integer() => ty.clone(),
+                    // unconstrained literals are `u8`, unless they don't fit in one
+                    _ if matches!(value, Some(value) if value > Width::U8.max() as u64) => Ty::U16,
+                    _ => Ty::U8,
+                };
+                let width = ty.width().expect("Expected an integer type");
+                if !matches!(value, Some(value) if value <= width.max() as u64) {
+                    let message = format!("literal out of range for `{}`", ty);
+                    self.diagnostics
+                        .emit(Diagnostic::error(expression.span(), message));
+                }
+                ty
+            }
+            Expression::True(_) | Expression::False(_) => Ty::Bool,
+            Expression::Str(str) => Ty::Array(Box::new(Ty::U8), str.str.contents().len()),
+            Expression::Identifier(identifier) => match self.resolution.binding(identifier.id) {
+                Some(id) if self.types.aliases.contains_key(&id) => {
+                    let message = format!(
+                        "expected value, found type `{}`",
+                        identifier.identifier.as_str()
+                    );
+                    self.diagnostics
+                        .emit(Diagnostic::error(expression.span(), message));
+                    Ty::Error
+                }
+                Some(id) => self
+                    .types
+                    .declarations
+                    .get(&id)
+                    .cloned()
+                    .unwrap_or(Ty::Error),
+                None => Ty::Error,
+            },
+            Expression::Add(add) => {
+                self.binary(&add.left, add.plus.span(), &add.right, hint, ARITHMETIC)
+            }
+            Expression::Subtract(sub) => {
+                self.binary(&sub.left, sub.minus.span(), &sub.right, hint, ARITHMETIC)
+            }
+            Expression::Multiply(mul) => {
+                self.binary(&mul.left, mul.star.span(), &mul.right, hint, ARITHMETIC)
+            }
+            Expression::Divide(div) => self.binary(
+                &div.left,
+                div.forward_slash.span(),
+                &div.right,
+                hint,
+                ARITHMETIC,
+            ),
+            Expression::BitAnd(and) => {
+                self.binary(&and.left, and.and.span(), &and.right, hint, BITWISE)
+            }
+            Expression::BitOr(or) => self.binary(&or.left, or.or.span(), &or.right, hint, BITWISE),
+            Expression::BitXor(xor) => {
+                self.binary(&xor.left, xor.xor.span(), &xor.right, hint, BITWISE)
+            }
+            Expression::Equal(eq) => {
+                self.comparison(&eq.left, eq.equals_equals.span(), &eq.right, EQUALITY)
+            }
+            Expression::NotEqual(ne) => {
+                self.comparison(&ne.left, ne.not_equals.span(), &ne.right, EQUALITY)
+            }
+            Expression::Less(lt) => {
+                self.comparison(&lt.left, lt.less_than.span(), &lt.right, ORDERING)
+            }
+            Expression::LessEqual(le) => {
+                self.comparison(&le.left, le.less_equals_than.span(), &le.right, ORDERING)
+            }
+            Expression::Greater(gt) => {
+                self.comparison(&gt.left, gt.greater_than.span(), &gt.right, ORDERING)
+            }
+            Expression::GreaterEqual(ge) => {
+                self.comparison(&ge.left, ge.greater_equals_than.span(), &ge.right, ORDERING)
+            }
+            Expression::BitNot(not) => {
+                let ty = self.typed(&not.inner, hint);
+                self.operand(&not.inner, &ty, not.not.span(), BITWISE);
+                ty
+            }
+            Expression::Negate(negate) => match &*negate.inner {
+                // the literal of a negative number goes one further than the positive ones
+                Expression::Number(number) => {
+                    let value = number.number.value();
+                    let ty = match hint {
+                        Some(ty) if ty.is_integer() => ty.clone(),
+                        // unconstrained negative literals are `i8`, unless they don't fit in one
+                        _ if matches!(value, Some(value) if value > (-Width::I8.min()) as u64) => {
+                            Ty::I16
+                        }
+                        _ => Ty::I8,
+                    };
+                    let width = ty.width().expect("Expected an integer type");
+                    if !matches!(value, Some(value) if value <= (-width.min()) as u64) {
+                        let message = format!("literal out of range for `{}`", ty);
+                        self.diagnostics
+                            .emit(Diagnostic::error(expression.span(), message));
+                    }
+                    self.types.expressions.insert(negate.inner.id(), ty.clone());
+                    ty
+                }
+                inner => {
+                    let ty = self.typed(inner, hint);
+                    let signed = matches!(ty.width(), Some(width) if width.is_signed());
+                    if !(signed || ty == Ty::Error) {
+                        let message = format!(
+                            "mismatched types: expected a signed integer, found `{}`",
+                            ty
+                        );
+                        let diagnostic = Diagnostic::error(inner.span(), message).with_label(
+                            negate.minus.span(),
+                            "negated operands must be signed integers",
+                        );
+                        self.diagnostics.emit(diagnostic);
+                    }
+                    ty
+                }
+            },
+            Expression::Cast(cast) => {
+                let from = self.expression(&cast.inner);
+                let to = self.types.casts[&cast.id].clone();
+                let primitive = |ty: &Ty| ty.is_integer() || *ty == Ty::Bool;
+                let valid = from == to
+                    || from == Ty::Error
+                    || to == Ty::Error
+                    || (primitive(&from) && primitive(&to))
+                    || matches!(
+                        (&from, &to),
+                        (Ty::Ptr(_), Ty::Ptr(_) | Ty::U16) | (Ty::U16, Ty::Ptr(_))
+                    );
+                if !valid {
+                    let message = format!("cannot cast `{}` as `{}`", from, to);
+                    let diagnostic = Diagnostic::error(expression.span(), message)
+                        .with_label(cast.as_.span(), "invalid cast");
+                    self.diagnostics.emit(diagnostic);
+                }
+                to
+            }
+            Expression::Addr(addr) => {
+                let ty = self.expression(&addr.inner);
+                if !self.is_place(&addr.inner) {
+                    let diagnostic = Diagnostic::error(
+                        addr.inner.span(),
+                        "cannot take the address of a value that isn't stored in memory",
+                    )
+                    .with_label(addr.addr.span(), "address taken here");
+                    self.diagnostics.emit(diagnostic);
+                }
+                Ty::Ptr(Box::new(ty))
+            }
+            Expression::Deref(deref) => match self.expression(&deref.inner) {
+                Ty::Ptr(ty) => self.types.expand(*ty),
+                Ty::Error => Ty::Error,
+                ty => {
+                    let message = format!("cannot dereference a value of type `{}`", ty);
+                    let diagnostic = Diagnostic::error(deref.inner.span(), message)
+                        .with_label(deref.deref.span(), "dereferenced here");
+                    self.diagnostics.emit(diagnostic);
+                    Ty::Error
+                }
+            },
+        };
+        self.types.expressions.insert(expression.id(), ty.clone());
+        ty
+    }
+
+    /// Type of a binary operation. Both operands must have the same type, which is the type of
+    /// the result.
+    fn binary(
+        &mut self,
+        left: &Expression<'_>,
+        operator: Span,
+        right: &Expression<'_>,
+        hint: Option<&Ty>,
+        kind: Operator,
+    ) -> Ty {
+        // a literal operand takes the type of the other one
+        let (left_ty, right_ty) = if is_literal(left) && !is_literal(right) {
+            let right_ty = self.typed(right, hint);
+            (self.typed(left, Some(&right_ty)), right_ty)
+        } else {
+            let left_ty = self.typed(left, hint);
+            let right_ty = self.typed(right, Some(&left_ty));
+            (left_ty, right_ty)
+        };
+        if !self.operand(left, &left_ty, operator, kind)
+            || !self.operand(right, &right_ty, operator, kind)
+        {
+            return Ty::Error;
+        }
+        if !self.types.is_compatible(&left_ty, &right_ty) {
+            let message = format!(
+                "mismatched types: expected `{}`, found `{}`",
+                left_ty, right_ty
+            );
+            let diagnostic = Diagnostic::error(right.span(), message).with_label(
+                operator,
+                "operands must have the same type (use `as` to convert)",
+            );
+            self.diagnostics.emit(diagnostic);
+            return Ty::Error;
+        }
+        if left_ty == Ty::Error {
+            right_ty
+        } else {
+            left_ty
+        }
+    }
+
+    /// Type of a comparison, which is a `bool`. Both operands must have the same type.
+    fn comparison(
+        &mut self,
+        left: &Expression<'_>,
+        operator: Span,
+        right: &Expression<'_>,
+        kind: Operator,
+    ) -> Ty {
+        self.binary(left, operator, right, None, kind);
+        Ty::Bool
+    }
+
+    /// Check the type of an operand, returning whether the operator supports it.
+    fn operand(
+        &mut self,
+        expression: &Expression<'_>,
+        ty: &Ty,
+        operator: Span,
+        kind: Operator,
+    ) -> bool {
+        let supported = ty.is_integer() || *ty == Ty::Error || (kind.bool && *ty == Ty::Bool);
+        if !supported {
+            let message = format!(
+                "mismatched types: expected {}, found `{}`",
+                kind.expected, ty
+            );
+            let diagnostic =
+                Diagnostic::error(expression.span(), message).with_label(operator, kind.label);
+            self.diagnostics.emit(diagnostic);
+        }
+        supported
+    }
+
+    /// Check the condition of an `if` or `while`, which can be a `bool` or an integer (that is
+    /// true when it isn't zero).
+    fn condition(&mut self, expression: &Expression<'_>, label: (Span, &str)) {
+        let ty = self.expression(expression);
+        if !(ty.is_integer() || ty == Ty::Bool || ty == Ty::Error) {
+            let message = format!(
+                "mismatched types: expected `bool` or an integer, found `{}`",
+                ty
+            );
+            let diagnostic =
+                Diagnostic::error(expression.span(), message).with_label(label.0, label.1);
+            self.diagnostics.emit(diagnostic);
+        }
+    }
+
+    /// Whether the expression refers to a location in memory.
+    fn is_place(&self, expression: &Expression<'_>) -> bool {
+        match expression {
+            Expression::Parenthesis(parenthesis) => self.is_place(&parenthesis.inner),
+            Expression::Index(index) => self.is_place(&index.indexable),
+            Expression::FieldAccess(access) => self.is_place(&access.inner),
+            Expression::Deref(_) => true,
+            Expression::Identifier(identifier) => {
+                match self.resolution.binding(identifier.id) {
+                    Some(declaration) => matches!(
+                        self.resolution.declaration(declaration).map(|d| d.kind),
+                        Some(
+                            DeclarationKind::Let | DeclarationKind::Static | DeclarationKind::Param
+                        )
+                    ),
+                    // already reported by the resolver
+                    None => true,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Operands supported by a binary (or unary) operator.
+#[derive(Clone, Copy)]
+struct Operator {
+    bool: bool,
+    expected: &'static str,
+    label: &'static str,
+}
+
+const ARITHMETIC: Operator = Operator {
+    bool: false,
+    expected: "an integer",
+    label: "arithmetic operands must be integers",
+};
+
+const BITWISE: Operator = Operator {
+    bool: true,
+    expected: "an integer or `bool`",
+    label: "bitwise operands must be integers or `bool`",
+};
+
+const EQUALITY: Operator = Operator {
+    bool: true,
+    expected: "an integer or `bool`",
+    label: "compared operands must be integers or `bool`",
+};
+
+const ORDERING: Operator = Operator {
+    bool: false,
+    expected: "an integer",
+    label: "ordered operands must be integers",
+};
+
+/// Whether the expression is made of integer literals only, so its type depends on its context.
+fn is_literal(expression: &Expression<'_>) -> bool {
+    match expression {
+        Expression::Number(_) => true,
+        Expression::Parenthesis(parenthesis) => is_literal(&parenthesis.inner),
+        Expression::BitNot(not) => is_literal(&not.inner),
+        Expression::Negate(negate) => is_literal(&negate.inner),
+        Expression::Add(add) => is_literal(&add.left) && is_literal(&add.right),
+        Expression::Subtract(sub) => is_literal(&sub.left) && is_literal(&sub.right),
+        Expression::Multiply(mul) => is_literal(&mul.left) && is_literal(&mul.right),
+        Expression::Divide(div) => is_literal(&div.left) && is_literal(&div.right),
+        Expression::BitAnd(and) => is_literal(&and.left) && is_literal(&and.right),
+        Expression::BitOr(or) => is_literal(&or.left) && is_literal(&or.right),
+        Expression::BitXor(xor) => is_literal(&xor.left) && is_literal(&xor.right),
+        _ => false,
+    }
+}
+
+impl<'input> Visit<'input> for Checker<'_> {
+    fn visit_statement(&mut self, statement: &Statement<'input>) {
+        match statement {
+            Statement::Let(let_) => {
+                let ty = self.types.declarations[&let_.id].clone();
+                let label = (let_.type_.span(), "expected due to this type");
+                self.expect(&let_.expression, &ty, label);
+            }
+            Statement::Const(const_) => {
+                let ty = self.types.declarations[&const_.id].clone();
+                let label = (const_.type_.span(), "expected due to this type");
+                self.expect(&const_.expression, &ty, label);
+            }
+            Statement::Static(static_) => {
+                if let Some(initializer) = &static_.initializer {
+                    let ty = self.types.declarations[&static_.id].clone();
+                    let label = (static_.type_.span(), "expected due to this type");
+                    self.expect(&initializer.expression, &ty, label);
+                }
+            }
+            Statement::Fn(fn_) => {
+                let ret = match &self.types.declarations[&fn_.id] {
+                    Ty::Fn(_, ret) => (**ret).clone(),
+                    _ => unreachable!(),
+                };
+                let span = match &fn_.return_type {
+                    Some(return_type) => return_type.type_.span(),
+                    None => fn_.identifier.span(),
+                };
+                self.returns.push((ret, span));
+                fn_.body.accept(self);
+                self.returns.pop();
+            }
+            Statement::Return(return_) => {
+                let expected = self.returns.last().cloned();
+                match (&return_.expression, expected) {
+                    (Some(expression), Some((Ty::Unit, span))) => {
+                        self.expression(expression);
+                        let diagnostic = Diagnostic::error(
+                            expression.span(),
+                            "returned a value from a function that doesn't return one",
+                        )
+                        .with_label(span, "function declared here");
+                        self.diagnostics.emit(diagnostic);
+                    }
+                    (Some(expression), Some((ty, span))) => {
+                        self.expect(expression, &ty, (span, "expected due to this return type"))
+                    }
+                    (None, Some((ty, span))) if ty != Ty::Unit && ty != Ty::Error => {
+                        let message = format!("expected a return value of type `{}`", ty);
+                        let diagnostic = Diagnostic::error(return_.span(), message)
+                            .with_label(span, "expected due to this return type");
+                        self.diagnostics.emit(diagnostic);
+                    }
+                    // `return` outside of a function is reported by the control flow checks
+                    (Some(expression), None) => {
+                        self.expression(expression);
+                    }
+                    (None, _) => {}
+                }
+            }
+            Statement::Expression(statement) => {
+                self.expression(&statement.expression);
+            }
+            Statement::Assign(assign) => {
+                let ty = self.expression(&assign.place);
+                if !self.is_place(&assign.place) {
+                    let diagnostic = Diagnostic::error(
+                        assign.place.span(),
+                        "cannot assign to a value that isn't stored in memory",
+                    )
+                    .with_label(assign.equals.span(), "assigned here");
+                    self.diagnostics.emit(diagnostic);
+                }
+                let label = (assign.place.span(), "expected due to this place");
+                self.expect(&assign.expression, &ty, label);
+            }
+            Statement::If(if_) => {
+                let label = (if_.if_.span(), "condition of this `if`");
+                self.condition(&if_.expression, label);
+                if_.inner.accept(self);
+                if_.else_.accept(self);
+            }
+            Statement::While(while_) => {
+                let label = (while_.while_.span(), "condition of this `while`");
+                self.condition(&while_.expression, label);
+                while_.inner.accept(self);
+            }
+            _ => visit::walk_statement(self, statement),
+        }
+    }
+}
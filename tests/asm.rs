@@ -0,0 +1,326 @@
+use gb_lang::asm::{
+    decode::{decode, disassemble},
+    encode::{self, assemble, Error},
+    inline::{self, expand, references},
+    interp::Interpreter,
+    parse::{self, parse},
+    Assembly, Instruction, Line, Source, Value,
+};
+
+// Every opcode, followed by operand bytes.
+fn opcodes() -> impl Iterator<Item = Vec<u8>> {
+    let unprefixed = (0..=0xffu8).map(|opcode| vec![opcode, 0x00, 0xc0]);
+    let prefixed = (0..=0xffu8).map(|opcode| vec![0xcb, opcode]);
+    unprefixed.chain(prefixed)
+}
+
+#[test]
+fn decode_encode_round_trip() {
+    let mut count = 0;
+    for bytes in opcodes() {
+        if let Some(instruction) = decode(&bytes, 0x150) {
+            let size = usize::from(instruction.size());
+            let encoded = instruction.encode(0x150, |_| None).unwrap();
+            assert_eq!(&bytes[..size], &encoded[..], "{}", instruction);
+            count += 1;
+        }
+    }
+    // 245 unprefixed opcodes (256 minus the prefix and the 11 illegal ones) and 256 prefixed
+    assert_eq!(245 + 256, count);
+}
+
+#[test]
+fn print_parse_round_trip() {
+    for bytes in opcodes() {
+        if let Some(instruction) = decode(&bytes, 0x150) {
+            let text = format!("    {}\n", instruction);
+            let assembly = parse(&text).unwrap();
+            assert_eq!(
+                vec![Line::Instruction(instruction)],
+                assembly.lines,
+                "{}",
+                text
+            );
+        }
+    }
+}
+
+#[test]
+fn illegal_opcodes() {
+    for opcode in [
+        0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc, 0xfd,
+    ] {
+        assert_eq!(None, decode(&[opcode, 0, 0], 0));
+    }
+    // truncated operands
+    assert_eq!(None, decode(&[0xc3, 0x00], 0));
+    assert_eq!(None, decode(&[0xcb], 0));
+}
+
+#[test]
+fn assemble_labels() {
+    let assembly = parse(
+        "start:
+    ld hl, message
+    call far
+    jr start
+message:
+    db $48, $49, 0
+far:
+    ret",
+    )
+    .unwrap();
+    let object = assemble(&assembly, 0x150).unwrap();
+    assert_eq!(
+        vec![
+            0x21, 0x58, 0x01, // ld hl, message
+            0xcd, 0x5b, 0x01, // call far
+            0x18, 0xf8, // jr start
+            0x48, 0x49, 0x00, // db
+            0xc9, // ret
+        ],
+        object.bytes
+    );
+    assert_eq!(Some(&0x158), object.labels.get("message"));
+    assert_eq!(
+        "    ld hl, $0158
+    call $015B
+    jr $0150
+    ld c, b
+    ld c, c
+    nop
+    ret
+",
+        disassemble(&object.bytes, 0x150).dump()
+    );
+}
+
+#[test]
+fn assemble_errors() {
+    let mut far = parse("start:\n    jr end\n    db 0\nend:").unwrap();
+    far.lines[2] = Line::Data(vec![0; 127]);
+    assert_eq!(0x7f, assemble(&far, 0).unwrap().bytes[1]);
+    far.lines[2] = Line::Data(vec![0; 128]);
+    assert_eq!(
+        Err(Error::JumpOutOfRange {
+            instruction: Instruction::Jr(None, Value::label("end")),
+            distance: 128,
+        }),
+        assemble(&far, 0)
+    );
+    let mut back = parse("start:\n    db 0\n    jr start").unwrap();
+    back.lines[1] = Line::Data(vec![0; 127]);
+    assert_eq!(
+        -129,
+        match assemble(&back, 0) {
+            Err(Error::JumpOutOfRange { distance, .. }) => distance,
+            other => panic!("{:?}", other),
+        }
+    );
+
+    assert_eq!(
+        Err(Error::UndefinedLabel("nowhere".to_string())),
+        assemble(&parse("    jp nowhere").unwrap(), 0)
+    );
+    assert_eq!(
+        Err(Error::DuplicateLabel("a".to_string())),
+        assemble(&parse("a:\na:").unwrap(), 0)
+    );
+    let invalid = Instruction::LdHlInd(Source::HlInd);
+    let mut assembly = Assembly::default();
+    assembly.push(invalid.clone());
+    assert_eq!(
+        Err(Error::InvalidInstruction(invalid)),
+        assemble(&assembly, 0)
+    );
+    assert_eq!(
+        "`ldh [$C000], a` isn't a valid instruction",
+        assemble(&parse("    ldh [$C000], a").unwrap(), 0)
+            .unwrap_err()
+            .to_string()
+    );
+}
+
+#[test]
+fn parse_syntax() {
+    let assembly = parse(
+        "; comment
+Main::  ld a, [hli] ; exported label
+.loop:
+    LD HL, SP - 2
+    ld hl, sp+3
+    ldh [$40], a
+    ldh a, [c]
+    and $f0
+    bit 7, [hl]
+    db -1, %11",
+    )
+    .unwrap();
+    assert_eq!(
+        "Main:
+    ld a, [hl+]
+.loop:
+    ld hl, sp - 2
+    ld hl, sp + 3
+    ldh [$FF40], a
+    ldh a, [c]
+    and a, $F0
+    bit 7, [hl]
+    db $FF, $03
+",
+        assembly.dump()
+    );
+
+    assert_eq!(
+        Err(parse::Error::UnknownInstruction {
+            line: 2,
+            mnemonic: "mov".to_string()
+        }),
+        parse("    nop\n    mov a, b")
+    );
+    for invalid in [
+        "ld a",
+        "ld [hl], [hl]",
+        "ld b, [bc]",
+        "bit 8, a",
+        "rst $10 + 1",
+        "rst 3",
+        "jp q, start",
+        "ld a, 256",
+        "push sp",
+        "db",
+    ] {
+        assert_eq!(
+            Err(parse::Error::InvalidOperands {
+                line: 1,
+                text: invalid.to_string()
+            }),
+            parse(invalid)
+        );
+    }
+}
+
+#[test]
+fn interpret() {
+    let assembly = parse(
+        "    ld a, $45
+    add a, $38
+    daa
+    ld [$C000], a
+    ld b, $81
+    rrc b
+    swap b
+    ld hl, $C001
+    ld [hl], b
+    set 0, [hl]
+    res 7, [hl]
+    scf
+    ccf
+    rra
+    ld sp, $D000
+    add sp, -2
+    ld [$C002], sp
+    halt",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::new(&assembly).unwrap();
+    interpreter.run(100).unwrap();
+    assert_eq!(0x83, interpreter.memory[0xc000]);
+    assert_eq!(0x0d, interpreter.memory[0xc001]);
+    assert_eq!([0xfe, 0xcf], interpreter.memory[0xc002..0xc004]);
+    assert_eq!(0x41, interpreter.registers.a);
+}
+
+#[test]
+fn inline_expand() {
+    let input = "
+.loop: ld a, [hl+] ; COMMENT
+    and MASK
+    jr z, .loop
+    ld bc, sp_end
+    call Screen.clear";
+    let names: Vec<_> = references(input)
+        .iter()
+        .map(|reference| (reference.base(), reference.fields()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("MASK", vec![]),
+            ("sp_end", vec![]),
+            ("Screen", vec!["clear"])
+        ],
+        names
+    );
+    let replacements = ["%11".to_string(), "$D000".to_string(), "clear".to_string()];
+    assert_eq!(
+        "__asm1.loop:
+    ld a, [hl+]
+    and a, $03
+    jr z, __asm1.loop
+    ld bc, $D000
+    call clear
+",
+        expand(input, &replacements, "__asm1").unwrap().dump()
+    );
+
+    assert_eq!(
+        Err(inline::Error::UnknownInstruction {
+            line: 2,
+            mnemonic: "mov".to_string()
+        }),
+        expand("nop\nmov a, b", &[], "")
+    );
+    assert_eq!(
+        Err(inline::Error::InvalidOperands {
+            line: 1,
+            text: "ld a, 4660".to_string()
+        }),
+        expand("ld a, BIG", &["4660".to_string()], "")
+    );
+    let far = format!("start:\n{}    jr start", "    ld a, [$C000]\n".repeat(43));
+    assert_eq!(
+        Err(inline::Error::Invalid {
+            line: 45,
+            error: encode::Error::JumpOutOfRange {
+                instruction: Instruction::Jr(None, Value::label(".start")),
+                distance: -131,
+            }
+        }),
+        expand(&far, &[], "")
+    );
+    assert_eq!(
+        "label `.a` is defined more than once",
+        expand("a:\na:", &[], "").unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn inline_check() {
+    let input = "const BIG::u16 = 0x1234;
+type Io = struct { ly::u8 };
+static IO @ 0xff00 :: Io;
+static OUT::u8;
+asm { ld a, [IO.lcdc] }
+asm { ld a, Io }
+asm { ldh [OUT], a }
+asm { ld a, BIG.low }
+asm {
+    ld a, BIG
+}
+asm {
+      mov a, b
+}";
+    let diagnostics = gb_lang::compile(input).unwrap_err();
+    let errors: Vec<_> = diagnostics.errors().map(ToString::to_string).collect();
+    assert_eq!(
+        vec![
+            "5:14: error: no field `lcdc` in `IO`",
+            "6:13: error: expected a value, found type `Io`",
+            "7:7: error: `ldh [$C000], a` isn't a valid instruction",
+            "8:13: error: `BIG` has no fields",
+            "10:5: error: invalid operands `ld a, 4660`",
+            "13:7: error: unknown instruction `mov`",
+        ],
+        errors
+    );
+}
@@ -1,7 +1,12 @@
-use gb_lang::ast::{
-    expressions::{Expression, Number, Str},
-    statements::{Break, Const, Continue, If, Let, Loop, Scope, Statement, Static, While},
-    types::{Array, Type, U8},
+use gb_lang::{
+    ast::{
+        expressions::{Expression, Number, Str},
+        statements::{Break, Const, Continue, If, Let, Loop, Scope, Statement, Static, While},
+        types::{Array, Type, U8},
+        visit::{Visit, Walk},
+        TokenGrammar,
+    },
+    Span, Spanned,
 };
 
 #[test]
@@ -13,9 +18,9 @@ fn statement_let() {
 
 #[test]
 fn statement_const() {
-    gb_lang::parse::<Const<U8>>("const FOO::u8;").unwrap();
-    gb_lang::parse::<Const<Type>>("const FOO::array<u8, 123>;").unwrap();
-    gb_lang::parse::<Const<Type>>("const FOO::array<u8, 123>;").unwrap();
+    gb_lang::parse::<Const<U8, Number>>("const FOO::u8 = 42;").unwrap();
+    gb_lang::parse::<Const<Type, Expression>>("const FOO::u8 = BAR * (2 + 1);").unwrap();
+    assert!(gb_lang::parse::<Const<U8, Number>>("const FOO::u8;").is_err());
 }
 
 #[test]
@@ -56,8 +61,277 @@ fn statement_loop_continue() {
     gb_lang::parse::<While<Number, Continue>>("while 1 continue;").unwrap();
 }
 
+#[test]
+fn statement_if_else() {
+    gb_lang::parse::<Statement>("if 0 {} else {}").unwrap();
+    gb_lang::parse::<Statement>("if 0 break; else if 1 continue; else {}").unwrap();
+}
+
+#[test]
+fn statement_static_initializer() {
+    let static_ = gb_lang::parse::<Static<U8, Number>>("static FOO::u8;").unwrap();
+    assert!(static_.initializer.is_none());
+    assert!(static_.placement.is_none());
+    let static_ = gb_lang::parse::<Static<U8, Number>>("static FOO::u8").unwrap();
+    assert!(static_.semi_colon.is_none());
+}
+
+#[test]
+fn expression_addr() {
+    let addr = gb_lang::parse::<Expression>("addr(foo)").unwrap();
+    assert!(matches!(addr, Expression::Addr(addr) if addr.addr.as_str() == "addr"));
+    let ptr = gb_lang::parse::<Expression>("ptr(foo)").unwrap();
+    assert!(matches!(ptr, Expression::Addr(addr) if addr.addr.as_str() == "ptr"));
+    gb_lang::parse::<Expression>("deref(addr(foo))").unwrap();
+}
+
+#[test]
+fn program() {
+    use gb_lang::ast::Program;
+
+    let program = gb_lang::parse::<Program>("let a::u8 = 0; if 1 {} loop break;").unwrap();
+    assert_eq!(3, program.statements.len());
+    match &program.statements[1] {
+        Statement::If(if_) => assert!(matches!(if_.inner[..], [Statement::Scope(_)])),
+        _ => panic!(),
+    }
+    assert!(gb_lang::parse::<Program>("let a::u8 = 0; }").is_err());
+    gb_lang::parse::<Program>(include_str!("../example.ggb")).unwrap();
+}
+
+#[test]
+fn expression_cast() {
+    use gb_lang::ast::expressions::Expression;
+
+    // casts bind tighter than binary operators and looser than unary ones
+    match gb_lang::parse::<Expression>("a + ~b as u16 as i8").unwrap() {
+        Expression::Add(add) => match *add.right {
+            Expression::Cast(cast) => {
+                assert!(matches!(cast.type_, Type::I8(_)));
+                assert!(matches!(*cast.inner, Expression::Cast(_)));
+            }
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn statement_fn() {
+    use gb_lang::ast::{expressions::Expression, statements::Fn};
+
+    let fn_ = gb_lang::parse::<Fn<Type, Scope<Vec<Statement>>>>(
+        "fn add(a::u8, b::u8) :: u8 { return a + b; }",
+    )
+    .unwrap();
+    assert_eq!(2, fn_.params.len());
+    assert!(fn_.return_type.is_some());
+    assert!(matches!(fn_.body.inner[0], Statement::Return(_)));
+    let fn_ = gb_lang::parse::<Statement>("fn main() { wait(1, 2,); return; }").unwrap();
+    match fn_ {
+        Statement::Fn(fn_) => {
+            assert!(fn_.params.is_empty() && fn_.return_type.is_none());
+            match &fn_.body.inner[0] {
+                Statement::Expression(statement) => match &statement.expression {
+                    Expression::Call(call) => assert_eq!(2, call.arguments.len()),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    }
+}
+
 #[test]
 fn statement_scope() {
     gb_lang::parse::<Scope<()>>("{}").unwrap();
     gb_lang::parse::<Scope<Vec<Statement>>>("{}").unwrap();
 }
+
+#[test]
+fn node_span() {
+    let let_ = gb_lang::parse::<Statement>("let foo::u8 =\n    42;").unwrap();
+    assert_eq!(
+        Span {
+            min: [1, 1],
+            max: [2, 7],
+            ..Span::default()
+        },
+        let_.span()
+    );
+    let scope = gb_lang::parse::<Scope<Vec<Statement>>>("{ }").unwrap();
+    assert_eq!(
+        Span {
+            min: [1, 1],
+            max: [1, 3],
+            ..Span::default()
+        },
+        scope.span()
+    );
+}
+
+#[test]
+fn walk_tokens() {
+    struct Tokens(Vec<String>);
+
+    impl<'input> Visit<'input> for Tokens {
+        fn visit_token<T: TokenGrammar<'input>>(&mut self, token: &T) {
+            self.0.push(format!("{:?}", token.span().min));
+        }
+    }
+
+    let let_ = gb_lang::parse::<Statement>("let a::u8 = 1;").unwrap();
+    let mut tokens = Tokens(Vec::new());
+    let_.walk(&mut tokens);
+    assert_eq!(
+        vec!["[1, 1]", "[1, 5]", "[1, 6]", "[1, 8]", "[1, 11]", "[1, 13]", "[1, 14]"],
+        tokens.0
+    );
+}
+
+#[test]
+fn expression_precedence() {
+    let expression = gb_lang::parse::<Expression>("1 + 2 * 3 - a[0]() / (4 - 5)").unwrap();
+    let sub = match expression {
+        Expression::Subtract(sub) => sub,
+        _ => panic!(),
+    };
+    match *sub.left {
+        Expression::Add(add) => assert!(matches!(*add.right, Expression::Multiply(_))),
+        _ => panic!(),
+    }
+    let div = match *sub.right {
+        Expression::Divide(div) => div,
+        _ => panic!(),
+    };
+    match *div.left {
+        Expression::Call(call) => assert!(matches!(*call.callable, Expression::Index(_))),
+        _ => panic!(),
+    }
+    assert!(matches!(*div.right, Expression::Parenthesis(_)));
+}
+
+#[test]
+fn expression_negate() {
+    let expression = gb_lang::parse::<Expression>("a - -1 as i16").unwrap();
+    match expression {
+        Expression::Subtract(sub) => match *sub.right {
+            Expression::Cast(cast) => assert!(matches!(*cast.inner, Expression::Negate(_))),
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn expression_field_access() {
+    let expression = gb_lang::parse::<Expression>("a[1].x.y + b").unwrap();
+    let add = match expression {
+        Expression::Add(add) => add,
+        _ => panic!(),
+    };
+    match *add.left {
+        Expression::FieldAccess(access) => {
+            assert_eq!("y", access.field.as_str());
+            match *access.inner {
+                Expression::FieldAccess(access) => {
+                    assert_eq!("x", access.field.as_str());
+                    assert!(matches!(*access.inner, Expression::Index(_)));
+                }
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    }
+    assert!(gb_lang::parse::<Expression>("a.1").is_err());
+}
+
+#[test]
+fn expression_comparison() {
+    let expression = gb_lang::parse::<Expression>("a | 1 == b + 2").unwrap();
+    match expression {
+        Expression::Equal(equal) => {
+            assert!(matches!(*equal.left, Expression::BitOr(_)));
+            assert!(matches!(*equal.right, Expression::Add(_)));
+        }
+        _ => panic!(),
+    }
+    for (input, less) in [("a < b", true), ("a <= b", false), ("a ~= b", false)] {
+        let expression = gb_lang::parse::<Expression>(input).unwrap();
+        assert_eq!(less, matches!(expression, Expression::Less(_)), "{}", input);
+    }
+    // the `>` of an array type ends it, so comparisons in its length must be parenthesized
+    gb_lang::parse::<Statement>("let a::array<u8, (1 > 2) as u8> = 0;").unwrap();
+    assert!(gb_lang::parse::<Statement>("let a::array<u8, 1 > 2> = 0;").is_err());
+}
+
+#[test]
+fn statement_static_placement() {
+    let static_ = gb_lang::parse::<Static<U8, Number>>("static LCDC @ 0xff40 :: u8;").unwrap();
+    match *static_.placement.unwrap().address {
+        Expression::Number(number) => assert_eq!(Some(0xff40), number.number.value()),
+        _ => panic!(),
+    }
+    // addresses are constant expressions
+    let static_ =
+        gb_lang::parse::<Static<U8, Number>>("static MAP @ VRAM + 0x1800 :: u8;").unwrap();
+    assert!(matches!(
+        *static_.placement.unwrap().address,
+        Expression::Add(_)
+    ));
+}
+
+#[test]
+fn statement_type_alias() {
+    use gb_lang::ast::statements::TypeAlias;
+
+    let alias = gb_lang::parse::<TypeAlias<Type>>(
+        "type Sprite = struct { y::u8, x::u8, tile::u8, flags::u8 };",
+    )
+    .unwrap();
+    assert_eq!("Sprite", alias.identifier.as_str());
+    assert!(matches!(alias.definition, Type::Struct(_)));
+    let alias = gb_lang::parse::<TypeAlias<Type>>("type Node = ptr<Node>;").unwrap();
+    match alias.definition {
+        Type::Ptr(ptr) => assert!(matches!(*ptr.type_, Type::Named(_))),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn statement_assign() {
+    let statement = gb_lang::parse::<Statement>("a[i + 1] = b * 2;").unwrap();
+    match statement {
+        Statement::Assign(assign) => {
+            assert!(matches!(assign.place, Expression::Index(_)));
+            assert!(matches!(assign.expression, Expression::Multiply(_)));
+        }
+        _ => panic!(),
+    }
+    let statement = gb_lang::parse::<Statement>("a == b;").unwrap();
+    assert!(matches!(statement, Statement::Expression(_)));
+    assert!(gb_lang::parse::<Statement>("a = b").is_err());
+    // `ptr` is an alias of `addr`
+    let statement = gb_lang::parse::<Statement>("ptr(a)[0] = 1;").unwrap();
+    assert!(matches!(statement, Statement::Assign(_)));
+    assert!(gb_lang::parse::<Statement>("a = b = c;").is_err());
+}
+
+#[test]
+fn statement_asm() {
+    let program =
+        gb_lang::parse::<gb_lang::ast::Program>("asm {\n    ldh a, [IO.LY]\n}\nlet a::u8 = 0;")
+            .unwrap();
+    match &program.statements[0] {
+        Statement::Asm(asm) => {
+            assert_eq!("\n    ldh a, [IO.LY]\n", asm.block.contents());
+            assert_eq!([1, 1], asm.span().min);
+            assert_eq!([3, 1], asm.span().max);
+            assert_eq!([2, 13], asm.block.span_of(13, 5).min);
+            assert_eq!([2, 17], asm.block.span_of(13, 5).max);
+        }
+        _ => panic!(),
+    }
+    assert!(matches!(program.statements[1], Statement::Let(_)));
+}
@@ -0,0 +1,370 @@
+mod common;
+
+use common::{compile_str, run};
+
+#[test]
+fn arithmetic() {
+    let memory = run("const X::u8 = 200;
+let a::u8 = 7;
+let b::u8 = a + X;
+let c::u8 = b - 10;
+let d::u8 = a * 9;
+let e::u8 = d / a;
+let f::u8 = (a ^ 0xff) & 0xf0 | 1;
+let g::u16 = 1000;
+let h::u16 = g * 3 + g / 7 - 2;
+let i::i8 = -100;
+let j::i8 = i / 3;
+let k::i16 = -1000;
+let l::i16 = k / -9;
+let m::u16 = g ^ 0xffff;");
+    assert_eq!(vec![207], memory("b"));
+    assert_eq!(vec![197], memory("c"));
+    assert_eq!(vec![63], memory("d"));
+    assert_eq!(vec![9], memory("e"));
+    assert_eq!(vec![0xf1], memory("f"));
+    assert_eq!(3140u16.to_le_bytes().to_vec(), memory("h"));
+    assert_eq!(vec![-33i8 as u8], memory("j"));
+    assert_eq!(111i16.to_le_bytes().to_vec(), memory("l"));
+    assert_eq!((!1000u16).to_le_bytes().to_vec(), memory("m"));
+}
+
+#[test]
+fn casts() {
+    let memory = run("let a::i8 = -2;
+let b::i16 = a as i16;
+let c::u16 = 0x1234;
+let d::u8 = c as u8;
+let e::bool = c as bool;
+let f::bool = 0 as bool;
+let g::u16 = 250 as u8 as u16;");
+    assert_eq!(vec![0xfe, 0xff], memory("b"));
+    assert_eq!(vec![0x34], memory("d"));
+    assert_eq!(vec![1], memory("e"));
+    assert_eq!(vec![0], memory("f"));
+    assert_eq!(vec![250, 0], memory("g"));
+}
+
+#[test]
+fn control_flow() {
+    let memory = run("static A::u8 = 3;
+if A & 1 {
+    let b::u8 = 1;
+    if A & 4 { let c::u8 = 1; } else { let d::u8 = 2; }
+} else {
+    let b::u8 = 2;
+}
+loop {
+    if A { let e::u8 = 5; break; }
+}
+while A {
+    let f::u8 = 6;
+    break;
+}");
+    assert_eq!(vec![1], memory("b"));
+    assert_eq!(vec![0], memory("c"));
+    assert_eq!(vec![2], memory("d"));
+    assert_eq!(vec![5], memory("e"));
+    assert_eq!(vec![6], memory("f"));
+}
+
+#[test]
+fn calls() {
+    let memory = run(
+        "fn add(a::u8, b::u16, c::i8) :: u16 { return b + (a as u16) + (c as i16 as u16); }
+fn twice(a::u8) :: u8 { return a + a; }
+fn nothing() { let x::u8 = 9; }
+let r::u16 = add(twice(5), 1000, -3);
+let s::u8 = twice(twice(3)) + twice(1);
+nothing();",
+    );
+    assert_eq!(1007u16.to_le_bytes().to_vec(), memory("r"));
+    assert_eq!(vec![14], memory("s"));
+    assert_eq!(vec![9], memory("x"));
+}
+
+#[test]
+fn recursion() {
+    let memory = run(
+        "fn s(n::u8) :: u8 { if n { return n + s(n - 1); } return 0; }
+static R::u8 = s(4);
+fn fib(n::u16) :: u16 {
+    if n < 2 { return n; }
+    let a::u16 = fib(n - 1);
+    let b::u16 = fib(n - 2);
+    return a + b;
+}
+let f::u16 = fib(12);
+fn even(n::u8) :: bool { if n == 0 { return true; } return odd(n - 1); }
+fn odd(n::u8) :: bool { if n == 0 { return false; } return even(n - 1); }
+let e::bool = even(7);
+let o::bool = odd(7);",
+    );
+    assert_eq!(vec![10], memory("R"));
+    assert_eq!(144u16.to_le_bytes().to_vec(), memory("f"));
+    assert_eq!(vec![0], memory("e"));
+    assert_eq!(vec![1], memory("o"));
+}
+
+#[test]
+fn pointers_and_copies() {
+    let memory = run("type Point = struct { x::u8, y::u16 };
+static P::array<Point, 3>;
+let a::array<u8, 6> = \"german\";
+let i::u8 = 4;
+let b::u8 = a[i];
+let c::ptr<u8> = addr(a[1]);
+let d::u8 = deref(c);
+let e::Point = P[2];
+let f::array<u8, 6> = a;");
+    assert_eq!(b"german".to_vec(), memory("a"));
+    assert_eq!(vec![b'a'], memory("b"));
+    assert_eq!(vec![b'e'], memory("d"));
+    assert_eq!(vec![0, 0, 0], memory("e"));
+    assert_eq!(b"german".to_vec(), memory("f"));
+}
+
+#[test]
+fn assignments() {
+    let memory = run("static COUNT::u8;
+static SQUARES::array<u16, 8>;
+let i::u8 = 0;
+while i < 8 {
+    SQUARES[i] = (i as u16) * (i as u16) * 100;
+    COUNT = COUNT + 1;
+    i = i + 1;
+}
+let name::array<u8, 6> = \"german\";
+let p::ptr<u8> = addr(name[2]);
+deref(p) = 0x52;
+p[1] = 0x4d;
+name = \"gb\";
+fn bump(q::ptr<u16>, by::u16) { deref(q) = deref(q) + by; }
+bump(addr(SQUARES[7]), 100);");
+    assert_eq!(vec![8], memory("COUNT"));
+    assert_eq!(vec![8], memory("i"));
+    let squares: Vec<u8> = (0..8u16)
+        .map(|i| i * i * 100 + if i == 7 { 100 } else { 0 })
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    assert_eq!(squares, memory("SQUARES"));
+    // the shorter string only overwrites the first bytes
+    assert_eq!(b"gbRMan".to_vec(), memory("name"));
+}
+
+#[test]
+fn dump() {
+    let assembly = compile_str(
+        "fn f(a::u8) :: u8 { return a + 1; }
+let b::u8 = f(2);
+if b { let c::u16 = 300 * (b as u16); }",
+    )
+    .assembly;
+    assert_eq!(
+        "__init:
+    ld sp, $E000
+    call __start
+__halt:
+    halt
+    jp __halt
+__start:
+    ld a, $02
+    ld [$C004], a
+    call f
+    ld b, a
+    ld [$C001], a
+    ld a, [$C001]
+    ld b, a
+    or a, a
+    jp z, __start.bb2
+__start.bb1:
+    ld a, [$C001]
+    ld b, a
+    ld l, a
+    ld h, $00
+    ld b, h
+    ld c, l
+    push bc
+    push de
+    ld hl, $012C
+    ld d, b
+    ld e, c
+    call __mul16
+    pop de
+    pop bc
+    ld b, h
+    ld c, l
+    ld hl, $C002
+    ld [hl], c
+    inc hl
+    ld [hl], b
+__start.bb2:
+    ret
+f:
+    ld a, [$C004]
+    ld b, a
+    ld [$C000], a
+    ld a, [$C000]
+    ld b, a
+    add a, $01
+    ld b, a
+    ret
+__mul16:
+    ld b, h
+    ld c, l
+    ld hl, $0000
+    ld a, $10
+__mul16.loop:
+    add hl, hl
+    sla e
+    rl d
+    jp nc, __mul16.next
+    add hl, bc
+__mul16.next:
+    dec a
+    jp nz, __mul16.loop
+    ret
+",
+        assembly.dump()
+    );
+}
+
+#[test]
+fn dump_comparisons() {
+    let assembly = compile_str(
+        "fn f(a::u8, b::i16) :: bool {
+    if a < 10 { return b >= -5; }
+    return a == 3;
+}
+let c::bool = f(2, 1);",
+    )
+    .assembly;
+    // the comparisons that decide a branch jump on the flags, the others become bytes
+    assert_eq!(
+        "__init:
+    ld sp, $E000
+    call __start
+__halt:
+    halt
+    jp __halt
+__start:
+    ld a, $02
+    ld [$C004], a
+    ld hl, $0001
+    ld a, l
+    ld [$C005], a
+    ld a, h
+    ld [$C006], a
+    call f
+    ld b, a
+    ld [$C003], a
+    ret
+f:
+    ld a, [$C004]
+    ld b, a
+    ld a, [$C005]
+    ld l, a
+    ld a, [$C006]
+    ld h, a
+    ld d, h
+    ld e, l
+    ld a, b
+    ld [$C000], a
+    ld hl, $C001
+    ld [hl], e
+    inc hl
+    ld [hl], d
+    ld a, [$C000]
+    ld b, a
+    cp a, $0A
+    jp nc, f.bb2
+f.bb1:
+    ld hl, $C001
+    ld a, [hl+]
+    ld h, [hl]
+    ld l, a
+    ld b, h
+    ld c, l
+    ld h, b
+    ld l, c
+    push de
+    ld de, $FFFB
+    ld a, d
+    xor a, $80
+    ld d, a
+    ld a, h
+    xor a, $80
+    ld h, a
+    ld a, l
+    sub a, e
+    ld l, a
+    ld a, h
+    sbc a, d
+    pop de
+    ld a, $01
+    jp nc, f.cmp0
+    ld a, $00
+f.cmp0:
+    ld b, a
+    ret
+f.bb2:
+    ld a, [$C000]
+    ld b, a
+    cp a, $03
+    ld a, $01
+    jp z, f.cmp1
+    ld a, $00
+f.cmp1:
+    ld b, a
+    ret
+",
+        assembly.dump()
+    );
+}
+
+#[test]
+fn assemble_generated() {
+    let assembly = compile_str(include_str!("../example.ggb")).assembly;
+    let object = gb_lang::asm::encode::assemble(&assembly, 0x150).unwrap();
+    assert_eq!(Some(&0x150), object.labels.get(gb_lang::codegen::INIT));
+    // spans aren't part of the text
+    let text = assembly.dump();
+    assert_eq!(
+        assembly.lines,
+        gb_lang::asm::parse::parse(&text).unwrap().lines
+    );
+}
+
+#[test]
+fn inline_asm() {
+    let memory = run("type Io = struct { ly::u8, lcdc::u8 };
+static IO @ 0xc100 :: Io;
+static OUT::u8;
+static CALLS::u8 = 0;
+const VALUE::u8 = 0x91;
+fn bump() {
+    asm {
+        ld hl, CALLS
+        ld b, 2
+    loop:
+        inc [hl]
+        dec b
+        jr nz, loop
+    }
+}
+asm {
+    ld a, VALUE
+    ld [IO.lcdc], a
+    ld b, 3
+loop:
+    dec b
+    jr nz, loop
+    ld a, b
+    ld [OUT], a
+    call bump
+    call bump
+}");
+    assert_eq!(vec![0, 0x91], memory("IO"));
+    assert_eq!(vec![0], memory("OUT"));
+    assert_eq!(vec![4], memory("CALLS"));
+}
@@ -0,0 +1,52 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use gb_lang::{asm, compile, interp::Interpreter, layout::Layouts, Compiled};
+
+/// Compile a program that reports no diagnostics.
+pub fn compile_str(input: &str) -> Compiled<'_> {
+    let compiled = compile(input).unwrap_or_else(|diagnostics| panic!("{:?}", diagnostics));
+    assert!(compiled.warnings.is_empty(), "{:?}", compiled.warnings);
+    compiled
+}
+
+/// Run the generated code of a program on the assembly interpreter, until it halts.
+pub fn run_generated<'a>(compiled: &'a Compiled<'_>) -> asm::interp::Interpreter<'a> {
+    let mut interpreter = asm::interp::Interpreter::new(&compiled.assembly).unwrap();
+    interpreter.run(1_000_000).unwrap();
+    assert!(interpreter.is_halted());
+    interpreter
+}
+
+/// Interpreter for the syntax tree of a compiled program.
+pub fn interpreter<'a, 'input>(compiled: &'a Compiled<'input>) -> Interpreter<'a, 'input> {
+    Interpreter::new(
+        &compiled.program,
+        &compiled.resolution,
+        &compiled.consts,
+        &compiled.types,
+        &compiled.layouts,
+    )
+}
+
+/// Bytes of the laid out items of a program in `memory`, by name.
+pub fn variables(layouts: &Layouts, memory: &[u8]) -> impl Fn(&str) -> Vec<u8> {
+    let items: Vec<_> = layouts
+        .items()
+        .map(|item| {
+            let bytes = memory[item.address..item.address + item.layout.size].to_vec();
+            (item.name.clone(), bytes)
+        })
+        .collect();
+    move |name| {
+        let (_, bytes) = items.iter().find(|(n, _)| n == name).unwrap();
+        bytes.clone()
+    }
+}
+
+/// Run a program until it halts, and return the bytes of its items, by name.
+pub fn run(input: &str) -> impl Fn(&str) -> Vec<u8> {
+    let compiled = compile_str(input);
+    let interpreter = run_generated(&compiled);
+    variables(&compiled.layouts, &interpreter.memory[..])
+}
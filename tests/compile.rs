@@ -0,0 +1,49 @@
+use gb_lang::compile;
+
+fn errors(input: &str) -> Vec<String> {
+    let diagnostics = compile(input).unwrap_err();
+    diagnostics.errors().map(ToString::to_string).collect()
+}
+
+#[test]
+fn compile_program() {
+    let compiled =
+        compile("static A::u8 = 1; fn f() :: u8 { return A; } let b::u8 = f();").unwrap();
+    assert!(compiled.warnings.is_empty());
+    assert_eq!(2, compiled.ir.functions().count());
+    assert!(compiled.layouts.items().any(|item| item.name == "b"));
+}
+
+#[test]
+fn compile_warnings() {
+    let compiled = compile("fn f() { return; let a::u8 = 1; }").unwrap();
+    let warnings: Vec<_> = compiled.warnings.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        vec![
+            "1:18: warning: unreachable statement\n  1:10: any code following this is unreachable"
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn compile_errors() {
+    assert_eq!(vec!["1:10: error: unexpected `;`"], errors("let a::u8;"));
+    assert_eq!(
+        vec!["1:12: error: unexpected end of input"],
+        errors("let a::u8 =")
+    );
+    assert_eq!(
+        vec!["1:13: error: unexpected character `$`"],
+        errors("let a::u8 = $;")
+    );
+    assert_eq!(
+        vec!["1:13: error: cannot find `b` in this scope"],
+        errors("let a::u8 = b;")
+    );
+    // the variables leave no WRAM for the argument passed to `f`
+    assert_eq!(
+        vec!["0:0: error: not enough WRAM for the temporaries of the program ($0001 more bytes needed)"],
+        errors("static BIG::array<u8, 0x1efe>; fn f(a::u8) :: u8 { return a + 1; } let b::u8 = f(1);")
+    );
+}
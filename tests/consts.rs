@@ -0,0 +1,186 @@
+use gb_lang::{
+    ast::{statements::Statement, Context, Program},
+    consts::{evaluate, Consts},
+    diagnostics::Diagnostic,
+    resolve::resolve,
+    typeck::{check, Ty},
+};
+
+fn evaluate_str(input: &str) -> (Program<'_>, Consts, Vec<Diagnostic>) {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    let resolution = resolve(&program, &mut context);
+    let consts = evaluate(&program, &resolution, &mut context);
+    (program, consts, context.diagnostics.take())
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics.iter().map(ToString::to_string).collect()
+}
+
+fn values(program: &Program<'_>, consts: &Consts) -> Vec<Option<i64>> {
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Const(const_) => Some(consts.value(const_.id)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn fold() {
+    let (program, consts, diagnostics) = evaluate_str(
+        "const A::u8 = (1 + 2) * 3 - 8 / 2;
+const B::u8 = ~A & 0xf0 | 0b11 ^ 1;
+const C::u8 = B + A;",
+    );
+    assert!(diagnostics.is_empty());
+    assert_eq!(
+        vec![
+            Some(5),
+            Some((!5u8 & 0xf0 | 0b11 ^ 1).into()),
+            Some(0xf2 + 5)
+        ],
+        values(&program, &consts)
+    );
+}
+
+#[test]
+fn overflow() {
+    let (program, consts, diagnostics) = evaluate_str(
+        "const A::u8 = 0x80 * 2;
+const B::u8 = 1 - 2;
+const C::u8 = 256;
+const D::u8 = 1 / (A - A);
+const E::u8 = 4 / 0;",
+    );
+    assert_eq!(
+        vec![None, None, None, None, None],
+        values(&program, &consts)
+    );
+    assert_eq!(
+        vec![
+            "1:15: error: attempt to multiply with `u8` overflow",
+            "2:15: error: attempt to subtract with `u8` overflow",
+            "3:15: error: literal out of range for `u8`",
+            "5:15: error: attempt to divide by zero",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn not_constant() {
+    let (_, _, diagnostics) = evaluate_str(
+        "let a::u8 = 1;
+const B::u8 = a + 1;
+const C::u8 = deref(addr(a));
+const D::array<u8, 2> = \"hi\";",
+    );
+    assert_eq!(
+        vec![
+            "2:15: error: `a` is not a const",
+            "3:15: error: expression is not constant",
+            "4:10: error: the type of a const must be an integer",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn cycle() {
+    let (_, _, diagnostics) = evaluate_str("const A::u8 = B;\nconst B::u8 = A + 1;");
+    assert_eq!(
+        vec!["2:15: error: cycle detected when evaluating `A`\n  1:7: `A` declared here"],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn array_lengths() {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(
+        "const TILE_COUNT::u8 = 0x80;
+static TILES::array<u8, TILE_COUNT * 16>;
+static MAP::array<u8, 0x10000>;",
+        &mut context,
+    )
+    .unwrap();
+    let resolution = resolve(&program, &mut context);
+    let consts = evaluate(&program, &resolution, &mut context);
+    let types = check(&program, &resolution, &consts, &mut context);
+    let ty = |statement: &Statement| match statement {
+        Statement::Static(static_) => types.declaration(static_.id).cloned(),
+        _ => panic!(),
+    };
+    assert_eq!(
+        Some(Ty::Array(Box::new(Ty::U8), 0x800)),
+        ty(&program.statements[1])
+    );
+    assert_eq!(Some(Ty::Error), ty(&program.statements[2]));
+    assert_eq!(
+        vec!["3:23: error: literal out of range for `u16`"],
+        messages(&context.diagnostics.take())
+    );
+}
+
+#[test]
+fn placements() {
+    let (program, consts, diagnostics) = evaluate_str(
+        "const VRAM::u16 = 0x8000;
+static MAP @ VRAM + 0x1800 :: u8;
+static A @ 0x10000 :: u8;",
+    );
+    let address = |statement: &Statement| match statement {
+        Statement::Static(static_) => {
+            consts.value(static_.placement.as_ref().unwrap().address.id())
+        }
+        _ => panic!(),
+    };
+    assert_eq!(Some(0x9800), address(&program.statements[1]));
+    assert_eq!(None, address(&program.statements[2]));
+    assert_eq!(
+        vec!["3:12: error: literal out of range for `u16`"],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn widths_and_casts() {
+    let (program, consts, diagnostics) = evaluate_str(
+        "const A::i8 = -128;
+const B::u8 = A as u8;
+const C::u16 = 0x1234 as u8 as u16;
+const D::i16 = ~0;
+const E::i8 = 127 + 1;
+const F::u16 = 0x10000;
+const G::i8 = -A;
+const H::u8 = -1;
+const I::i16 = -(A as i16);",
+    );
+    assert_eq!(
+        vec![
+            Some(-128),
+            Some(0x80),
+            Some(0x34),
+            Some(-1),
+            None,
+            None,
+            None,
+            None,
+            Some(128)
+        ],
+        values(&program, &consts)
+    );
+    assert_eq!(
+        vec![
+            "5:15: error: attempt to add with `i8` overflow",
+            "6:16: error: literal out of range for `u16`",
+            "7:15: error: attempt to negate with `i8` overflow",
+            "8:15: error: literal out of range for `u8`",
+        ],
+        messages(&diagnostics)
+    );
+}
@@ -0,0 +1,56 @@
+use gb_lang::{
+    ast::{statements::Let, types::U8, Context, Edition, Options},
+    diagnostics::{Diagnostic, Level},
+    Span,
+};
+
+#[test]
+fn interner() {
+    let mut context = Context::default();
+    let foo = context.interner.intern("foo");
+    let bar = context.interner.intern("bar");
+    assert_ne!(foo, bar);
+    assert_eq!(foo, context.interner.intern("foo"));
+    assert_eq!(Some("bar"), context.interner.resolve(bar));
+}
+
+#[test]
+fn node_ids() {
+    let mut context = Context::default();
+    let a: Let<U8, gb_lang::ast::expressions::Identifier> =
+        gb_lang::ast::parse_with_context("let a::u8 = b;", &mut context).unwrap();
+    let b: Let<U8, gb_lang::ast::expressions::Identifier> =
+        gb_lang::ast::parse_with_context("let b::u8 = a;", &mut context).unwrap();
+    assert_ne!(a.id, a.expression.id);
+    assert_ne!(a.id, b.id);
+    assert_eq!(
+        context.symbol(&a.identifier),
+        context.symbol(&b.expression.identifier)
+    );
+}
+
+#[test]
+fn options() {
+    let mut options = Options::default();
+    options.features.enable("foo");
+    let context = Context::new(options);
+    assert_eq!(Edition::Edition2021, context.options.edition);
+    assert!(context.options.features.is_enabled("foo"));
+    assert!(!context.options.features.is_enabled("bar"));
+}
+
+#[test]
+fn diagnostics() {
+    let mut context = Context::default();
+    context
+        .diagnostics
+        .emit(Diagnostic::warning(Span::default(), "unused"));
+    assert!(!context.diagnostics.has_errors());
+    context
+        .diagnostics
+        .emit(Diagnostic::error(Span::default(), "undefined"));
+    assert!(context.diagnostics.has_errors());
+    let levels: Vec<_> = context.diagnostics.take().iter().map(|d| d.level).collect();
+    assert_eq!(vec![Level::Warning, Level::Error], levels);
+    assert!(context.diagnostics.is_empty());
+}
@@ -0,0 +1,254 @@
+mod common;
+
+use common::{compile_str, run_generated, variables};
+use gb_lang::{
+    asm::parse::parse,
+    cpu::{Bus, Cpu, Error, Flat, Mbc},
+    link::{link, Kind, Section},
+    rom::{build, Cartridge, Header, RamSize},
+};
+
+// Run a program on the CPU, from a ROM, and check that every variable ends up with the same bytes
+// as on the assembly interpreter. Returns the bytes of the variables, by name.
+fn run(input: &str) -> impl Fn(&str) -> Vec<u8> {
+    let compiled = compile_str(input);
+    let rom = build(&compiled.assembly, &Header::default()).unwrap();
+    let mut cpu = Cpu::new(Flat::new(&rom.bytes));
+    cpu.run(1_000_000).unwrap();
+    assert!(cpu.is_halted());
+    let interpreter = run_generated(&compiled);
+    for item in compiled.layouts.items() {
+        let range = item.address..item.address + item.layout.size;
+        assert_eq!(
+            interpreter.memory[range.clone()],
+            cpu.bus.memory[range],
+            "{}",
+            item.name
+        );
+    }
+    variables(&compiled.layouts, &cpu.bus.memory[..])
+}
+
+#[test]
+fn run_program() {
+    let compiled = compile_str(
+        "static TOTAL::u16 = 0;
+fn sum(n::u8) :: u16 {
+    let total::u16 = (n as u16) * 300;
+    if n {
+        return total / 7;
+    }
+    return 0;
+}
+static RESULT::u16 = sum(10);
+let text::array<u8, 5> = \"hello\";
+let last::u8 = text[4];",
+    );
+    let rom = build(&compiled.assembly, &Header::default()).unwrap();
+    let mut cpu = Cpu::new(Flat::new(&rom.bytes));
+    cpu.run(100_000).unwrap();
+    assert!(cpu.is_halted());
+    let memory = variables(&compiled.layouts, &cpu.bus.memory[..]);
+    assert_eq!((3000u16 / 7).to_le_bytes().to_vec(), memory("RESULT"));
+    assert_eq!(vec![b'o'], memory("last"));
+    // halted at the end of the `__init` routine, with the stack back where it started
+    assert_eq!(rom.labels["__halt"] + 1, cpu.pc);
+    assert_eq!(0xe000, cpu.registers.sp);
+}
+
+#[test]
+fn run_generated_code() {
+    let memory = run("static COUNT::u8 = 0;
+static SIGNS::array<i8, 4>;
+fn fib(n::u16) :: u16 {
+    if n < 2 { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+let f::u16 = fib(10);
+let i::i8 = -2;
+while i <= 1 {
+    SIGNS[(i + 2) as u8] = -i * 3;
+    if i >= 0 { COUNT = COUNT + 1; }
+    i = i + 1;
+}
+let w::i16 = -300;
+let less::bool = w < (i as i16);");
+    assert_eq!(55u16.to_le_bytes().to_vec(), memory("f"));
+    assert_eq!(vec![6, 3, 0, -3i8 as u8], memory("SIGNS"));
+    assert_eq!(vec![2], memory("COUNT"));
+    assert_eq!(vec![1], memory("less"));
+}
+
+#[test]
+fn instructions() {
+    let assembly = parse(
+        "    ld sp, $D000
+    ld a, $0F
+    add a, $01
+    push af
+    pop bc
+    ld hl, $C000
+    ld [hl+], a
+    ld [hl], $80
+    sla [hl]
+    jr c, carry
+    halt
+carry:
+    call double
+    ld de, $1234
+    halt
+double:
+    add a, a
+    ret",
+    )
+    .unwrap();
+    let rom = build(&assembly, &Header::default()).unwrap();
+    let mut cpu = Cpu::new(Flat::new(&rom.bytes));
+    assert_eq!(0x0100, cpu.pc);
+    assert_eq!(
+        (0x01, 0xb0, 0xfffe),
+        (cpu.registers.a, cpu.registers.f, cpu.registers.sp)
+    );
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(0x0150, cpu.pc, "jumped past the header");
+
+    assert_eq!(15, cpu.run(100).unwrap());
+    let r = cpu.registers;
+    assert_eq!(0x20, r.a);
+    // half carry from `add a, $01`
+    assert_eq!((0x10, 0x20), (r.b, r.c));
+    assert_eq!((0x12, 0x34), (r.d, r.e));
+    assert_eq!(0xc001, r.hl());
+    assert_eq!(0xd000, r.sp);
+    assert_eq!([0x10, 0x00], cpu.bus.memory[0xc000..0xc002]);
+    assert_eq!(Ok(0), cpu.run(100), "already halted");
+}
+
+#[test]
+fn run_errors() {
+    let mut cpu = Cpu::new(Flat::new(&[]));
+    cpu.bus.memory[0x100] = 0x18;
+    cpu.bus.memory[0x101] = 0xfe;
+    assert_eq!(Err(Error::Timeout(50)), cpu.run(50));
+    assert_eq!(0x100, cpu.pc);
+
+    cpu.bus.memory[0x100] = 0xdd;
+    let error = cpu.step().unwrap_err();
+    assert_eq!(
+        Error::InvalidInstruction {
+            address: 0x100,
+            opcode: 0xdd
+        },
+        error
+    );
+    assert_eq!(
+        "invalid instruction at $0100 (opcode $DD)",
+        error.to_string()
+    );
+}
+
+#[test]
+fn far_calls() {
+    for cartridge in [
+        Cartridge::Mbc1 {
+            ram: false,
+            battery: false,
+        },
+        Cartridge::Mbc5 {
+            ram: false,
+            battery: false,
+            rumble: false,
+        },
+    ] {
+        let main = parse(
+            "__init:
+    ld sp, $E000
+    call triple
+    ld [$C000], a
+    call add_one
+    ld [$C001], a
+    halt",
+        )
+        .unwrap();
+        let triple =
+            parse("triple:\n    ld a, $05\n    add a, a\n    add a, $05\n    ret").unwrap();
+        let add_one = parse("add_one:\n    inc a\n    call triple\n    ret").unwrap();
+        let sections = [
+            Section::new("main", Kind::Rom0, main),
+            Section::new("triple", Kind::Romx, triple).in_bank(0x21),
+            Section::new("add_one", Kind::Romx, add_one).in_bank(3),
+        ];
+        let header = Header {
+            cartridge,
+            ..Default::default()
+        };
+        let linked = link(&sections, &header).unwrap();
+        let mut cpu = Cpu::new(Mbc::new(linked.rom, cartridge, RamSize::None));
+        cpu.run(10_000).unwrap();
+        assert_eq!([15, 15], cpu.bus.memory[0xc000..0xc002], "{:?}", cartridge);
+        // back to the first ROMX bank
+        assert_eq!(1, cpu.bus.rom_bank());
+    }
+}
+
+#[test]
+fn bank_registers() {
+    let mut rom = vec![0; 0x4000 * 64];
+    for (bank, chunk) in rom.chunks_mut(0x4000).enumerate() {
+        chunk[0] = bank as u8;
+    }
+    let mbc1 = Cartridge::Mbc1 {
+        ram: true,
+        battery: false,
+    };
+    let mut bus = Mbc::new(rom.clone(), mbc1, RamSize::Kib32);
+    assert_eq!(1, bus.read(0x4000));
+    // bank 0 selects bank 1
+    bus.write(0x2000, 0);
+    assert_eq!(1, bus.read(0x4000));
+    bus.write(0x2000, 0x25);
+    assert_eq!(5, bus.rom_bank());
+    bus.write(0x4000, 1);
+    assert_eq!(0x25, bus.read(0x4000));
+    assert_eq!(0, bus.read(0x0000));
+    bus.write(0x6000, 1);
+    assert_eq!(0x20, bus.read(0x0000), "advanced banking mode");
+
+    // RAM only once enabled, in the bank of the second register
+    bus.write(0xa000, 0x42);
+    assert_eq!(0xff, bus.read(0xa000));
+    bus.write(0x0000, 0x0a);
+    bus.write(0xa000, 0x42);
+    assert_eq!(0x42, bus.read(0xa000));
+    assert_eq!(0x42, bus.ram[0x2000]);
+    bus.write(0x0000, 0x00);
+    assert_eq!(0xff, bus.read(0xa000));
+
+    let mbc5 = Cartridge::Mbc5 {
+        ram: true,
+        battery: false,
+        rumble: false,
+    };
+    let mut rom = vec![0; 0x4000 * 0x200];
+    rom[0x4000 * 0x102] = 0x99;
+    let mut bus = Mbc::new(rom, mbc5, RamSize::Kib128);
+    bus.write(0x2000, 0);
+    assert_eq!(0, bus.rom_bank(), "MBC5 maps bank 0");
+    bus.write(0x2000, 0x02);
+    bus.write(0x3000, 0x01);
+    assert_eq!(0x102, bus.rom_bank());
+    assert_eq!(0x99, bus.read(0x4000));
+    bus.write(0x0000, 0x0a);
+    bus.write(0x4000, 0x0f);
+    bus.write(0xbfff, 7);
+    assert_eq!(
+        16 * 0x2000 - 1,
+        bus.ram.iter().rposition(|byte| *byte == 7).unwrap()
+    );
+
+    // echo RAM
+    bus.write(0xe123, 0x77);
+    assert_eq!(0x77, bus.read(0xc123));
+    assert_eq!(0x77, bus.memory[0xc123]);
+}
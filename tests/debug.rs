@@ -0,0 +1,120 @@
+mod common;
+
+use common::compile_str;
+use gb_lang::{
+    asm::parse::parse,
+    debug::{source_map, sym},
+    link::{link, variables, Kind, Linked, Section},
+    loader::Files,
+    rom::{Cartridge, Header},
+    Span,
+};
+
+fn link_str(input: &str) -> Linked {
+    let compiled = compile_str(input);
+    let mut sections = variables(&compiled.layouts, &compiled.resolution);
+    sections.push(Section::new("code", Kind::Rom0, compiled.assembly));
+    link(&sections, &Header::default()).unwrap()
+}
+
+const INPUT: &str = "static A::u8 = 1;
+fn double(x::u8) :: u8 {
+    return x + x;
+}
+let b::u8 = double(A);
+if b { let c::u8 = 3; }
+";
+
+#[test]
+fn symbol_file() {
+    let linked = link_str(INPUT);
+    assert_eq!(
+        "00:0150 __init
+00:0156 __halt
+00:015A __start
+00:0175 __start.bb1
+00:017A __start.bb2
+00:017B double
+00:C000 A
+",
+        sym(&linked.symbols)
+    );
+
+    let sections = [
+        Section::new("main", Kind::Rom0, parse("__init:\n    halt").unwrap()),
+        Section::new("far", Kind::Romx, parse("far:\n    ret").unwrap()).in_bank(0x12),
+        Section::new("buffer", Kind::Wramx, parse("buffer:\n    db 0").unwrap()),
+    ];
+    let header = Header {
+        cartridge: Cartridge::Mbc3 {
+            ram: false,
+            battery: false,
+            timer: false,
+        },
+        ..Default::default()
+    };
+    let linked = link(&sections, &header).unwrap();
+    assert_eq!(
+        "00:0150 __init
+00:0151 __entry
+00:015A __switch_bank
+12:4000 far
+01:D000 buffer
+00:FFFD __rom_bank
+",
+        sym(&linked.symbols)
+    );
+}
+
+#[test]
+fn source_map_file() {
+    let linked = link_str(INPUT);
+    assert_eq!(
+        "00:015A-015E 1:1-1:17
+00:015F-016C 5:1-5:22
+00:016D-0174 6:4-6:4
+00:0175-0179 6:8-6:21
+00:017F-0181 2:11-2:15
+00:0182-018D 3:5-3:17
+",
+        source_map(&linked.source_map, &Files::default())
+    );
+    // the bytes of `return x + x;`
+    let mapping = linked.source_map.last().unwrap();
+    assert_eq!(
+        Span {
+            min: [3, 5],
+            max: [3, 17],
+            ..Span::default()
+        },
+        mapping.span
+    );
+    let bytes = &linked.rom[usize::from(mapping.address)..][..usize::from(mapping.size)];
+    assert_eq!(Some(&0xc9), bytes.last(), "ret");
+}
+
+#[test]
+fn assembly_spans() {
+    let span = |line| Span {
+        min: [line, 1],
+        max: [line, 2],
+        ..Span::default()
+    };
+    let mut assembly = parse("nop").unwrap();
+    assembly.span(span(1));
+    assembly.span(span(1));
+    assembly.label("a");
+    assembly.span(span(2));
+    assembly.span(span(3));
+    assembly.push(gb_lang::asm::Instruction::Halt);
+    assembly.span(Span::default());
+    assembly.push(gb_lang::asm::Instruction::Halt);
+    assert_eq!(
+        vec![(1, span(1)), (2, span(3)), (3, Span::default())],
+        assembly.spans
+    );
+    assert_eq!(Span::default(), assembly.span_of(0));
+    assert_eq!(span(1), assembly.span_of(1));
+    assert_eq!(span(3), assembly.span_of(2));
+    assert_eq!(Span::default(), assembly.span_of(3));
+}
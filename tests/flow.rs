@@ -0,0 +1,113 @@
+use gb_lang::{
+    ast::{Context, Program},
+    cfg::{Cfg, Terminator},
+    diagnostics::Diagnostic,
+};
+
+fn check_str(input: &str) -> Vec<Diagnostic> {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    gb_lang::flow::check(&program, &mut context);
+    context.diagnostics.take()
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn example() {
+    let diagnostics = check_str(include_str!("../example.ggb"));
+    assert_eq!(Vec::<String>::new(), messages(&diagnostics));
+}
+
+#[test]
+fn cfg_while() {
+    let program = gb_lang::parse::<Program>("let a::u8 = 0; while a { let b::u8 = 1; }").unwrap();
+    let cfg = Cfg::build(&program.statements);
+    let entry = cfg.block(cfg.entry());
+    assert_eq!(1, entry.statements.len());
+    let condition = match entry.terminator {
+        Terminator::Goto(condition) => condition,
+        _ => panic!(),
+    };
+    let (body, exit) = match cfg.block(condition).terminator {
+        Terminator::Branch { then, else_, .. } => (then, else_),
+        _ => panic!(),
+    };
+    assert_eq!(1, cfg.block(body).statements.len());
+    assert!(matches!(cfg.block(body).terminator, Terminator::Goto(c) if c == condition));
+    assert!(matches!(cfg.block(exit).terminator, Terminator::Goto(e) if e == cfg.exit()));
+    assert!(cfg.blocks().all(|(id, _)| cfg.is_reachable(id)));
+}
+
+#[test]
+fn cfg_infinite_loop() {
+    let program = gb_lang::parse::<Program>("loop { if 1 continue; }").unwrap();
+    let cfg = Cfg::build(&program.statements);
+    assert!(!cfg.is_reachable(cfg.exit()));
+    let program = gb_lang::parse::<Program>("loop { if 1 break; }").unwrap();
+    let cfg = Cfg::build(&program.statements);
+    assert!(cfg.is_reachable(cfg.exit()));
+}
+
+#[test]
+fn misplaced_jumps() {
+    let diagnostics = check_str(
+        "break;
+loop { fn f() { continue; } break; }
+while 1 { if 0 { continue; } break; }
+return;",
+    );
+    assert_eq!(
+        vec![
+            "1:1: error: `break` outside of a loop",
+            "2:17: error: `continue` outside of a loop",
+            "4:1: error: `return` outside of a function",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn unreachable_code() {
+    let diagnostics = check_str(
+        "loop {
+    break;
+    let a::u8 = 0;
+    let b::u8 = 1;
+}
+loop {}
+if 1 {}
+fn f() {
+    return;
+    f();
+}",
+    );
+    assert_eq!(
+        vec![
+            "3:5: warning: unreachable statement\n  2:5: any code following this is unreachable",
+            "7:1: warning: unreachable statement\n  6:1: any code following this is unreachable",
+            "10:5: warning: unreachable statement\n  9:5: any code following this is unreachable",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn missing_return() {
+    let diagnostics = check_str(
+        "fn a(x::u8) :: u8 { if x { return 1; } else { return 2; } }
+fn b(x::u8) :: u8 { while x { return 1; } }
+fn c() :: u8 { loop {} }
+fn d() :: u8 { loop { break; } }
+fn e() {}",
+    );
+    assert_eq!(
+        vec![
+            "2:4: error: function `b` doesn't return a value on all paths\n  2:43: the function can reach its end here",
+            "4:4: error: function `d` doesn't return a value on all paths\n  4:32: the function can reach its end here",
+        ],
+        messages(&diagnostics)
+    );
+}
@@ -0,0 +1,166 @@
+use gb_lang::{
+    ast::Program,
+    fmt::{format, format_with_trivia, Options},
+    lex::tokenize_with_trivia,
+};
+
+fn fmt(input: &str) -> String {
+    let program = gb_lang::parse::<Program>(input).unwrap();
+    format_with_trivia(&program, tokenize_with_trivia(input), &Options::default())
+}
+
+#[test]
+fn format_example_idempotent() {
+    let formatted = fmt(include_str!("../example.ggb"));
+    assert_eq!(formatted, fmt(&formatted));
+}
+
+#[test]
+fn format_example_without_trivia_idempotent() {
+    let program = gb_lang::parse::<Program>(include_str!("../example.ggb")).unwrap();
+    let formatted = format(&program, &Options::default());
+    let reformatted = format(
+        &gb_lang::parse::<Program>(&formatted).unwrap(),
+        &Options::default(),
+    );
+    assert_eq!(formatted, reformatted);
+    assert!(!formatted.contains("//"));
+}
+
+#[test]
+fn format_spacing() {
+    assert_eq!(
+        "let foo::ptr<array<u8, 0x10>> = addr(bar);\n",
+        fmt("let foo :: ptr<array<u8,0x10>> =addr( bar ) ;")
+    );
+    assert_eq!(
+        "const FOO::u8 = 1 | ~2;\nstatic BAR::u8;\n",
+        fmt("const FOO::u8=1|~ 2; static BAR :: u8 ;")
+    );
+    assert_eq!(
+        "static LCDC @ 0xff40 :: u8;\nstatic MAP @ VRAM + 0x1800 :: u8;\n",
+        fmt("static LCDC@0xff40::u8; static MAP@VRAM+0x1800::u8;")
+    );
+    assert_eq!(
+        "static IO::struct {};
+let baz::ptr<u8> = ptr(foo);
+",
+        fmt("static IO::struct {}
+let baz::ptr<u8> = ptr (foo);")
+    );
+}
+
+#[test]
+fn format_blocks() {
+    assert_eq!(
+        "if 1 {\n    loop {\n        break;\n    }\n} else {}\n",
+        fmt("if 1 { loop { break; } } else { }")
+    );
+    assert_eq!(
+        "if 0\n    continue;\nelse if 1 {}\n",
+        fmt("if 0 continue; else if 1 {}")
+    );
+}
+
+#[test]
+fn format_fn() {
+    assert_eq!(
+        "fn add(a::u8, b::u8) :: u8 {\n    return a + b;\n}\nfn main() {\n    add(1, add(2, 3));\n    return;\n}\n",
+        fmt("fn add(a::u8,b::u8)::u8{return a+b;} fn main(){add(1,add(2,3,),);return;}")
+    );
+}
+
+#[test]
+fn format_casts() {
+    assert_eq!(
+        "let a::i16 = ~(b as i16) + 1 as i16;\n",
+        fmt("let a::i16=~(b as i16)+1 as i16;")
+    );
+    assert_eq!("let c::i8 = -1 - -d;\n", fmt("let c::i8=-1- -d;"));
+}
+
+#[test]
+fn format_comparisons() {
+    assert_eq!(
+        "let a::bool = b + 1 == c & 2;\nif a ~= (b < c) {}\nlet d::bool = b <= c >= (c > b);\n",
+        fmt("let a::bool=b+1==c&2;if a~=(b<c){}let d::bool=b<=c>=(c>b);")
+    );
+}
+
+#[test]
+fn format_assign() {
+    assert_eq!(
+        "a[i + 1] = deref(p) * 2;\nwhile a {\n    a = a - 1;\n}\n",
+        fmt("a[i+1]=deref(p)*2;while a{a=a-1;}")
+    );
+    assert_eq!("p[0].x = q.y;\n", fmt("p [0] . x=q.y;"));
+}
+
+#[test]
+fn format_type_alias() {
+    assert_eq!(
+        "type Node = struct { next::ptr<Node>, value::u8 };\nstatic a::Node;\n",
+        fmt("type Node=struct{next::ptr<Node>,value::u8};static a::Node;")
+    );
+}
+
+#[test]
+fn format_imports() {
+    assert_eq!(
+        "import \"gfx/sprites.ggb\";\nuse sprites::draw;\n",
+        fmt("import   \"gfx/sprites.ggb\" ;use sprites :: draw;")
+    );
+}
+
+#[test]
+fn format_asm() {
+    assert_eq!(
+        "fn wait() {\n    asm {\n        wait:\n        ldh a, [IO.LY] ; scanline\n        cp 144\n        jr nz, wait\n    }\n}\nasm {}\n",
+        fmt("fn wait() { asm {\nwait:\n\n  ldh a, [IO.LY] ; scanline\n    cp 144\n  jr nz, wait  } }\nasm { }")
+    );
+}
+
+#[test]
+fn format_comments() {
+    let input = "// header\n\nlet a::u8 = 1; // trailing\n\n\n\nif 1 {\n    // inside\n}\n";
+    assert_eq!(
+        "// header\n\nlet a::u8 = 1; // trailing\n\nif 1 {\n    // inside\n}\n",
+        fmt(input)
+    );
+    // expressions are written on a single line, so their comments go after their statement
+    assert_eq!(
+        "let a::u8 = f(1, 2); // two\n// one\nlet b::u8 = 3;\n",
+        fmt("let a::u8 = f(1, // one\n    2); // two\nlet b::u8 = 3;")
+    );
+    assert_eq!(
+        "while a {\n    // condition\n    a = 0;\n}\n",
+        fmt("while a // condition\n{ a = 0; }")
+    );
+}
+
+#[test]
+fn format_struct_width() {
+    assert_eq!(
+        "static IO::struct { LCDC::u8, STAT::u8 };\n",
+        fmt("static IO::struct {\n    LCDC::u8,\n    STAT::u8,\n};")
+    );
+    let program = gb_lang::parse::<Program>("static IO::struct { LCDC::u8, STAT::u8 };").unwrap();
+    let options = Options {
+        max_width: 20,
+        ..Options::default()
+    };
+    assert_eq!(
+        "static IO::struct {\n    LCDC::u8,\n    STAT::u8,\n};\n",
+        format(&program, &options)
+    );
+    // widths are counted in chars, not bytes
+    let program = gb_lang::parse::<Program>("static IO::struct { año::u8 };").unwrap();
+    let options = Options {
+        max_width: 29,
+        ..Options::default()
+    };
+    assert_eq!(
+        "static IO::struct { año::u8 };\n",
+        format(&program, &options)
+    );
+}
@@ -0,0 +1,336 @@
+mod common;
+
+use common::{compile_str, interpreter, run_generated, variables};
+use gb_lang::{
+    asm,
+    interp::{Error, MAX_DEPTH},
+};
+
+// Run a program with the interpreter and as generated code, and check that every variable ends
+// up with the same bytes. Returns the bytes of the variables, by name.
+fn run(input: &str) -> impl Fn(&str) -> Vec<u8> {
+    let compiled = compile_str(input);
+    let mut interpreter = interpreter(&compiled);
+    interpreter.run(100_000).unwrap();
+    let generated = run_generated(&compiled);
+    for item in compiled.layouts.items() {
+        let range = item.address..item.address + item.layout.size;
+        assert_eq!(
+            generated.memory[range.clone()],
+            interpreter.memory[range],
+            "{}",
+            item.name
+        );
+    }
+    variables(&compiled.layouts, &interpreter.memory[..])
+}
+
+#[test]
+fn arithmetic() {
+    let memory = run("const X::u8 = 200;
+let a::u8 = 7;
+let b::u8 = a + X;
+let c::u8 = b - 10;
+let d::u8 = a * 9;
+let e::u8 = d / a;
+let f::u8 = (a ^ 0xff) & 0xf0 | 1;
+let g::u16 = 1000;
+let h::u16 = g * 3 + g / 7 - 2;
+let i::i8 = -100;
+let j::i8 = i / 3;
+let k::i16 = -1000;
+let l::i16 = k / -9;
+let m::u16 = g ^ 0xffff;
+let n::u8 = 0;
+let o::u8 = a / n;
+let p::i8 = i / (n as i8);
+let q::i16 = g as i16 / 0;
+let r::u8 = 255 + a;");
+    assert_eq!(vec![207], memory("b"));
+    assert_eq!(vec![197], memory("c"));
+    assert_eq!(vec![63], memory("d"));
+    assert_eq!(vec![9], memory("e"));
+    assert_eq!(vec![0xf1], memory("f"));
+    assert_eq!(3140u16.to_le_bytes().to_vec(), memory("h"));
+    assert_eq!(vec![-33i8 as u8], memory("j"));
+    assert_eq!(111i16.to_le_bytes().to_vec(), memory("l"));
+    assert_eq!((!1000u16).to_le_bytes().to_vec(), memory("m"));
+    // dividing by zero
+    assert_eq!(vec![0xff], memory("o"));
+    assert_eq!(vec![1], memory("p"));
+    assert_eq!(vec![0xff, 0xff], memory("q"));
+    assert_eq!(vec![6], memory("r"));
+}
+
+#[test]
+fn casts() {
+    let memory = run("let a::i8 = -2;
+let b::i16 = a as i16;
+let c::u16 = 0x1234;
+let d::u8 = c as u8;
+let e::bool = c as bool;
+let f::bool = 0 as bool;
+let g::u16 = 250 as u8 as u16;
+let h::u16 = a as u8 as u16;");
+    assert_eq!(vec![0xfe, 0xff], memory("b"));
+    assert_eq!(vec![0x34], memory("d"));
+    assert_eq!(vec![1], memory("e"));
+    assert_eq!(vec![0], memory("f"));
+    assert_eq!(vec![250, 0], memory("g"));
+    assert_eq!(vec![0xfe, 0], memory("h"));
+}
+
+#[test]
+fn control_flow() {
+    let memory = run("static A::u8 = 3;
+static I::u8 = 10;
+static EVENS::u8 = 0;
+if A & 1 {
+    let b::u8 = 1;
+    if A & 4 { let c::u8 = 1; } else { let d::u8 = 2; }
+} else {
+    let b::u8 = 2;
+}
+while I {
+    I = I - 1;
+    if I & 1 { continue; }
+    EVENS = EVENS + 1;
+    let last::u8 = I;
+}
+loop {
+    if A { let e::u8 = 5; break; }
+}");
+    assert_eq!(vec![1], memory("b"));
+    assert_eq!(vec![0], memory("c"));
+    assert_eq!(vec![2], memory("d"));
+    assert_eq!(vec![5], memory("e"));
+    assert_eq!(vec![5], memory("EVENS"));
+    assert_eq!(vec![0], memory("last"));
+}
+
+#[test]
+fn comparisons() {
+    // every operator on every integer type, both as a value and as a branch condition, with
+    // operands that make it hold (`t`) and operands that don't (`f`)
+    let mut input = String::from("static COUNT::u8 = 0;\n");
+    let cases = [
+        ("u8", "7", "100", "200"),
+        ("i8", "-100", "-1", "100"),
+        ("u16", "300", "0x1ff", "0xfffe"),
+        ("i16", "-1000", "-2", "0x100"),
+    ];
+    let operators = [
+        ("eq", "=="),
+        ("ne", "~="),
+        ("lt", "<"),
+        ("le", "<="),
+        ("gt", ">"),
+        ("ge", ">="),
+    ];
+    for (ty, low, middle, high) in cases {
+        input.push_str(&format!(
+            "let {0}_low::{0} = {1};\nlet {0}_middle::{0} = {2};\nlet {0}_high::{0} = {3};\n",
+            ty, low, middle, high
+        ));
+        for (name, operator) in operators {
+            for (a, b) in [("low", "middle"), ("middle", "middle"), ("high", "middle")] {
+                let left = format!("{}_{}", ty, a);
+                let right = format!("{}_{}", ty, b);
+                input.push_str(&format!(
+                    "let {0}_{1}_{2}::bool = {3} {4} {5};
+if {3} {4} {5} {{ let {0}_{1}_{2}_if::u8 = 1; }} else {{ let {0}_{1}_{2}_else::u8 = 1; }}
+",
+                    ty, name, a, left, operator, right
+                ));
+            }
+        }
+    }
+    // a comparison whose value is used after the branch on it
+    input.push_str(
+        "let kept::bool = u8_low < u8_middle;
+if kept { let taken::u8 = 1; }
+let both::u8 = kept as u8 + (kept as u8);
+while COUNT < 5 { COUNT = COUNT + 1; }",
+    );
+    let memory = run(&input);
+    for (ty, _, _, _) in cases {
+        for (name, _) in operators {
+            let expected = match name {
+                "eq" => [false, true, false],
+                "ne" => [true, false, true],
+                "lt" => [true, false, false],
+                "le" => [true, true, false],
+                "gt" => [false, false, true],
+                _ => [false, true, true],
+            };
+            for (a, expected) in ["low", "middle", "high"].iter().zip(expected) {
+                let variable = format!("{}_{}_{}", ty, name, a);
+                assert_eq!(vec![expected as u8], memory(&variable), "{}", variable);
+                let taken = format!("{}_if", variable);
+                assert_eq!(vec![expected as u8], memory(&taken), "{}", taken);
+                let not_taken = format!("{}_else", variable);
+                assert_eq!(vec![!expected as u8], memory(&not_taken), "{}", not_taken);
+            }
+        }
+    }
+    assert_eq!(vec![1], memory("kept"));
+    assert_eq!(vec![1], memory("taken"));
+    assert_eq!(vec![2], memory("both"));
+    assert_eq!(vec![5], memory("COUNT"));
+}
+
+#[test]
+fn calls() {
+    let memory = run(
+        "fn add(a::u8, b::u16, c::i8) :: u16 { return b + (a as u16) + (c as i16 as u16); }
+fn twice(a::u8) :: u8 { return a + a; }
+fn nothing() { let x::u8 = 9; }
+fn first(values::array<u8, 3>) :: u8 { return values[0]; }
+let r::u16 = add(twice(5), 1000, -3);
+let s::u8 = twice(twice(3)) + twice(1);
+nothing();
+let values::array<u8, 3> = \"xyz\";
+let t::u8 = first(values);",
+    );
+    assert_eq!(1007u16.to_le_bytes().to_vec(), memory("r"));
+    assert_eq!(vec![14], memory("s"));
+    assert_eq!(vec![9], memory("x"));
+    assert_eq!(vec![b'x'], memory("t"));
+}
+
+#[test]
+fn recursion() {
+    let memory = run(
+        "fn s(n::u8) :: u8 { if n { return n + s(n - 1); } return 0; }
+static R::u8 = s(4);
+fn fib(n::u16) :: u16 {
+    if n < 2 { return n; }
+    let a::u16 = fib(n - 1);
+    let b::u16 = fib(n - 2);
+    return a + b;
+}
+let f::u16 = fib(12);
+fn even(n::u8) :: bool { if n == 0 { return true; } return odd(n - 1); }
+fn odd(n::u8) :: bool { if n == 0 { return false; } return even(n - 1); }
+let e::bool = even(7);
+let o::bool = odd(7);",
+    );
+    assert_eq!(vec![10], memory("R"));
+    assert_eq!(144u16.to_le_bytes().to_vec(), memory("f"));
+    assert_eq!(vec![0], memory("e"));
+    assert_eq!(vec![1], memory("o"));
+    // the variables of the outermost runs are left in memory
+    assert_eq!(vec![4], memory("n"));
+}
+
+#[test]
+fn pointers_and_copies() {
+    let memory = run("type Point = struct { x::u8, y::u16 };
+static P::array<Point, 3>;
+let a::array<u8, 6> = \"german\";
+let i::u8 = 4;
+let b::u8 = a[i];
+let c::ptr<u8> = addr(a[1]);
+let d::u8 = deref(c);
+let e::Point = P[2];
+let f::array<u8, 6> = a;
+let g::array<u8, 8> = \"abc\";
+P[1] = e;
+deref(c) = 0x41;
+c[2] = b;
+f = a;
+g = \"xy\";");
+    assert_eq!(vec![b'a'], memory("b"));
+    assert_eq!(vec![b'e'], memory("d"));
+    assert_eq!(vec![0, 0, 0], memory("e"));
+    // assignments through pointers, and strings shorter than the array they are assigned to
+    assert_eq!(b"gAraan".to_vec(), memory("a"));
+    assert_eq!(b"gAraan".to_vec(), memory("f"));
+    assert_eq!(b"xyc\0\0\0\0\0".to_vec(), memory("g"));
+}
+
+#[test]
+fn field_accesses() {
+    let memory = run("type Point = struct { x::u8, y::u16 };
+static P::array<Point, 3>;
+let a::ptr<Point> = addr(P[1]);
+P[2].y = 0x1234;
+P[1].x = 7;
+a[0].y = P[2].y + 1;
+let b::u16 = P[1].y;
+let c::u8 = deref(a).x;");
+    assert_eq!(vec![0x35, 0x12], memory("b"));
+    assert_eq!(vec![7], memory("c"));
+    assert_eq!(vec![0, 0, 0, 7, 0x35, 0x12, 0, 0x34, 0x12], memory("P"));
+}
+
+#[test]
+fn inline_asm() {
+    let memory = run("type Io = struct { ly::u8, lcdc::u8 };
+static IO @ 0xc100 :: Io;
+static OUT::u8;
+const VALUE::u8 = 0x91;
+asm {
+    ld a, VALUE
+    ld [IO.lcdc], a
+    ld b, 3
+loop:
+    dec b
+    jr nz, loop
+    ld a, b
+    inc a
+    ld [OUT], a
+}");
+    assert_eq!(vec![0, 0x91], memory("IO"));
+    assert_eq!(vec![1], memory("OUT"));
+}
+
+#[test]
+fn call_functions() {
+    let input = "static SCORE::u16 = 0;
+fn bump(points::u8) :: u16 {
+    let total::u16 = SCORE + (points as u16);
+    return total;
+}
+fn reset() { let zero::u8 = 0; }";
+    let compiled = compile_str(input);
+    let mut interpreter = interpreter(&compiled);
+    interpreter.memory[0xc000] = 0xfe;
+    interpreter.memory[0xc001] = 0xff;
+    assert_eq!(Ok(Some(3)), interpreter.call("bump", &[5], 100));
+    assert_eq!(Some(&[5][..]), interpreter.variable("points"));
+    assert_eq!(Ok(None), interpreter.call("reset", &[], 100));
+
+    assert_eq!(
+        Err(Error::UndefinedFunction("missing".to_string())),
+        interpreter.call("missing", &[], 100)
+    );
+    let error = interpreter.call("bump", &[], 100).unwrap_err();
+    assert_eq!(
+        "`bump` takes 1 argument(s) but 0 were given",
+        error.to_string()
+    );
+}
+
+#[test]
+fn run_errors() {
+    let errors = |input: &str| {
+        let compiled = compile_str(input);
+        let mut interpreter = interpreter(&compiled);
+        interpreter.run(1000).unwrap_err()
+    };
+    assert_eq!(Error::Timeout(1000), errors("loop {}"));
+    assert_eq!(Error::Timeout(1000), errors("asm {\nloop:\n    jr loop\n}"));
+    assert_eq!(
+        Error::StackOverflow,
+        errors("fn forever() { forever(); } forever();")
+    );
+    assert_eq!(
+        format!("calls nested more than {} deep", MAX_DEPTH),
+        Error::StackOverflow.to_string()
+    );
+    assert_eq!(
+        Error::Asm(asm::interp::Error::UndefinedLabel("f".to_string())),
+        errors("fn f() {} asm {\n    call f\n}")
+    );
+}
@@ -0,0 +1,204 @@
+use gb_lang::{
+    ast::Context,
+    consts::evaluate,
+    ir::{lower, Program},
+    layout::layout,
+    resolve::resolve,
+    typeck::check,
+};
+
+fn lower_str(input: &str) -> Program {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    let resolution = resolve(&program, &mut context);
+    let consts = evaluate(&program, &resolution, &mut context);
+    let types = check(&program, &resolution, &consts, &mut context);
+    let layouts = layout(&program, &consts, &types, &mut context);
+    assert!(context.diagnostics.is_empty(), "{:?}", context.diagnostics);
+    lower(&program, &resolution, &consts, &types, &layouts)
+}
+
+#[test]
+fn lower_example() {
+    let program = lower_str(include_str!("../example.ggb"));
+    assert_eq!(1, program.functions().count());
+    assert_eq!(b"german", program.all_data().next().unwrap().1);
+}
+
+#[test]
+fn dump_control_flow() {
+    let program = lower_str(
+        "static A @ 0xff44 :: u8;
+while A as bool {
+    if A & 1 { break; } else { continue; }
+}
+loop {
+    if A { break; }
+}
+let b::u8 = 1;",
+    );
+    assert_eq!(
+        "fn __start() {
+bb0:
+    jmp bb1
+bb1:
+    %0 = load i8 $FF44
+    %1 = nonzero i8 %0
+    br i8 %1, bb2, bb3
+bb2:
+    %2 = load i8 $FF44
+    %3 = and i8 %2, $1
+    br i8 %3, bb4, bb5
+bb3:
+    jmp bb6
+bb4:
+    jmp bb3
+bb5:
+    jmp bb1
+bb6:
+    %4 = load i8 $FF44
+    br i8 %4, bb8, bb9
+bb7:
+    store i8 $C000, $1
+    ret
+bb8:
+    jmp bb7
+bb9:
+    jmp bb6
+}
+",
+        program.dump()
+    );
+}
+
+#[test]
+fn dump_calls() {
+    let program = lower_str(
+        "type Sprite = struct { y::u8, x::u8, tile::u8, flags::u8 };
+static OAM @ 0xfe00 :: array<Sprite, 40>;
+const N::i8 = -2;
+static I::u8 = 2;
+fn sprite(i::u8) :: ptr<Sprite> { return addr(OAM[i]); }
+fn scale(a::i8, b::i16) :: i16 { return a as i16 * b / (N as i16); }
+let s::Sprite = deref(sprite(3));
+let t::u8 = (sprite(4) as ptr<u8>)[I];
+let x::i16 = scale(1, 300);
+let name::array<u8, 8> = \"mario\";",
+    );
+    assert_eq!(
+        "data0 = \"mario\"
+
+fn __start() {
+bb0:
+    store i8 $C000, $2
+    %0 = call @sprite($3)
+    copy $C005, %0, 4
+    %1 = call @sprite($4)
+    %2 = load i8 $C000
+    %3 = zext %2
+    %4 = add i16 %1, %3
+    %5 = load i8 %4
+    store i8 $C009, %5
+    %6 = call @scale($1, $12C)
+    store i16 $C00A, %6
+    copy $C00C, data0, 5
+    ret
+}
+
+fn sprite(%0: i8) -> i16 {
+bb0:
+    store i8 $C001, %0
+    %1 = load i8 $C001
+    %2 = zext %1
+    %3 = mul i16 %2, $4
+    %4 = add i16 $FE00, %3
+    ret %4
+}
+
+fn scale(%0: i8, %1: i16) -> i16 {
+bb0:
+    store i8 $C002, %0
+    store i16 $C003, %1
+    %2 = load i8 $C002
+    %3 = sext %2
+    %4 = load i16 $C003
+    %5 = mul i16 %3, %4
+    %6 = sdiv i16 %5, $FFFE
+    ret %6
+}
+",
+        program.dump()
+    );
+}
+
+#[test]
+fn dump_comparisons() {
+    let program = lower_str(
+        "static A::i8 = -1;
+static B::u16 = 2;
+let c::bool = A > 3;
+if B <= 2 { let d::bool = B == 2; }
+let e::i8 = -A;",
+    );
+    // `>` is `<` with its operands swapped, after they are evaluated, and negative literals are
+    // constants
+    assert_eq!(
+        "fn __start() {
+bb0:
+    store i8 $C000, $FF
+    store i16 $C001, $2
+    %0 = load i8 $C000
+    %1 = cmp slt i8 $3, %0
+    store i8 $C003, %1
+    %2 = load i16 $C001
+    %3 = cmp ule i16 %2, $2
+    br i8 %3, bb1, bb2
+bb1:
+    %4 = load i16 $C001
+    %5 = cmp eq i16 %4, $2
+    store i8 $C004, %5
+    jmp bb2
+bb2:
+    %6 = load i8 $C000
+    %7 = sub i8 $0, %6
+    store i8 $C005, %7
+    ret
+}
+",
+        program.dump()
+    );
+}
+
+#[test]
+fn dump_assignments() {
+    let program = lower_str(
+        "static A @ 0xc800 :: array<u16, 4>;
+let i::u8 = 2;
+A[i] = 1000;
+i = i + 1;
+let s::array<u8, 3> = \"abc\";
+s = \"de\";",
+    );
+    assert_eq!(
+        "data0 = \"abc\"
+data1 = \"de\"
+
+fn __start() {
+bb0:
+    store i8 $C000, $2
+    %0 = load i8 $C000
+    %1 = zext %0
+    %2 = mul i16 %1, $2
+    %3 = add i16 $C800, %2
+    store i16 %3, $3E8
+    %4 = load i8 $C000
+    %5 = add i8 %4, $1
+    store i8 $C000, %5
+    copy $C001, data0, 3
+    copy $C001, data1, 2
+    ret
+}
+",
+        program.dump()
+    );
+}
@@ -0,0 +1,198 @@
+use gb_lang::{
+    ast::Context,
+    consts::evaluate,
+    diagnostics::Diagnostic,
+    layout::{layout, Layout, Layouts},
+    resolve::resolve,
+    typeck::{check, Ty},
+};
+
+fn layout_str(input: &str) -> (Layouts, Vec<Diagnostic>) {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    let resolution = resolve(&program, &mut context);
+    let consts = evaluate(&program, &resolution, &mut context);
+    let types = check(&program, &resolution, &consts, &mut context);
+    let layouts = layout(&program, &consts, &types, &mut context);
+    (layouts, context.diagnostics.take())
+}
+
+#[test]
+fn scalars() {
+    assert_eq!(1, Layout::of(&Ty::U8).unwrap().size);
+    assert_eq!(2, Layout::of(&Ty::Ptr(Box::new(Ty::U8))).unwrap().size);
+    assert_eq!(1, Layout::of(&Ty::Ptr(Box::new(Ty::U8))).unwrap().align);
+}
+
+#[test]
+fn struct_and_union() {
+    let (layouts, diagnostics) = layout_str(
+        "static a::struct {
+            b::u8,
+            c::union { d::ptr<u8>, e::array<u8, 3>, f::u8 },
+            g::array<struct { h::u8, i::ptr<u8> }, 2>,
+        };",
+    );
+    assert!(diagnostics.is_empty());
+    let layout = &layouts.items().next().unwrap().layout;
+    assert_eq!(1 + 3 + 6, layout.size);
+    assert_eq!(3, layout.field("c").unwrap().layout.size);
+    assert_eq!(Some(1), layout.offset_of(&["c", "f"]));
+    assert_eq!(Some(4), layout.offset_of(&["g"]));
+    assert_eq!(None, layout.offset_of(&["c", "z"]));
+}
+
+#[test]
+fn too_large() {
+    let (layouts, diagnostics) = layout_str(
+        "static a::array<u8, 0x1f00>;
+static b::struct { c::array<u8, 0x8000>, d::array<u8, 0x8001> };",
+    );
+    assert_eq!(1, layouts.items().count());
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "2:11: error: type `struct { c::array<u8, 32768>, d::array<u8, 32769> }` doesn't fit in the address space",
+        diagnostics[0].to_string()
+    );
+}
+
+#[test]
+fn dump_example() {
+    let (layouts, diagnostics) = layout_str(include_str!("../example.ggb"));
+    assert!(diagnostics.is_empty());
+    let vram = layouts.items().find(|item| item.name == "VRAM").unwrap();
+    assert_eq!(Some(0x1800), vram.layout.offset_of(&["tile_maps"]));
+    assert_eq!(
+        "foo @ $C000 in WRAM, size $0001
+bar @ $C001 in WRAM, size $0006
+baz @ $C007 in WRAM, size $0002
+VRAM @ $8000 in VRAM, size $2000
+  $8000 tile_data: union
+  $8000 tile_data.data0: struct
+  $8000 tile_data.data0.padding: array<u8, 2048>
+  $8800 tile_data.data0.data: array<u8, 4096>
+  $8000 tile_data.data1: array<u8, 4096>
+  $9800 tile_maps: array<array<u8, 1024>, 2>
+IO @ $FF40 in IO, size $0002
+  $FF40 LCDC: u8
+  $FF41 STAT: u8
+",
+        layouts.dump()
+    );
+}
+
+#[test]
+fn allocate_around_placements() {
+    let (layouts, diagnostics) = layout_str(
+        "let a::u8 = 0;
+static B @ WRAM + 2 :: array<u8, 2>;
+let c::array<u8, 2> = \"hi\";
+static D::u8;
+const WRAM::u16 = 0xc000;",
+    );
+    assert!(diagnostics.is_empty());
+    let addresses: Vec<_> = layouts.items().map(|item| item.address).collect();
+    assert_eq!(vec![0xc000, 0xc002, 0xc004, 0xc006], addresses);
+}
+
+#[test]
+fn invalid_placements() {
+    let (layouts, diagnostics) = layout_str(
+        "static A @ 0x9ffe :: array<u8, 4>;
+static B @ 0xe000 :: u8;
+static C @ 0x0150 :: u8;
+static D @ 0xff80 :: array<u8, 0x7f>;",
+    );
+    assert_eq!(1, layouts.items().count());
+    let messages: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        vec![
+            "1:10: error: `A` doesn't fit in VRAM ($8000..=$9FFF)\n  1:8: `A` takes $0004 bytes",
+            "2:10: error: `B` can't be placed in echo RAM",
+            "3:10: error: `C` can't be placed in ROM",
+        ],
+        messages
+    );
+}
+
+#[test]
+fn overlapping_placements() {
+    let (_, diagnostics) = layout_str(
+        "static A @ 0xff40 :: struct { LCDC::u8, STAT::u8 };
+static B @ 0xff41 :: u8;
+static C @ 0xff42 :: u8;",
+    );
+    let messages: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        vec!["2:10: error: `B` overlaps with `A`\n  1:10: `A` is placed here"],
+        messages
+    );
+}
+
+#[test]
+fn fn_params_and_locals() {
+    let (layouts, diagnostics) = layout_str(
+        "fn f(a::u8, b::ptr<u8>) :: u8 { let c::u16 = 1; return a; }\nlet d::u8 = f(1, addr(E));\nstatic E::u8;",
+    );
+    assert!(diagnostics.is_empty());
+    assert_eq!(
+        "a @ $C000 in WRAM, size $0001
+b @ $C001 in WRAM, size $0002
+c @ $C003 in WRAM, size $0002
+d @ $C005 in WRAM, size $0001
+E @ $C006 in WRAM, size $0001
+",
+        layouts.dump()
+    );
+}
+
+#[test]
+fn fn_frames() {
+    let (layouts, diagnostics) = layout_str(
+        "fn f(a::u8) { let b::u16 = 1; static C::u8; fn g() { let d::u8 = 0; } }\nlet e::u8 = 2;",
+    );
+    assert!(diagnostics.is_empty());
+    let frame = |name| {
+        let function = layouts
+            .items()
+            .find(|item| item.name == name)
+            .unwrap()
+            .function
+            .unwrap();
+        layouts
+            .frame(function)
+            .map(|item| item.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    // the frame of `f` is made of its parameters and `let`s, not of its statics or nested `fn`s
+    assert_eq!(vec!["a", "b"], frame("a"));
+    assert_eq!(vec!["d"], frame("d"));
+    assert_eq!(
+        None,
+        layouts
+            .items()
+            .find(|item| item.name == "e")
+            .unwrap()
+            .function
+    );
+}
+
+#[test]
+fn stack_reserved() {
+    let (layouts, diagnostics) = layout_str(
+        "static BIG::array<u8, 0x1eff>;
+static A::u8;
+static B::u8;
+static C @ 0xdff0 :: u8;",
+    );
+    let messages: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        vec![
+            "4:10: error: `C` overlaps with the stack ($DF00..$E000)",
+            "3:8: error: not enough WRAM for `B` ($0001 bytes)",
+        ],
+        messages
+    );
+    let addresses: Vec<_> = layouts.items().map(|item| item.address).collect();
+    assert_eq!(vec![0xc000, 0xdeff], addresses);
+}
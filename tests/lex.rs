@@ -77,24 +77,36 @@ fn tokenize_one_char() {
 #[test]
 fn tokenize_keywords() {
     assert_token_matches!(
-        "addr array asm break const continue deref else if let loop ptr static struct union u8 while",
+        "addr array as asm bool break const continue deref else false fn i8 i16 if import let loop ptr return static struct true type union u8 u16 use while",
         [
             Token::Addr(_),
             Token::Array(_),
+            Token::As(_),
             Token::Asm(_),
+            Token::Bool(_),
             Token::Break(_),
             Token::Const(_),
             Token::Continue(_),
             Token::Deref(_),
             Token::Else(_),
+            Token::False(_),
+            Token::Fn(_),
+            Token::I8(_),
+            Token::I16(_),
             Token::If(_),
+            Token::Import(_),
             Token::Let(_),
             Token::Loop(_),
             Token::Ptr(_),
+            Token::Return(_),
             Token::Static(_),
             Token::Struct(_),
+            Token::True(_),
+            Token::Type(_),
             Token::Union(_),
             Token::U8(_),
+            Token::U16(_),
+            Token::Use(_),
             Token::While(_),
             Token::EOF(_),
         ],
@@ -111,7 +123,28 @@ fn tokenize_string_error() {
     let mut tokens = gb_lang::lex::tokenize("\"hello");
     assert!(matches!(
         tokens.next(),
-        Some(Err(Error::OpenEndedStringToken))
+        Some(Err(Error::OpenEndedStringToken(_)))
+    ));
+}
+
+#[test]
+fn tokenize_asm_block() {
+    let mut tokens = gb_lang::lex::tokenize("asm { ld a, [IO.LCDC] ; } \n} asm;");
+    assert!(matches!(tokens.next(), Some(Ok(Token::Asm(_)))));
+    match tokens.next() {
+        Some(Ok(Token::AsmBlock(block))) => {
+            assert_eq!(" ld a, [IO.LCDC] ; } \n", block.contents())
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(tokens.next(), Some(Ok(Token::Asm(_)))));
+    assert!(matches!(tokens.next(), Some(Ok(Token::SemiColon(_)))));
+
+    let mut tokens = gb_lang::lex::tokenize("asm { nop");
+    tokens.next();
+    assert!(matches!(
+        tokens.next(),
+        Some(Err(Error::OpenEndedAsmBlock(_)))
     ));
 }
 
@@ -124,7 +157,6 @@ fn tokenize_num() {
 }
 
 #[test]
-#[ignore]
 fn tokenize_num_multiple_raxix() {
     assert_token_matches!(
         "0 123456789 0x123456789abcdefABCDEF 01234567 0b1010",
@@ -140,9 +172,59 @@ fn tokenize_num_multiple_raxix() {
 }
 
 #[test]
-#[ignore]
 fn tokenize_invalid_number_error() {
-    todo!()
+    for input in ["0x", "0b102", "12ab", "0xffz"] {
+        let mut tokens = gb_lang::lex::tokenize(input);
+        assert!(matches!(
+            tokens.next(),
+            Some(Err(Error::InvalidNumberToken(_)))
+        ));
+    }
+}
+
+#[test]
+fn tokenize_unexpected_char_error() {
+    use gb_lang::{Span, Spanned};
+
+    let mut tokens = gb_lang::lex::tokenize("foo$bar");
+    assert!(matches!(tokens.next(), Some(Ok(Token::Identifier(_)))));
+    match tokens.next() {
+        Some(Err(error @ Error::UnexpectedChar('$', _))) => assert_eq!(
+            Span {
+                min: [1, 4],
+                max: [1, 4],
+                ..Span::default()
+            },
+            error.span()
+        ),
+        other => panic!("{:?}", other),
+    }
+    assert!(tokens.next().is_none());
+}
+
+#[test]
+fn tokenize_field_access() {
+    assert_token_matches!(
+        "foo.bar",
+        [
+            Token::Identifier(_),
+            Token::Dot(_),
+            Token::Identifier(_),
+            Token::EOF(_),
+        ],
+    );
+}
+
+#[test]
+fn tokenize_comments() {
+    assert_token_matches!(
+        "// hello\nlet // world\n/ //",
+        [Token::Let(_), Token::ForwardSlash(_), Token::EOF(_)],
+    );
+    let mut tokens = gb_lang::lex::tokenize_with_trivia("let // world\n");
+    assert!(matches!(tokens.next(), Some(Ok(Token::Let(_)))));
+    assert!(matches!(tokens.next(), Some(Ok(Token::Comment(c))) if c.as_str() == "// world"));
+    assert!(matches!(tokens.next(), Some(Ok(Token::EOF(_)))));
 }
 
 #[test]
@@ -152,3 +234,44 @@ fn tokenize_identifier() {
         [Token::Identifier(_), Token::Identifier(_), Token::EOF(_),],
     );
 }
+
+#[test]
+fn tokenize_span() {
+    use gb_lang::{Span, Spanned};
+
+    let tokens: Vec<_> = gb_lang::tokenize("let foo\n  ::u8")
+        .map(Result::unwrap)
+        .collect();
+    let spans: Vec<_> = tokens.iter().map(Spanned::span).collect();
+    assert_eq!(
+        vec![
+            Span {
+                min: [1, 1],
+                max: [1, 3],
+                ..Span::default()
+            },
+            Span {
+                min: [1, 5],
+                max: [1, 7],
+                ..Span::default()
+            },
+            Span {
+                min: [2, 3],
+                max: [2, 4],
+                ..Span::default()
+            },
+            Span {
+                min: [2, 5],
+                max: [2, 6],
+                ..Span::default()
+            },
+            Span {
+                min: [2, 7],
+                max: [2, 7],
+                ..Span::default()
+            },
+        ],
+        spans
+    );
+    assert!(matches!(&tokens[1], Token::Identifier(t) if t.as_str() == "foo"));
+}
@@ -0,0 +1,426 @@
+mod common;
+
+use common::compile_str;
+use gb_lang::{
+    asm::{decode::disassemble, encode, parse::parse, Assembly, Line},
+    link::{self, link, variables, Bank, Error, Kind, Section, Symbol},
+    rom::{self, Cartridge, Cgb, Header, RamSize},
+};
+
+fn data(label: &str, size: usize) -> Assembly {
+    let mut assembly = Assembly::default();
+    assembly.label(label);
+    assembly.lines.push(Line::Data(vec![0xaa; size]));
+    assembly
+}
+
+fn code(input: &str) -> Assembly {
+    parse(input).unwrap()
+}
+
+fn mbc(cartridge: Cartridge) -> Header {
+    Header {
+        title: "LINK".to_string(),
+        cartridge,
+        ..Default::default()
+    }
+}
+
+const MBC1: Cartridge = Cartridge::Mbc1 {
+    ram: false,
+    battery: false,
+};
+
+const MBC5: Cartridge = Cartridge::Mbc5 {
+    ram: true,
+    battery: false,
+    rumble: false,
+};
+
+fn bank(kind: Kind, number: u16) -> Bank {
+    Bank { kind, number }
+}
+
+#[test]
+fn place_sections() {
+    let sections = [
+        Section::new("main", Kind::Rom0, code("__init:\n    halt")),
+        Section::new("vblank", Kind::Rom0, code("vblank:\n    reti")).at(0x40),
+        Section::new("tiles", Kind::Romx, data("tiles", 0x3000)),
+        Section::new("maps", Kind::Romx, data("maps", 0x2000)),
+        Section::new("text", Kind::Romx, data("text", 0x1000)),
+        Section::new("music", Kind::Romx, data("music", 0x10)).in_bank(5),
+    ];
+    let linked = link(&sections, &mbc(MBC1)).unwrap();
+    assert_eq!(Ok(()), rom::verify(&linked.rom));
+    assert_eq!(8 * rom::BANK_SIZE, linked.rom.len());
+
+    let symbol = |name: &str| linked.symbols[name];
+    let rom0 = bank(Kind::Rom0, 0);
+    assert_eq!(
+        Symbol {
+            bank: rom0,
+            address: 0x40
+        },
+        symbol("vblank")
+    );
+    // floating `ROM0` sections go after the header, and the banking runtime after them
+    assert_eq!(0x150, symbol("__init").address);
+    assert_eq!(0x151, symbol(link::ENTRY).address);
+    // first fit, in order
+    assert_eq!(bank(Kind::Romx, 1), symbol("tiles").bank);
+    assert_eq!(bank(Kind::Romx, 2), symbol("maps").bank);
+    assert_eq!(bank(Kind::Romx, 1), symbol("text").bank);
+    assert_eq!(0x7000, symbol("text").address);
+    assert_eq!(bank(Kind::Romx, 5), symbol("music").bank);
+    assert_eq!(bank(Kind::Hram, 0), symbol(link::CURRENT_BANK).bank);
+
+    // the entry point maps bank 1 before jumping to `__init`
+    assert_eq!(rom::jump(0x151), linked.rom[0x100..0x104]);
+    assert_eq!(
+        "    ld de, $0001
+    call $015A
+    jp $0150
+",
+        disassemble(&linked.rom[0x151..0x15a], 0x151).dump()
+    );
+    assert_eq!(
+        "    ld a, e
+    ld [$FFFD], a
+    ld [$2000], a
+    swap a
+    rrca
+    and a, $03
+    ld [$4000], a
+    ret
+",
+        disassemble(&linked.rom[0x15a..0x16a], 0x15a).dump()
+    );
+    assert_eq!(0xd9, linked.rom[0x40]);
+    assert_eq!([0xaa; 4], linked.rom[0x4000..0x4004]);
+    assert_eq!([0xaa; 4], linked.rom[0x7000..0x7004]);
+    assert_eq!([0xaa; 4], linked.rom[0x8000..0x8004]);
+    assert_eq!(rom::PADDING, linked.rom[0xa000]);
+    assert_eq!([0xaa; 4], linked.rom[5 * 0x4000..5 * 0x4000 + 4]);
+}
+
+#[test]
+fn usage_map() {
+    let sections = [
+        Section::new("main", Kind::Rom0, code("__init:\n    halt")),
+        Section::new("tiles", Kind::Romx, data("tiles", 0x3000)),
+        Section::new("variables", Kind::Wram0, data("x", 0x10)),
+        Section::new("fast", Kind::Hram, data("y", 2)).at(0xff90),
+    ];
+    let linked = link(&sections, &Header::default()).unwrap();
+    assert_eq!(
+        "ROM0: $0051 bytes used, $3FAF free
+    $0100 ($0050 bytes) header
+    $0150 ($0001 bytes) main
+ROMX[1]: $3000 bytes used, $1000 free
+    $4000 ($3000 bytes) tiles
+WRAM0: $0010 bytes used, $0FF0 free
+    $C000 ($0010 bytes) variables
+HRAM: $0002 bytes used, $007D free
+    $FF90 ($0002 bytes) fast
+",
+        linked.map.to_string()
+    );
+    let usage = linked.map.usage(bank(Kind::Romx, 1)).unwrap();
+    assert_eq!((0x3000, 0x1000), (usage.used(), usage.free()));
+    // no banking runtime without a memory bank controller
+    assert!(!linked.symbols.contains_key(link::CURRENT_BANK));
+    assert_eq!(rom::jump(0x150), linked.rom[0x100..0x104]);
+    assert_eq!(Ok(()), rom::verify(&linked.rom));
+}
+
+#[test]
+fn far_calls() {
+    let sections = [
+        Section::new("main", Kind::Rom0, code("__init:\n    call a\n    halt")),
+        Section::new("a", Kind::Romx, code("a:\n    call b\n    call c\n    ret")).in_bank(2),
+        Section::new("b", Kind::Romx, code("b:\n    ret")).in_bank(2),
+        Section::new("c", Kind::Romx, code("c:\n    ld a, 1\n    ret")).in_bank(0x101),
+    ];
+    let linked = link(&sections, &mbc(MBC5)).unwrap();
+    assert_eq!(Ok(()), rom::verify(&linked.rom));
+    assert_eq!(0x200 * rom::BANK_SIZE, linked.rom.len());
+    let address = |name: &str| linked.symbols[name].address;
+
+    // calls from `ROM0`, or to another bank, go through trampolines
+    let far_a = address("__far.a");
+    let far_c = address("__far.c");
+    assert!(!linked.symbols.contains_key("__far.b"));
+    assert_eq!(
+        format!("    call ${:04X}\n    halt\n", far_a),
+        disassemble(&linked.rom[0x150..0x154], 0x150).dump()
+    );
+    let a = 2 * rom::BANK_SIZE;
+    assert_eq!(
+        format!(
+            "    call ${:04X}\n    call ${:04X}\n    ret\n",
+            address("b"),
+            far_c
+        ),
+        disassemble(&linked.rom[a..a + 7], 0x4000).dump()
+    );
+    let start = usize::from(far_c);
+    assert_eq!(
+        format!(
+            "    ld a, [$FFFD]
+    ld e, a
+    ld a, [$FFFE]
+    ld d, a
+    push de
+    ld de, $0101
+    call ${switch:04X}
+    call $4000
+    ld b, a
+    pop de
+    call ${switch:04X}
+    ld a, b
+    ret
+",
+            switch = address(link::SWITCH_BANK)
+        ),
+        disassemble(&linked.rom[start..start + 25], far_c).dump()
+    );
+    let switch = usize::from(address(link::SWITCH_BANK));
+    assert_eq!(
+        "    ld a, e
+    ld [$FFFD], a
+    ld [$2000], a
+    ld a, d
+    ld [$FFFE], a
+    ld [$3000], a
+    ret
+",
+        disassemble(&linked.rom[switch..switch + 15], switch as u16).dump()
+    );
+}
+
+#[test]
+fn bank_limits() {
+    // MBC1 can't map banks $20, $40 and $60
+    let mut sections = vec![Section::new("main", Kind::Rom0, code("__init:\n    halt"))];
+    for i in 0..0x20 {
+        let name = format!("data{}", i);
+        sections.push(Section::new(name.as_str(), Kind::Romx, data(&name, 0x4000)));
+    }
+    let linked = link(&sections, &mbc(MBC1)).unwrap();
+    assert_eq!(bank(Kind::Romx, 0x1f), linked.symbols["data30"].bank);
+    assert_eq!(bank(Kind::Romx, 0x21), linked.symbols["data31"].bank);
+    assert_eq!(0x40 * rom::BANK_SIZE, linked.rom.len());
+
+    let section = Section::new("data", Kind::Romx, data("data", 1)).in_bank(0x40);
+    assert_eq!(
+        Err(Error::InvalidBank {
+            section: "data".to_string(),
+            bank: bank(Kind::Romx, 0x40),
+        }),
+        link(std::slice::from_ref(&section), &mbc(MBC1))
+    );
+    assert_eq!(
+        "section `data` can't be placed in ROMX[64], which the cartridge doesn't have",
+        link(&[section], &mbc(MBC1)).unwrap_err().to_string()
+    );
+
+    // RAM banks depend on the header
+    let ram = Section::new("save", Kind::Sram, data("save", 0x10)).in_bank(3);
+    let header = Header {
+        cartridge: MBC5,
+        ram_size: RamSize::Kib8,
+        ..Default::default()
+    };
+    assert!(matches!(
+        link(std::slice::from_ref(&ram), &header),
+        Err(Error::InvalidBank { .. })
+    ));
+    let header = Header {
+        ram_size: RamSize::Kib32,
+        ..header
+    };
+    let main = Section::new("main", Kind::Rom0, code("__init:\n    halt"));
+    let linked = link(&[main.clone(), ram], &header).unwrap();
+    assert_eq!(
+        Symbol {
+            bank: bank(Kind::Sram, 3),
+            address: 0xa000
+        },
+        linked.symbols["save"]
+    );
+
+    let wram = Section::new("buffer", Kind::Wramx, data("buffer", 0x10)).in_bank(7);
+    assert!(matches!(
+        link(&[main.clone(), wram.clone()], &Header::default()),
+        Err(Error::InvalidBank { .. })
+    ));
+    let header = Header {
+        cgb: Cgb::Compatible,
+        ..Default::default()
+    };
+    let linked = link(&[main, wram], &header).unwrap();
+    assert_eq!(bank(Kind::Wramx, 7), linked.symbols["buffer"].bank);
+}
+
+#[test]
+fn link_errors() {
+    let main = || Section::new("main", Kind::Rom0, code("__init:\n    halt"));
+
+    let section = Section::new("vars", Kind::Wram0, code("x:\n    nop"));
+    let error = link(&[main(), section], &Header::default()).unwrap_err();
+    assert_eq!(
+        "section `vars` holds instructions, which can't be placed in WRAM0",
+        error.to_string()
+    );
+
+    let section = Section::new("vars", Kind::Wram0, data("x", 1)).at(0xd000);
+    let error = link(&[main(), section], &Header::default()).unwrap_err();
+    assert_eq!(
+        "section `vars` can't be placed at $D000, outside of WRAM0",
+        error.to_string()
+    );
+
+    let section = Section::new("vectors", Kind::Rom0, data("vectors", 0x101));
+    let error = link(&[main(), section.at(0)], &Header::default()).unwrap_err();
+    assert_eq!(
+        Error::Overlap {
+            section: "vectors".to_string(),
+            other: "header".to_string(),
+            bank: bank(Kind::Rom0, 0),
+        },
+        error
+    );
+
+    let sections = [
+        main(),
+        Section::new("a", Kind::Romx, data("a", 0x3000)),
+        Section::new("b", Kind::Romx, data("b", 0x3000)),
+    ];
+    let error = link(&sections, &Header::default()).unwrap_err();
+    assert_eq!(
+        "section `b` ($3000 bytes) doesn't fit in any ROMX bank",
+        error.to_string()
+    );
+    match error {
+        Error::Overflow { map, .. } => assert_eq!(
+            "ROM0: $0051 bytes used, $3FAF free
+    $0100 ($0050 bytes) header
+    $0150 ($0001 bytes) main
+ROMX[1]: $3000 bytes used, $1000 free
+    $4000 ($3000 bytes) a
+",
+            map.to_string()
+        ),
+        _ => panic!("Expected an overflow"),
+    }
+    let section = Section::new("big", Kind::Hram, data("big", 0x80));
+    let error = link(&[main(), section], &Header::default()).unwrap_err();
+    assert_eq!(
+        "section `big` ($0080 bytes) doesn't fit in HRAM",
+        error.to_string()
+    );
+    let section = Section::new("big", Kind::Romx, data("big", 0x4001)).in_bank(2);
+    let error = link(&[main(), section], &mbc(MBC1)).unwrap_err();
+    assert_eq!(
+        "section `big` ($4001 bytes) doesn't fit in ROMX[2]",
+        error.to_string()
+    );
+
+    let section = Section::new("other", Kind::Rom0, code("__init:\n    nop"));
+    let error = link(&[main(), section], &Header::default()).unwrap_err();
+    assert_eq!(Error::DuplicateLabel("__init".to_string()), error);
+
+    let sections = [
+        Section::new(
+            "main",
+            Kind::Rom0,
+            code("__init:\n    ld hl, far\n    halt"),
+        ),
+        Section::new("far", Kind::Romx, data("far", 1)).in_bank(2),
+    ];
+    let error = link(&sections, &mbc(MBC1)).unwrap_err();
+    assert_eq!(
+        "`far` is in ROMX[2], which isn't mapped from section `main` (only calls can cross ROM banks)",
+        error.to_string()
+    );
+    // without a memory bank controller, bank 1 is always mapped
+    let sections = [
+        Section::new(
+            "main",
+            Kind::Rom0,
+            code("__init:\n    ld hl, far\n    halt"),
+        ),
+        Section::new("far", Kind::Romx, data("far", 1)),
+    ];
+    assert!(link(&sections, &Header::default()).is_ok());
+
+    let section = Section::new("main", Kind::Rom0, code("main:\n    halt"));
+    let error = link(std::slice::from_ref(&section), &Header::default()).unwrap_err();
+    assert_eq!(Error::MissingEntry("__init".to_string()), error);
+    let error = link(&[section], &mbc(MBC1)).unwrap_err();
+    assert_eq!("undefined entry point `__init`", error.to_string());
+
+    let section = Section::new("main", Kind::Rom0, code("__init:\n    jp nowhere"));
+    let error = link(&[section], &Header::default()).unwrap_err();
+    assert_eq!(
+        Error::Encode {
+            section: "main".to_string(),
+            error: encode::Error::UndefinedLabel("nowhere".to_string()),
+        },
+        error
+    );
+
+    let header = Header {
+        title: "a title that is too long".to_string(),
+        ..Default::default()
+    };
+    let error = link(&[main()], &header).unwrap_err();
+    assert!(matches!(error, Error::Rom(rom::Error::InvalidTitle { .. })));
+}
+
+#[test]
+fn variable_sections() {
+    let input = "let a::u8 = 0;
+static A::u8;
+static B @ 0xcffe :: array<u8, 4>;
+static C @ 0xff90 :: u16;
+static D @ 0xa000 :: u8;
+static LCDC @ 0xff40 :: u8;";
+    let compiled = compile_str(input);
+
+    let sections = variables(&compiled.layouts, &compiled.resolution);
+    let placed: Vec<_> = sections
+        .iter()
+        .map(|s| (s.name.as_str(), s.kind, s.bank, s.address, s.size()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("a", Kind::Wram0, Some(0), Some(0xc000), 1),
+            ("A", Kind::Wram0, Some(0), Some(0xc001), 1),
+            ("B", Kind::Wram0, Some(0), Some(0xcffe), 2),
+            ("B", Kind::Wramx, Some(1), Some(0xd000), 2),
+            ("C", Kind::Hram, Some(0), Some(0xff90), 2),
+            ("D", Kind::Sram, Some(0), Some(0xa000), 1),
+        ],
+        placed
+    );
+
+    let mut sections = sections;
+    sections.push(Section::new("main", Kind::Rom0, code("__init:\n    halt")));
+    sections.push(Section::new("more", Kind::Wram0, data("more", 2)));
+    let header = Header {
+        cartridge: MBC5,
+        ram_size: RamSize::Kib8,
+        ..Default::default()
+    };
+    let linked = link(&sections, &header).unwrap();
+    assert_eq!(0xc002, linked.symbols["more"].address);
+    assert_eq!(0xc001, linked.symbols["A"].address);
+    // only `static`s get symbols
+    assert!(!linked.symbols.contains_key("a"));
+    assert_eq!(0xff90, linked.symbols["C"].address);
+    // the banking runtime goes at the top of HRAM
+    assert_eq!(0xfffd, linked.symbols[link::CURRENT_BANK].address);
+    assert_eq!(0xfffe, linked.symbols["__rom_bank.high"].address);
+}
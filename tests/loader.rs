@@ -0,0 +1,320 @@
+mod common;
+
+use common::interpreter;
+use gb_lang::{
+    ast::{statements::Statement, Context, Program},
+    compile_program,
+    cpu::{Cpu, Flat},
+    debug,
+    link::{self, Kind, Section},
+    loader::{self, Files, Sources},
+    resolve::resolve,
+    rom::Header,
+    Compiled, FileId, Spanned,
+};
+use std::{collections::HashMap, io, path::Path};
+
+fn load(files: &[(&str, &str)], context: &mut Context) -> io::Result<Sources> {
+    let files: HashMap<_, _> = files.iter().copied().collect();
+    loader::load_with(Path::new("main.ggb"), context, |path| {
+        let file = files.get(path.to_str().unwrap());
+        file.map(|file| file.to_string())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    })
+}
+
+fn errors(context: &Context) -> Vec<String> {
+    context
+        .diagnostics
+        .errors()
+        .map(|diagnostic| diagnostic.render(&context.files))
+        .collect()
+}
+
+fn compile<'input>(program: Program<'input>, context: &mut Context) -> Compiled<'input> {
+    compile_program(program, context).unwrap_or_else(|diagnostics| {
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(&context.files))
+            .collect();
+        panic!("{:?}", errors)
+    })
+}
+
+const MAIN: &str = "import \"gfx/sprites.ggb\";
+import \"./util/math.ggb\";
+import \"hardware\";
+use sprites::draw;
+use math::double;
+use hardware::LCDC_ON;
+
+static COUNT::u8 = double(2);
+fn init() :: u8 { return LCDC_ON; }
+let total::u8 = draw(COUNT);
+let on::u8 = init();
+";
+
+const SPRITES: &str = "import \"../util/math.ggb\";
+use math::double;
+
+static COUNT::u8 = 1;
+fn init() :: u8 { return COUNT; }
+fn draw(n::u8) :: u8 { return double(n) + init(); }
+";
+
+const MATH: &str = "fn double(x::u8) :: u8 { return x + x; }";
+
+#[test]
+fn load_program() {
+    let mut context = Context::default();
+    let sources = load(
+        &[
+            ("main.ggb", MAIN),
+            ("gfx/sprites.ggb", SPRITES),
+            ("util/math.ggb", MATH),
+        ],
+        &mut context,
+    )
+    .unwrap();
+    let files: Vec<_> = context
+        .files
+        .iter()
+        .map(|(id, file)| (id.0, file.path.to_str().unwrap(), file.name.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (0, "main.ggb", "main"),
+            (1, "gfx/sprites.ggb", "sprites"),
+            (2, "util/math.ggb", "math"),
+            (3, "<hardware>", "hardware"),
+        ],
+        files
+    );
+    assert_eq!(Some(MATH), sources.text(FileId(2)));
+
+    let program = sources.parse(&mut context).unwrap();
+    // imported files first, so their statics are initialized before their importers run
+    let mut order: Vec<_> = program
+        .statements
+        .iter()
+        .map(|statement| statement.span().file.0)
+        .collect();
+    order.dedup();
+    assert_eq!(vec![2, 1, 3, 0], order);
+    assert_eq!(FileId(0), program.eof.span().file);
+    let imports: Vec<_> = program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Import(import) => context.files.import(import.id),
+            _ => None,
+        })
+        .map(|file| file.0)
+        .collect();
+    assert_eq!(vec![2, 1, 2, 3], imports);
+
+    let compiled = compile(program, &mut context);
+    let mut interpreter = interpreter(&compiled);
+    interpreter.run(1000).unwrap();
+    assert_eq!(Some(&[9][..]), interpreter.variable("total"));
+    assert_eq!(Some(&[0x80][..]), interpreter.variable("on"));
+    assert_eq!(
+        Some(Some(1)),
+        interpreter.call("sprites__init", &[], 100).ok()
+    );
+
+    // the `fn`s and `static`s of imported modules don't clash with the ones of the root file
+    let mut sections = vec![Section::new("code", Kind::Rom0, compiled.assembly)];
+    sections.extend(link::variables(&compiled.layouts, &compiled.resolution));
+    let linked = link::link(&sections, &Header::default()).unwrap();
+    for symbol in [
+        "init",
+        "sprites__init",
+        "math__double",
+        "COUNT",
+        "sprites__COUNT",
+    ] {
+        assert!(linked.symbols.contains_key(symbol), "{}", symbol);
+    }
+    let mut cpu = Cpu::new(Flat::new(&linked.rom));
+    cpu.run(10_000).unwrap();
+    let layouts = &compiled.layouts;
+    let item = |name: &str| layouts.items().find(|item| item.name == name).unwrap();
+    assert_eq!(9, cpu.bus.memory[item("total").address]);
+    assert_eq!(0x80, cpu.bus.memory[item("on").address]);
+
+    // the source map tells the files apart
+    let source_map = debug::source_map(&linked.source_map, &context.files);
+    for line in [
+        " util/math.ggb:1:26-1:38",
+        " gfx/sprites.ggb:5:19-5:31",
+        " main.ggb:9:19-9:33",
+    ] {
+        assert!(source_map.contains(line), "{}\n{}", line, source_map);
+    }
+}
+
+#[test]
+fn namespaces() {
+    let mut context = Context::default();
+    let sources = load(
+        &[
+            (
+                "main.ggb",
+                "import \"lib.ggb\";
+use lib::hidden;
+use other::double;
+fn double() {}
+use lib::double;
+let x::u8 = triple(1);
+fn f() { use lib::double; }",
+            ),
+            (
+                "lib.ggb",
+                "fn double(x::u8) :: u8 { return x + x; }\nlet hidden::u8 = 0;\nfn triple() {}",
+            ),
+        ],
+        &mut context,
+    )
+    .unwrap();
+    let program = sources.parse(&mut context).unwrap();
+    resolve(&program, &mut context);
+    assert_eq!(
+        vec![
+            "main.ggb:2:10: error: no `hidden` in module `lib`",
+            "main.ggb:3:5: error: unresolved module `other`",
+            "main.ggb:5:10: error: `double` is defined multiple times
+  main.ggb:4:4: previous definition of `double` here",
+            "main.ggb:6:13: error: cannot find `triple` in this scope",
+            "main.ggb:7:10: error: `use` is only allowed at the top level of a file",
+        ],
+        errors(&context)
+    );
+}
+
+#[test]
+fn module_names() {
+    let a = "fn one() :: u8 { return 1; }";
+    let b = "fn two() :: u8 { return 2; }";
+    let mut context = Context::default();
+    let sources = load(
+        &[
+            (
+                "main.ggb",
+                "import \"a/util.ggb\";\nimport \"b/util.ggb\";\nimport \"./a/util.ggb\";",
+            ),
+            ("a/util.ggb", a),
+            ("b/util.ggb", b),
+        ],
+        &mut context,
+    )
+    .unwrap();
+    let program = sources.parse(&mut context).unwrap();
+    resolve(&program, &mut context);
+    // the third import loads the same file as the first one, which is fine
+    assert_eq!(
+        vec![
+            "main.ggb:2:8: error: module `util` is imported multiple times
+  main.ggb:1:8: previous import of `util` here"
+        ],
+        errors(&context)
+    );
+
+    // modules can be renamed
+    let main = "import \"a/util.ggb\";
+import \"b/util.ggb\" as other;
+use util::one;
+use other::two;
+let x::u8 = one() + two();";
+    let mut context = Context::default();
+    let sources = load(
+        &[("main.ggb", main), ("a/util.ggb", a), ("b/util.ggb", b)],
+        &mut context,
+    )
+    .unwrap();
+    let program = sources.parse(&mut context).unwrap();
+    let compiled = compile(program, &mut context);
+    let mut interpreter = interpreter(&compiled);
+    interpreter.run(1000).unwrap();
+    assert_eq!(Some(&[3][..]), interpreter.variable("x"));
+    // labels are still named after the files
+    assert_eq!(
+        Some(Some(2)),
+        interpreter.call("util_2__two", &[], 100).ok()
+    );
+    let formatted = gb_lang::fmt::format(&compiled.program, &Default::default());
+    assert!(
+        formatted.contains("import \"b/util.ggb\" as other;"),
+        "{}",
+        formatted
+    );
+}
+
+#[test]
+fn load_errors() {
+    let mut context = Context::default();
+    let sources = load(
+        &[
+            ("main.ggb", "import \"a.ggb\";\nimport \"missing.ggb\";"),
+            ("a.ggb", "import \"b.ggb\";"),
+            ("b.ggb", "import \"main.ggb\";\nimport \"b.ggb\";"),
+        ],
+        &mut context,
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            "b.ggb:1:8: error: cyclic import of `main.ggb`
+  main.ggb:1:8: `a.ggb` imported here
+  a.ggb:1:8: `b.ggb` imported here",
+            "b.ggb:2:8: error: cyclic import of `b.ggb`",
+            "main.ggb:2:8: error: cannot read `missing.ggb`: entity not found",
+        ],
+        errors(&context)
+    );
+    // the files of a cycle are still loaded, once
+    assert_eq!(3, context.files.len());
+    sources.parse(&mut context).unwrap();
+
+    // the root file must exist
+    let mut context = Context::default();
+    assert!(load(&[], &mut context).is_err());
+
+    // syntax errors are reported with the file they are in
+    let mut context = Context::default();
+    let sources = load(
+        &[
+            ("main.ggb", "import \"a.ggb\";"),
+            ("a.ggb", "let x::u8 = ;"),
+        ],
+        &mut context,
+    )
+    .unwrap();
+    assert_eq!(FileId(1), sources.parse(&mut context).unwrap_err().file);
+    assert_eq!(FileId(0), context.file);
+
+    // imports need the loader
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context("import \"a.ggb\";", &mut context).unwrap();
+    resolve(&program, &mut context);
+    assert_eq!(
+        vec!["1:8: error: unresolved import \"a.ggb\""],
+        errors(&context)
+    );
+}
+
+#[test]
+fn labels() {
+    let mut files = Files::default();
+    let main = files.add("main.ggb".into(), "main");
+    let util = files.add("a/util.ggb".into(), "util");
+    let other = files.add("b/util.ggb".into(), "util");
+    let dashed = files.add("my-lib.ggb".into(), "my-lib");
+    let digit = files.add("2d.ggb".into(), "2d");
+    assert_eq!("draw", files.label(main, "draw"));
+    assert_eq!("util__draw", files.label(util, "draw"));
+    assert_eq!("util_2__draw", files.label(other, "draw"));
+    assert_eq!("my_lib__draw", files.label(dashed, "draw"));
+    assert_eq!("_2d__draw", files.label(digit, "draw"));
+    assert_eq!("draw", files.label(FileId(9), "draw"));
+}
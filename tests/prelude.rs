@@ -0,0 +1,120 @@
+mod common;
+
+use common::compile_str;
+use gb_lang::{
+    interp::Interpreter,
+    link::{self, Kind, Section},
+    loader::Files,
+    memory::Region,
+    prelude::{self, HARDWARE},
+    rgbds,
+    rom::{Cartridge, Header},
+};
+
+#[test]
+fn hardware() {
+    let input = format!(
+        "{}
+static MODE::u8 = STAT & STAT_MODE;
+let enabled::u8 = LCDC & (LCDC_ON | LCDC_BG_ON);
+let interrupts::u8 = IE & INT_VBLANK;
+let sprite::Sprite = OAM[39];
+let tile::Tile = TILE_DATA[1];
+let fast::u8 = HRAM[2];",
+        HARDWARE
+    );
+    let compiled = compile_str(&input);
+    let layouts = &compiled.layouts;
+
+    let item = |name: &str| layouts.items().find(|item| item.name == name).unwrap();
+    assert_eq!(
+        (0xff44, Region::Io),
+        (item("LY").address, item("LY").region)
+    );
+    assert_eq!(
+        (0xffff, Region::Ie),
+        (item("IE").address, item("IE").region)
+    );
+    assert_eq!(
+        (0xfe00, 160),
+        (item("OAM").address, item("OAM").layout.size)
+    );
+    assert_eq!(0x1800, item("TILE_DATA").layout.size);
+    assert_eq!(0x9c00, item("TILE_MAP1").address);
+    assert_eq!(
+        0xff30..0xff40,
+        item("WAVE").address..item("WAVE").address + 16
+    );
+    assert_eq!(0xff50, item("BOOT").address);
+    assert_eq!(
+        (0xff80, 0x7d, Region::Hram),
+        (
+            item("HRAM").address,
+            item("HRAM").layout.size,
+            item("HRAM").region
+        )
+    );
+
+    let mut interpreter = Interpreter::new(
+        &compiled.program,
+        &compiled.resolution,
+        &compiled.consts,
+        &compiled.types,
+        layouts,
+    );
+    interpreter.memory[0xff40] = 0x91;
+    interpreter.memory[0xff41] = 0x85;
+    interpreter.memory[0xffff] = 0x03;
+    interpreter.memory[0x8010] = 0x7e;
+    interpreter.memory[0xff82] = 0x55;
+    interpreter.run(1000).unwrap();
+    assert_eq!(Some(&[0x01][..]), interpreter.variable("MODE"));
+    assert_eq!(Some(&[0x81][..]), interpreter.variable("enabled"));
+    assert_eq!(Some(&[0x01][..]), interpreter.variable("interrupts"));
+    assert_eq!(Some(0x7e), interpreter.variable("tile").map(|tile| tile[0]));
+    assert_eq!(Some(&[0x55][..]), interpreter.variable("fast"));
+
+    // registers don't take up memory of the program
+    let mut sections = vec![Section::new("code", Kind::Rom0, compiled.assembly)];
+    sections.extend(link::variables(layouts, &compiled.resolution));
+    assert_eq!(
+        [
+            "HRAM",
+            "MODE",
+            "enabled",
+            "interrupts",
+            "sprite",
+            "tile",
+            "fast"
+        ],
+        sections[1..]
+            .iter()
+            .map(|section| section.name.as_str())
+            .collect::<Vec<_>>()[..]
+    );
+    // the banking runtime keeps the current bank past the end of `HRAM`
+    let header = Header {
+        cartridge: Cartridge::Mbc5 {
+            ram: false,
+            battery: false,
+            rumble: false,
+        },
+        ..Default::default()
+    };
+    let linked = link::link(&sections, &header).unwrap();
+    assert_eq!(0xff80, linked.symbols["HRAM"].address);
+    assert_eq!(0xfffd, linked.symbols[link::CURRENT_BANK].address);
+
+    let constants = rgbds::constants(&compiled.program, &compiled.consts, &Files::default());
+    assert!(constants.contains("DEF LCDC_ON EQU 128\n"), "{}", constants);
+    assert!(constants.contains("DEF STAT_MODE EQU 3\n"));
+}
+
+#[test]
+fn modules() {
+    assert_eq!(Some(HARDWARE), prelude::module("hardware"));
+    assert_eq!(None, prelude::module("missing"));
+    assert!(prelude::MODULES
+        .iter()
+        .all(|(name, source)| prelude::module(name) == Some(*source)));
+}
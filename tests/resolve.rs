@@ -0,0 +1,248 @@
+use gb_lang::{
+    ast::{
+        expressions::Identifier,
+        statements::Statement,
+        visit::{Visit, Walk},
+        Context, NodeId, Program,
+    },
+    diagnostics::Diagnostic,
+    resolve::{resolve, DeclarationKind, Resolution},
+};
+
+// IDs of the identifier expressions, in source order.
+#[derive(Default)]
+struct Uses(Vec<NodeId>);
+
+impl<'input> Visit<'input> for Uses {
+    fn visit_identifier(&mut self, node: &Identifier<'input>) {
+        self.0.push(node.id);
+    }
+}
+
+fn resolve_str(input: &str) -> (Program<'_>, Resolution, Vec<Diagnostic>) {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    let resolution = resolve(&program, &mut context);
+    (program, resolution, context.diagnostics.take())
+}
+
+fn uses(program: &Program<'_>) -> Vec<NodeId> {
+    let mut uses = Uses::default();
+    program.accept(&mut uses);
+    uses.0
+}
+
+fn let_id(statement: &Statement<'_>) -> NodeId {
+    match statement {
+        Statement::Let(let_) => let_.id,
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn bind_uses() {
+    let (program, resolution, diagnostics) = resolve_str(
+        "let a::u8 = 1;
+         { let a::u8 = 2; let b::u8 = a; }
+         let c::u8 = a;",
+    );
+    assert!(diagnostics.is_empty());
+    let inner = match &program.statements[1] {
+        Statement::Scope(scope) => let_id(&scope.inner[0]),
+        _ => panic!(),
+    };
+    let outer = let_id(&program.statements[0]);
+    assert_eq!(vec![Some(inner), Some(outer)], {
+        let uses = uses(&program);
+        uses.iter()
+            .map(|u| resolution.binding(*u))
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(
+        DeclarationKind::Let,
+        resolution.declaration(outer).unwrap().kind
+    );
+}
+
+#[test]
+fn items_are_visible_from_the_whole_scope() {
+    let (program, resolution, diagnostics) =
+        resolve_str("let a::u8 = FOO; static FOO::u8 = BAR; const BAR::u8 = 1;");
+    assert!(diagnostics.is_empty());
+    let kinds: Vec<_> = uses(&program)
+        .into_iter()
+        .map(|u| {
+            resolution
+                .declaration(resolution.binding(u).unwrap())
+                .unwrap()
+                .kind
+        })
+        .collect();
+    assert_eq!(vec![DeclarationKind::Static, DeclarationKind::Const], kinds);
+}
+
+#[test]
+fn undefined() {
+    let (program, resolution, diagnostics) = resolve_str("if 1 { let a::u8 = 0; }\nlet b::u8 = a;");
+    assert_eq!(None, resolution.binding(uses(&program)[0]));
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "2:13: error: cannot find `a` in this scope",
+        diagnostics[0].to_string()
+    );
+}
+
+#[test]
+fn bodies_open_scopes() {
+    let (_, _, diagnostics) =
+        resolve_str("loop let a::u8 = 0;\nwhile 1 let b::u8 = a;\nif 1 {} else let c::u8 = b;");
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        vec![
+            "cannot find `a` in this scope",
+            "cannot find `b` in this scope"
+        ],
+        messages
+    );
+}
+
+#[test]
+fn use_before_declaration() {
+    let (_, _, diagnostics) = resolve_str("let a::u8 = b;\nlet b::u8 = b;");
+    assert_eq!(2, diagnostics.len());
+    assert_eq!(
+        "1:13: error: `b` used before its declaration\n  2:5: `b` declared here",
+        diagnostics[0].to_string()
+    );
+    assert_eq!("2:13: error: `b` used before its declaration", {
+        let message = diagnostics[1].to_string();
+        message.lines().next().unwrap().to_string()
+    });
+}
+
+#[test]
+fn shadowed_use_before_declaration() {
+    let (program, resolution, diagnostics) =
+        resolve_str("let a::u8 = 0; { let b::u8 = a; let a::u8 = 1; }");
+    assert!(diagnostics.is_empty());
+    assert_eq!(
+        Some(let_id(&program.statements[0])),
+        resolution.binding(uses(&program)[0])
+    );
+}
+
+#[test]
+fn duplicate() {
+    let (_, _, diagnostics) = resolve_str("static a::u8;\nlet a::u8 = 0;\n{ let a::u8 = a; }");
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "2:5: error: `a` is defined multiple times\n  1:8: previous definition of `a` here",
+        diagnostics[0].to_string()
+    );
+}
+
+#[test]
+fn fn_params() {
+    let (program, resolution, diagnostics) =
+        resolve_str("let b::u8 = f(1);\nfn f(a::u8) :: u8 { return a + b; }\nlet c::u8 = a;");
+    let uses = uses(&program);
+    let param = match &program.statements[1] {
+        Statement::Fn(fn_) => fn_.params.iter().next().unwrap().id,
+        _ => panic!(),
+    };
+    assert_eq!(
+        Some(DeclarationKind::Fn),
+        resolution
+            .binding(uses[0])
+            .and_then(|id| resolution.declaration(id))
+            .map(|d| d.kind)
+    );
+    assert_eq!(Some(param), resolution.binding(uses[1]));
+    assert_eq!(
+        Some(let_id(&program.statements[0])),
+        resolution.binding(uses[2])
+    );
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "3:13: error: cannot find `a` in this scope",
+        diagnostics[0].to_string()
+    );
+}
+
+#[test]
+fn type_aliases() {
+    use gb_lang::ast::{types::Named, visit};
+
+    #[derive(Default)]
+    struct Names(Vec<NodeId>);
+
+    impl<'input> Visit<'input> for Names {
+        fn visit_named(&mut self, node: &Named<'input>) {
+            self.0.push(node.id);
+            visit::walk_named(self, node);
+        }
+    }
+
+    let (program, resolution, diagnostics) =
+        resolve_str("static a::Node;\ntype Node = struct { next::ptr<Node> };\nstatic b::Sprite;");
+    let mut names = Names::default();
+    program.accept(&mut names);
+    let alias = match &program.statements[1] {
+        Statement::TypeAlias(alias) => alias.id,
+        _ => panic!(),
+    };
+    assert_eq!(Some(alias), resolution.binding(names.0[0]));
+    assert_eq!(Some(alias), resolution.binding(names.0[1]));
+    assert_eq!(
+        Some(DeclarationKind::Type),
+        resolution.declaration(alias).map(|d| d.kind)
+    );
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "3:11: error: cannot find `Sprite` in this scope",
+        diagnostics[0].to_string()
+    );
+}
+
+#[test]
+fn asm_names() {
+    let (program, resolution, diagnostics) = resolve_str(
+        "static IO::struct { LY::u8 };
+fn wait() {
+    asm {
+    wait:
+        ldh a, [IO.LY]
+        cp LINES
+        jr nz, wait
+        call missing
+    }
+}
+const LINES::u8 = 144;",
+    );
+    let asm = match &program.statements[1] {
+        Statement::Fn(fn_) => match &fn_.body.inner[0] {
+            Statement::Asm(asm) => asm.id,
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    let kinds: Vec<_> = (0..3)
+        .map(|i| {
+            let id = resolution.asm_binding(asm, i)?;
+            Some(resolution.declaration(id)?.kind)
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            Some(DeclarationKind::Static),
+            Some(DeclarationKind::Const),
+            None
+        ],
+        kinds
+    );
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(
+        "8:14: error: cannot find `missing` in this scope",
+        diagnostics[0].to_string()
+    );
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use common::compile_str;
+use gb_lang::{
+    asm::parse::parse,
+    ast::Context,
+    codegen::scratch,
+    compile_program,
+    link::{self, Kind, Section},
+    loader::{self, Files},
+    rgbds::{constants, export},
+};
+use std::{collections::HashMap, io, path::Path};
+
+#[test]
+fn export_program() {
+    let input = "static COUNT::u8 = 0;
+const STEP::u8 = 2;
+fn main() {
+    asm {
+        ld b, 2
+    wait:
+        dec b
+        jr nz, wait
+    }
+    let next::u8 = COUNT + STEP;
+}";
+    let compiled = compile_str(input);
+    let mut sections = vec![Section::new("code", Kind::Rom0, compiled.assembly)];
+    sections.extend(link::variables(&compiled.layouts, &compiled.resolution));
+    sections.extend(link::scratch(&compiled.ir, &compiled.layouts));
+
+    // the label of the inline assembly is renamed into the scope of `main`
+    let expected = r#"SECTION "code", ROM0
+__init::
+    ld sp, $E000
+    call __start
+__halt::
+    halt
+    jp __halt
+__start::
+    ld a, $00
+    ld [$C000], a
+    ret
+main::
+    ld b, $02
+main.__asm0_wait:
+    dec b
+    jr nz, main.__asm0_wait
+    ld a, [$C000]
+    ld b, a
+    add a, $02
+    ld b, a
+    ld [$C001], a
+    ret
+
+SECTION "COUNT", WRAM0[$C000]
+COUNT::
+    ds 1
+
+SECTION "next", WRAM0[$C001]
+    ds 1
+
+SECTION "__stack", WRAMX[$DF00], BANK[1]
+    ds 256
+"#;
+    assert_eq!(expected, export(&sections));
+}
+
+#[test]
+fn export_sections() {
+    let sections = [
+        Section::new("vectors", Kind::Rom0, parse("    reti").unwrap()).at(0x40),
+        Section::new(
+            "data",
+            Kind::Romx,
+            parse("table:\n    db $01, $02").unwrap(),
+        )
+        .in_bank(3)
+        .at(0x4000),
+        Section::new("data", Kind::Romx, parse("    db $03").unwrap()),
+        Section::new("hram", Kind::Hram, parse("flag:\n    db $00").unwrap()).in_bank(0),
+        Section::new("save", Kind::Sram, parse("    db $00, $00").unwrap()).in_bank(1),
+    ];
+    let expected = r#"SECTION "vectors", ROM0[$0040]
+    reti
+
+SECTION "data", ROMX[$4000], BANK[3]
+table::
+    db $01, $02
+
+SECTION "data.1", ROMX
+    db $03
+
+SECTION "hram", HRAM
+flag::
+    ds 1
+
+SECTION "save", SRAM, BANK[1]
+    ds 2
+"#;
+    assert_eq!(expected, export(&sections));
+}
+
+#[test]
+fn rename_labels() {
+    let assembly = parse(
+        "outer.first:
+    jp main.loop
+main:
+main.loop:
+    jp other.loop
+main.a.b:
+    jr outer.first",
+    )
+    .unwrap();
+    let other = parse("other.loop:\n    jp main.a.b").unwrap();
+    let sections = [
+        Section::new("main", Kind::Rom0, assembly),
+        Section::new("other", Kind::Rom0, other),
+    ];
+    let expected = r#"SECTION "main", ROM0
+outer_first::
+    jp main.loop
+main::
+main.loop:
+    jp other_loop
+main.main_a_b:
+    jr outer_first
+
+SECTION "other", ROM0
+other_loop::
+    jp main.main_a_b
+"#;
+    assert_eq!(expected, export(&sections));
+}
+
+#[test]
+fn export_constants() {
+    let input = "const WIDTH::u8 = 20;
+const AREA::u16 = WIDTH as u16 * HEIGHT as u16;
+const HEIGHT::u8 = 18;
+const OFFSET::i8 = -4;
+fn main() {
+    const WIDTH::u8 = 1;
+    const LOCAL::u16 = 0x1234;
+}";
+    let compiled = compile_str(input);
+    let expected = "DEF WIDTH EQU 20
+DEF AREA EQU 360
+DEF HEIGHT EQU 18
+DEF OFFSET EQU -4
+DEF LOCAL EQU 4660
+";
+    assert_eq!(
+        expected,
+        constants(&compiled.program, &compiled.consts, &Files::default())
+    );
+}
+
+#[test]
+fn export_module_constants() {
+    let files = HashMap::from([
+        (
+            "main.ggb",
+            "import \"gfx.ggb\";\nconst WIDTH::u8 = 20;\nlet a::u8 = WIDTH;",
+        ),
+        ("gfx.ggb", "const WIDTH::u8 = 160;"),
+    ]);
+    let mut context = Context::default();
+    let sources = loader::load_with(Path::new("main.ggb"), &mut context, |path| {
+        let file = files.get(path.to_str().unwrap());
+        file.map(|file| file.to_string())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    })
+    .unwrap();
+    let program = sources.parse(&mut context).unwrap();
+    let compiled = compile_program(program, &mut context).unwrap();
+    // the consts of imported modules don't clash with the ones of the root file
+    assert_eq!(
+        "DEF gfx__WIDTH EQU 160\nDEF WIDTH EQU 20\n",
+        constants(&compiled.program, &compiled.consts, &context.files)
+    );
+}
+
+#[test]
+fn scratch_sections() {
+    let input = "static TOTAL::u16 = 0;
+fn add(a::u8, b::u8) :: u8 { return a + b; }
+fn main() { let sum::u8 = add(1, 2); }";
+    let compiled = compile_str(input);
+    let range = scratch(&compiled.ir, &compiled.layouts);
+    let end = compiled
+        .layouts
+        .items()
+        .map(|item| item.address + item.layout.size)
+        .max();
+    assert_eq!(end, Some(range.start));
+    assert!(range.len() >= 2, "{:?}", range);
+
+    let sections = link::scratch(&compiled.ir, &compiled.layouts);
+    assert_eq!(2, sections.len());
+    assert_eq!("__scratch", sections[0].name);
+    assert_eq!(Kind::Wram0, sections[0].kind);
+    assert_eq!(Some(range.start as u16), sections[0].address);
+    assert_eq!(range.len(), sections[0].size());
+    assert_eq!("__stack", sections[1].name);
+    assert_eq!(
+        (Kind::Wramx, Some(0xdf00)),
+        (sections[1].kind, sections[1].address)
+    );
+    assert_eq!(0x100, sections[1].size());
+}
@@ -0,0 +1,240 @@
+mod common;
+
+use common::compile_str;
+use gb_lang::{
+    asm::{encode, parse::parse},
+    rom::{self, build, verify, Cartridge, Cgb, Error, Header, RamSize},
+};
+
+#[test]
+fn build_program() {
+    let assembly = compile_str(
+        "static COUNTER::u8 = 0;
+        fn main() {
+            loop { let next::u8 = COUNTER + 1; }
+        }",
+    )
+    .assembly;
+    let header = Header {
+        title: "COUNTER".to_string(),
+        ..Default::default()
+    };
+    let image = build(&assembly, &header).unwrap();
+    assert_eq!(Ok(()), verify(&image.bytes));
+    assert_eq!(0x8000, image.bytes.len());
+
+    // entry point and logo
+    assert_eq!([0x00, 0xc3, 0x50, 0x01], image.bytes[0x100..0x104]);
+    assert_eq!(rom::LOGO, image.bytes[0x104..0x134]);
+    // the program starts right after the header
+    assert_eq!(Some(&0x150), image.labels.get("__init"));
+    assert!(image.labels.contains_key("main"));
+    assert_eq!(0x31, image.bytes[0x150], "ld sp, $E000");
+    // padded to the end of the bank
+    assert_eq!(rom::PADDING, *image.bytes.last().unwrap());
+}
+
+#[test]
+fn header_fields() {
+    let assembly = parse("halt").unwrap();
+    let header = Header {
+        title: "ABCDEFGHIJKLMNOP".to_string(),
+        cartridge: Cartridge::Mbc5 {
+            ram: true,
+            battery: true,
+            rumble: false,
+        },
+        ram_size: RamSize::Kib32,
+        japanese: true,
+        version: 2,
+        ..Default::default()
+    };
+    let bytes = build(&assembly, &header).unwrap().bytes;
+    assert_eq!(b"ABCDEFGHIJKLMNOP", &bytes[0x134..0x144]);
+    assert_eq!([0x00, 0x00, 0x00], bytes[0x144..0x147], "licensee, SGB");
+    assert_eq!(0x1b, bytes[0x147]);
+    assert_eq!(0x00, bytes[0x148], "32 KiB");
+    assert_eq!(0x03, bytes[0x149]);
+    assert_eq!(0x00, bytes[0x14a], "Japanese");
+    assert_eq!(0x02, bytes[0x14c]);
+    assert_eq!(rom::header_checksum(&bytes), bytes[0x14d]);
+    let global = rom::global_checksum(&bytes);
+    assert_eq!(global.to_be_bytes(), bytes[0x14e..0x150]);
+    assert_eq!(0x76, bytes[0x150], "halt");
+
+    let header = Header {
+        title: "GAME".to_string(),
+        cgb: Cgb::Compatible,
+        ..Default::default()
+    };
+    let bytes = build(&assembly, &header).unwrap().bytes;
+    assert_eq!(b"GAME\0\0\0\0\0\0\0\0\0\0\0", &bytes[0x134..0x143]);
+    assert_eq!(0x80, bytes[0x143]);
+    assert_eq!(0x01, bytes[0x14a], "overseas");
+}
+
+#[test]
+fn header_checksum() {
+    // the header checksum of a ROM with an empty title and a zeroed header
+    let mut bytes = vec![0; 0x8000];
+    assert_eq!(0xe7, rom::header_checksum(&bytes));
+    bytes[0x134] = 1;
+    assert_eq!(0xe6, rom::header_checksum(&bytes));
+    // the checksums themselves are left out of the global one
+    bytes[0x14d] = 0xff;
+    bytes[0x14e] = 0xff;
+    bytes[0x14f] = 0xff;
+    assert_eq!(0x01 + 0xff, rom::global_checksum(&bytes));
+}
+
+#[test]
+fn cartridge_codes() {
+    let mbc3 = |ram, battery, timer| Cartridge::Mbc3 {
+        ram,
+        battery,
+        timer,
+    };
+    let mbc5 = |ram, battery, rumble| Cartridge::Mbc5 {
+        ram,
+        battery,
+        rumble,
+    };
+    let codes = [
+        (Cartridge::RomOnly, 0x00),
+        (
+            Cartridge::Mbc1 {
+                ram: false,
+                battery: true,
+            },
+            0x01,
+        ),
+        (
+            Cartridge::Mbc1 {
+                ram: true,
+                battery: false,
+            },
+            0x02,
+        ),
+        (
+            Cartridge::Mbc1 {
+                ram: true,
+                battery: true,
+            },
+            0x03,
+        ),
+        (mbc3(false, true, true), 0x0f),
+        (mbc3(true, true, true), 0x10),
+        (mbc3(false, false, false), 0x11),
+        (mbc3(true, false, false), 0x12),
+        (mbc3(true, true, false), 0x13),
+        (mbc5(false, false, false), 0x19),
+        (mbc5(true, false, false), 0x1a),
+        (mbc5(true, true, false), 0x1b),
+        (mbc5(false, true, true), 0x1c),
+        (mbc5(true, false, true), 0x1d),
+        (mbc5(true, true, true), 0x1e),
+    ];
+    for (cartridge, code) in codes {
+        assert_eq!(Some(code), cartridge.code(), "{:?}", cartridge);
+    }
+    // the timer needs the battery
+    assert_eq!(None, mbc3(false, false, true).code());
+    assert_eq!(None, mbc3(true, false, true).code());
+    assert_eq!(0, rom::size_code(0x8000));
+    assert_eq!(1, rom::size_code(0x10000));
+    assert_eq!(8, rom::size_code(0x80_0000));
+}
+
+#[test]
+fn build_errors() {
+    let assembly = parse("halt").unwrap();
+    let header = Header {
+        title: "ABCDEFGHIJKLMNOP".to_string(),
+        cgb: Cgb::Only,
+        ..Default::default()
+    };
+    let error = build(&assembly, &header).unwrap_err();
+    assert_eq!(
+        "invalid title `ABCDEFGHIJKLMNOP` (expected at most 15 printable ASCII characters)",
+        error.to_string()
+    );
+    let header = Header {
+        title: "caf\u{e9}".to_string(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        build(&assembly, &header),
+        Err(Error::InvalidTitle { max: 16, .. })
+    ));
+
+    let assembly = parse("jp missing").unwrap();
+    assert_eq!(
+        Err(Error::Assemble(encode::Error::UndefinedLabel(
+            "missing".to_string()
+        ))),
+        build(&assembly, &Header::default())
+    );
+
+    let mut assembly = parse("halt").unwrap();
+    assembly
+        .lines
+        .extend(parse(&"db $00\n".repeat(0x8000)).unwrap().lines);
+    let error = build(&assembly, &Header::default()).unwrap_err();
+    assert_eq!(Error::TooLarge(0x8151), error);
+    assert_eq!(
+        "program ends at $8151, past the end of the ROM banks at $0000..=$7FFF",
+        error.to_string()
+    );
+
+    let cartridge = Cartridge::Mbc3 {
+        ram: true,
+        battery: false,
+        timer: true,
+    };
+    let header = Header {
+        cartridge,
+        ..Default::default()
+    };
+    let error = build(&parse("halt").unwrap(), &header).unwrap_err();
+    assert_eq!(Error::UnsupportedCartridge(cartridge), error);
+    assert_eq!(
+        "no cartridge type has the hardware of Mbc3 { ram: true, battery: false, timer: true }",
+        error.to_string()
+    );
+}
+
+#[test]
+fn verify_errors() {
+    let assembly = parse("halt").unwrap();
+    let bytes = build(&assembly, &Header::default()).unwrap().bytes;
+
+    assert_eq!(Err(Error::Truncated(0x100)), verify(&bytes[..0x100]));
+
+    let mut logo = bytes.clone();
+    logo[0x110] ^= 1;
+    assert_eq!(Err(Error::InvalidLogo), verify(&logo));
+
+    let mut title = bytes.clone();
+    title[0x134] = b'X';
+    assert!(matches!(verify(&title), Err(Error::HeaderChecksum { .. })));
+
+    let mut code = bytes.clone();
+    code[0x150] = 0x00;
+    assert_eq!(
+        Err(Error::GlobalChecksum {
+            expected: rom::global_checksum(&bytes) - 0x76,
+            found: rom::global_checksum(&bytes),
+        }),
+        verify(&code)
+    );
+
+    let mut size = bytes.clone();
+    size.extend(vec![rom::PADDING; 0x8000]);
+    assert_eq!(
+        Err(Error::InvalidSize {
+            code: 0,
+            size: 0x10000
+        }),
+        verify(&size)
+    );
+}
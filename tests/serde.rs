@@ -0,0 +1,66 @@
+#![cfg(feature = "serde")]
+
+use gb_lang::{
+    ast::{statements::Statement, Program},
+    lex::Token,
+    Span,
+};
+
+#[test]
+fn json_span() {
+    let span = Span {
+        min: [1, 2],
+        max: [3, 4],
+        ..Span::default()
+    };
+    let json = serde_json::to_string(&span).unwrap();
+    assert_eq!(r#"{"min":[1,2],"max":[3,4]}"#, json);
+    assert_eq!(span, serde_json::from_str(&json).unwrap());
+}
+
+#[test]
+fn json_tokens() {
+    let tokens: Vec<Token> = gb_lang::tokenize("let foo").map(Result::unwrap).collect();
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert!(json.starts_with(r#"[{"Let":{"text":"let","span":{"min":[1,1],"max":[1,3]}}}"#));
+    let tokens_de: Vec<Token> = serde_json::from_str(&json).unwrap();
+    assert_eq!(tokens, tokens_de);
+}
+
+#[test]
+fn json_program_round_trip() {
+    let program = gb_lang::parse::<Program>(include_str!("../example.ggb")).unwrap();
+    let json = serde_json::to_string(&program).unwrap();
+    let program_de: Program = serde_json::from_str(&json).unwrap();
+    let options = Default::default();
+    assert_eq!(
+        gb_lang::fmt::format(&program, &options),
+        gb_lang::fmt::format(&program_de, &options)
+    );
+}
+
+#[test]
+fn sexp_statements() {
+    let sexp =
+        |input| gb_lang::sexp::to_string(&gb_lang::parse::<Statement>(input).unwrap()).unwrap();
+    assert_eq!("(Loop loop [(Break break ;)])", sexp("loop break;"));
+    assert_eq!(
+        "(If if (Number 1) [(Scope { [] })] (Else else [(Scope { [(Continue continue ;)] })]))",
+        sexp("if 1 {} else { continue; }")
+    );
+    assert_eq!(
+        "(Static static IO :: (Struct struct { (Punctuated [(Field A :: (U8 u8)) , (Field B :: (Ptr ptr < (U8 u8) >))]) }) ;)",
+        sexp("static IO::struct { A::u8, B::ptr<u8> };")
+    );
+}
+
+#[test]
+fn sexp_tokens_and_programs() {
+    let tokens: Vec<Token> = gb_lang::tokenize("let a").map(Result::unwrap).collect();
+    assert_eq!("[let a \"\"]", gb_lang::sexp::to_string(&tokens).unwrap());
+    let program = gb_lang::parse::<Program>("const A::u8 = 1;\nA = 2;").unwrap();
+    assert_eq!(
+        "(Program [(Const const A :: (U8 u8) = (Number 1) ;) (Assign (Identifier A) = (Number 2) ;)] \"\")",
+        gb_lang::sexp::to_string(&program).unwrap()
+    );
+}
@@ -0,0 +1,339 @@
+use gb_lang::{
+    ast::{statements::Statement, types::Type, Context, Program},
+    consts::evaluate,
+    diagnostics::Diagnostic,
+    resolve::resolve,
+    typeck::{check, Ty, Types},
+};
+
+fn check_str(input: &str) -> (Program<'_>, Types, Vec<Diagnostic>) {
+    let mut context = Context::default();
+    let program = gb_lang::ast::parse_with_context(input, &mut context).unwrap();
+    let resolution = resolve(&program, &mut context);
+    let consts = evaluate(&program, &resolution, &mut context);
+    let types = check(&program, &resolution, &consts, &mut context);
+    (program, types, context.diagnostics.take())
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn example() {
+    let input = include_str!("../example.ggb");
+    let (_, _, diagnostics) = check_str(input);
+    assert_eq!(Vec::<String>::new(), messages(&diagnostics));
+}
+
+#[test]
+fn expression_types() {
+    let (program, types, diagnostics) = check_str(
+        "static a::array<ptr<u8>, 4>;
+         let b::u8 = deref(a[1]) + 2;
+         let c::ptr<array<ptr<u8>, 4>> = addr(a);",
+    );
+    assert!(diagnostics.is_empty());
+    let ty = |statement: &Statement| match statement {
+        Statement::Let(let_) => types.expression(let_.expression.id()).cloned(),
+        _ => panic!(),
+    };
+    assert_eq!(Some(Ty::U8), ty(&program.statements[1]));
+    assert_eq!(
+        "ptr<array<ptr<u8>, 4>>",
+        ty(&program.statements[2]).unwrap().to_string()
+    );
+}
+
+#[test]
+fn initializer_mismatch() {
+    let (_, _, diagnostics) = check_str(
+        "let a::u8 = \"foo\";
+static b::array<u8, 6> = \"german\";
+static c::array<u8, 2> = \"german\";
+static d::ptr<u8> = addr(b);
+static e @ true :: u8;",
+    );
+    assert_eq!(
+        vec![
+            "1:13: error: mismatched types: expected `u8`, found `array<u8, 3>`\n  1:8: expected due to this type",
+            "3:26: error: string literal of 6 bytes doesn't fit in `array<u8, 2>`\n  3:11: expected due to this type",
+            "4:21: error: mismatched types: expected `ptr<u8>`, found `ptr<array<u8, 6>>`\n  4:11: expected due to this type",
+            "5:12: error: mismatched types: expected an integer, found `bool`\n  5:10: expected due to this placement",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn operands() {
+    let (_, _, diagnostics) = check_str(
+        "let a::array<u8, 2> = \"hi\";
+let b::u8 = a * 2;
+let c::u8 = b[0];
+let d::u8 = deref(b);
+let e::ptr<u8> = addr(4);
+let f::u8 = a[a];",
+    );
+    assert_eq!(
+        vec![
+            "2:13: error: mismatched types: expected an integer, found `array<u8, 2>`\n  2:15: arithmetic operands must be integers",
+            "3:13: error: cannot index into a value of type `u8`\n  3:14: indexed here",
+            "4:19: error: cannot dereference a value of type `u8`\n  4:13: dereferenced here",
+            "5:23: error: cannot take the address of a value that isn't stored in memory\n  5:18: address taken here",
+            "6:15: error: mismatched types: expected `u8` or `u16`, found `array<u8, 2>`\n  6:13: indexed value",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn comparisons() {
+    let (program, types, diagnostics) = check_str(
+        "let a::u8 = 1;
+let b::bool = a < 2;
+let c::bool = b == true;
+let d::bool = b < true;
+let e::bool = a ~= 300;
+let f::bool = a >= \"hi\";",
+    );
+    let ty = |statement: &Statement| match statement {
+        Statement::Let(let_) => types.expression(let_.expression.id()).cloned(),
+        _ => panic!(),
+    };
+    assert_eq!(Some(Ty::Bool), ty(&program.statements[1]));
+    assert_eq!(Some(Ty::Bool), ty(&program.statements[2]));
+    assert_eq!(
+        vec![
+            "4:15: error: mismatched types: expected an integer, found `bool`\n  4:17: ordered operands must be integers",
+            "5:20: error: literal out of range for `u8`",
+            "6:20: error: mismatched types: expected an integer, found `array<u8, 2>`\n  6:17: ordered operands must be integers",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn conditions() {
+    let (_, _, diagnostics) = check_str("let a::array<u8, 2> = \"hi\";\nwhile a {}");
+    assert_eq!(
+        vec!["2:7: error: mismatched types: expected `bool` or an integer, found `array<u8, 2>`\n  2:1: condition of this `while`"],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn calls() {
+    let (_, _, diagnostics) = check_str(
+        "fn f(a::u8, b::ptr<u8>) :: u8 { return b; }
+fn g() { return 1; }
+fn h() :: u8 { return; }
+let a::u8 = f(1);
+let b::u8 = g();
+let c::u8 = f(a, addr(a)) + f(1, 2);",
+    );
+    assert_eq!(
+        vec![
+            "1:40: error: mismatched types: expected `u8`, found `ptr<u8>`\n  1:28: expected due to this return type",
+            "2:17: error: returned a value from a function that doesn't return one\n  2:4: function declared here",
+            "3:16: error: expected a return value of type `u8`\n  3:11: expected due to this return type",
+            "4:13: error: this function takes 2 arguments but 1 was supplied\n  4:13: called here",
+            "5:13: error: mismatched types: expected `u8`, found `()`\n  5:8: expected due to this type",
+            "6:34: error: mismatched types: expected `ptr<u8>`, found `u8`\n  6:29: argument of this call",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn widths() {
+    let (program, types, diagnostics) = check_str(
+        "let a::u8 = 1;
+let b::u16 = 0x100 + a as u16;
+let c::u16 = a + 1;
+let d::i8 = 1 + ~a as i8;
+let e::bool = true & (b as bool);
+let f::u8 = a + b;
+let g::i8 = 200;
+let h::ptr<u8> = b as ptr<u8>;
+let i::u8 = e + 1;
+let j::u8 = addr(a) as u8;",
+    );
+    let ty = |i: usize| match &program.statements[i] {
+        Statement::Let(let_) => types.expression(let_.expression.id()).cloned(),
+        _ => panic!(),
+    };
+    assert_eq!(Some(Ty::U16), ty(1));
+    assert_eq!(Some(Ty::I8), ty(3));
+    assert_eq!(Some(Ty::Bool), ty(4));
+    assert_eq!(
+        vec![
+            "3:14: error: mismatched types: expected `u16`, found `u8`\n  3:8: expected due to this type",
+            "6:17: error: mismatched types: expected `u8`, found `u16`\n  6:15: operands must have the same type (use `as` to convert)",
+            "7:13: error: literal out of range for `i8`",
+            "9:13: error: mismatched types: expected an integer, found `bool`\n  9:15: arithmetic operands must be integers",
+            "10:13: error: cannot cast `ptr<u8>` as `u8`\n  10:21: invalid cast",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn negation() {
+    let (program, types, diagnostics) = check_str(
+        "let a::i8 = -128;
+let b::i8 = -129;
+let c::u8 = -1;
+let d::i16 = -a as i16;
+let e::u8 = 1;
+let f::u8 = -e;
+let g::i16 = 1 - -1000;
+let h::bool = -1 < a;",
+    );
+    let ty = |i: usize| match &program.statements[i] {
+        Statement::Let(let_) => types.expression(let_.expression.id()).cloned(),
+        _ => panic!(),
+    };
+    assert_eq!(Some(Ty::I8), ty(0));
+    assert_eq!(Some(Ty::I16), ty(3));
+    assert_eq!(Some(Ty::I16), ty(6));
+    assert_eq!(
+        vec![
+            "2:13: error: literal out of range for `i8`",
+            "3:13: error: literal out of range for `u8`",
+            "6:14: error: mismatched types: expected a signed integer, found `u8`\n  6:13: negated operands must be signed integers",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn assignments() {
+    let (_, _, diagnostics) = check_str(
+        "static A::u8;
+let b::array<u8, 4> = \"abc\";
+fn f(p::ptr<u16>) { A = 1; b[A] = A + 1; deref(p) = 300; p[1] = 2; b = \"xy\"; (A) = 3; }
+const C::u8 = 1;
+C = 2;
+f = 3;
+A + 1 = 4;
+A = true;
+b = \"hello\";",
+    );
+    assert_eq!(
+        vec![
+            "5:1: error: cannot assign to a value that isn't stored in memory\n  5:3: assigned here",
+            "6:1: error: cannot assign to a value that isn't stored in memory\n  6:3: assigned here",
+            "6:5: error: mismatched types: expected `fn(ptr<u16>)`, found `u8`\n  6:1: expected due to this place",
+            "7:1: error: cannot assign to a value that isn't stored in memory\n  7:7: assigned here",
+            "8:5: error: mismatched types: expected `u8`, found `bool`\n  8:1: expected due to this place",
+            "9:5: error: string literal of 5 bytes doesn't fit in `array<u8, 4>`\n  9:1: expected due to this place",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn field_accesses() {
+    let (_, _, diagnostics) = check_str(
+        "type Point = struct { x::u8, y::i16 };
+static P::array<Point, 2>;
+let a::i16 = P[1].y;
+let b::u8 = P[0].x + a;
+let c::u8 = P.x;
+let d::u8 = a.x;
+P[0].x = 1;
+P[1] = P[0];
+P[1].z = 2;",
+    );
+    assert_eq!(
+        vec![
+            "4:22: error: mismatched types: expected `u8`, found `i16`\n  4:20: operands must have the same type (use `as` to convert)",
+            "5:15: error: no field `x` on type `array<struct { x::u8, y::i16 }, 2>`\n  5:13: accessed value",
+            "6:15: error: no field `x` on type `i16`\n  6:13: accessed value",
+            "9:6: error: no field `z` on type `struct { x::u8, y::i16 }`\n  9:1: accessed value",
+        ],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn array_lengths() {
+    let (program, types, diagnostics) = check_str(
+        "const N::u8 = 2;
+let a::array<u8, N * 2> = \"abc\";
+let b::array<u8, true> = \"\";",
+    );
+    assert_eq!(
+        vec!["3:18: error: mismatched types: expected an integer, found `bool`\n  3:8: expected due to this array type"],
+        messages(&diagnostics)
+    );
+    let length = match &program.statements[1] {
+        Statement::Let(let_) => match &let_.type_ {
+            Type::Array(array) => array.length.id(),
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    assert_eq!("u8", types.expression(length).unwrap().to_string());
+}
+
+#[test]
+fn type_aliases() {
+    let (program, types, diagnostics) = check_str(
+        "type Sprite = struct { y::u8, x::u8, tile::u8, flags::u8 };
+type Sprites = array<Sprite, 40>;
+static OAM @ 0xfe00 :: Sprites;
+let a::struct { y::u8, x::u8, tile::u8, flags::u8 } = OAM[0];
+let b::u8 = Sprite;",
+    );
+    assert_eq!(
+        vec!["5:13: error: expected value, found type `Sprite`"],
+        messages(&diagnostics)
+    );
+    let id = match &program.statements[2] {
+        Statement::Static(static_) => static_.id,
+        _ => panic!(),
+    };
+    assert_eq!(
+        "array<struct { y::u8, x::u8, tile::u8, flags::u8 }, 40>",
+        types.declaration(id).unwrap().to_string()
+    );
+}
+
+#[test]
+fn recursive_types() {
+    let (_, _, diagnostics) = check_str(
+        "type Node = struct { value::u8, next::ptr<Node> };
+type List = ptr<Node>;
+static a::Node;
+let b::List = addr(a);
+let c::ptr<struct { value::u8, next::ptr<Node> }> = b;
+let d::Node = deref(b);
+let e::ptr<u8> = b;",
+    );
+    assert_eq!(
+        vec!["7:18: error: mismatched types: expected `ptr<u8>`, found `ptr<Node>`\n  7:8: expected due to this type"],
+        messages(&diagnostics)
+    );
+}
+
+#[test]
+fn infinite_types() {
+    let (_, _, diagnostics) = check_str(
+        "type A = struct { b::B };
+type B = array<A, 2>;
+type C = C;
+type D = struct { d::ptr<D> };
+let x::u8 = 1;
+type E = x;",
+    );
+    assert_eq!(
+        vec![
+            "1:6: error: recursive type `A` has infinite size\n  2:16: recursive without indirection (use a `ptr<...>`)",
+            "3:6: error: recursive type `C` has infinite size\n  3:10: recursive without indirection (use a `ptr<...>`)",
+            "6:10: error: expected type, found `x`\n  5:5: `x` declared here",
+        ],
+        messages(&diagnostics)
+    );
+}
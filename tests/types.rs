@@ -1,4 +1,22 @@
+use gb_lang::ast::types::{Struct, Type, Union};
+
 #[test]
 fn parse_struct() {
+    let struct_ = gb_lang::parse::<Struct>("struct { a::u8, b::array<u8, 2>, }").unwrap();
+    assert_eq!(2, struct_.fields.len());
+    assert!(struct_.fields.trailing_punct());
+    let struct_ = gb_lang::parse::<Struct>("struct { a::struct { b::u8 } }").unwrap();
+    assert!(matches!(
+        struct_.fields.iter().next().unwrap().type_,
+        Type::Struct(_)
+    ));
+    gb_lang::parse::<Struct>("struct {}").unwrap();
+}
 
-}
\ No newline at end of file
+#[test]
+fn parse_union() {
+    let union = gb_lang::parse::<Union>("union { a::u8, b::ptr<u8> }").unwrap();
+    assert_eq!(2, union.fields.len());
+    assert!(!union.fields.trailing_punct());
+    gb_lang::parse::<Type>("union { a::union { b::u8, }, }").unwrap();
+}
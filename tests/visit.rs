@@ -0,0 +1,87 @@
+use gb_lang::ast::{
+    expressions::{ExpressionGrammar, Identifier, Number},
+    statements::{Let, Statement},
+    types::{TypeGrammar, U8},
+    visit,
+    visit::{Visit, VisitMut, Walk},
+    TokenGrammar,
+};
+
+#[derive(Default)]
+struct Trace(Vec<&'static str>);
+
+impl<'input> Visit<'input> for Trace {
+    fn visit_token<T: TokenGrammar<'input>>(&mut self, _: &T) {
+        self.0.push("token");
+    }
+
+    fn visit_let<T, E>(&mut self, node: &Let<'input, T, E>)
+    where
+        T: TypeGrammar<'input>,
+        E: ExpressionGrammar<'input>,
+    {
+        self.0.push("let");
+        visit::walk_let(self, node);
+    }
+
+    fn visit_u8(&mut self, node: &U8<'input>) {
+        self.0.push("u8");
+        visit::walk_u8(self, node);
+    }
+
+    fn visit_number(&mut self, _: &Number<'input>) {
+        self.0.push("number");
+    }
+
+    fn visit_identifier(&mut self, _: &Identifier<'input>) {
+        self.0.push("identifier");
+    }
+}
+
+#[test]
+fn visit_generic_node() {
+    let let_ = gb_lang::parse::<Let<U8, Number>>("let a::u8 = 1;").unwrap();
+    let mut trace = Trace::default();
+    let_.accept(&mut trace);
+    assert_eq!(
+        vec!["let", "token", "token", "token", "u8", "token", "token", "number", "token"],
+        trace.0
+    );
+}
+
+#[test]
+fn visit_statements() {
+    let scope =
+        gb_lang::parse::<Statement>("{ let a::u8 = b; loop { let c::u8 = (d); } }").unwrap();
+    let mut trace = Trace::default();
+    scope.accept(&mut trace);
+    trace.0.retain(|node| *node != "token");
+    assert_eq!(
+        vec!["let", "u8", "identifier", "let", "u8", "identifier"],
+        trace.0
+    );
+}
+
+#[test]
+fn visit_mut() {
+    // replaces every `if` with its body
+    struct UnwrapIf;
+
+    impl<'input> VisitMut<'input> for UnwrapIf {
+        fn visit_statement_mut(&mut self, node: &mut Statement<'input>) {
+            if let Statement::If(if_) = node {
+                if !if_.inner.is_empty() {
+                    *node = if_.inner.remove(0);
+                }
+            }
+            visit::walk_statement_mut(self, node);
+        }
+    }
+
+    let mut if_ = gb_lang::parse::<Statement>("if 1 { if 0 break; }").unwrap();
+    if_.accept_mut(&mut UnwrapIf);
+    match if_ {
+        Statement::Scope(scope) => assert!(matches!(scope.inner[..], [Statement::Break(_)])),
+        _ => panic!(),
+    }
+}